@@ -0,0 +1,168 @@
+//! Common bounds for the orthophoto and the DEM, so that the two outputs
+//! can be draped on each other pixel for pixel.
+
+use std::fmt;
+
+use crate::geo::Extent;
+
+/// Relative slack when deciding whether a coordinate lies on a grid line.
+const GRID_EPSILON: f64 = 1e-6;
+
+/// Pixel grid of an output: its top-left corner and pixel size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    pub origin_x: f64,
+    pub origin_y: f64,
+    pub res_x: f64,
+    pub res_y: f64,
+}
+
+impl Grid {
+    /// The largest extent inside `extent` whose edges fall on grid lines.
+    pub fn snap_inward(&self, extent: &Extent) -> Extent {
+        let column = |x: f64| (x - self.origin_x) / self.res_x;
+        let row = |y: f64| (self.origin_y - y) / self.res_y;
+        Extent::new(
+            self.origin_x + (column(extent.min_x) - GRID_EPSILON).ceil() * self.res_x,
+            self.origin_y - (row(extent.min_y) + GRID_EPSILON).floor() * self.res_y,
+            self.origin_x + (column(extent.max_x) + GRID_EPSILON).floor() * self.res_x,
+            self.origin_y - (row(extent.max_y) - GRID_EPSILON).ceil() * self.res_y,
+        )
+    }
+
+    /// Whether every edge of `extent` falls on a grid line.
+    pub fn is_aligned(&self, extent: &Extent) -> bool {
+        let on_line = |v: f64| (v - v.round()).abs() <= GRID_EPSILON;
+        on_line((extent.min_x - self.origin_x) / self.res_x)
+            && on_line((extent.max_x - self.origin_x) / self.res_x)
+            && on_line((self.origin_y - extent.min_y) / self.res_y)
+            && on_line((self.origin_y - extent.max_y) / self.res_y)
+    }
+}
+
+/// An output as it would be produced without alignment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layer {
+    pub extent: Extent,
+    pub grid: Grid,
+}
+
+/// How much of a layer the common bounds cut away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub original: Extent,
+    /// Share of the original area removed, in percent.
+    pub percent: f64,
+}
+
+/// Bounds shared by every output of the build.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Alignment {
+    pub bounds: Extent,
+    /// Crop of the orthophoto, when its extent is known.
+    pub ortho: Option<Crop>,
+    /// Crop of the DEM, when its extent is known.
+    pub dem: Option<Crop>,
+    /// Whether `bounds` falls on the pixel grid of every known layer. When
+    /// the resolutions do not divide each other GDAL rounds to the nearest
+    /// pixel and the edges may differ by less than a pixel.
+    pub exact: bool,
+}
+
+impl Alignment {
+    /// Whether any layer loses part of its area.
+    pub fn crops(&self) -> bool {
+        [self.ortho, self.dem]
+            .iter()
+            .flatten()
+            .any(|c| c.percent > 0.0)
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "common bounds {}", self.bounds)?;
+        for (name, crop) in [("orthophoto", self.ortho), ("DEM", self.dem)] {
+            if let Some(crop) = crop {
+                write!(f, ", {} cropped by {:.1}%", name, crop.percent)?;
+            }
+        }
+        if !self.exact {
+            write!(f, " (pixel grids differ; edges may shift by under a pixel)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The layers and AOI have no area in common.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoOverlap {
+    pub ortho: Option<Extent>,
+    pub dem: Option<Extent>,
+    pub aoi: Option<Extent>,
+}
+
+impl fmt::Display for NoOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "outputs have no area in common:")?;
+        for (name, extent) in [
+            ("orthophoto", self.ortho),
+            ("DEM", self.dem),
+            ("AOI", self.aoi),
+        ] {
+            if let Some(extent) = extent {
+                write!(f, " {} {};", name, extent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Computes the bounds shared by the known layers, restricted to `aoi`
+/// when given. Returns `None` when there is nothing to align: no AOI and
+/// fewer than two known layers.
+pub fn align(
+    ortho: Option<&Layer>,
+    dem: Option<&Layer>,
+    aoi: Option<&Extent>,
+) -> Result<Option<Alignment>, NoOverlap> {
+    let layers: Vec<&Layer> = [ortho, dem].into_iter().flatten().collect();
+    if aoi.is_none() && layers.len() < 2 {
+        return Ok(None);
+    }
+    let no_overlap = || NoOverlap {
+        ortho: ortho.map(|l| l.extent),
+        dem: dem.map(|l| l.extent),
+        aoi: aoi.copied(),
+    };
+
+    let mut bounds = aoi.copied().or_else(|| layers.first().map(|l| l.extent));
+    for layer in &layers {
+        bounds = bounds.and_then(|b| b.intersection(&layer.extent));
+    }
+    let mut bounds = bounds.ok_or_else(no_overlap)?;
+
+    // Snap on the coarsest grid first so the finer ones only move edges
+    // that the coarse grid left off their lines.
+    let mut grids: Vec<Grid> = layers.iter().map(|l| l.grid).collect();
+    grids.sort_by(|a, b| b.res_x.total_cmp(&a.res_x));
+    for grid in &grids {
+        bounds = grid.snap_inward(&bounds);
+    }
+    if bounds.width() <= 0.0 || bounds.height() <= 0.0 {
+        return Err(no_overlap());
+    }
+
+    let crop = |layer: Option<&Layer>| {
+        layer.map(|l| Crop {
+            original: l.extent,
+            percent: (100.0 * (1.0 - bounds.area() / l.extent.area())).max(0.0),
+        })
+    };
+    Ok(Some(Alignment {
+        bounds,
+        ortho: crop(ortho),
+        dem: crop(dem),
+        exact: grids.iter().all(|g| g.is_aligned(&bounds)),
+    }))
+}
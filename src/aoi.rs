@@ -0,0 +1,348 @@
+//! Area of interest: a polygon footprint read from a GeoJSON or WKT file,
+//! used to select the tiles worth mosaicking and to mask the outputs.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::config::ConfigError;
+use crate::geo::Extent;
+
+/// A closed ring of `(x, y)` vertices; the closing vertex may be omitted.
+pub type Ring = Vec<(f64, f64)>;
+
+/// A polygon with optional holes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub exterior: Ring,
+    pub holes: Vec<Ring>,
+}
+
+impl Polygon {
+    /// Whether `(x, y)` lies inside the exterior ring and outside every hole.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        ring_contains(&self.exterior, x, y) && !self.holes.iter().any(|h| ring_contains(h, x, y))
+    }
+
+    fn rings(&self) -> impl Iterator<Item = &Ring> {
+        std::iter::once(&self.exterior).chain(&self.holes)
+    }
+}
+
+/// Union of one or more polygons, in the inputs' CRS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aoi {
+    pub polygons: Vec<Polygon>,
+}
+
+impl Aoi {
+    /// The rectangle `extent` as an AOI.
+    pub fn from_extent(extent: &Extent) -> Aoi {
+        let ring = vec![
+            (extent.min_x, extent.min_y),
+            (extent.max_x, extent.min_y),
+            (extent.max_x, extent.max_y),
+            (extent.min_x, extent.max_y),
+        ];
+        Aoi {
+            polygons: vec![Polygon {
+                exterior: ring,
+                holes: Vec::new(),
+            }],
+        }
+    }
+
+    /// Reads a GeoJSON (geometry, feature or feature collection) or WKT
+    /// (`POLYGON` or `MULTIPOLYGON`) file; the format is told from the
+    /// content.
+    pub fn load(path: &Path) -> Result<Aoi, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let invalid =
+            |msg: String| ConfigError::Invalid(format!("AOI {}: {}", path.display(), msg));
+        let polygons = if text.trim_start().starts_with('{') {
+            let value: Value = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+            geojson_polygons(&value).map_err(invalid)?
+        } else {
+            parse_wkt(&text).map_err(invalid)?
+        };
+        if polygons.is_empty() {
+            return Err(invalid("no polygon found".to_string()));
+        }
+        for polygon in &polygons {
+            if polygon.rings().any(|r| r.len() < 3) {
+                return Err(invalid("ring with fewer than 3 vertices".to_string()));
+            }
+        }
+        Ok(Aoi { polygons })
+    }
+
+    pub fn extent(&self) -> Extent {
+        let mut points = self.polygons.iter().flat_map(|p| p.exterior.iter());
+        let &(x, y) = points.next().expect("AOI has at least one vertex");
+        points.fold(Extent::new(x, y, x, y), |e, &(x, y)| {
+            e.union(&Extent::new(x, y, x, y))
+        })
+    }
+
+    /// Whether the AOI is exactly its bounding box, so cropping to the box
+    /// already clips to the shape.
+    pub fn is_rectangle(&self) -> bool {
+        let [polygon] = self.polygons.as_slice() else {
+            return false;
+        };
+        let extent = self.extent();
+        let mut ring = polygon.exterior.as_slice();
+        if ring.len() > 1 && ring.first() == ring.last() {
+            ring = &ring[..ring.len() - 1];
+        }
+        let on_corner = |&(x, y): &(f64, f64)| {
+            (x == extent.min_x || x == extent.max_x) && (y == extent.min_y || y == extent.max_y)
+        };
+        polygon.holes.is_empty()
+            && ring.len() == 4
+            && ring.iter().all(on_corner)
+            && edges(ring).all(|(a, b)| (a.0 == b.0) != (a.1 == b.1))
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.polygons.iter().any(|p| p.contains(x, y))
+    }
+
+    /// Whether the AOI and `extent` share some area. Touching edges do not
+    /// count.
+    pub fn intersects(&self, extent: &Extent) -> bool {
+        if !self.extent().intersects(extent) {
+            return false;
+        }
+        let (cx, cy) = (
+            (extent.min_x + extent.max_x) / 2.0,
+            (extent.min_y + extent.max_y) / 2.0,
+        );
+        if self.contains(cx, cy) {
+            return true;
+        }
+        let corners = [
+            (extent.min_x, extent.min_y),
+            (extent.max_x, extent.min_y),
+            (extent.max_x, extent.max_y),
+            (extent.min_x, extent.max_y),
+        ];
+        self.polygons.iter().flat_map(Polygon::rings).any(|ring| {
+            let strictly_inside = |&(x, y): &(f64, f64)| {
+                x > extent.min_x && x < extent.max_x && y > extent.min_y && y < extent.max_y
+            };
+            ring.iter().any(strictly_inside)
+                || edges(ring).any(|a| edges(&corners).any(|b| segments_cross(a, b)))
+        })
+    }
+
+    /// The AOI as a GeoJSON feature collection, for `gdalwarp -cutline`.
+    pub fn to_geojson(&self) -> String {
+        let ring = |r: &Ring| {
+            let mut points: Vec<[f64; 2]> = r.iter().map(|&(x, y)| [x, y]).collect();
+            if r.first() != r.last() {
+                points.push([r[0].0, r[0].1]);
+            }
+            points
+        };
+        let polygons: Vec<Vec<Vec<[f64; 2]>>> = self
+            .polygons
+            .iter()
+            .map(|p| p.rings().map(ring).collect())
+            .collect();
+        let document = json!({
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {},
+                "geometry": { "type": "MultiPolygon", "coordinates": polygons },
+            }],
+        });
+        format!("{:#}\n", document)
+    }
+}
+
+/// Even-odd test of `(x, y)` against a ring.
+fn ring_contains(ring: &Ring, x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for ((x0, y0), (x1, y1)) in edges(ring) {
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Every edge of `ring`, closing it if needed.
+fn edges(ring: &[(f64, f64)]) -> impl Iterator<Item = ((f64, f64), (f64, f64))> + '_ {
+    (0..ring.len()).map(move |i| (ring[i], ring[(i + 1) % ring.len()]))
+}
+
+/// Whether two segments cross at a single point strictly inside both.
+fn segments_cross(a: ((f64, f64), (f64, f64)), b: ((f64, f64), (f64, f64))) -> bool {
+    let side = |p: (f64, f64), q: (f64, f64), r: (f64, f64)| {
+        (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0)
+    };
+    let d1 = side(b.0, b.1, a.0);
+    let d2 = side(b.0, b.1, a.1);
+    let d3 = side(a.0, a.1, b.0);
+    let d4 = side(a.0, a.1, b.1);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// Polygons of a GeoJSON object. Non-polygonal geometries are ignored.
+fn geojson_polygons(value: &Value) -> Result<Vec<Polygon>, String> {
+    let kind = value["type"]
+        .as_str()
+        .ok_or("GeoJSON object without a type")?;
+    match kind {
+        "FeatureCollection" => {
+            let features = value["features"]
+                .as_array()
+                .ok_or("FeatureCollection without features")?;
+            let mut polygons = Vec::new();
+            for feature in features {
+                polygons.extend(geojson_polygons(feature)?);
+            }
+            Ok(polygons)
+        }
+        "Feature" if value["geometry"].is_null() => Ok(Vec::new()),
+        "Feature" => geojson_polygons(&value["geometry"]),
+        "GeometryCollection" => {
+            let geometries = value["geometries"]
+                .as_array()
+                .ok_or("GeometryCollection without geometries")?;
+            let mut polygons = Vec::new();
+            for geometry in geometries {
+                polygons.extend(geojson_polygons(geometry)?);
+            }
+            Ok(polygons)
+        }
+        "Polygon" => Ok(vec![json_polygon(&value["coordinates"])?]),
+        "MultiPolygon" => value["coordinates"]
+            .as_array()
+            .ok_or("MultiPolygon without coordinates")?
+            .iter()
+            .map(json_polygon)
+            .collect(),
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn json_polygon(value: &Value) -> Result<Polygon, String> {
+    let rings = value
+        .as_array()
+        .ok_or("polygon coordinates must be an array")?;
+    let mut rings = rings.iter().map(|ring| {
+        ring.as_array()
+            .ok_or_else(|| "ring must be an array of positions".to_string())?
+            .iter()
+            .map(|position| match position.as_array().map(Vec::as_slice) {
+                Some([x, y, ..]) => match (x.as_f64(), y.as_f64()) {
+                    (Some(x), Some(y)) => Ok((x, y)),
+                    _ => Err(format!("invalid position {}", position)),
+                },
+                _ => Err(format!("invalid position {}", position)),
+            })
+            .collect::<Result<Ring, String>>()
+    });
+    let exterior = rings.next().ok_or("polygon without rings")??;
+    Ok(Polygon {
+        exterior,
+        holes: rings.collect::<Result<_, _>>()?,
+    })
+}
+
+/// Parses a WKT `POLYGON` or `MULTIPOLYGON`, with an optional `SRID=...;`
+/// prefix.
+fn parse_wkt(text: &str) -> Result<Vec<Polygon>, String> {
+    let text = text.trim();
+    let text = match text.split_once(';') {
+        Some((srid, rest)) if srid.trim_start().to_ascii_uppercase().starts_with("SRID=") => {
+            rest.trim()
+        }
+        _ => text,
+    };
+    let open = text.find('(').ok_or("expected POLYGON or MULTIPOLYGON")?;
+    let keyword = text[..open].trim().to_ascii_uppercase();
+    let keyword = keyword.trim_end_matches(" Z").trim_end_matches(" M");
+    let tree = parse_parens(&text[open..])?;
+    match keyword {
+        "POLYGON" => Ok(vec![wkt_polygon(&tree)?]),
+        "MULTIPOLYGON" => tree.children()?.iter().map(wkt_polygon).collect(),
+        other => Err(format!("expected POLYGON or MULTIPOLYGON, got {}", other)),
+    }
+}
+
+/// A parenthesized WKT group: nested groups or a coordinate list.
+enum Group {
+    Nested(Vec<Group>),
+    Points(Ring),
+}
+
+impl Group {
+    fn children(&self) -> Result<&[Group], String> {
+        match self {
+            Group::Nested(children) => Ok(children),
+            Group::Points(_) => Err("unexpected coordinate list".to_string()),
+        }
+    }
+}
+
+fn wkt_polygon(group: &Group) -> Result<Polygon, String> {
+    let mut rings = group.children()?.iter().map(|ring| match ring {
+        Group::Points(points) => Ok(points.clone()),
+        Group::Nested(_) => Err("unexpected nesting in ring".to_string()),
+    });
+    let exterior = rings.next().ok_or("polygon without rings")??;
+    Ok(Polygon {
+        exterior,
+        holes: rings.collect::<Result<_, _>>()?,
+    })
+}
+
+/// Parses `text`, which starts with `(`, into a group tree.
+fn parse_parens(text: &str) -> Result<Group, String> {
+    fn group(text: &str, pos: &mut usize) -> Result<Group, String> {
+        // `text[*pos]` is the opening parenthesis.
+        *pos += 1;
+        let rest = &text[*pos..];
+        let next = rest.trim_start();
+        if next.starts_with('(') {
+            let mut children = Vec::new();
+            loop {
+                *pos += text[*pos..].len() - text[*pos..].trim_start().len();
+                match text[*pos..].chars().next() {
+                    Some('(') => children.push(group(text, pos)?),
+                    Some(',') => *pos += 1,
+                    Some(')') => {
+                        *pos += 1;
+                        return Ok(Group::Nested(children));
+                    }
+                    _ => return Err("unbalanced parentheses".to_string()),
+                }
+            }
+        }
+        let close = rest.find(')').ok_or("unbalanced parentheses")?;
+        *pos += close + 1;
+        let points = rest[..close]
+            .split(',')
+            .map(|point| {
+                let mut numbers = point.split_whitespace().map(str::parse::<f64>);
+                match (numbers.next(), numbers.next()) {
+                    (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+                    _ => Err(format!("invalid coordinate {:?}", point.trim())),
+                }
+            })
+            .collect::<Result<Ring, String>>()?;
+        Ok(Group::Points(points))
+    }
+
+    let mut pos = 0;
+    let tree = group(text, &mut pos)?;
+    if !text[pos..].trim().is_empty() {
+        return Err("unexpected text after the geometry".to_string());
+    }
+    Ok(tree)
+}
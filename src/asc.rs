@@ -0,0 +1,453 @@
+//! Streaming reader for ESRI ASCII grids (`.asc`), the format of the DEM
+//! tiles.
+//!
+//! A file starts with a small header:
+//!
+//! ```text
+//! ncols         1000
+//! nrows         1000
+//! xllcorner     620000.0
+//! yllcorner     6119000.0
+//! cellsize      1.0
+//! NODATA_value  -99999
+//! ```
+//!
+//! followed by `nrows` rows of `ncols` whitespace-separated values, top row
+//! first. Rows are read one at a time so tiles of any size can be scanned in
+//! constant memory.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::raster::{read_prj_sidecar, DataType, RasterInfo};
+
+/// Whether `xll`/`yll` give the outer corner or the centre of the
+/// lower-left cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    Corner,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AscHeader {
+    pub ncols: usize,
+    pub nrows: usize,
+    pub xll: f64,
+    pub yll: f64,
+    pub anchor: Anchor,
+    /// Cell width; `cellsize` or `dx`.
+    pub cell_x: f64,
+    /// Cell height; `cellsize` or `dy`.
+    pub cell_y: f64,
+    pub nodata: Option<f64>,
+}
+
+impl AscHeader {
+    /// Left edge of the grid in map units.
+    pub fn x_min(&self) -> f64 {
+        match self.anchor {
+            Anchor::Corner => self.xll,
+            Anchor::Center => self.xll - self.cell_x / 2.0,
+        }
+    }
+
+    /// Bottom edge of the grid in map units.
+    pub fn y_min(&self) -> f64 {
+        match self.anchor {
+            Anchor::Corner => self.yll,
+            Anchor::Center => self.yll - self.cell_y / 2.0,
+        }
+    }
+
+    pub fn x_max(&self) -> f64 {
+        self.x_min() + self.ncols as f64 * self.cell_x
+    }
+
+    pub fn y_max(&self) -> f64 {
+        self.y_min() + self.nrows as f64 * self.cell_y
+    }
+
+    /// GDAL-style geotransform for a north-up grid.
+    pub fn geo_transform(&self) -> [f64; 6] {
+        [
+            self.x_min(),
+            self.cell_x,
+            0.0,
+            self.y_max(),
+            0.0,
+            -self.cell_y,
+        ]
+    }
+
+    /// Whether `value` is this grid's nodata marker.
+    pub fn is_nodata(&self, value: f32) -> bool {
+        match self.nodata {
+            Some(nodata) => value == nodata as f32,
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AscError {
+    Io(io::Error),
+    Header { line: usize, message: String },
+    Data { line: usize, message: String },
+}
+
+impl fmt::Display for AscError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AscError::Io(e) => write!(f, "{}", e),
+            AscError::Header { line, message } => {
+                write!(f, "invalid header at line {}: {}", line, message)
+            }
+            AscError::Data { line, message } => {
+                write!(f, "invalid data at line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AscError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AscError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AscError {
+    fn from(e: io::Error) -> Self {
+        AscError::Io(e)
+    }
+}
+
+/// Reads only the header of an `.asc` file.
+pub fn read_header(path: &Path) -> Result<AscHeader, AscError> {
+    Ok(*AscReader::open(path)?.header())
+}
+
+/// Metadata of an `.asc` tile, with its CRS taken from the `.prj`
+/// sidecar. Values are always exposed as Float32.
+pub fn raster_info(path: &Path) -> Result<RasterInfo, AscError> {
+    let header = read_header(path)?;
+    Ok(RasterInfo {
+        path: path.to_path_buf(),
+        width: header.ncols,
+        height: header.nrows,
+        bands: 1,
+        data_type: DataType::Float32,
+        geo_transform: header.geo_transform(),
+        nodata: header.nodata,
+        crs: read_prj_sidecar(path)?,
+    })
+}
+
+/// Row-by-row reader over an ESRI ASCII grid.
+pub struct AscReader<R> {
+    reader: R,
+    header: AscHeader,
+    line: String,
+    line_no: usize,
+    /// Values already tokenised from the current line but not yet consumed.
+    pending: Vec<f32>,
+    pending_pos: usize,
+    rows_read: usize,
+}
+
+impl AscReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self, AscError> {
+        AscReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> AscReader<R> {
+    /// Parses the header, leaving the reader positioned on the first row.
+    pub fn new(reader: R) -> Result<Self, AscError> {
+        let mut asc = AscReader {
+            reader,
+            header: AscHeader {
+                ncols: 0,
+                nrows: 0,
+                xll: 0.0,
+                yll: 0.0,
+                anchor: Anchor::Corner,
+                cell_x: 0.0,
+                cell_y: 0.0,
+                nodata: None,
+            },
+            line: String::new(),
+            line_no: 0,
+            pending: Vec::new(),
+            pending_pos: 0,
+            rows_read: 0,
+        };
+        asc.header = asc.parse_header()?;
+        Ok(asc)
+    }
+
+    pub fn header(&self) -> &AscHeader {
+        &self.header
+    }
+
+    /// Number of rows returned so far.
+    pub fn rows_read(&self) -> usize {
+        self.rows_read
+    }
+
+    fn parse_header(&mut self) -> Result<AscHeader, AscError> {
+        let mut ncols = None;
+        let mut nrows = None;
+        let mut xll = None;
+        let mut yll = None;
+        let mut cellsize = None;
+        let mut dx = None;
+        let mut dy = None;
+        let mut nodata = None;
+
+        loop {
+            if !self.next_line()? {
+                break;
+            }
+            let mut words = self.line.split_whitespace();
+            let Some(key) = words.next() else {
+                continue;
+            };
+            // The first line starting with a number is the first data row.
+            if key.parse::<f64>().is_ok() {
+                self.tokenize_line()?;
+                break;
+            }
+            let value = words
+                .next()
+                .ok_or_else(|| self.header_error(format!("{} has no value", key)))?;
+            let number = |name: &str| -> Result<f64, AscError> {
+                value.parse::<f64>().map_err(|_| {
+                    self.header_error(format!("{} is not a number: {:?}", name, value))
+                })
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "ncols" => ncols = Some(self.count(value, "ncols")?),
+                "nrows" => nrows = Some(self.count(value, "nrows")?),
+                "xllcorner" => xll = Some((number("xllcorner")?, Anchor::Corner)),
+                "xllcenter" => xll = Some((number("xllcenter")?, Anchor::Center)),
+                "yllcorner" => yll = Some((number("yllcorner")?, Anchor::Corner)),
+                "yllcenter" => yll = Some((number("yllcenter")?, Anchor::Center)),
+                "cellsize" => cellsize = Some(number("cellsize")?),
+                "dx" => dx = Some(number("dx")?),
+                "dy" => dy = Some(number("dy")?),
+                "nodata_value" => nodata = Some(number("NODATA_value")?),
+                other => return Err(self.header_error(format!("unknown key {:?}", other))),
+            }
+        }
+
+        let missing = |key: &str| AscError::Header {
+            line: self.line_no,
+            message: format!("missing {}", key),
+        };
+        let ncols = ncols.ok_or_else(|| missing("ncols"))?;
+        let nrows = nrows.ok_or_else(|| missing("nrows"))?;
+        let (xll, x_anchor) = xll.ok_or_else(|| missing("xllcorner or xllcenter"))?;
+        let (yll, y_anchor) = yll.ok_or_else(|| missing("yllcorner or yllcenter"))?;
+        if x_anchor != y_anchor {
+            return Err(self.header_error("mixes corner and center origins".to_string()));
+        }
+        let (cell_x, cell_y) = match (cellsize, dx, dy) {
+            (Some(size), None, None) => (size, size),
+            (None, Some(dx), Some(dy)) => (dx, dy),
+            (None, None, None) => return Err(missing("cellsize")),
+            _ => {
+                return Err(
+                    self.header_error("expected either cellsize or both dx and dy".to_string())
+                )
+            }
+        };
+        if !(cell_x > 0.0 && cell_y > 0.0) {
+            return Err(self.header_error(format!(
+                "cell size must be positive, got {} x {}",
+                cell_x, cell_y
+            )));
+        }
+
+        Ok(AscHeader {
+            ncols,
+            nrows,
+            xll,
+            yll,
+            anchor: x_anchor,
+            cell_x,
+            cell_y,
+            nodata,
+        })
+    }
+
+    fn count(&self, value: &str, key: &str) -> Result<usize, AscError> {
+        match value.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(self.header_error(format!(
+                "{} must be a positive integer, got {:?}",
+                key, value
+            ))),
+        }
+    }
+
+    fn header_error(&self, message: String) -> AscError {
+        AscError::Header {
+            line: self.line_no,
+            message,
+        }
+    }
+
+    fn next_line(&mut self) -> Result<bool, AscError> {
+        self.line.clear();
+        let read = self.reader.read_line(&mut self.line)?;
+        if read > 0 {
+            self.line_no += 1;
+        }
+        Ok(read > 0)
+    }
+
+    fn tokenize_line(&mut self) -> Result<(), AscError> {
+        self.pending.clear();
+        self.pending_pos = 0;
+        for word in self.line.split_whitespace() {
+            let value = word.parse::<f32>().map_err(|_| AscError::Data {
+                line: self.line_no,
+                message: format!("{:?} is not a number", word),
+            })?;
+            self.pending.push(value);
+        }
+        Ok(())
+    }
+
+    /// Fills `row` with the next grid row. Returns `false` once all
+    /// `nrows` rows have been read.
+    ///
+    /// Values may be spread over lines arbitrarily; only the total count
+    /// matters.
+    pub fn read_row(&mut self, row: &mut [f32]) -> Result<bool, AscError> {
+        assert_eq!(
+            row.len(),
+            self.header.ncols,
+            "row buffer must hold ncols values"
+        );
+        if self.rows_read == self.header.nrows {
+            return Ok(false);
+        }
+
+        let mut filled = 0;
+        while filled < row.len() {
+            if self.pending_pos == self.pending.len() {
+                if !self.next_line()? {
+                    return Err(AscError::Data {
+                        line: self.line_no,
+                        message: format!(
+                            "file ends in row {} of {} after {} of {} values",
+                            self.rows_read + 1,
+                            self.header.nrows,
+                            filled,
+                            self.header.ncols
+                        ),
+                    });
+                }
+                self.tokenize_line()?;
+                continue;
+            }
+            let n = (self.pending.len() - self.pending_pos).min(row.len() - filled);
+            row[filled..filled + n]
+                .copy_from_slice(&self.pending[self.pending_pos..self.pending_pos + n]);
+            self.pending_pos += n;
+            filled += n;
+        }
+        self.rows_read += 1;
+        Ok(true)
+    }
+
+    /// Checks that nothing but whitespace follows the last row.
+    pub fn finish(mut self) -> Result<(), AscError> {
+        loop {
+            if self.pending_pos < self.pending.len() {
+                return Err(AscError::Data {
+                    line: self.line_no,
+                    message: format!(
+                        "unexpected values after the {} declared rows",
+                        self.header.nrows
+                    ),
+                });
+            }
+            if !self.next_line()? {
+                return Ok(());
+            }
+            self.tokenize_line()?;
+        }
+    }
+
+    /// Reads the remaining rows into a row-major buffer.
+    pub fn read_all(mut self) -> Result<Vec<f32>, AscError> {
+        let ncols = self.header.ncols;
+        let mut data = vec![0.0; ncols * (self.header.nrows - self.rows_read)];
+        for row in data.chunks_mut(ncols) {
+            self.read_row(row)?;
+        }
+        self.finish()?;
+        Ok(data)
+    }
+
+    /// Streams the whole grid, validating its layout and collecting value
+    /// statistics.
+    pub fn statistics(mut self) -> Result<AscStatistics, AscError> {
+        let header = self.header;
+        let mut stats = AscStatistics {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            mean: 0.0,
+            valid: 0,
+            nodata: 0,
+        };
+        let mut sum = 0.0f64;
+        let mut row = vec![0.0; header.ncols];
+        while self.read_row(&mut row)? {
+            for &value in &row {
+                if header.is_nodata(value) || value.is_nan() {
+                    stats.nodata += 1;
+                } else {
+                    stats.valid += 1;
+                    stats.min = stats.min.min(value);
+                    stats.max = stats.max.max(value);
+                    sum += value as f64;
+                }
+            }
+        }
+        self.finish()?;
+        if stats.valid > 0 {
+            stats.mean = sum / stats.valid as f64;
+        }
+        Ok(stats)
+    }
+}
+
+/// Summary of a grid's values, see [`AscReader::statistics`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AscStatistics {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f64,
+    pub valid: u64,
+    pub nodata: u64,
+}
+
+impl AscStatistics {
+    pub fn nodata_ratio(&self) -> f64 {
+        let total = self.valid + self.nodata;
+        if total == 0 {
+            0.0
+        } else {
+            self.nodata as f64 / total as f64
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use vrt_maker::config::{Config, ConfigError, Derivative};
+use vrt_maker::Stages;
+
+/// Builds orthophoto and DEM GeoTIFFs from tiled JP2 and ASC deliveries.
+#[derive(Parser)]
+#[command(
+    name = "vrt_maker",
+    version,
+    about,
+    after_help = "Exit codes: 0 success, 2 invalid configuration, 3 I/O error, \
+                  4 GDAL tool missing or could not be started, 5 tool failed, 6 no input tiles found, \
+                  7 invalid input tile, 8 incomplete tile coverage"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Build the orthophoto mosaic only
+    Ortho(BuildArgs),
+    /// Build the DEM only
+    Dem(BuildArgs),
+    /// Build both the orthophoto and the DEM
+    All(BuildArgs),
+    /// Read and validate DEM .asc tiles without GDAL
+    Inspect(InspectArgs),
+    /// Check that GeoTIFFs follow the Cloud Optimized GeoTIFF layout
+    ValidateCog(ValidateCogArgs),
+    /// Check that the GDAL tools and the JPEG 2000 driver are installed
+    Doctor,
+}
+
+impl Command {
+    /// The build arguments and selected stages, for build subcommands.
+    pub fn build(&self) -> Option<(&BuildArgs, Stages)> {
+        match self {
+            Command::Ortho(args) => Some((args, Stages::ORTHO)),
+            Command::Dem(args) => Some((args, Stages::DEM)),
+            Command::All(args) => Some((args, Stages::ALL)),
+            Command::Inspect(_) | Command::ValidateCog(_) | Command::Doctor => None,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    /// .asc files, or directories searched recursively for them
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ValidateCogArgs {
+    /// GeoTIFF files to check
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct BuildArgs {
+    /// Project file describing the build (defaults to ./vrt_maker.toml if present)
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Directory containing the orthophoto .jp2 tiles [default: data/jp2]
+    #[arg(long)]
+    pub jp2_dir: Option<PathBuf>,
+
+    /// Directory containing the DEM .asc tiles [default: data/asc]
+    #[arg(long)]
+    pub asc_dir: Option<PathBuf>,
+
+    /// Directory receiving the final GeoTIFFs [default: out]
+    #[arg(short, long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Directory for intermediate VRTs [default: <OUT_DIR>/tmp]
+    #[arg(long)]
+    pub tmp_dir: Option<PathBuf>,
+
+    /// File name of the orthophoto GeoTIFF inside the output directory [default: orthophoto.tiff]
+    #[arg(long)]
+    pub ortho_name: Option<String>,
+
+    /// File name of the DEM GeoTIFF inside the output directory [default: dem.tiff]
+    #[arg(long)]
+    pub dem_name: Option<String>,
+
+    /// Print the commands, intermediate files and output sizes without running anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Rerun every step, ignoring the build manifest
+    #[arg(long)]
+    pub force: bool,
+
+    /// Reproject input tiles in another CRS to this one (e.g. EPSG:2154) instead of refusing to build
+    #[arg(long, value_name = "CRS")]
+    pub input_crs: Option<String>,
+
+    /// Reproject both outputs to this CRS (e.g. EPSG:4326 or EPSG:3857)
+    #[arg(long, value_name = "CRS")]
+    pub output_crs: Option<String>,
+
+    /// Abort when tiles are missing from the input grid
+    #[arg(long)]
+    pub require_complete_coverage: bool,
+
+    /// Also compute a shaded relief of the DEM
+    #[arg(long)]
+    pub hillshade: bool,
+
+    /// Also derive these rasters from the DEM: slope, aspect, tri, tpi, roughness, curvature
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub terrain: Option<Vec<Derivative>>,
+
+    /// Also trace contour lines of the DEM, this far apart in height
+    #[arg(long, value_name = "INTERVAL")]
+    pub contours: Option<f64>,
+
+    /// Crop every output to this area, in the inputs' CRS
+    #[arg(
+        long,
+        value_name = "XMIN,YMIN,XMAX,YMAX",
+        value_delimiter = ',',
+        allow_hyphen_values = true
+    )]
+    pub bounds: Option<Vec<f64>>,
+
+    /// Use only the tiles whose IGN dalle name puts their origin in this range, in Lambert-93 km
+    #[arg(long, value_name = "XMIN,YMIN,XMAX,YMAX", value_delimiter = ',')]
+    pub tiles: Option<Vec<u32>>,
+
+    /// Use only the tiles touching this GeoJSON or WKT polygon and mask the outputs outside it
+    #[arg(long, value_name = "FILE")]
+    pub aoi: Option<PathBuf>,
+
+    /// Keep the orthophoto and DEM extents as they are instead of cropping both to their common area
+    #[arg(long)]
+    pub no_align: bool,
+}
+
+impl BuildArgs {
+    /// Loads the project file and lets command-line values override it.
+    pub fn resolve(&self) -> Result<Config, ConfigError> {
+        let mut config = Config::discover(self.config.as_deref())?;
+
+        if let Some(dir) = &self.jp2_dir {
+            config.inputs.jp2_dir = dir.clone();
+        }
+        if let Some(dir) = &self.asc_dir {
+            config.inputs.asc_dir = dir.clone();
+        }
+        if let Some(dir) = &self.out_dir {
+            config.outputs.dir = dir.clone();
+        }
+        if let Some(dir) = &self.tmp_dir {
+            config.outputs.tmp_dir = Some(dir.clone());
+        }
+        if let Some(name) = &self.ortho_name {
+            config.outputs.ortho = name.clone();
+        }
+        if let Some(name) = &self.dem_name {
+            config.outputs.dem = name.clone();
+        }
+        if let Some(crs) = &self.input_crs {
+            config.crs.inputs = Some(crs.clone());
+        }
+        if let Some(crs) = &self.output_crs {
+            config.crs.output = Some(crs.clone());
+        }
+        if self.require_complete_coverage {
+            config.coverage.require_complete = true;
+        }
+        if self.hillshade {
+            config.hillshade.enabled = true;
+        }
+        if let Some(derivatives) = &self.terrain {
+            config.terrain.derivatives = derivatives.clone();
+        }
+        if let Some(interval) = self.contours {
+            config.contours.enabled = true;
+            config.contours.interval = interval;
+        }
+        if let Some(bounds) = &self.bounds {
+            let &[x0, y0, x1, y1] = bounds.as_slice() else {
+                return Err(ConfigError::Invalid(format!(
+                    "--bounds takes XMIN,YMIN,XMAX,YMAX, got {} value(s)",
+                    bounds.len()
+                )));
+            };
+            config.extent.bounds = Some([x0, y0, x1, y1]);
+        }
+        if let Some(tiles) = &self.tiles {
+            let &[x0, y0, x1, y1] = tiles.as_slice() else {
+                return Err(ConfigError::Invalid(format!(
+                    "--tiles takes XMIN,YMIN,XMAX,YMAX, got {} value(s)",
+                    tiles.len()
+                )));
+            };
+            config.inputs.tiles = Some([x0, y0, x1, y1]);
+        }
+        if let Some(aoi) = &self.aoi {
+            config.extent.aoi = Some(aoi.clone());
+        }
+        if self.no_align {
+            config.extent.align = false;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
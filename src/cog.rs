@@ -0,0 +1,218 @@
+//! Cloud Optimized GeoTIFF validation: checks that a file is laid out so
+//! HTTP readers can fetch its IFDs in one request and then only the tiles
+//! they need, following the rules of GDAL's
+//! `validate_cloud_optimized_geotiff.py`.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::tiff::{
+    Ifd, TiffError, TiffReader, TAG_IMAGE_LENGTH, TAG_IMAGE_WIDTH, TAG_NEW_SUBFILE_TYPE,
+    TAG_STRIP_OFFSETS, TAG_TILE_OFFSETS, TAG_TILE_WIDTH,
+};
+
+/// Images up to this size in both directions need neither tiles nor
+/// overviews.
+const SMALL_IMAGE: i64 = 512;
+
+/// The first IFD must start within the first bytes a reader fetches.
+const MAX_FIRST_IFD: u64 = 300;
+
+/// What stands between a file and the COG layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CogReport {
+    pub path: PathBuf,
+    /// Layout problems: the file is not a COG.
+    pub errors: Vec<String>,
+    /// A COG, but one readers will not make the most of.
+    pub warnings: Vec<String>,
+}
+
+impl CogReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for CogReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.is_valid() {
+            "Cloud Optimized GeoTIFF"
+        } else {
+            "not a Cloud Optimized GeoTIFF"
+        };
+        writeln!(f, "{}: {}", self.path.display(), verdict)?;
+        for error in &self.errors {
+            writeln!(f, "  error: {}", error)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// One resolution level of the file: the full-resolution image or an
+/// overview, and where its IFD and blocks are.
+struct Level<'a> {
+    name: String,
+    ifd: &'a Ifd,
+    width: i64,
+    height: i64,
+}
+
+impl Level<'_> {
+    fn block_offsets(&self) -> Vec<i64> {
+        let offsets = self
+            .ifd
+            .integers(TAG_TILE_OFFSETS)
+            .or_else(|| self.ifd.integers(TAG_STRIP_OFFSETS))
+            .unwrap_or_default();
+        // Offset 0 marks a sparse block, absent from the file.
+        offsets.iter().copied().filter(|&o| o != 0).collect()
+    }
+}
+
+/// Checks the layout of the TIFF at `path`. Fails only when the file
+/// cannot be read as a TIFF at all.
+pub fn validate(path: &Path) -> Result<CogReport, TiffError> {
+    let mut reader = TiffReader::new(BufReader::new(File::open(path)?))?;
+    let ifds = reader.read_ifds()?;
+    let first_ifd = reader.first_ifd_offset();
+    let header_size = if reader.is_big_tiff() { 16 } else { 8 };
+    let ghost = read_ghost_area(reader.into_inner(), header_size)?;
+
+    let mut report = CogReport {
+        path: path.to_path_buf(),
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let Some(main) = ifds.first() else {
+        report.errors.push("the file holds no image".to_string());
+        return Ok(report);
+    };
+    let level = |name: String, ifd| Level {
+        name,
+        ifd,
+        width: ifd.integer(TAG_IMAGE_WIDTH).unwrap_or(0),
+        height: ifd.integer(TAG_IMAGE_LENGTH).unwrap_or(0),
+    };
+    let mut levels = vec![level("the full-resolution image".to_string(), main)];
+    // Reduced-resolution images, masks (bit 2) left aside.
+    let overviews = ifds[1..].iter().filter(|ifd| {
+        let kind = ifd.integer(TAG_NEW_SUBFILE_TYPE).unwrap_or(0);
+        kind & 1 == 1 && kind & 4 == 0
+    });
+    for (index, ifd) in overviews.enumerate() {
+        levels.push(level(format!("overview {}", index + 1), ifd));
+    }
+    let main = &levels[0];
+    let large = main.width > SMALL_IMAGE || main.height > SMALL_IMAGE;
+
+    for (index, level) in levels.iter().enumerate() {
+        if level.ifd.get(TAG_TILE_WIDTH).is_none() && (large || index > 0) {
+            report
+                .errors
+                .push(format!("{} is stored in strips, not tiles", level.name));
+        }
+    }
+    if large && levels.len() == 1 {
+        report.warnings.push(format!(
+            "the {} x {} image has no overviews: zoomed-out views read every tile",
+            main.width, main.height
+        ));
+    }
+    for pair in levels.windows(2) {
+        if pair[1].width >= pair[0].width && pair[1].height >= pair[0].height {
+            report.errors.push(format!(
+                "{} ({} x {}) is not smaller than {} ({} x {})",
+                pair[1].name,
+                pair[1].width,
+                pair[1].height,
+                pair[0].name,
+                pair[0].width,
+                pair[0].height
+            ));
+        }
+    }
+
+    // Every IFD first, in order, so one read at the start gets them all.
+    if first_ifd > MAX_FIRST_IFD {
+        report.errors.push(format!(
+            "the first IFD is at byte {}, not within the first {} bytes",
+            first_ifd, MAX_FIRST_IFD
+        ));
+    }
+    for pair in levels.windows(2) {
+        if pair[1].ifd.offset < pair[0].ifd.offset {
+            report.errors.push(format!(
+                "the IFD of {} (byte {}) comes before that of {} (byte {})",
+                pair[1].name, pair[1].ifd.offset, pair[0].name, pair[0].ifd.offset
+            ));
+        }
+    }
+    let last_ifd = ifds.iter().map(|ifd| ifd.offset).max().unwrap_or(0);
+    for level in &levels {
+        let offsets = level.block_offsets();
+        if offsets.first().is_some_and(|&o| (o as u64) < last_ifd) {
+            report.errors.push(format!(
+                "the blocks of {} start at byte {}, before the last IFD (byte {})",
+                level.name, offsets[0], last_ifd
+            ));
+        }
+        if offsets.windows(2).any(|w| w[1] < w[0]) {
+            report.errors.push(format!(
+                "the blocks of {} are not stored in row-major order",
+                level.name
+            ));
+        }
+    }
+
+    // The smallest overview's pixels first, the full resolution last.
+    for pair in levels.windows(2) {
+        let first = |level: &Level| level.block_offsets().first().copied();
+        if let (Some(larger), Some(smaller)) = (first(&pair[0]), first(&pair[1])) {
+            if larger < smaller {
+                report.errors.push(format!(
+                    "the blocks of {} come before those of {}",
+                    pair[0].name, pair[1].name
+                ));
+            }
+        }
+    }
+
+    match ghost {
+        Some(metadata) if !metadata.contains("LAYOUT=IFDS_BEFORE_DATA") => report
+            .warnings
+            .push("the GDAL structural metadata does not declare the COG layout".to_string()),
+        Some(_) => {}
+        None => report.warnings.push(
+            "no GDAL structural metadata: GDAL will not report the file as a COG".to_string(),
+        ),
+    }
+    Ok(report)
+}
+
+/// The structural metadata GDAL writes right after the header, if any.
+fn read_ghost_area(
+    mut reader: impl Read + Seek,
+    header_size: u64,
+) -> Result<Option<String>, TiffError> {
+    const PREFIX: &str = "GDAL_STRUCTURAL_METADATA_SIZE=";
+    reader.seek(SeekFrom::Start(header_size))?;
+    let mut line = [0; 43];
+    if reader.read_exact(&mut line).is_err() || !line.starts_with(PREFIX.as_bytes()) {
+        return Ok(None);
+    }
+    let size = std::str::from_utf8(&line[PREFIX.len()..PREFIX.len() + 6])
+        .ok()
+        .and_then(|digits| digits.parse::<u64>().ok());
+    let Some(size) = size else {
+        return Ok(None);
+    };
+    let mut metadata = String::new();
+    reader.take(size).read_to_string(&mut metadata)?;
+    Ok(Some(metadata))
+}
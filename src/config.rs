@@ -0,0 +1,1150 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::crs::CrsId;
+
+/// File name looked up in the working directory when no `--config` is given.
+pub const DEFAULT_CONFIG_FILE: &str = "vrt_maker.toml";
+
+/// A full build description, as read from a `vrt_maker.toml` project file.
+///
+/// Every section is optional; missing keys fall back to the values the
+/// pipeline has always used.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub inputs: Inputs,
+    pub outputs: Outputs,
+    pub vrt: VrtOptions,
+    pub extent: ExtentOptions,
+    pub coverage: CoverageOptions,
+    pub crs: CrsOptions,
+    pub ortho: OrthoOptions,
+    pub dem: DemOptions,
+    pub hillshade: HillshadeOptions,
+    pub terrain: TerrainOptions,
+    pub contours: ContourOptions,
+    pub geotiff: GeoTiffOptions,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Inputs {
+    /// Directory containing the orthophoto .jp2 tiles.
+    pub jp2_dir: PathBuf,
+    /// Directory containing the DEM .asc tiles.
+    pub asc_dir: PathBuf,
+    /// Only use the tiles whose IGN dalle name (`..._0620_6120_...`) puts
+    /// their top-left corner in `[min_x, min_y, max_x, max_y]`, in
+    /// Lambert-93 kilometres.
+    pub tiles: Option<[u32; 4]>,
+}
+
+impl Default for Inputs {
+    fn default() -> Self {
+        Inputs {
+            jp2_dir: PathBuf::from("data/jp2"),
+            asc_dir: PathBuf::from("data/asc"),
+            tiles: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Outputs {
+    /// Directory receiving the final GeoTIFFs.
+    pub dir: PathBuf,
+    /// Directory for intermediate VRTs, `<dir>/tmp` when unset.
+    pub tmp_dir: Option<PathBuf>,
+    /// File name of the orthophoto GeoTIFF inside `dir`.
+    pub ortho: String,
+    /// File name of the DEM GeoTIFF inside `dir`.
+    pub dem: String,
+    /// File name of the hillshade GeoTIFF inside `dir`.
+    pub hillshade: String,
+    /// File name of the contour lines inside `dir`, without extension.
+    pub contours: String,
+}
+
+impl Default for Outputs {
+    fn default() -> Self {
+        Outputs {
+            dir: PathBuf::from("out"),
+            tmp_dir: None,
+            ortho: "orthophoto.tiff".to_string(),
+            dem: "dem.tiff".to_string(),
+            hillshade: "hillshade.tiff".to_string(),
+            contours: "contours".to_string(),
+        }
+    }
+}
+
+impl Outputs {
+    pub fn tmp_dir(&self) -> PathBuf {
+        self.tmp_dir.clone().unwrap_or_else(|| self.dir.join("tmp"))
+    }
+
+    pub fn ortho_path(&self) -> PathBuf {
+        self.dir.join(&self.ortho)
+    }
+
+    pub fn dem_path(&self) -> PathBuf {
+        self.dir.join(&self.dem)
+    }
+
+    pub fn hillshade_path(&self) -> PathBuf {
+        self.dir.join(&self.hillshade)
+    }
+
+    pub fn contours_path(&self, format: ContourFormat) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}", self.contours, format.extension()))
+    }
+
+    /// `dem_<derivative>.tiff` for `dem = "dem.tiff"`, next to the DEM.
+    pub fn derivative_path(&self, derivative: Derivative) -> PathBuf {
+        let stem = Path::new(&self.dem)
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default();
+        self.dir
+            .join(format!("{}_{}.tiff", stem, derivative.name()))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VrtOptions {
+    /// Tool writing the mosaic VRTs.
+    pub backend: VrtBackend,
+    /// Resolution picked when tiles disagree (`gdalbuildvrt -resolution`).
+    pub resolution: VrtResolution,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VrtBackend {
+    /// Read tile headers and write the VRT in-process.
+    #[default]
+    Native,
+    /// Shell out to `gdalbuildvrt`.
+    Gdal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VrtResolution {
+    #[default]
+    Highest,
+    Lowest,
+    Average,
+}
+
+impl VrtResolution {
+    pub fn as_gdal(self) -> &'static str {
+        match self {
+            VrtResolution::Highest => "highest",
+            VrtResolution::Lowest => "lowest",
+            VrtResolution::Average => "average",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtentOptions {
+    /// Crop the orthophoto and the DEM to the area they have in common.
+    pub align: bool,
+    /// Area of interest `[min_x, min_y, max_x, max_y]` in the inputs' CRS.
+    /// Every output is cropped to it.
+    pub bounds: Option<[f64; 4]>,
+    /// GeoJSON or WKT polygon file in the inputs' CRS. Only the tiles
+    /// touching it are used, and output pixels outside it become nodata.
+    pub aoi: Option<PathBuf>,
+}
+
+impl Default for ExtentOptions {
+    fn default() -> Self {
+        ExtentOptions {
+            align: true,
+            bounds: None,
+            aoi: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoverageOptions {
+    /// Abort the build when the tile grid has holes instead of only
+    /// reporting them.
+    pub require_complete: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrsOptions {
+    /// CRS every input tile is brought to, as `EPSG:<code>` or WKT. When
+    /// unset, tiles disagreeing on their CRS stop the build.
+    pub inputs: Option<String>,
+    /// CRS the outputs are reprojected to in the warp stage, e.g.
+    /// `EPSG:4326` or `EPSG:3857`. When unset, outputs keep the inputs'.
+    pub output: Option<String>,
+    /// Pixel size of both outputs in `output`'s units (degrees for
+    /// `EPSG:4326`). When unset, `gdalwarp` keeps about the source's.
+    pub output_resolution: Option<Resolution>,
+    /// Resampling used when reprojecting tiles or outputs.
+    pub resampling: Resampling,
+}
+
+impl Default for CrsOptions {
+    fn default() -> Self {
+        CrsOptions {
+            inputs: None,
+            output: None,
+            output_resolution: None,
+            resampling: Resampling::Bilinear,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OrthoOptions {
+    /// Pixel size of the orthophoto GeoTIFF (`gdal_translate -tr`); the
+    /// mosaic's own when unset.
+    pub resolution: Option<Resolution>,
+    /// Resampling kernel used when `resolution` is set.
+    pub resampling: Resampling,
+}
+
+impl Default for OrthoOptions {
+    fn default() -> Self {
+        OrthoOptions {
+            resolution: None,
+            resampling: Resampling::Bilinear,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DemOptions {
+    pub fill: FillOptions,
+    pub warp: WarpOptions,
+    pub vertical: VerticalOptions,
+}
+
+/// Vertical reference of the DEM heights, and their conversion to another
+/// one with a geoid grid.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerticalOptions {
+    /// Vertical reference of the tile heights, e.g. `"EPSG:5720"`
+    /// (NGF-IGN69). Tiles declaring another one are refused.
+    pub datum: Option<String>,
+    /// ESRI ASCII grid of geoid heights above the ellipsoid, in the DEM's
+    /// CRS. Its heights, interpolated bilinearly, are added to the DEM's.
+    pub geoid: Option<PathBuf>,
+    /// Vertical reference of the heights once `geoid` is applied, e.g.
+    /// `"EPSG:4965"` (RGF93 ellipsoidal heights).
+    pub output_datum: Option<String>,
+}
+
+impl VerticalOptions {
+    /// The vertical reference of the output DEM, recorded in its metadata.
+    pub fn recorded(&self) -> Option<&str> {
+        match &self.geoid {
+            Some(_) => self.output_datum.as_deref(),
+            None => self.datum.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FillOptions {
+    pub backend: FillBackend,
+    /// How holes are interpolated; anything but `idw` needs the native
+    /// backend.
+    pub strategy: FillStrategy,
+    /// Maximum search distance in pixels (`gdal_fillnodata -md`).
+    pub max_distance: u32,
+    /// Smoothing passes applied to filled areas (`gdal_fillnodata -si`).
+    pub smoothing_iterations: u32,
+    /// Relaxation passes of the `laplacian` strategy.
+    pub relaxation_iterations: u32,
+    /// Rows processed at a time by the native backend.
+    pub block_rows: usize,
+}
+
+impl Default for FillOptions {
+    fn default() -> Self {
+        FillOptions {
+            backend: FillBackend::default(),
+            strategy: FillStrategy::default(),
+            max_distance: 200,
+            smoothing_iterations: 1,
+            relaxation_iterations: 50,
+            block_rows: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FillBackend {
+    /// Shell out to `gdal_fillnodata`.
+    #[default]
+    Gdal,
+    /// Fill in-process, reading the `.asc` tiles directly.
+    Native,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FillStrategy {
+    /// Inverse-distance weighting of the nearest valid pixel in eight
+    /// directions, as `gdal_fillnodata` does.
+    #[default]
+    Idw,
+    /// Value of the closest valid pixel found.
+    Nearest,
+    /// Least-squares plane through the valid pixels found.
+    Planar,
+    /// Inverse-distance estimate relaxed towards a smooth (harmonic)
+    /// surface.
+    Laplacian,
+}
+
+impl FillStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FillStrategy::Idw => "idw",
+            FillStrategy::Nearest => "nearest",
+            FillStrategy::Planar => "planar",
+            FillStrategy::Laplacian => "laplacian",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WarpOptions {
+    pub backend: WarpBackend,
+    /// Target pixel size in map units (`gdalwarp -tr`).
+    pub resolution: Resolution,
+    /// Resampling kernel (`gdalwarp -r`).
+    pub resampling: Resampling,
+    /// Nodata value written to the DEM (`gdalwarp -dstnodata`).
+    pub nodata: f64,
+    /// Worker threads for gdalwarp, a count or `ALL_CPUS`.
+    pub threads: String,
+    /// Output rows produced at a time by the native backend.
+    pub block_rows: usize,
+}
+
+impl Default for WarpOptions {
+    fn default() -> Self {
+        WarpOptions {
+            resolution: Resolution::Square(0.2),
+            resampling: Resampling::CubicSpline,
+            backend: WarpBackend::default(),
+            nodata: 0.0,
+            threads: "ALL_CPUS".to_string(),
+            block_rows: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarpBackend {
+    /// Shell out to `gdalwarp`.
+    #[default]
+    Gdal,
+    /// Resample in-process the raster written by the native filler.
+    Native,
+}
+
+/// Shaded relief computed from the DEM once it is written.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HillshadeOptions {
+    /// Write `outputs.hillshade` whenever the DEM is built.
+    pub enabled: bool,
+    /// Direction the light comes from, in degrees clockwise from north.
+    pub azimuth: f64,
+    /// Height of the light above the horizon, in degrees.
+    pub altitude: f64,
+    /// Vertical exaggeration. Heights must be in the units of the CRS: see
+    /// [`METRES_PER_DEGREE`](crate::crs::METRES_PER_DEGREE) for a DEM in
+    /// degrees.
+    pub z_factor: f64,
+    /// Light from 225, 270, 315 and 360 degrees at once, each weighted by
+    /// how much it grazes the slope, instead of from `azimuth` alone.
+    pub multidirectional: bool,
+    /// Rows processed at a time.
+    pub block_rows: usize,
+}
+
+impl Default for HillshadeOptions {
+    fn default() -> Self {
+        HillshadeOptions {
+            enabled: false,
+            azimuth: 315.0,
+            altitude: 45.0,
+            z_factor: 1.0,
+            multidirectional: false,
+            block_rows: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerrainOptions {
+    /// Rasters derived from the DEM whenever it is built, each written to
+    /// [`Outputs::derivative_path`].
+    pub derivatives: Vec<Derivative>,
+    pub slope_units: SlopeUnits,
+    /// Ratio of height units to horizontal units, for slope and curvature;
+    /// see [`METRES_PER_DEGREE`](crate::crs::METRES_PER_DEGREE) for a DEM
+    /// in degrees.
+    pub z_factor: f64,
+    /// Rows processed at a time.
+    pub block_rows: usize,
+}
+
+impl Default for TerrainOptions {
+    fn default() -> Self {
+        TerrainOptions {
+            derivatives: Vec::new(),
+            slope_units: SlopeUnits::default(),
+            z_factor: 1.0,
+            block_rows: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Derivative {
+    Slope,
+    /// Direction the slope faces, in degrees clockwise from north.
+    Aspect,
+    /// Terrain Ruggedness Index: root of the squared height differences
+    /// with the 8 neighbours.
+    Tri,
+    /// Topographic Position Index: height above the neighbours' mean.
+    Tpi,
+    /// Largest height difference within the 3 x 3 neighbourhood.
+    Roughness,
+    /// Curvature of the surface, positive where convex.
+    Curvature,
+}
+
+impl Derivative {
+    pub const ALL: [Derivative; 6] = [
+        Derivative::Slope,
+        Derivative::Aspect,
+        Derivative::Tri,
+        Derivative::Tpi,
+        Derivative::Roughness,
+        Derivative::Curvature,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Derivative::Slope => "slope",
+            Derivative::Aspect => "aspect",
+            Derivative::Tri => "tri",
+            Derivative::Tpi => "tpi",
+            Derivative::Roughness => "roughness",
+            Derivative::Curvature => "curvature",
+        }
+    }
+}
+
+impl FromStr for Derivative {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Derivative::ALL
+            .into_iter()
+            .find(|d| d.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Derivative::ALL.iter().map(|d| d.name()).collect();
+                format!("expected one of {}, got {:?}", names.join(", "), s)
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlopeUnits {
+    #[default]
+    Degrees,
+    /// Rise over run, times 100.
+    Percent,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContourOptions {
+    /// Write contour lines whenever the DEM is built.
+    pub enabled: bool,
+    /// Height between two contours.
+    pub interval: f64,
+    /// Height of one contour; the others are whole intervals away.
+    pub base: f64,
+    /// Every `index`-th contour from `base` is flagged as an index
+    /// contour; 0 flags none.
+    pub index: u32,
+    /// Files written, each to [`Outputs::contours_path`].
+    pub formats: Vec<ContourFormat>,
+    /// Rows processed at a time.
+    pub block_rows: usize,
+}
+
+impl Default for ContourOptions {
+    fn default() -> Self {
+        ContourOptions {
+            enabled: false,
+            interval: 10.0,
+            base: 0.0,
+            index: 5,
+            formats: vec![ContourFormat::GeoJson],
+            block_rows: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContourFormat {
+    /// Written natively.
+    GeoJson,
+    /// GeoPackage, converted from the GeoJSON by `ogr2ogr`.
+    Gpkg,
+}
+
+impl ContourFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ContourFormat::GeoJson => "geojson",
+            ContourFormat::Gpkg => "gpkg",
+        }
+    }
+}
+
+/// Pixel size, either `0.2` or `[0.2, 0.2]` in the project file.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Resolution {
+    Square(f64),
+    Xy([f64; 2]),
+}
+
+impl Resolution {
+    pub fn xy(self) -> (f64, f64) {
+        match self {
+            Resolution::Square(r) => (r, r),
+            Resolution::Xy([x, y]) => (x, y),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resampling {
+    Near,
+    Bilinear,
+    Cubic,
+    CubicSpline,
+    Lanczos,
+    Average,
+    Mode,
+    Min,
+    Max,
+    Med,
+    Q1,
+    Q3,
+}
+
+impl Resampling {
+    pub fn as_gdal(self) -> &'static str {
+        match self {
+            Resampling::Near => "near",
+            Resampling::Bilinear => "bilinear",
+            Resampling::Cubic => "cubic",
+            Resampling::CubicSpline => "cubicspline",
+            Resampling::Lanczos => "lanczos",
+            Resampling::Average => "average",
+            Resampling::Mode => "mode",
+            Resampling::Min => "min",
+            Resampling::Max => "max",
+            Resampling::Med => "med",
+            Resampling::Q1 => "q1",
+            Resampling::Q3 => "q3",
+        }
+    }
+
+    /// Whether the built-in resampler implements this kernel.
+    pub fn is_native(self) -> bool {
+        matches!(
+            self,
+            Resampling::Near
+                | Resampling::Bilinear
+                | Resampling::Cubic
+                | Resampling::CubicSpline
+                | Resampling::Lanczos
+                | Resampling::Average
+        )
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeoTiffOptions {
+    pub backend: GeoTiffBackend,
+    pub format: TiffFormat,
+    /// Store pixels in square tiles rather than strips. Cloud Optimized
+    /// GeoTIFFs are always tiled.
+    pub tiled: bool,
+    /// Tile width and height in pixels, a multiple of 16.
+    pub tile_size: u32,
+    pub compression: Compression,
+    /// Predictor applied before compression. When unset, horizontal
+    /// differencing for integer rasters and the floating-point predictor
+    /// for the DEM; `floating_point` falls back to horizontal differencing
+    /// on integer rasters.
+    pub predictor: Option<Predictor>,
+    /// Compression level: 1-9 for DEFLATE, 1-22 for ZSTD.
+    pub level: Option<u32>,
+    /// Internal overview decimation factors, e.g. `[2, 4, 8]`. Cloud
+    /// Optimized GeoTIFFs get theirs chosen automatically.
+    pub overviews: Vec<u32>,
+    /// Resampling used to compute the overviews.
+    pub overview_resampling: Resampling,
+}
+
+impl Default for GeoTiffOptions {
+    fn default() -> Self {
+        GeoTiffOptions {
+            backend: GeoTiffBackend::default(),
+            format: TiffFormat::default(),
+            tiled: false,
+            tile_size: 256,
+            compression: Compression::default(),
+            predictor: None,
+            level: None,
+            overviews: Vec::new(),
+            overview_resampling: Resampling::Average,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeoTiffBackend {
+    /// Hand the options to `gdal_translate` (or `gdalwarp`) as creation
+    /// options, and to `gdaladdo` for the overviews.
+    #[default]
+    Gdal,
+    /// Write the DEM in-process from the raster the native resampler
+    /// wrote. The orthophoto is always written by GDAL.
+    Native,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TiffFormat {
+    /// A plain GeoTIFF, laid out as configured.
+    #[default]
+    GTiff,
+    /// A Cloud Optimized GeoTIFF: tiled, with overviews down to a single
+    /// tile, every IFD ahead of the pixels and the smallest overview's
+    /// tiles first, so readers fetch what they need with range requests.
+    Cog,
+}
+
+impl TiffFormat {
+    /// The GDAL driver writing this format.
+    pub fn as_gdal(self) -> &'static str {
+        match self {
+            TiffFormat::GTiff => "GTiff",
+            TiffFormat::Cog => "COG",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+    Lzw,
+    Zstd,
+}
+
+impl Compression {
+    pub fn as_gdal(self) -> &'static str {
+        match self {
+            Compression::None => "NONE",
+            Compression::Deflate => "DEFLATE",
+            Compression::Lzw => "LZW",
+            Compression::Zstd => "ZSTD",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Predictor {
+    None,
+    /// Difference between neighbouring samples (TIFF predictor 2).
+    Horizontal,
+    /// Byte-wise differencing of floating-point samples (TIFF predictor 3).
+    FloatingPoint,
+}
+
+impl Predictor {
+    /// The TIFF `Predictor` tag value, also GDAL's `PREDICTOR` option.
+    pub fn code(self) -> u16 {
+        match self {
+            Predictor::None => 1,
+            Predictor::Horizontal => 2,
+            Predictor::FloatingPoint => 3,
+        }
+    }
+}
+
+impl GeoTiffOptions {
+    /// The predictor used for a raster of floating-point samples or not.
+    pub fn predictor_for(&self, floating_point: bool) -> Predictor {
+        if self.compression == Compression::None {
+            return Predictor::None;
+        }
+        match self.predictor {
+            None | Some(Predictor::FloatingPoint) if floating_point => Predictor::FloatingPoint,
+            None | Some(Predictor::FloatingPoint) => Predictor::Horizontal,
+            Some(predictor) => predictor,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid {}: {}", path.display(), e),
+            ConfigError::Invalid(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Reads and validates a project file. Relative paths inside it are
+    /// resolved against the directory holding the file.
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let mut config: Config =
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+
+        if let Some(base) = path.parent() {
+            config.rebase(base);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads `path` when given, otherwise `vrt_maker.toml` from the working
+    /// directory if present, otherwise the built-in defaults.
+    pub fn discover(path: Option<&Path>) -> Result<Config, ConfigError> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                Config::load(Path::new(DEFAULT_CONFIG_FILE))
+            }
+            None => Ok(Config::default()),
+        }
+    }
+
+    fn rebase(&mut self, base: &Path) {
+        let rebase = |p: &mut PathBuf| {
+            if p.is_relative() {
+                *p = base.join(&*p);
+            }
+        };
+        rebase(&mut self.inputs.jp2_dir);
+        rebase(&mut self.inputs.asc_dir);
+        rebase(&mut self.outputs.dir);
+        if let Some(aoi) = self.extent.aoi.as_mut() {
+            rebase(aoi);
+        }
+        if let Some(tmp) = self.outputs.tmp_dir.as_mut() {
+            rebase(tmp);
+        }
+        if let Some(geoid) = self.dem.vertical.geoid.as_mut() {
+            rebase(geoid);
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (key, name) in [
+            ("outputs.ortho", &self.outputs.ortho),
+            ("outputs.dem", &self.outputs.dem),
+            ("outputs.hillshade", &self.outputs.hillshade),
+            ("outputs.contours", &self.outputs.contours),
+        ] {
+            if name.is_empty() || name.contains(['/', '\\']) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be a plain file name, got {:?}",
+                    key, name
+                )));
+            }
+        }
+        if self.outputs.ortho == self.outputs.dem {
+            return Err(ConfigError::Invalid(
+                "outputs.ortho and outputs.dem must differ".to_string(),
+            ));
+        }
+        if self.hillshade.enabled
+            && [&self.outputs.ortho, &self.outputs.dem].contains(&&self.outputs.hillshade)
+        {
+            return Err(ConfigError::Invalid(
+                "outputs.hillshade must differ from outputs.ortho and outputs.dem".to_string(),
+            ));
+        }
+
+        if let Some([x0, y0, x1, y1]) = self.inputs.tiles {
+            if x0 > x1 || y0 > y1 {
+                return Err(ConfigError::Invalid(format!(
+                    "inputs.tiles must be [min_x, min_y, max_x, max_y], got {:?}",
+                    [x0, y0, x1, y1]
+                )));
+            }
+        }
+
+        if let Some([x0, y0, x1, y1]) = self.extent.bounds {
+            if ![x0, y0, x1, y1].iter().all(|v| v.is_finite()) || x0 >= x1 || y0 >= y1 {
+                return Err(ConfigError::Invalid(format!(
+                    "extent.bounds must be [min_x, min_y, max_x, max_y], got {:?}",
+                    [x0, y0, x1, y1]
+                )));
+            }
+        }
+
+        if let Some(crs) = &self.crs.inputs {
+            if CrsId::identify(crs).is_none() {
+                return Err(ConfigError::Invalid(format!(
+                    "crs.inputs must be \"EPSG:<code>\" or a WKT definition, got {:?}",
+                    crs
+                )));
+            }
+        }
+
+        match &self.crs.output {
+            Some(crs) if CrsId::identify(crs).is_none() => {
+                return Err(ConfigError::Invalid(format!(
+                    "crs.output must be \"EPSG:<code>\" or a WKT definition, got {:?}",
+                    crs
+                )));
+            }
+            Some(_) => {
+                if self.ortho.resolution.is_some() {
+                    return Err(ConfigError::Invalid(
+                        "ortho.resolution cannot be set with crs.output; \
+                         use crs.output_resolution"
+                            .to_string(),
+                    ));
+                }
+                if self.geotiff.backend == GeoTiffBackend::Native {
+                    return Err(ConfigError::Invalid(
+                        "crs.output needs geotiff.backend = \"gdal\"".to_string(),
+                    ));
+                }
+            }
+            None if self.crs.output_resolution.is_some() => {
+                return Err(ConfigError::Invalid(
+                    "crs.output_resolution needs crs.output".to_string(),
+                ));
+            }
+            None => {}
+        }
+
+        if self.dem.fill.max_distance == 0 {
+            return Err(ConfigError::Invalid(
+                "dem.fill.max_distance must be greater than 0".to_string(),
+            ));
+        }
+
+        let fill = &self.dem.fill;
+        if fill.backend == FillBackend::Gdal && fill.strategy != FillStrategy::Idw {
+            return Err(ConfigError::Invalid(format!(
+                "dem.fill.strategy = \"{}\" needs dem.fill.backend = \"native\"",
+                fill.strategy.as_str()
+            )));
+        }
+        if fill.block_rows == 0 {
+            return Err(ConfigError::Invalid(
+                "dem.fill.block_rows must be at least 1".to_string(),
+            ));
+        }
+
+        let vertical = &self.dem.vertical;
+        for (key, datum) in [
+            ("dem.vertical.datum", &vertical.datum),
+            ("dem.vertical.output_datum", &vertical.output_datum),
+        ] {
+            if let Some(datum) = datum {
+                if CrsId::vertical_reference(datum).is_none() {
+                    return Err(ConfigError::Invalid(format!(
+                        "{} must be \"EPSG:<code>\" or a WKT definition, got {:?}",
+                        key, datum
+                    )));
+                }
+            }
+        }
+        match (&vertical.geoid, &vertical.output_datum) {
+            (Some(_), None) => {
+                return Err(ConfigError::Invalid(
+                    "dem.vertical.geoid needs dem.vertical.output_datum".to_string(),
+                ));
+            }
+            (None, Some(_)) => {
+                return Err(ConfigError::Invalid(
+                    "dem.vertical.output_datum needs dem.vertical.geoid".to_string(),
+                ));
+            }
+            (Some(_), Some(_)) if fill.backend != FillBackend::Native => {
+                return Err(ConfigError::Invalid(
+                    "dem.vertical.geoid needs dem.fill.backend = \"native\"".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        let warp = &self.dem.warp;
+        let resolutions = [
+            ("ortho.resolution", self.ortho.resolution),
+            ("dem.warp.resolution", Some(warp.resolution)),
+            ("crs.output_resolution", self.crs.output_resolution),
+        ];
+        for (key, resolution) in resolutions {
+            let Some((x, y)) = resolution.map(Resolution::xy) else {
+                continue;
+            };
+            if !(x.is_finite() && x > 0.0 && y.is_finite() && y > 0.0) {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be positive, got {} x {}",
+                    key, x, y
+                )));
+            }
+        }
+        if !warp.nodata.is_finite() {
+            return Err(ConfigError::Invalid(
+                "dem.warp.nodata must be a finite number".to_string(),
+            ));
+        }
+        if warp.threads != "ALL_CPUS" && warp.threads.parse::<u32>().map_or(true, |n| n == 0) {
+            return Err(ConfigError::Invalid(format!(
+                "dem.warp.threads must be a positive count or \"ALL_CPUS\", got {:?}",
+                warp.threads
+            )));
+        }
+        if warp.backend == WarpBackend::Native {
+            if fill.backend != FillBackend::Native {
+                return Err(ConfigError::Invalid(
+                    "dem.warp.backend = \"native\" needs dem.fill.backend = \"native\"".to_string(),
+                ));
+            }
+            if !warp.resampling.is_native() {
+                return Err(ConfigError::Invalid(format!(
+                    "dem.warp.resampling = \"{}\" is not available with dem.warp.backend = \"native\"",
+                    warp.resampling.as_gdal()
+                )));
+            }
+            if warp.block_rows == 0 {
+                return Err(ConfigError::Invalid(
+                    "dem.warp.block_rows must be at least 1".to_string(),
+                ));
+            }
+        }
+
+        let geotiff = &self.geotiff;
+        if geotiff.tile_size == 0 || !geotiff.tile_size.is_multiple_of(16) {
+            return Err(ConfigError::Invalid(format!(
+                "geotiff.tile_size must be a positive multiple of 16, got {}",
+                geotiff.tile_size
+            )));
+        }
+        let levels = match geotiff.compression {
+            Compression::Deflate => Some(1..=9),
+            Compression::Zstd => Some(1..=22),
+            Compression::None | Compression::Lzw => None,
+        };
+        if let Some(level) = geotiff.level {
+            if !levels.as_ref().is_some_and(|l| l.contains(&level)) {
+                return Err(ConfigError::Invalid(format!(
+                    "geotiff.level {} does not apply to compression \"{}\"",
+                    level,
+                    geotiff.compression.as_gdal().to_lowercase()
+                )));
+            }
+        }
+        if geotiff.format == TiffFormat::Cog && !geotiff.overviews.is_empty() {
+            return Err(ConfigError::Invalid(
+                "geotiff.overviews cannot be set with geotiff.format = \"cog\", which chooses them"
+                    .to_string(),
+            ));
+        }
+        let increasing = geotiff.overviews.windows(2).all(|w| w[0] < w[1]);
+        if !increasing || geotiff.overviews.first().is_some_and(|&f| f < 2) {
+            return Err(ConfigError::Invalid(format!(
+                "geotiff.overviews must be increasing factors of at least 2, got {:?}",
+                geotiff.overviews
+            )));
+        }
+        let overview_resampling = geotiff.overview_resampling;
+        let supported = match geotiff.backend {
+            GeoTiffBackend::Gdal => overview_resampling.is_native(),
+            GeoTiffBackend::Native => {
+                matches!(overview_resampling, Resampling::Near | Resampling::Average)
+            }
+        };
+        if !supported {
+            return Err(ConfigError::Invalid(format!(
+                "geotiff.overview_resampling = \"{}\" is not available with geotiff.backend = \"{}\"",
+                overview_resampling.as_gdal(),
+                match geotiff.backend {
+                    GeoTiffBackend::Gdal => "gdal",
+                    GeoTiffBackend::Native => "native",
+                }
+            )));
+        }
+        if geotiff.backend == GeoTiffBackend::Native && warp.backend != WarpBackend::Native {
+            return Err(ConfigError::Invalid(
+                "geotiff.backend = \"native\" needs dem.warp.backend = \"native\"".to_string(),
+            ));
+        }
+
+        let hillshade = &self.hillshade;
+        if hillshade.enabled {
+            if !(0.0..=360.0).contains(&hillshade.azimuth) {
+                return Err(ConfigError::Invalid(format!(
+                    "hillshade.azimuth must be within [0, 360] degrees, got {}",
+                    hillshade.azimuth
+                )));
+            }
+            if !(0.0..=90.0).contains(&hillshade.altitude) {
+                return Err(ConfigError::Invalid(format!(
+                    "hillshade.altitude must be within [0, 90] degrees, got {}",
+                    hillshade.altitude
+                )));
+            }
+            if !(hillshade.z_factor.is_finite() && hillshade.z_factor > 0.0) {
+                return Err(ConfigError::Invalid(format!(
+                    "hillshade.z_factor must be positive, got {}",
+                    hillshade.z_factor
+                )));
+            }
+            if hillshade.block_rows == 0 {
+                return Err(ConfigError::Invalid(
+                    "hillshade.block_rows must be at least 1".to_string(),
+                ));
+            }
+        }
+
+        let terrain = &self.terrain;
+        for (i, d) in terrain.derivatives.iter().enumerate() {
+            if terrain.derivatives[..i].contains(d) {
+                return Err(ConfigError::Invalid(format!(
+                    "terrain.derivatives lists {} twice",
+                    d.name()
+                )));
+            }
+        }
+        if !terrain.derivatives.is_empty() {
+            if !(terrain.z_factor.is_finite() && terrain.z_factor > 0.0) {
+                return Err(ConfigError::Invalid(format!(
+                    "terrain.z_factor must be positive, got {}",
+                    terrain.z_factor
+                )));
+            }
+            if terrain.block_rows == 0 {
+                return Err(ConfigError::Invalid(
+                    "terrain.block_rows must be at least 1".to_string(),
+                ));
+            }
+        }
+
+        let contours = &self.contours;
+        if contours.enabled {
+            if !(contours.interval.is_finite() && contours.interval > 0.0) {
+                return Err(ConfigError::Invalid(format!(
+                    "contours.interval must be positive, got {}",
+                    contours.interval
+                )));
+            }
+            if !contours.base.is_finite() {
+                return Err(ConfigError::Invalid(format!(
+                    "contours.base must be a number, got {}",
+                    contours.base
+                )));
+            }
+            if contours.formats.is_empty() {
+                return Err(ConfigError::Invalid(
+                    "contours.formats must list geojson, gpkg or both".to_string(),
+                ));
+            }
+            for (i, f) in contours.formats.iter().enumerate() {
+                if contours.formats[..i].contains(f) {
+                    return Err(ConfigError::Invalid(format!(
+                        "contours.formats lists {} twice",
+                        f.extension()
+                    )));
+                }
+            }
+            if contours.block_rows == 0 {
+                return Err(ConfigError::Invalid(
+                    "contours.block_rows must be at least 1".to_string(),
+                ));
+            }
+        }
+
+        // The hillshade and the derivatives are always written by the
+        // native writer.
+        let native = [
+            (hillshade.enabled, "the hillshade"),
+            (!terrain.derivatives.is_empty(), "terrain derivatives"),
+        ];
+        if let Some((_, product)) = native.iter().find(|(wanted, _)| *wanted) {
+            if !matches!(overview_resampling, Resampling::Near | Resampling::Average) {
+                return Err(ConfigError::Invalid(format!(
+                    "geotiff.overview_resampling = \"{}\" is not available for {}, written \
+                     natively",
+                    overview_resampling.as_gdal(),
+                    product
+                )));
+            }
+        }
+        Ok(())
+    }
+}
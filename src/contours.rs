@@ -0,0 +1,352 @@
+//! Contour lines of the DEM, traced natively from the written GeoTIFF.
+//!
+//! Marching squares runs over the cells joining four pixel centres, one
+//! row of cells at a time, a block of DEM rows being read with one row of
+//! margin below. Each cell yields segments crossing its edges, which are
+//! joined into lines through the edges they share; a line is handed over
+//! as soon as the next row of cells cannot extend it, so blocks leave no
+//! seams and only the lines crossing the current row are held. Cells
+//! touching a pixel without a height yield nothing: lines stop there.
+//! Saddles are resolved by the mean of the cell's corners.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+use crate::config::ContourOptions;
+use crate::rows::{for_each_block, RowError, RowSource, Window};
+use crate::tiff_rows::GeoTiffRows;
+
+/// One contour line, closed when its first and last points are equal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourLine {
+    pub elevation: f64,
+    /// Whether it is an index contour.
+    pub index: bool,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// The contour lines of the DEM GeoTIFF, as planned.
+#[derive(Debug, Clone)]
+pub struct NativeContours {
+    /// The DEM GeoTIFF.
+    pub input: PathBuf,
+    pub options: ContourOptions,
+}
+
+impl NativeContours {
+    /// Everything the result depends on besides the DEM's pixels.
+    pub fn recipe(&self) -> String {
+        let o = &self.options;
+        format!(
+            "contours {} interval={} base={} index={}",
+            self.input.display(),
+            o.interval,
+            o.base,
+            o.index
+        )
+    }
+
+    /// Traces the contours of `input` into the GeoJSON file `output`, one
+    /// `LineString` feature per line with its `elevation` and `index`.
+    pub fn run(&self, output: &Path) -> Result<(), RowError> {
+        let mut dem = GeoTiffRows::open(&self.input)?;
+        let transform = dem.geo_transform();
+        let file = File::create(output).map_err(|e| RowError::io(output, e))?;
+        let mut out = BufWriter::new(file);
+        let io = |e| RowError::io(output, e);
+
+        write!(
+            out,
+            "{{\"type\":\"FeatureCollection\",\"name\":\"contours\","
+        )
+        .map_err(io)?;
+        // GDAL reads the CRS from this pre-RFC 7946 member.
+        if let Some(code) = dem.crs().and_then(|crs| crs.strip_prefix("EPSG:")) {
+            let crs = json!({
+                "type": "name",
+                "properties": { "name": format!("urn:ogc:def:crs:EPSG::{}", code) },
+            });
+            write!(out, "\"crs\":{},", crs).map_err(io)?;
+        }
+        write!(out, "\"features\":[").map_err(io)?;
+        let mut separator = "\n";
+        trace_contours(&mut dem, &transform, &self.options, |line| {
+            let coordinates: Vec<[f64; 2]> = line.points.iter().map(|&(x, y)| [x, y]).collect();
+            let feature = json!({
+                "type": "Feature",
+                "properties": { "elevation": line.elevation, "index": line.index },
+                "geometry": { "type": "LineString", "coordinates": coordinates },
+            });
+            write!(out, "{}{}", separator, feature).map_err(io)?;
+            separator = ",\n";
+            Ok(())
+        })?;
+        writeln!(out, "\n]}}").map_err(io)?;
+        out.flush().map_err(io)
+    }
+}
+
+/// Traces the contours of `source`, laid out by `transform`, handing each
+/// line to `sink` once complete.
+pub fn trace_contours(
+    source: &mut dyn RowSource,
+    transform: &[f64; 6],
+    options: &ContourOptions,
+    mut sink: impl FnMut(ContourLine) -> Result<(), RowError>,
+) -> Result<(), RowError> {
+    let height = source.height();
+    let mut tracer = Tracer {
+        transform: *transform,
+        options,
+        lines: BTreeMap::new(),
+        ends: HashMap::new(),
+        next_line: 0,
+        done: Vec::new(),
+    };
+    for_each_block(source, options.block_rows, 1, |window| {
+        for row in window.block.clone() {
+            if row + 1 < height {
+                tracer.cell_row(window, row);
+            }
+            tracer.finish_lines(|edge| matches!(edge, Edge::Row(r, _) if r == row + 1));
+            for line in tracer.done.drain(..) {
+                sink(line)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// A cell edge holding the end of a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Edge {
+    /// Between pixels `(row, column)` and `(row, column + 1)`.
+    Row(usize, usize),
+    /// Between pixels `(row, column)` and `(row + 1, column)`.
+    Column(usize, usize),
+}
+
+/// A line being traced, at the `level`-th contour from the base.
+#[derive(Debug)]
+struct Line {
+    level: i64,
+    points: VecDeque<(f64, f64)>,
+    start: Edge,
+    end: Edge,
+}
+
+impl Line {
+    /// Extends the line at its end on `at` to `point`, on `edge`.
+    fn extend(&mut self, at: Edge, edge: Edge, point: (f64, f64)) {
+        if self.start == at {
+            self.points.push_front(point);
+            self.start = edge;
+        } else {
+            self.points.push_back(point);
+            self.end = edge;
+        }
+    }
+
+    fn reverse(&mut self) {
+        self.points.make_contiguous().reverse();
+        std::mem::swap(&mut self.start, &mut self.end);
+    }
+}
+
+struct Tracer<'a> {
+    transform: [f64; 6],
+    options: &'a ContourOptions,
+    lines: BTreeMap<usize, Line>,
+    /// The open ends of `lines`, by contour and edge.
+    ends: HashMap<(i64, Edge), usize>,
+    next_line: usize,
+    done: Vec<ContourLine>,
+}
+
+impl Tracer<'_> {
+    /// Traces the cells between pixel rows `row` and `row + 1`.
+    fn cell_row(&mut self, window: &Window, row: usize) {
+        for column in 0..window.width.saturating_sub(1) {
+            let corners = [
+                window.get(row, column),
+                window.get(row, column + 1),
+                window.get(row + 1, column + 1),
+                window.get(row + 1, column),
+            ]
+            .map(f64::from);
+            if corners.iter().any(|v| v.is_nan()) {
+                continue;
+            }
+            self.cell(row, column, corners);
+        }
+    }
+
+    /// Traces the cell whose top-left pixel is `(row, column)`, its
+    /// `corners` clockwise from the top left.
+    fn cell(&mut self, row: usize, column: usize, corners: [f64; 4]) {
+        let [tl, tr, br, bl] = corners;
+        let (interval, base) = (self.options.interval, self.options.base);
+        let transform = self.transform;
+        let min = corners.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = corners.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let first = ((min - base) / interval).floor() as i64 + 1;
+        let last = ((max - base) / interval).floor() as i64;
+        for level in first..=last {
+            let height = base + level as f64 * interval;
+            let above = corners.map(|v| v >= height);
+            let case = above
+                .iter()
+                .fold(0, |case, &above| case << 1 | usize::from(above));
+            // Where `height` crosses each side, between its two corners.
+            let crossing = |side: Side| {
+                let (edge, from, to) = match side {
+                    Side::Top => (Edge::Row(row, column), tl, tr),
+                    Side::Right => (Edge::Column(row, column + 1), tr, br),
+                    Side::Bottom => (Edge::Row(row + 1, column), bl, br),
+                    Side::Left => (Edge::Column(row, column), tl, bl),
+                };
+                let t = (height - from) / (to - from);
+                let (x, y) = match edge {
+                    Edge::Row(r, c) => (c as f64 + t, r as f64),
+                    Edge::Column(r, c) => (c as f64, r as f64 + t),
+                };
+                (edge, point(&transform, x, y))
+            };
+            let centre_above = (tl + tr + br + bl) / 4.0 >= height;
+            for &(a, b) in segments(case, centre_above) {
+                let (a, b) = (crossing(a), crossing(b));
+                self.add_segment(level, a, b);
+            }
+        }
+    }
+
+    fn add_segment(&mut self, level: i64, a: (Edge, (f64, f64)), b: (Edge, (f64, f64))) {
+        let ((edge_a, point_a), (edge_b, point_b)) = (a, b);
+        let line_a = self.ends.remove(&(level, edge_a));
+        let line_b = self.ends.remove(&(level, edge_b));
+        match (line_a, line_b) {
+            (None, None) => {
+                let id = self.next_line;
+                self.next_line += 1;
+                self.lines.insert(
+                    id,
+                    Line {
+                        level,
+                        points: VecDeque::from([point_a, point_b]),
+                        start: edge_a,
+                        end: edge_b,
+                    },
+                );
+                self.ends.insert((level, edge_a), id);
+                self.ends.insert((level, edge_b), id);
+            }
+            (Some(id), None) => {
+                self.lines
+                    .get_mut(&id)
+                    .unwrap()
+                    .extend(edge_a, edge_b, point_b);
+                self.ends.insert((level, edge_b), id);
+            }
+            (None, Some(id)) => {
+                self.lines
+                    .get_mut(&id)
+                    .unwrap()
+                    .extend(edge_b, edge_a, point_a);
+                self.ends.insert((level, edge_a), id);
+            }
+            (Some(id_a), Some(id_b)) if id_a == id_b => {
+                let mut line = self.lines.remove(&id_a).unwrap();
+                let first = line.points[0];
+                line.points.push_back(first);
+                self.finish(line);
+            }
+            (Some(id_a), Some(id_b)) => {
+                let mut second = self.lines.remove(&id_b).unwrap();
+                let first = self.lines.get_mut(&id_a).unwrap();
+                if first.start == edge_a {
+                    first.reverse();
+                }
+                if second.end == edge_b {
+                    second.reverse();
+                }
+                first.points.extend(second.points);
+                first.end = second.end;
+                self.ends.insert((level, second.end), id_a);
+            }
+        }
+    }
+
+    /// Hands over every line without an end on an edge `open` says the
+    /// next cells may extend.
+    fn finish_lines(&mut self, open: impl Fn(Edge) -> bool) {
+        let finished: Vec<usize> = self
+            .lines
+            .iter()
+            .filter(|(_, line)| !open(line.start) && !open(line.end))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in finished {
+            let line = self.lines.remove(&id).unwrap();
+            self.ends.remove(&(line.level, line.start));
+            self.ends.remove(&(line.level, line.end));
+            self.finish(line);
+        }
+    }
+
+    /// Queues `line` for the sink. Pixels right on a contour put several
+    /// crossings on the same point: repeats are dropped, and so are lines
+    /// left with a single point.
+    fn finish(&mut self, line: Line) {
+        let mut points: Vec<(f64, f64)> = line.points.into();
+        points.dedup();
+        if points.len() < 2 {
+            return;
+        }
+        let index = self.options.index;
+        self.done.push(ContourLine {
+            elevation: self.options.base + line.level as f64 * self.options.interval,
+            index: index != 0 && line.level.rem_euclid(i64::from(index)) == 0,
+            points,
+        });
+    }
+}
+
+/// Map coordinates of the point `x` columns and `y` rows from the centre
+/// of the top-left pixel.
+fn point(gt: &[f64; 6], x: f64, y: f64) -> (f64, f64) {
+    let (x, y) = (x + 0.5, y + 0.5);
+    (gt[0] + x * gt[1] + y * gt[2], gt[3] + x * gt[4] + y * gt[5])
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+/// The segments of a cell whose corners above the contour are the bits of
+/// `case`, top left first; saddles join the corners on the centre's side.
+fn segments(case: usize, centre_above: bool) -> &'static [(Side, Side)] {
+    use Side::*;
+    match case {
+        0b0001 | 0b1110 => &[(Left, Bottom)],
+        0b0010 | 0b1101 => &[(Bottom, Right)],
+        0b0011 | 0b1100 => &[(Left, Right)],
+        0b0100 | 0b1011 => &[(Top, Right)],
+        0b0110 | 0b1001 => &[(Top, Bottom)],
+        0b0111 | 0b1000 => &[(Left, Top)],
+        // Top right and bottom left above.
+        0b0101 if centre_above => &[(Left, Top), (Bottom, Right)],
+        0b0101 => &[(Left, Bottom), (Top, Right)],
+        // Top left and bottom right above.
+        0b1010 if centre_above => &[(Top, Right), (Left, Bottom)],
+        0b1010 => &[(Left, Top), (Bottom, Right)],
+        _ => &[],
+    }
+}
@@ -0,0 +1,237 @@
+//! Tile coverage checks run before mosaicking: finds the regular grid the
+//! tiles sit on and reports holes, duplicates and overlaps that would
+//! otherwise end up silently in the mosaic.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::error::Stage;
+use crate::geo::Extent;
+use crate::raster::RasterInfo;
+
+/// Number of entries printed per problem list before eliding the rest.
+const MAX_LISTED: usize = 20;
+
+/// A grid cell with no tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridCell {
+    pub column: usize,
+    pub row: usize,
+    pub extent: Extent,
+}
+
+/// Coverage of a stage's tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub stage: Stage,
+    pub tiles: usize,
+    /// Size of the most common tile, in map units.
+    pub tile_width: f64,
+    pub tile_height: f64,
+    /// Top-left corner of the grid.
+    pub origin: (f64, f64),
+    pub columns: usize,
+    pub rows: usize,
+    /// Cells inside the grid that no tile touches.
+    pub missing: Vec<GridCell>,
+    /// Groups of tiles sharing the same cell.
+    pub duplicates: Vec<Vec<PathBuf>>,
+    /// Pairs of tiles overlapping without covering the same cell.
+    pub overlaps: Vec<(PathBuf, PathBuf)>,
+    /// Tiles whose size or position does not fit the grid.
+    pub off_grid: Vec<PathBuf>,
+}
+
+impl Coverage {
+    /// Checks `tiles`, returning `None` when there are none.
+    pub fn check(stage: Stage, tiles: &[RasterInfo]) -> Option<Coverage> {
+        let extents: Vec<Extent> = tiles.iter().map(RasterInfo::extent).collect();
+        // Half the finest pixel: anything closer is the same coordinate.
+        let tolerance = tiles
+            .iter()
+            .map(|t| {
+                let (x, y) = t.pixel_size();
+                x.min(y)
+            })
+            .fold(f64::INFINITY, f64::min)
+            / 2.0;
+        if !tolerance.is_finite() {
+            return None;
+        }
+
+        let tile_width = most_common(extents.iter().map(Extent::width), tolerance)?;
+        let tile_height = most_common(extents.iter().map(Extent::height), tolerance)?;
+        let same = |a: f64, b: f64| (a - b).abs() <= tolerance;
+        let regular: Vec<bool> = extents
+            .iter()
+            .map(|e| same(e.width(), tile_width) && same(e.height(), tile_height))
+            .collect();
+        let origin_x = extents
+            .iter()
+            .zip(&regular)
+            .filter(|(_, &r)| r)
+            .map(|(e, _)| e.min_x)
+            .fold(f64::INFINITY, f64::min);
+        let origin_y = extents
+            .iter()
+            .zip(&regular)
+            .filter(|(_, &r)| r)
+            .map(|(e, _)| e.max_y)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        // Place every regular tile on the grid.
+        let mut cells: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        let mut off_grid = Vec::new();
+        for (i, extent) in extents.iter().enumerate() {
+            let column = (extent.min_x - origin_x) / tile_width;
+            let row = (origin_y - extent.max_y) / tile_height;
+            let aligned = regular[i]
+                && same(column.round() * tile_width, column * tile_width)
+                && same(row.round() * tile_height, row * tile_height);
+            if aligned {
+                cells
+                    .entry((column.round() as usize, row.round() as usize))
+                    .or_default()
+                    .push(i);
+            } else {
+                off_grid.push(i);
+            }
+        }
+
+        let columns = cells.keys().map(|&(c, _)| c + 1).max().unwrap_or(0);
+        let rows = cells.keys().map(|&(_, r)| r + 1).max().unwrap_or(0);
+        let cell_extent = |column: usize, row: usize| {
+            let min_x = origin_x + column as f64 * tile_width;
+            let max_y = origin_y - row as f64 * tile_height;
+            Extent::new(min_x, max_y - tile_height, min_x + tile_width, max_y)
+        };
+
+        let mut missing = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                if cells.contains_key(&(column, row)) {
+                    continue;
+                }
+                let extent = cell_extent(column, row);
+                if !off_grid.iter().any(|&i| extents[i].intersects(&extent)) {
+                    missing.push(GridCell {
+                        column,
+                        row,
+                        extent,
+                    });
+                }
+            }
+        }
+
+        let duplicates = cells
+            .values()
+            .filter(|group| group.len() > 1)
+            .map(|group| group.iter().map(|&i| tiles[i].path.clone()).collect())
+            .collect();
+
+        // Grid tiles cannot overlap each other outside their own cell, so
+        // only off-grid tiles need a pairwise test.
+        let mut overlaps = Vec::new();
+        for (n, &i) in off_grid.iter().enumerate() {
+            for j in 0..tiles.len() {
+                let checked = off_grid[..=n].contains(&j);
+                if !checked
+                    && extents[i]
+                        .intersection(&extents[j])
+                        .is_some_and(|e| e.width() > tolerance && e.height() > tolerance)
+                {
+                    overlaps.push((tiles[i].path.clone(), tiles[j].path.clone()));
+                }
+            }
+        }
+
+        Some(Coverage {
+            stage,
+            tiles: tiles.len(),
+            tile_width,
+            tile_height,
+            origin: (origin_x, origin_y),
+            columns,
+            rows,
+            missing,
+            duplicates,
+            overlaps,
+            off_grid: off_grid.iter().map(|&i| tiles[i].path.clone()).collect(),
+        })
+    }
+
+    /// Whether every cell of the grid has a tile.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Whether anything at all was found wrong.
+    pub fn has_problems(&self) -> bool {
+        !(self.missing.is_empty()
+            && self.duplicates.is_empty()
+            && self.overlaps.is_empty()
+            && self.off_grid.is_empty())
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "[{}] {} tile(s) on a {} x {} grid of {} x {} tiles",
+            self.stage, self.tiles, self.columns, self.rows, self.tile_width, self.tile_height
+        )?;
+        list(f, "missing tile(s)", &self.missing, |c| {
+            format!("column {}, row {}: {}", c.column, c.row, c.extent)
+        })?;
+        list(f, "duplicate tile(s)", &self.duplicates, |group| {
+            let names: Vec<String> = group.iter().map(|p| p.display().to_string()).collect();
+            names.join(" = ")
+        })?;
+        list(f, "overlapping tile(s)", &self.overlaps, |(a, b)| {
+            format!("{} overlaps {}", a.display(), b.display())
+        })?;
+        list(f, "off-grid tile(s)", &self.off_grid, |p| {
+            p.display().to_string()
+        })
+    }
+}
+
+fn list<T>(
+    f: &mut fmt::Formatter<'_>,
+    title: &str,
+    items: &[T],
+    describe: impl Fn(&T) -> String,
+) -> fmt::Result {
+    if items.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "  {} {}:", items.len(), title)?;
+    for item in items.iter().take(MAX_LISTED) {
+        writeln!(f, "    {}", describe(item))?;
+    }
+    if items.len() > MAX_LISTED {
+        writeln!(f, "    ... and {} more", items.len() - MAX_LISTED)?;
+    }
+    Ok(())
+}
+
+/// The most frequent value of `values`, grouping values within
+/// `tolerance` of each other.
+fn most_common(values: impl Iterator<Item = f64>, tolerance: f64) -> Option<f64> {
+    let mut groups: Vec<(f64, usize)> = Vec::new();
+    for value in values {
+        match groups
+            .iter_mut()
+            .find(|(v, _)| (v - value).abs() <= tolerance)
+        {
+            Some(group) => group.1 += 1,
+            None => groups.push((value, 1)),
+        }
+    }
+    groups
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map(|(v, _)| v)
+}
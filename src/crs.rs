@@ -0,0 +1,359 @@
+//! Coordinate reference system identification, so tiles coming from
+//! different deliveries can be compared before they are mosaicked.
+//!
+//! No projection library is involved: a CRS is reduced to its EPSG code
+//! when the definition carries one (or is a well-known ESRI name), and to
+//! its normalized name otherwise.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::Stage;
+use crate::jp2;
+
+/// ESRI `.prj` names without an authority code, mapped to EPSG.
+const KNOWN_NAMES: [(&str, u32); 8] = [
+    ("rgf93lambert93", 2154),
+    ("rgf1993lambert93", 2154),
+    ("rgf93v1lambert93", 2154),
+    ("gcswgs1984", 4326),
+    ("wgs84", 4326),
+    ("wgs1984", 4326),
+    ("wgs84pseudomercator", 3857),
+    ("wgs1984webmercatorauxiliarysphere", 3857),
+];
+
+/// Names of vertical references without an authority code, mapped to
+/// EPSG.
+const KNOWN_VERTICAL_NAMES: [(&str, u32); 5] = [
+    ("ngfign69", 5720),
+    ("ngfign69height", 5720),
+    ("ign69", 5720),
+    ("egm96height", 5773),
+    ("navd88height", 5703),
+];
+
+/// Root keywords of a compound (horizontal + vertical) WKT definition.
+const COMPOUND_KEYWORDS: [&str; 2] = ["COMPD_CS[", "COMPOUNDCRS["];
+
+/// Keywords of the horizontal part of a compound definition.
+const HORIZONTAL_KEYWORDS: [&str; 6] = [
+    "PROJCS[",
+    "GEOGCS[",
+    "PROJCRS[",
+    "PROJECTEDCRS[",
+    "GEOGCRS[",
+    "GEOGRAPHICCRS[",
+];
+
+/// Root keywords of a geographic WKT definition.
+const GEOGRAPHIC_KEYWORDS: [&str; 3] = ["GEOGCS[", "GEOGCRS[", "GEOGRAPHICCRS["];
+
+/// EPSG codes of the usual geographic CRSs, 2D and 3D. Their neighbours in
+/// the 4000s are not all geographic: EPSG:4087 is projected and EPSG:4978
+/// geocentric.
+const GEOGRAPHIC_CODES: [u32; 20] = [
+    4326, // WGS 84
+    4979, // WGS 84 (3D)
+    4258, // ETRS89
+    4937, // ETRS89 (3D)
+    4171, // RGF93
+    4275, // NTF
+    4230, // ED50
+    4269, // NAD83
+    4267, // NAD27
+    4617, // NAD83(CSRS)
+    6318, // NAD83(2011)
+    4283, // GDA94
+    7844, // GDA2020
+    4674, // SIRGAS 2000
+    4490, // CGCS2000
+    4612, // JGD2000
+    6668, // JGD2011
+    4322, // WGS 72
+    4624, // RGFG95
+    4627, // RGR92
+];
+
+/// Metres in a degree of latitude. Slopes need heights in the units of the
+/// grid, so over a geographic CRS heights in metres take a `z_factor` of
+/// about `1 / (METRES_PER_DEGREE * cos(latitude))`; a factor more than ten
+/// times that, which leaves heights in metres, is refused when the plan is
+/// built.
+pub const METRES_PER_DEGREE: f64 = 111_120.0;
+
+/// Keywords of a vertical CRS, in WKT1, ESRI and WKT2 definitions.
+const VERTICAL_KEYWORDS: [&str; 4] = ["VERT_CS[", "VERTCS[", "VERTCRS[", "VERTICALCRS["];
+
+/// Identity of a CRS, for comparisons.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CrsId {
+    Epsg(u32),
+    /// A definition without a recognizable code, by normalized name.
+    Named(String),
+}
+
+impl CrsId {
+    /// Identifies `EPSG:<code>`, an OGC URN, or a WKT1/WKT2 definition.
+    pub fn identify(definition: &str) -> Option<CrsId> {
+        let text = definition.trim();
+        if text.is_empty() {
+            return None;
+        }
+        if let Some(codes) = epsg_codes(text) {
+            // `EPSG:2154+5720` is Lambert-93 with NGF-IGN69 heights.
+            return codes
+                .split('+')
+                .next()?
+                .trim()
+                .parse()
+                .ok()
+                .map(CrsId::Epsg);
+        }
+        if text.starts_with("urn:") {
+            return jp2::epsg_from_urn(text).map(CrsId::Epsg);
+        }
+        if starts_with_keyword(text, &COMPOUND_KEYWORDS) {
+            // Compared by their horizontal part, heights being checked
+            // separately.
+            return CrsId::identify(wkt_element(text, &HORIZONTAL_KEYWORDS)?);
+        }
+        if let Some(code) = wkt_authority(text) {
+            return Some(CrsId::Epsg(code));
+        }
+
+        let normalized = normalize(wkt_name(text)?);
+        match KNOWN_NAMES.iter().find(|(n, _)| *n == normalized) {
+            Some(&(_, code)) => Some(CrsId::Epsg(code)),
+            None => Some(CrsId::Named(normalized)),
+        }
+    }
+
+    /// Identifies the vertical reference of a definition: the second code
+    /// of `EPSG:<horizontal>+<vertical>`, or the vertical CRS of a WKT
+    /// definition, compound or not. `None` for a purely horizontal CRS.
+    pub fn vertical(definition: &str) -> Option<CrsId> {
+        let text = definition.trim();
+        if let Some(codes) = epsg_codes(text) {
+            let (_, vertical) = codes.split_once('+')?;
+            return vertical.trim().parse().ok().map(CrsId::Epsg);
+        }
+        let vertical = wkt_element(text, &VERTICAL_KEYWORDS)?;
+        if let Some(code) = wkt_authority(vertical) {
+            return Some(CrsId::Epsg(code));
+        }
+        let normalized = normalize(wkt_name(vertical)?);
+        match KNOWN_VERTICAL_NAMES.iter().find(|(n, _)| *n == normalized) {
+            Some(&(_, code)) => Some(CrsId::Epsg(code)),
+            None => Some(CrsId::Named(normalized)),
+        }
+    }
+
+    /// Identifies a vertical reference given on its own, `EPSG:5720` or a
+    /// vertical WKT definition, or as part of a compound definition.
+    pub fn vertical_reference(definition: &str) -> Option<CrsId> {
+        match epsg_codes(definition.trim()) {
+            Some(codes) if !codes.contains('+') => codes.trim().parse().ok().map(CrsId::Epsg),
+            _ => CrsId::vertical(definition),
+        }
+    }
+
+    /// The CRS as accepted by `gdalwarp -t_srs`, when it has a code.
+    pub fn epsg(&self) -> Option<u32> {
+        match self {
+            CrsId::Epsg(code) => Some(*code),
+            CrsId::Named(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CrsId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrsId::Epsg(code) => write!(f, "EPSG:{}", code),
+            CrsId::Named(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+/// Whether `definition` is a geographic CRS, counted in degrees: a WKT
+/// definition rooted in a geographic CRS, or one of
+/// [`GEOGRAPHIC_CODES`].
+pub fn is_geographic(definition: &str) -> bool {
+    let text = definition.trim();
+    let horizontal = if starts_with_keyword(text, &COMPOUND_KEYWORDS) {
+        wkt_element(text, &HORIZONTAL_KEYWORDS).unwrap_or(text)
+    } else {
+        text
+    };
+    if starts_with_keyword(horizontal, &GEOGRAPHIC_KEYWORDS) {
+        return true;
+    }
+    matches!(CrsId::identify(horizontal), Some(CrsId::Epsg(code)) if GEOGRAPHIC_CODES.contains(&code))
+}
+
+/// What follows `EPSG:` in a definition, if it starts with it.
+fn epsg_codes(text: &str) -> Option<&str> {
+    text.get(..5)
+        .filter(|p| p.eq_ignore_ascii_case("EPSG:"))
+        .map(|_| &text[5..])
+}
+
+/// A name reduced to lowercase letters and digits.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn starts_with_keyword(text: &str, keywords: &[&str]) -> bool {
+    keywords
+        .iter()
+        .any(|k| text.len() >= k.len() && text[..k.len()].eq_ignore_ascii_case(k))
+}
+
+/// The first WKT element opened by one of `keywords`, up to its closing
+/// bracket, at whatever depth.
+fn wkt_element<'a>(wkt: &'a str, keywords: &[&str]) -> Option<&'a str> {
+    let start = wkt.char_indices().find_map(|(i, _)| {
+        let preceded = i == 0 || wkt[..i].ends_with(['[', ',', ' ', '\n']);
+        (preceded && starts_with_keyword(&wkt[i..], keywords)).then_some(i)
+    })?;
+    let mut depth = 0usize;
+    for (i, c) in wkt[start..].char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(&wkt[start..=start + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The EPSG code attached to the root of a WKT definition:
+/// `AUTHORITY["EPSG","2154"]` (WKT1) or `ID["EPSG",2154]` (WKT2).
+fn wkt_authority(wkt: &str) -> Option<u32> {
+    let mut depth = 0usize;
+    let mut found = None;
+    for (i, c) in wkt.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            _ if depth == 1 => {
+                let rest = &wkt[i..];
+                let keyword = ["AUTHORITY[", "ID["]
+                    .into_iter()
+                    .find(|k| rest.len() >= k.len() && rest[..k.len()].eq_ignore_ascii_case(k));
+                let preceded = wkt[..i].ends_with([',', ' ', '\n']);
+                if let (Some(keyword), true) = (keyword, preceded) {
+                    let args = &rest[keyword.len()..];
+                    let args = &args[..args.find([']', ')'])?];
+                    let mut parts = args.split(',').map(|p| p.trim().trim_matches('"'));
+                    if parts.next().is_some_and(|a| a.eq_ignore_ascii_case("EPSG")) {
+                        found = parts.next().and_then(|code| code.parse().ok());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// The quoted name right after the root keyword of a WKT definition.
+fn wkt_name(wkt: &str) -> Option<&str> {
+    let open = wkt.find(['[', '('])?;
+    let rest = wkt[open + 1..].trim_start().strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+/// CRS of one input tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrsEntry {
+    pub path: PathBuf,
+    /// `None` when the tile has no CRS or could not be read.
+    pub crs: Option<CrsId>,
+}
+
+/// CRS of every tile of one or more stages.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrsReport {
+    pub entries: Vec<(Stage, CrsEntry)>,
+}
+
+impl CrsReport {
+    pub fn new(stage: Stage, tiles: impl IntoIterator<Item = (PathBuf, Option<CrsId>)>) -> Self {
+        CrsReport {
+            entries: tiles
+                .into_iter()
+                .map(|(path, crs)| (stage, CrsEntry { path, crs }))
+                .collect(),
+        }
+    }
+
+    pub fn merge(mut self, other: &CrsReport) -> Self {
+        self.entries.extend(other.entries.iter().cloned());
+        self
+    }
+
+    /// Distinct CRSs in use, most common first. Tiles without a CRS are
+    /// not counted.
+    pub fn distinct(&self) -> Vec<CrsId> {
+        let mut counts: Vec<(CrsId, usize)> = Vec::new();
+        for crs in self.entries.iter().filter_map(|(_, e)| e.crs.as_ref()) {
+            match counts.iter_mut().find(|(c, _)| c == crs) {
+                Some(entry) => entry.1 += 1,
+                None => counts.push((crs.clone(), 1)),
+            }
+        }
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.into_iter().map(|(crs, _)| crs).collect()
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.distinct().len() <= 1
+    }
+
+    /// Tiles whose known CRS differs from `target`.
+    pub fn outliers<'a>(&'a self, target: &'a CrsId) -> impl Iterator<Item = &'a Path> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(_, e)| e.crs.as_ref().is_some_and(|c| c != target))
+            .map(|(_, e)| e.path.as_path())
+    }
+
+    fn group(&self, crs: Option<&CrsId>) -> Vec<&(Stage, CrsEntry)> {
+        self.entries
+            .iter()
+            .filter(|(_, e)| e.crs.as_ref() == crs)
+            .collect()
+    }
+}
+
+impl fmt::Display for CrsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let groups = self
+            .distinct()
+            .into_iter()
+            .map(Some)
+            .chain(std::iter::once(None));
+        for crs in groups {
+            let tiles = self.group(crs.as_ref());
+            if tiles.is_empty() {
+                continue;
+            }
+            let label = crs.map_or("no CRS".to_string(), |c| c.to_string());
+            writeln!(f, "  {} ({} tile(s)):", label, tiles.len())?;
+            for (stage, entry) in tiles {
+                writeln!(f, "    [{}] {}", stage, entry.path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! IGN "dalle" file names, which encode the Lambert-93 kilometre
+//! coordinates of a tile's top-left corner, as in
+//! `RGEALTI_FXX_0620_6120_MNT_LAMB93_IGN69.asc` or
+//! `75-2021-0620-6120-LA93-0M20-E080.jp2`.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::Stage;
+use crate::raster::RasterInfo;
+
+/// Plausible Lambert-93 eastings and northings over metropolitan France,
+/// in kilometres, so that dates and version numbers are not mistaken for
+/// coordinates.
+const EASTING_KM: std::ops::RangeInclusive<u32> = 0..=1300;
+const NORTHING_KM: std::ops::RangeInclusive<u32> = 6000..=7200;
+
+/// Tile origin read from a dalle name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DalleName {
+    pub x_km: u32,
+    pub y_km: u32,
+}
+
+impl DalleName {
+    /// Finds the first `<easting>_<northing>` pair (or `-` separated) in
+    /// the file name of `path`.
+    pub fn parse(path: &Path) -> Option<DalleName> {
+        let stem = path.file_stem()?.to_str()?;
+        let tokens: Vec<&str> = stem.split(['_', '-']).collect();
+        tokens.windows(2).find_map(|pair| {
+            let numeric = |t: &str, len: std::ops::RangeInclusive<usize>| {
+                (len.contains(&t.len()) && t.bytes().all(|b| b.is_ascii_digit()))
+                    .then(|| t.parse::<u32>().ok())
+                    .flatten()
+            };
+            let x_km = numeric(pair[0], 3..=4).filter(|x| EASTING_KM.contains(x))?;
+            let y_km = numeric(pair[1], 4..=4).filter(|y| NORTHING_KM.contains(y))?;
+            Some(DalleName { x_km, y_km })
+        })
+    }
+
+    /// Top-left corner in metres.
+    pub fn origin(&self) -> (f64, f64) {
+        (self.x_km as f64 * 1000.0, self.y_km as f64 * 1000.0)
+    }
+
+    /// Whether the origin lies in `range`, `[min_x, min_y, max_x, max_y]`
+    /// in kilometres, bounds included.
+    pub fn within(&self, range: &[u32; 4]) -> bool {
+        let [min_x, min_y, max_x, max_y] = *range;
+        (min_x..=max_x).contains(&self.x_km) && (min_y..=max_y).contains(&self.y_km)
+    }
+}
+
+impl fmt::Display for DalleName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}_{:04}", self.x_km, self.y_km)
+    }
+}
+
+/// A tile whose name disagrees with the origin in its header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameMismatch {
+    pub stage: Stage,
+    pub path: PathBuf,
+    pub name: DalleName,
+    /// Top-left corner read from the tile.
+    pub origin: (f64, f64),
+}
+
+impl fmt::Display for NameMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x, y) = self.name.origin();
+        write!(
+            f,
+            "[{}] {}: name says ({}, {}), header origin is ({}, {})",
+            self.stage,
+            self.path.display(),
+            x,
+            y,
+            self.origin.0,
+            self.origin.1
+        )
+    }
+}
+
+/// Tiles whose dalle name puts them more than a pixel away from their
+/// actual top-left corner. Some deliveries place the header origin half a
+/// pixel off the kilometre line, which is accepted. Tiles without a dalle
+/// name are not checked.
+pub fn check_names(stage: Stage, tiles: &[RasterInfo]) -> Vec<NameMismatch> {
+    tiles
+        .iter()
+        .filter_map(|tile| {
+            let name = DalleName::parse(&tile.path)?;
+            let (x, y) = name.origin();
+            let gt = &tile.geo_transform;
+            let (res_x, res_y) = tile.pixel_size();
+            let off = (gt[0] - x).abs() > res_x || (gt[3] - y).abs() > res_y;
+            off.then(|| NameMismatch {
+                stage,
+                path: tile.path.clone(),
+                name,
+                origin: (gt[0], gt[3]),
+            })
+        })
+        .collect()
+}
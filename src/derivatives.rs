@@ -0,0 +1,126 @@
+//! Terrain derivatives of the DEM, computed natively from the written
+//! GeoTIFF, one `Float32` GeoTIFF each on the DEM's grid.
+//!
+//! Slope, aspect, TRI (Riley's), TPI and roughness follow `gdaldem`'s
+//! definitions; curvature is Zevenbergen and Thorne's, as in ArcGIS:
+//! hundredths of a unit per height unit, positive on convex ground.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{Derivative, GeoTiffOptions, SlopeUnits, TerrainOptions};
+use crate::raster::DataType;
+use crate::rows::{RowError, RowSource};
+use crate::terrain::{for_each_row, gradient, Neighbourhood};
+use crate::tiff_rows::GeoTiffRows;
+use crate::tiff_writer::{options_recipe, GeoTiffImage, GeoTiffWriter};
+
+/// Value of pixels without a height, and of the aspect of flat ground.
+pub const DERIVATIVE_NODATA: f64 = -9999.0;
+
+/// One derivative of the DEM GeoTIFF, as planned.
+#[derive(Debug, Clone)]
+pub struct NativeDerivative {
+    /// The DEM GeoTIFF.
+    pub input: PathBuf,
+    pub derivative: Derivative,
+    pub options: TerrainOptions,
+    pub geotiff: GeoTiffOptions,
+}
+
+impl NativeDerivative {
+    /// Everything the result depends on besides the DEM's pixels.
+    pub fn recipe(&self) -> String {
+        let o = &self.options;
+        format!(
+            "{} {} slope_units={:?} z_factor={} {}",
+            self.derivative.name(),
+            self.input.display(),
+            o.slope_units,
+            o.z_factor,
+            options_recipe(&self.geotiff)
+        )
+    }
+
+    /// Derives `output` from `input`, on the DEM's grid.
+    pub fn run(&self, output: &Path) -> Result<(), RowError> {
+        let mut dem = GeoTiffRows::open(&self.input)?;
+        let gt = dem.geo_transform();
+        let image = GeoTiffImage {
+            width: dem.width(),
+            height: dem.height(),
+            geo_transform: gt,
+            crs: dem.crs().map(str::to_string),
+            nodata: DERIVATIVE_NODATA,
+            data_type: DataType::Float32,
+        };
+        let mut writer = GeoTiffWriter::create(output, image, &self.geotiff)
+            .map_err(|e| RowError::io(output, e))?;
+        let (res_x, res_y) = (gt[1], -gt[5]);
+        let mut values = Vec::with_capacity(dem.width());
+        for_each_row(&mut dem, self.options.block_rows, |row| {
+            values.clear();
+            values.extend(row.iter().map(|z| match z {
+                Some(z) => derive(self.derivative, &self.options, z, res_x, res_y),
+                None => f32::NAN,
+            }));
+            writer
+                .write_row(&values)
+                .map_err(|e| RowError::io(output, e))
+        })?;
+        writer.finish().map_err(|e| RowError::io(output, e))
+    }
+}
+
+/// `derivative` at the centre of `z`, `NaN` where it has no value.
+pub fn derive(
+    derivative: Derivative,
+    options: &TerrainOptions,
+    z: &Neighbourhood,
+    res_x: f64,
+    res_y: f64,
+) -> f32 {
+    let centre = z[4];
+    let neighbours = || {
+        z.iter()
+            .enumerate()
+            .filter(|&(i, _)| i != 4)
+            .map(|(_, v)| v)
+    };
+    let value = match derivative {
+        Derivative::Slope => {
+            let (east, north) = gradient(z, res_x, res_y);
+            let rise = options.z_factor * east.hypot(north);
+            match options.slope_units {
+                SlopeUnits::Degrees => rise.atan().to_degrees(),
+                SlopeUnits::Percent => 100.0 * rise,
+            }
+        }
+        Derivative::Aspect => {
+            let (east, north) = gradient(z, res_x, res_y);
+            if east == 0.0 && north == 0.0 {
+                return f32::NAN;
+            }
+            // Downhill, clockwise from north.
+            (-east).atan2(-north).to_degrees().rem_euclid(360.0)
+        }
+        Derivative::Tri => neighbours()
+            .map(|v| (v - centre) * (v - centre))
+            .sum::<f64>()
+            .sqrt(),
+        Derivative::Tpi => centre - neighbours().sum::<f64>() / 8.0,
+        Derivative::Roughness => {
+            let (min, max) = z
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+                    (min.min(v), max.max(v))
+                });
+            max - min
+        }
+        Derivative::Curvature => {
+            let d = ((z[3] + z[5]) / 2.0 - centre) / (res_x * res_x);
+            let e = ((z[1] + z[7]) / 2.0 - centre) / (res_y * res_y);
+            -200.0 * options.z_factor * (d + e)
+        }
+    };
+    value as f32
+}
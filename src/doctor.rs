@@ -0,0 +1,304 @@
+//! Pre-flight checks: locates the GDAL tools a build needs, reads their
+//! versions and makes sure a JPEG 2000 driver is available, so a missing
+//! installation is reported up front instead of halfway through a build.
+
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Every GDAL tool the pipeline may invoke.
+pub const GDAL_TOOLS: [&str; 5] = [
+    "gdalbuildvrt",
+    "gdal_fillnodata",
+    "gdalwarp",
+    "gdal_translate",
+    "gdaladdo",
+];
+
+/// Tools only some outputs need, often packaged apart from GDAL's raster
+/// utilities: `ogr2ogr` writes the GeoPackage contours.
+pub const OPTIONAL_TOOLS: [&str; 1] = ["ogr2ogr"];
+
+/// GDAL drivers able to read JPEG 2000.
+pub const JP2_DRIVERS: [&str; 6] = [
+    "JP2OpenJPEG",
+    "JP2KAK",
+    "JP2ECW",
+    "JP2MrSID",
+    "JP2Lura",
+    "JPEG2000",
+];
+
+/// A GDAL release number, as printed by `<tool> --version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GdalVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl GdalVersion {
+    /// Parses output such as `GDAL 3.8.4, released 2024/02/08`. Trailing
+    /// suffixes (`3.9.0dev-...`) are ignored.
+    pub fn parse(text: &str) -> Option<GdalVersion> {
+        let rest = &text[text.find("GDAL ")? + 5..];
+        let number = rest.split([',', ' ']).next()?;
+        let mut parts = number.split('.').map(|p| {
+            let digits: String = p.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u32>().ok()
+        });
+        Some(GdalVersion {
+            major: parts.next()??,
+            minor: parts.next().flatten().unwrap_or(0),
+            patch: parts.next().flatten().unwrap_or(0),
+        })
+    }
+}
+
+impl fmt::Display for GdalVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Result of looking up one tool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCheck {
+    pub name: String,
+    /// Where the tool was found on `PATH`.
+    pub path: Option<PathBuf>,
+    /// Version reported by `--version`, when it could be parsed.
+    pub version: Option<GdalVersion>,
+    /// Why the tool cannot be used, if it cannot.
+    pub problem: Option<String>,
+}
+
+/// Result of looking for a JPEG 2000 driver.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jp2Check {
+    /// JPEG 2000 drivers listed by `--formats`.
+    pub drivers: Vec<String>,
+    pub problem: Option<String>,
+}
+
+/// Outcome of a pre-flight check, printed as a table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub tools: Vec<ToolCheck>,
+    /// Only present when JPEG 2000 tiles will be read by GDAL.
+    pub jp2: Option<Jp2Check>,
+    /// Tools from [`OPTIONAL_TOOLS`], listed without failing the check.
+    pub optional: Vec<ToolCheck>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.tools.iter().all(|t| t.problem.is_none())
+            && self.jp2.as_ref().is_none_or(|j| j.problem.is_none())
+    }
+
+    /// Distinct versions among the tools found.
+    pub fn versions(&self) -> Vec<GdalVersion> {
+        let mut versions: Vec<_> = self
+            .tools
+            .iter()
+            .chain(&self.optional)
+            .filter_map(|t| t.version)
+            .collect();
+        versions.sort();
+        versions.dedup();
+        versions
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = vec![[
+            "TOOL".to_string(),
+            "STATUS".to_string(),
+            "VERSION".to_string(),
+            "DETAILS".to_string(),
+        ]];
+        let optional = self.optional.iter().map(|tool| (tool, true));
+        for (tool, optional) in self.tools.iter().map(|tool| (tool, false)).chain(optional) {
+            let details = match (&tool.problem, &tool.path) {
+                (Some(problem), _) if optional => {
+                    format!(
+                        "{} (optional: needed for GeoPackage contours only)",
+                        problem
+                    )
+                }
+                (Some(problem), _) => problem.clone(),
+                (None, Some(path)) => path.display().to_string(),
+                (None, None) => String::new(),
+            };
+            rows.push([
+                tool.name.clone(),
+                match (&tool.problem, &tool.path) {
+                    (None, _) => "ok",
+                    (Some(_), None) => "MISSING",
+                    (Some(_), Some(_)) => "BROKEN",
+                }
+                .to_string(),
+                tool.version.map_or("-".to_string(), |v| v.to_string()),
+                details,
+            ]);
+        }
+        if let Some(jp2) = &self.jp2 {
+            rows.push([
+                "JPEG 2000 driver".to_string(),
+                if jp2.problem.is_some() {
+                    "MISSING"
+                } else {
+                    "ok"
+                }
+                .to_string(),
+                "-".to_string(),
+                jp2.problem
+                    .clone()
+                    .unwrap_or_else(|| jp2.drivers.join(", ")),
+            ]);
+        }
+
+        let widths: Vec<usize> = (0..3)
+            .map(|c| rows.iter().map(|r| r[c].len()).max().unwrap_or(0))
+            .collect();
+        for row in &rows {
+            writeln!(
+                f,
+                "{:w0$}  {:w1$}  {:w2$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2]
+            )?;
+        }
+
+        let versions = self.versions();
+        if versions.len() > 1 {
+            let list: Vec<String> = versions.iter().map(ToString::to_string).collect();
+            writeln!(
+                f,
+                "warning: tools come from different GDAL releases ({})",
+                list.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks `tools` and, with `jp2`, that GDAL can read JPEG 2000.
+pub fn check<S: AsRef<str>>(tools: &[S], jp2: bool) -> Report {
+    let tools: Vec<ToolCheck> = tools.iter().map(|t| check_tool(t.as_ref())).collect();
+
+    let jp2 = jp2.then(|| {
+        // Any tool lists the drivers of the library it is linked against.
+        match tools.iter().find_map(|t| t.path.as_deref()) {
+            Some(path) => check_jp2(path),
+            None => Jp2Check {
+                drivers: Vec::new(),
+                problem: Some("cannot be checked without a GDAL tool".to_string()),
+            },
+        }
+    });
+    Report {
+        tools,
+        jp2,
+        optional: Vec::new(),
+    }
+}
+
+/// Checks every tool in [`GDAL_TOOLS`] and the JPEG 2000 driver, and
+/// lists those in [`OPTIONAL_TOOLS`].
+pub fn check_all() -> Report {
+    Report {
+        optional: OPTIONAL_TOOLS.iter().map(|t| check_tool(t)).collect(),
+        ..check(&GDAL_TOOLS, true)
+    }
+}
+
+fn check_tool(name: &str) -> ToolCheck {
+    let Some(path) = find_on_path(name) else {
+        let mut problem = "not found on PATH".to_string();
+        if find_on_path(&format!("{}.py", name)).is_some() {
+            problem.push_str(&format!(
+                "; {}.py exists, older GDAL releases install it with a .py suffix",
+                name
+            ));
+        }
+        return ToolCheck {
+            name: name.to_string(),
+            path: None,
+            version: None,
+            problem: Some(problem),
+        };
+    };
+
+    let (version, problem) = match capture(&path, "--version") {
+        Ok(output) => (GdalVersion::parse(&output), None),
+        Err(e) => (
+            None,
+            Some(format!("{} cannot be run: {}", path.display(), e)),
+        ),
+    };
+    ToolCheck {
+        name: name.to_string(),
+        path: Some(path),
+        version,
+        problem,
+    }
+}
+
+fn check_jp2(tool: &Path) -> Jp2Check {
+    let formats = match capture(tool, "--formats") {
+        Ok(formats) => formats,
+        Err(e) => {
+            return Jp2Check {
+                drivers: Vec::new(),
+                problem: Some(format!("{} --formats failed: {}", tool.display(), e)),
+            }
+        }
+    };
+    let drivers: Vec<String> = formats
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| JP2_DRIVERS.contains(name))
+        .map(str::to_string)
+        .collect();
+    let problem = drivers
+        .is_empty()
+        .then(|| "GDAL was built without a JPEG 2000 driver (e.g. JP2OpenJPEG)".to_string());
+    Jp2Check { drivers, problem }
+}
+
+/// Runs `tool <flag>` and returns its stdout.
+fn capture(tool: &Path, flag: &str) -> Result<String, String> {
+    let output = Command::new(tool)
+        .arg(flag)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Looks `name` up in the directories of `PATH`, like a shell would.
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path).find_map(|dir| {
+        [
+            name.to_string(),
+            format!("{}{}", name, env::consts::EXE_SUFFIX),
+        ]
+        .into_iter()
+        .map(|file| dir.join(file))
+        .find(|candidate| candidate.is_file())
+    })
+}
@@ -0,0 +1,266 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+use crate::align::NoOverlap;
+use crate::config::ConfigError;
+use crate::coverage::Coverage;
+use crate::crs::{CrsId, CrsReport};
+use crate::doctor::Report;
+use crate::gdal::quote_arg;
+
+/// Step of the build a failure happened in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Prepare,
+    OrthoVrt,
+    DemVrt,
+    Warp,
+    Convert,
+    Hillshade,
+    Terrain,
+    Contours,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Prepare => "prepare",
+            Stage::OrthoVrt => "ortho-vrt",
+            Stage::DemVrt => "dem-vrt",
+            Stage::Warp => "warp",
+            Stage::Convert => "convert",
+            Stage::Hillshade => "hillshade",
+            Stage::Terrain => "terrain",
+            Stage::Contours => "contours",
+        })
+    }
+}
+
+/// Everything that can stop a build.
+///
+/// Each variant maps to its own process exit code (see [`exit_code`]) so
+/// that scripts can tell a bad project file from a crashing GDAL tool.
+///
+/// [`exit_code`]: PipelineError::exit_code
+#[derive(Debug)]
+pub enum PipelineError {
+    /// The project file or command-line settings are invalid.
+    Config(ConfigError),
+    /// Reading or writing the working directories failed.
+    Io {
+        stage: Stage,
+        context: String,
+        source: io::Error,
+    },
+    /// No input tiles with the expected extension were found.
+    NoInputs {
+        stage: Stage,
+        dir: PathBuf,
+        extension: &'static str,
+    },
+    /// An input tile is unreadable or malformed.
+    Input { path: PathBuf, message: String },
+    /// The outputs and the area of interest do not overlap.
+    NoOverlap(NoOverlap),
+    /// Input tiles disagree on their CRS and no common one was chosen.
+    MixedCrs(CrsReport),
+    /// DEM tiles declare different vertical references, or one other than
+    /// `dem.vertical.datum`. The report lists the tiles declaring one.
+    MixedVertical {
+        expected: Option<CrsId>,
+        report: CrsReport,
+    },
+    /// Tiles are missing from the grid and complete coverage is required.
+    IncompleteCoverage(Box<Coverage>),
+    /// The pre-flight check found tools or drivers missing.
+    Preflight(Report),
+    /// The tool could not be started at all.
+    Spawn {
+        stage: Stage,
+        tool: String,
+        args: Vec<String>,
+        source: io::Error,
+    },
+    /// The tool ran and exited unsuccessfully.
+    ToolFailed {
+        stage: Stage,
+        tool: String,
+        args: Vec<String>,
+        status: ExitStatus,
+        stderr: String,
+    },
+}
+
+impl PipelineError {
+    pub const EXIT_CONFIG: u8 = 2;
+    pub const EXIT_IO: u8 = 3;
+    pub const EXIT_SPAWN: u8 = 4;
+    pub const EXIT_TOOL_FAILED: u8 = 5;
+    pub const EXIT_NO_INPUTS: u8 = 6;
+    pub const EXIT_INVALID_INPUT: u8 = 7;
+    pub const EXIT_INCOMPLETE_COVERAGE: u8 = 8;
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PipelineError::Config(_) => Self::EXIT_CONFIG,
+            PipelineError::Io { .. } => Self::EXIT_IO,
+            PipelineError::Preflight(_) | PipelineError::Spawn { .. } => Self::EXIT_SPAWN,
+            PipelineError::ToolFailed { .. } => Self::EXIT_TOOL_FAILED,
+            PipelineError::NoInputs { .. } => Self::EXIT_NO_INPUTS,
+            PipelineError::Input { .. }
+            | PipelineError::MixedCrs(_)
+            | PipelineError::MixedVertical { .. }
+            | PipelineError::NoOverlap(_) => Self::EXIT_INVALID_INPUT,
+            PipelineError::IncompleteCoverage(_) => Self::EXIT_INCOMPLETE_COVERAGE,
+        }
+    }
+
+    pub fn input(path: &Path, message: impl fmt::Display) -> Self {
+        PipelineError::Input {
+            path: path.to_path_buf(),
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn io(stage: Stage, context: impl Into<String>, source: io::Error) -> Self {
+        PipelineError::Io {
+            stage,
+            context: context.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Config(e) => write!(f, "{}", e),
+            PipelineError::Io {
+                stage,
+                context,
+                source,
+            } => write!(f, "[{}] {}: {}", stage, context, source),
+            PipelineError::Input { path, message } => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+            PipelineError::NoInputs {
+                stage,
+                dir,
+                extension,
+            } => write!(
+                f,
+                "[{}] no .{} files found under {}",
+                stage,
+                extension,
+                dir.display()
+            ),
+            PipelineError::NoOverlap(e) => write!(f, "{}", e),
+            PipelineError::MixedCrs(report) => write!(
+                f,
+                "input tiles disagree on their CRS; set crs.inputs to reproject them:\n{}",
+                report.to_string().trim_end()
+            ),
+            PipelineError::MixedVertical {
+                expected: Some(expected),
+                report,
+            } => write!(
+                f,
+                "DEM tiles declare another vertical reference than dem.vertical.datum = {}:\n{}",
+                expected,
+                report.to_string().trim_end()
+            ),
+            PipelineError::MixedVertical {
+                expected: None,
+                report,
+            } => write!(
+                f,
+                "DEM tiles declare different vertical references; convert their heights \
+                 to one before building:\n{}",
+                report.to_string().trim_end()
+            ),
+            PipelineError::IncompleteCoverage(coverage) => write!(
+                f,
+                "incomplete tile coverage:\n{}",
+                coverage.to_string().trim_end()
+            ),
+            PipelineError::Preflight(report) => write!(
+                f,
+                "[{}] GDAL pre-flight check failed:\n{}",
+                Stage::Prepare,
+                report.to_string().trim_end()
+            ),
+            PipelineError::Spawn {
+                stage,
+                tool,
+                args,
+                source,
+            } => write!(
+                f,
+                "[{}] failed to start {} {}: {}",
+                stage,
+                tool,
+                display_args(args),
+                source
+            ),
+            PipelineError::ToolFailed {
+                stage,
+                tool,
+                args,
+                status,
+                stderr,
+            } => {
+                write!(
+                    f,
+                    "[{}] {} {} failed ({})",
+                    stage,
+                    tool,
+                    display_args(args),
+                    status
+                )?;
+                let stderr = stderr.trim_end();
+                if !stderr.is_empty() {
+                    write!(f, ":\n{}", stderr)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::Config(e) => Some(e),
+            PipelineError::Io { source, .. } | PipelineError::Spawn { source, .. } => Some(source),
+            PipelineError::Preflight(_)
+            | PipelineError::ToolFailed { .. }
+            | PipelineError::NoInputs { .. }
+            | PipelineError::Input { .. }
+            | PipelineError::MixedCrs(_)
+            | PipelineError::MixedVertical { .. }
+            | PipelineError::NoOverlap(_)
+            | PipelineError::IncompleteCoverage(_) => None,
+        }
+    }
+}
+
+fn display_args(args: &[String]) -> String {
+    args.iter()
+        .map(|a| quote_arg(a))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl From<NoOverlap> for PipelineError {
+    fn from(e: NoOverlap) -> Self {
+        PipelineError::NoOverlap(e)
+    }
+}
+
+impl From<ConfigError> for PipelineError {
+    fn from(e: ConfigError) -> Self {
+        PipelineError::Config(e)
+    }
+}
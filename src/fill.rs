@@ -0,0 +1,299 @@
+//! Native replacement for `gdal_fillnodata`: interpolates the holes of a
+//! DEM mosaic block by block, reading the `.asc` tiles directly.
+//!
+//! Like GDAL, each hole pixel looks along eight directions for the first
+//! valid pixel within `max_distance` pixels; the strategy decides how those
+//! samples become a value. Filled pixels are then smoothed with a 3 x 3
+//! mean, `smoothing_iterations` times. Holes with no valid pixel in reach
+//! stay nodata.
+
+use std::path::Path;
+
+use crate::config::{FillOptions, FillStrategy};
+use crate::rows::{for_each_block, MosaicRows, RawWriter, RowError, RowSource, Window};
+use crate::vrt::{Mosaic, RawVrt};
+
+/// Nodata written when the tiles declare none.
+pub const DEFAULT_NODATA: f64 = -99999.0;
+
+const DIRECTIONS: [(isize, isize); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+const FOUR_NEIGHBOURS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+const THREE_BY_THREE: [(isize, isize); 9] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (0, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// A fill of a whole DEM mosaic into a raw raster, as planned.
+#[derive(Debug, Clone)]
+pub struct NativeFill {
+    pub mosaic: Mosaic,
+    pub options: FillOptions,
+}
+
+impl NativeFill {
+    /// Everything the result depends on besides the tile contents.
+    pub fn recipe(&self) -> String {
+        let o = &self.options;
+        let mut recipe = format!(
+            "fill {} md={} si={} ri={} nodata={}",
+            o.strategy.as_str(),
+            o.max_distance,
+            o.smoothing_iterations,
+            o.relaxation_iterations,
+            self.nodata()
+        );
+        for tile in &self.mosaic.tiles {
+            recipe.push(' ');
+            recipe.push_str(&tile.path.to_string_lossy());
+        }
+        recipe
+    }
+
+    pub fn nodata(&self) -> f64 {
+        self.mosaic.nodata.unwrap_or(DEFAULT_NODATA)
+    }
+
+    /// Layout of the raster written: the mosaic's.
+    pub fn output(&self) -> RawVrt {
+        RawVrt {
+            width: self.mosaic.width,
+            height: self.mosaic.height,
+            geo_transform: self.mosaic.geo_transform,
+            crs: self.mosaic.crs.clone(),
+            nodata: self.nodata(),
+        }
+    }
+
+    /// Fills the mosaic into `output` (a VRT) and its `.bin` pixel file.
+    pub fn run(&self, output: &Path) -> Result<(), RowError> {
+        let mut source = MosaicRows::new(&self.mosaic)?;
+        let mut writer = RawWriter::create(output, self.output())?;
+        fill_rows(&mut source, &self.options, |row| writer.write_row(row))?;
+        writer.finish()
+    }
+}
+
+/// Fills the holes (`NaN`) of `source`, handing each finished row to
+/// `sink` top row first. Only `block_rows` rows plus the search margin are
+/// held in memory.
+pub fn fill_rows(
+    source: &mut dyn RowSource,
+    options: &FillOptions,
+    mut sink: impl FnMut(&[f32]) -> Result<(), RowError>,
+) -> Result<(), RowError> {
+    let passes = passes(options);
+    let margin = options.max_distance as usize + passes;
+    for_each_block(source, options.block_rows, margin, |window| {
+        let (values, region_start) = fill_block(window, options, passes);
+        for r in window.block.clone() {
+            let start = (r - region_start) * window.width;
+            sink(&values[start..start + window.width])?;
+        }
+        Ok(())
+    })
+}
+
+/// Smoothing and relaxation passes, each of which reads one pixel further.
+fn passes(options: &FillOptions) -> usize {
+    let relaxation = match options.strategy {
+        FillStrategy::Laplacian => options.relaxation_iterations,
+        _ => 0,
+    };
+    (options.smoothing_iterations + relaxation) as usize
+}
+
+/// Fills the block of `window` plus `passes` rows on each side, so the
+/// passes have valid neighbours at the block edges. Returns the region
+/// and its first row.
+fn fill_block(window: &Window, options: &FillOptions, passes: usize) -> (Vec<f32>, usize) {
+    let held = window.held();
+    let start = window.block.start.saturating_sub(passes).max(held.start);
+    let end = (window.block.end + passes).min(held.end);
+    let width = window.width;
+
+    let mut values: Vec<f32> = window.rows[start - held.start..end - held.start].concat();
+    let mut filled = vec![false; values.len()];
+    for r in start..end {
+        for c in 0..width {
+            let i = (r - start) * width + c;
+            if values[i].is_nan() {
+                if let Some(v) = estimate(window, r, c, options) {
+                    values[i] = v;
+                    filled[i] = true;
+                }
+            }
+        }
+    }
+
+    let rows = end - start;
+    if options.strategy == FillStrategy::Laplacian {
+        let iterations = options.relaxation_iterations as usize;
+        relax(
+            &mut values,
+            &filled,
+            width,
+            rows,
+            iterations,
+            &FOUR_NEIGHBOURS,
+        );
+    }
+    let smoothing = options.smoothing_iterations as usize;
+    relax(
+        &mut values,
+        &filled,
+        width,
+        rows,
+        smoothing,
+        &THREE_BY_THREE,
+    );
+    (values, start)
+}
+
+/// A valid pixel found from a hole: offset in pixels, value and distance.
+struct Sample {
+    dx: f64,
+    dy: f64,
+    value: f64,
+    distance: f64,
+}
+
+fn estimate(window: &Window, row: usize, column: usize, options: &FillOptions) -> Option<f32> {
+    let held = window.held();
+    let max_distance = options.max_distance as f64;
+    let mut samples = Vec::with_capacity(DIRECTIONS.len());
+    for (dx, dy) in DIRECTIONS {
+        let step = ((dx * dx + dy * dy) as f64).sqrt();
+        for k in 1.. {
+            let distance = k as f64 * step;
+            if distance > max_distance {
+                break;
+            }
+            let r = row as isize + dy * k;
+            let c = column as isize + dx * k;
+            if r < held.start as isize
+                || r >= held.end as isize
+                || c < 0
+                || c >= window.width as isize
+            {
+                break;
+            }
+            let value = window.get(r as usize, c as usize);
+            if !value.is_nan() {
+                samples.push(Sample {
+                    dx: (dx * k) as f64,
+                    dy: (dy * k) as f64,
+                    value: value as f64,
+                    distance,
+                });
+                break;
+            }
+        }
+    }
+    if samples.is_empty() {
+        return None;
+    }
+
+    let value = match options.strategy {
+        FillStrategy::Idw | FillStrategy::Laplacian => idw(&samples),
+        FillStrategy::Nearest => {
+            let nearest = samples
+                .iter()
+                .min_by(|a, b| a.distance.total_cmp(&b.distance))?;
+            nearest.value
+        }
+        FillStrategy::Planar => plane_at_origin(&samples).unwrap_or_else(|| idw(&samples)),
+    };
+    Some(value as f32)
+}
+
+fn idw(samples: &[Sample]) -> f64 {
+    let (sum, weights) = samples.iter().fold((0.0, 0.0), |(sum, weights), s| {
+        let w = 1.0 / (s.distance * s.distance);
+        (sum + w * s.value, weights + w)
+    });
+    sum / weights
+}
+
+/// Value at the hole of the least-squares plane `z = a + b x + c y`
+/// through the samples, or `None` when they do not define a plane.
+fn plane_at_origin(samples: &[Sample]) -> Option<f64> {
+    if samples.len() < 3 {
+        return None;
+    }
+    // Normal equations of the fit, solved with Cramer's rule.
+    let mut m = [[0.0f64; 3]; 3];
+    let mut v = [0.0f64; 3];
+    for s in samples {
+        let basis = [1.0, s.dx, s.dy];
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] += basis[i] * basis[j];
+            }
+            v[i] += basis[i] * s.value;
+        }
+    }
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(&m);
+    if d.abs() < 1e-9 {
+        return None;
+    }
+    let mut a = m;
+    for (row, value) in a.iter_mut().zip(v) {
+        row[0] = value;
+    }
+    Some(det(&a) / d)
+}
+
+/// Replaces every filled pixel with the mean of the valid pixels at
+/// `kernel` offsets, `passes` times.
+fn relax(
+    values: &mut [f32],
+    filled: &[bool],
+    width: usize,
+    rows: usize,
+    passes: usize,
+    kernel: &[(isize, isize)],
+) {
+    if !filled.contains(&true) {
+        return;
+    }
+    let mut previous = values.to_vec();
+    for _ in 0..passes {
+        previous.copy_from_slice(values);
+        for (i, _) in filled.iter().enumerate().filter(|(_, &f)| f) {
+            let (r, c) = ((i / width) as isize, (i % width) as isize);
+            let (sum, count) = kernel
+                .iter()
+                .map(|(dx, dy)| (r + dy, c + dx))
+                .filter(|&(r, c)| r >= 0 && r < rows as isize && c >= 0 && c < width as isize)
+                .map(|(r, c)| previous[r as usize * width + c as usize])
+                .filter(|v| !v.is_nan())
+                .fold((0.0f64, 0usize), |(sum, n), v| (sum + v as f64, n + 1));
+            if count > 0 {
+                values[i] = (sum / count as f64) as f32;
+            }
+        }
+    }
+}
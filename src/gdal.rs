@@ -0,0 +1,215 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::error::{PipelineError, Stage};
+
+/// A single GDAL invocation: the program name and its arguments, passed to
+/// the OS as-is without going through a shell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolCommand {
+    pub program: String,
+    pub args: Vec<OsString>,
+}
+
+impl ToolCommand {
+    pub fn new(program: &str) -> Self {
+        ToolCommand {
+            program: program.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    pub fn args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|a| a.as_ref().to_os_string()));
+        self
+    }
+
+    /// Arguments as UTF-8 strings, for error reports and logs.
+    pub fn lossy_args(&self) -> Vec<String> {
+        self.args
+            .iter()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Runs the tool, forwarding its stdout and capturing stderr for the
+    /// error report.
+    pub fn run(&self, stage: Stage) -> Result<(), PipelineError> {
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|source| PipelineError::Spawn {
+                stage,
+                tool: self.program.clone(),
+                args: self.lossy_args(),
+                source,
+            })?;
+
+        if !output.status.success() {
+            return Err(PipelineError::ToolFailed {
+                stage,
+                tool: self.program.clone(),
+                args: self.lossy_args(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ToolCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.program)?;
+        for arg in self.lossy_args() {
+            write!(f, " {}", quote_arg(&arg))?;
+        }
+        Ok(())
+    }
+}
+
+/// Executes [`ToolCommand`]s on behalf of the pipeline. Every GDAL call
+/// goes through a runner so builds can be exercised without GDAL.
+pub trait ToolRunner {
+    /// Runs `command`, which is expected to produce `output`.
+    fn run(&self, stage: Stage, command: &ToolCommand, output: &Path) -> Result<(), PipelineError>;
+}
+
+/// Spawns the real GDAL binaries found on `PATH`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl ToolRunner for SystemRunner {
+    fn run(&self, stage: Stage, command: &ToolCommand, _: &Path) -> Result<(), PipelineError> {
+        command.run(stage)
+    }
+}
+
+/// How a [`RecordingRunner`] answers for a given program.
+#[derive(Debug, Clone)]
+enum Outcome {
+    /// The program cannot be started, as if it were not installed.
+    Missing,
+    /// The program exits with `code` after printing `stderr`.
+    Fail { code: i32, stderr: String },
+}
+
+/// A fake GDAL that records every command instead of running it.
+///
+/// Successful commands create an empty file at their output, so later
+/// steps and the build manifest see the files they would with GDAL.
+#[derive(Debug, Default)]
+pub struct RecordingRunner {
+    commands: RefCell<Vec<ToolCommand>>,
+    outcomes: HashMap<String, Outcome>,
+}
+
+impl RecordingRunner {
+    pub fn new() -> Self {
+        RecordingRunner::default()
+    }
+
+    /// Makes `program` fail with exit code `code` and `stderr`.
+    pub fn fail(mut self, program: &str, code: i32, stderr: &str) -> Self {
+        let outcome = Outcome::Fail {
+            code,
+            stderr: stderr.to_string(),
+        };
+        self.outcomes.insert(program.to_string(), outcome);
+        self
+    }
+
+    /// Makes `program` impossible to start.
+    pub fn missing(mut self, program: &str) -> Self {
+        self.outcomes.insert(program.to_string(), Outcome::Missing);
+        self
+    }
+
+    /// Every command received so far, including failed ones.
+    pub fn commands(&self) -> Vec<ToolCommand> {
+        self.commands.borrow().clone()
+    }
+
+    /// Program names of [`RecordingRunner::commands`].
+    pub fn programs(&self) -> Vec<String> {
+        self.commands
+            .borrow()
+            .iter()
+            .map(|c| c.program.clone())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.commands.borrow_mut().clear();
+    }
+}
+
+impl ToolRunner for RecordingRunner {
+    fn run(&self, stage: Stage, command: &ToolCommand, output: &Path) -> Result<(), PipelineError> {
+        self.commands.borrow_mut().push(command.clone());
+
+        match self.outcomes.get(&command.program) {
+            Some(Outcome::Missing) => Err(PipelineError::Spawn {
+                stage,
+                tool: command.program.clone(),
+                args: command.lossy_args(),
+                source: io::Error::new(io::ErrorKind::NotFound, "program not found"),
+            }),
+            Some(Outcome::Fail { code, stderr }) => Err(PipelineError::ToolFailed {
+                stage,
+                tool: command.program.clone(),
+                args: command.lossy_args(),
+                status: exit_status(*code),
+                stderr: stderr.clone(),
+            }),
+            None => fs::write(output, b"").map_err(|e| {
+                PipelineError::io(stage, format!("failed to write {}", output.display()), e)
+            }),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw((code & 0xff) << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+/// Quotes an argument for display when it would not survive copy-pasting
+/// into a shell.
+pub fn quote_arg(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
@@ -0,0 +1,86 @@
+//! Planar geometry helpers shared by the raster modules.
+
+use std::fmt;
+
+/// Axis-aligned bounding box in map units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extent {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl Extent {
+    pub fn new(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Self {
+        Extent {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    /// Extent covered by a north-up raster of `width` x `height` pixels.
+    pub fn from_geo_transform(gt: &[f64; 6], width: usize, height: usize) -> Self {
+        let x0 = gt[0];
+        let y0 = gt[3];
+        let x1 = gt[0] + gt[1] * width as f64;
+        let y1 = gt[3] + gt[5] * height as f64;
+        Extent::new(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1))
+    }
+
+    pub fn width(&self) -> f64 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max_y - self.min_y
+    }
+
+    pub fn area(&self) -> f64 {
+        self.width().max(0.0) * self.height().max(0.0)
+    }
+
+    pub fn union(&self, other: &Extent) -> Extent {
+        Extent::new(
+            self.min_x.min(other.min_x),
+            self.min_y.min(other.min_y),
+            self.max_x.max(other.max_x),
+            self.max_y.max(other.max_y),
+        )
+    }
+
+    /// Overlapping area, or `None` when the extents only touch or are
+    /// disjoint.
+    pub fn intersection(&self, other: &Extent) -> Option<Extent> {
+        let e = Extent::new(
+            self.min_x.max(other.min_x),
+            self.min_y.max(other.min_y),
+            self.max_x.min(other.max_x),
+            self.max_y.min(other.max_y),
+        );
+        (e.min_x < e.max_x && e.min_y < e.max_y).then_some(e)
+    }
+
+    pub fn intersects(&self, other: &Extent) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    pub fn contains(&self, other: &Extent) -> bool {
+        self.min_x <= other.min_x
+            && self.min_y <= other.min_y
+            && self.max_x >= other.max_x
+            && self.max_y >= other.max_y
+    }
+}
+
+impl fmt::Display for Extent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}, {}, {}, {}]",
+            self.min_x, self.min_y, self.max_x, self.max_y
+        )
+    }
+}
@@ -0,0 +1,191 @@
+//! Conversion of DEM heights to another vertical reference with a geoid
+//! grid: orthometric heights (above the geoid, e.g. NGF-IGN69) become
+//! ellipsoidal ones once the geoid's height above the ellipsoid is added.
+//!
+//! The grid is an ESRI ASCII grid in the DEM's CRS, so no projection is
+//! needed: its heights are sampled bilinearly at every pixel centre. Grids
+//! such as RAF20 come in geographic coordinates and are warped to the DEM's
+//! CRS beforehand.
+
+use std::path::{Path, PathBuf};
+
+use crate::asc::AscReader;
+use crate::crs::CrsId;
+use crate::geo::Extent;
+use crate::raster;
+use crate::rows::{RawRows, RawWriter, RowError, RowSource};
+use crate::vrt::RawVrt;
+
+/// Geoid heights above the ellipsoid, held in memory: national grids are
+/// a few thousand nodes across.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoidGrid {
+    pub width: usize,
+    pub height: usize,
+    /// GDAL-style geotransform of the grid cells; each node is the centre
+    /// of its cell.
+    pub geo_transform: [f64; 6],
+    /// Row-major heights, `NaN` where the grid has none.
+    pub heights: Vec<f32>,
+}
+
+impl GeoidGrid {
+    pub fn load(path: &Path) -> Result<GeoidGrid, RowError> {
+        let reader = AscReader::open(path).map_err(|e| RowError::input(path, e))?;
+        let header = *reader.header();
+        let mut heights = reader.read_all().map_err(|e| RowError::input(path, e))?;
+        for h in &mut heights {
+            if header.is_nodata(*h) {
+                *h = f32::NAN;
+            }
+        }
+        Ok(GeoidGrid {
+            width: header.ncols,
+            height: header.nrows,
+            geo_transform: header.geo_transform(),
+            heights,
+        })
+    }
+
+    /// The area between the outermost nodes, where heights can be
+    /// interpolated.
+    pub fn node_extent(&self) -> Extent {
+        let gt = &self.geo_transform;
+        let (x0, y0) = (gt[0] + gt[1] / 2.0, gt[3] + gt[5] / 2.0);
+        let x1 = x0 + (self.width - 1) as f64 * gt[1];
+        let y1 = y0 + (self.height - 1) as f64 * gt[5];
+        Extent::new(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1))
+    }
+
+    /// Geoid height at `(x, y)`, bilinear between the four nodes around
+    /// it. `None` outside the nodes or next to a node without a height.
+    pub fn height_at(&self, x: f64, y: f64) -> Option<f32> {
+        let gt = &self.geo_transform;
+        let column = (x - gt[0]) / gt[1] - 0.5;
+        let row = (y - gt[3]) / gt[5] - 0.5;
+        let last_column = self.width.saturating_sub(1) as f64;
+        let last_row = self.height.saturating_sub(1) as f64;
+        // A hair of tolerance, for pixel centres right on the outer nodes.
+        const EPSILON: f64 = 1e-9;
+        if !(-EPSILON..=last_column + EPSILON).contains(&column)
+            || !(-EPSILON..=last_row + EPSILON).contains(&row)
+        {
+            return None;
+        }
+        let (column, row) = (column.clamp(0.0, last_column), row.clamp(0.0, last_row));
+        let (c0, r0) = (column.floor() as usize, row.floor() as usize);
+        let (c1, r1) = ((c0 + 1).min(self.width - 1), (r0 + 1).min(self.height - 1));
+        let (fx, fy) = ((column - c0 as f64) as f32, (row - r0 as f64) as f32);
+        let at = |r: usize, c: usize| self.heights[r * self.width + c];
+        let top = at(r0, c0) * (1.0 - fx) + at(r0, c1) * fx;
+        let bottom = at(r1, c0) * (1.0 - fx) + at(r1, c1) * fx;
+        let height = top * (1.0 - fy) + bottom * fy;
+        (!height.is_nan()).then_some(height)
+    }
+}
+
+/// A DEM brought to another vertical reference, as planned.
+#[derive(Debug, Clone)]
+pub struct NativeGeoid {
+    /// VRT of the DEM read, as written by [`RawWriter`].
+    pub input: PathBuf,
+    pub raster: RawVrt,
+    /// The geoid grid file.
+    pub grid: PathBuf,
+}
+
+impl NativeGeoid {
+    /// Everything the result depends on besides the source pixels and the
+    /// grid's heights.
+    pub fn recipe(&self) -> String {
+        format!("geoid {} + {}", self.input.display(), self.grid.display())
+    }
+
+    /// Adds the geoid heights to `input`, writing `output` (a VRT) and its
+    /// `.bin` pixel file on the same grid.
+    pub fn run(&self, output: &Path) -> Result<(), RowError> {
+        let grid = GeoidGrid::load(&self.grid)?;
+        self.check_grid(&grid)?;
+        let mut source = RawRows::open(&self.input, &self.raster)?;
+        let mut writer = RawWriter::create(output, self.raster.clone())?;
+        apply_geoid(&mut source, &self.raster.geo_transform, &grid, |row| {
+            writer.write_row(row)
+        })?;
+        writer.finish()
+    }
+
+    /// Refuses a grid in another CRS than the DEM, or not covering it.
+    fn check_grid(&self, grid: &GeoidGrid) -> Result<(), RowError> {
+        let grid_crs = raster::probe(&self.grid)
+            .ok()
+            .and_then(|info| info.crs)
+            .and_then(|d| CrsId::identify(&d));
+        let dem_crs = self.raster.crs.as_deref().and_then(CrsId::identify);
+        if let (Some(grid_crs), Some(dem_crs)) = (grid_crs, dem_crs) {
+            if grid_crs != dem_crs {
+                return Err(RowError::input(
+                    &self.grid,
+                    format!(
+                        "the geoid grid is in {}, the DEM in {}; warp the grid to the DEM's CRS",
+                        grid_crs, dem_crs
+                    ),
+                ));
+            }
+        }
+        let gt = &self.raster.geo_transform;
+        let first = (gt[0] + gt[1] / 2.0, gt[3] + gt[5] / 2.0);
+        let last = (
+            first.0 + (self.raster.width - 1) as f64 * gt[1],
+            first.1 + (self.raster.height - 1) as f64 * gt[5],
+        );
+        let centres = Extent::new(
+            first.0.min(last.0),
+            first.1.min(last.1),
+            first.0.max(last.0),
+            first.1.max(last.1),
+        );
+        let nodes = grid.node_extent();
+        let covered = nodes.min_x <= centres.min_x
+            && nodes.min_y <= centres.min_y
+            && nodes.max_x >= centres.max_x
+            && nodes.max_y >= centres.max_y;
+        if !covered {
+            return Err(RowError::input(
+                &self.grid,
+                format!(
+                    "the geoid grid spans {}, short of the DEM's pixel centres {}",
+                    nodes, centres
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Adds to every valid pixel of `source`, laid out by `transform`, the
+/// geoid height at its centre, handing each row to `sink` top row first.
+/// Pixels where the grid has no height become nodata.
+pub fn apply_geoid(
+    source: &mut dyn RowSource,
+    transform: &[f64; 6],
+    grid: &GeoidGrid,
+    mut sink: impl FnMut(&[f32]) -> Result<(), RowError>,
+) -> Result<(), RowError> {
+    let mut row = vec![0.0; source.width()];
+    for r in 0..source.height() {
+        source.read_row(&mut row)?;
+        let y = transform[3] + (r as f64 + 0.5) * transform[5];
+        for (c, value) in row.iter_mut().enumerate() {
+            if value.is_nan() {
+                continue;
+            }
+            let x = transform[0] + (c as f64 + 0.5) * transform[1];
+            *value = match grid.height_at(x, y) {
+                Some(n) => *value + n,
+                None => f32::NAN,
+            };
+        }
+        sink(&row)?;
+    }
+    Ok(())
+}
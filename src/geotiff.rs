@@ -0,0 +1,171 @@
+//! GeoTIFF semantics on top of [`crate::tiff`]: GeoKeys and the
+//! pixel-to-map transform.
+
+use std::collections::BTreeMap;
+
+use crate::tiff::{
+    Ifd, TAG_GEO_ASCII_PARAMS, TAG_GEO_DOUBLE_PARAMS, TAG_GEO_KEY_DIRECTORY, TAG_MODEL_PIXEL_SCALE,
+    TAG_MODEL_TIEPOINT, TAG_MODEL_TRANSFORMATION,
+};
+
+pub const KEY_GT_MODEL_TYPE: u16 = 1024;
+pub const KEY_GT_RASTER_TYPE: u16 = 1025;
+pub const KEY_GEOGRAPHIC_TYPE: u16 = 2048;
+pub const KEY_PROJECTED_CS_TYPE: u16 = 3072;
+pub const KEY_VERTICAL_CS_TYPE: u16 = 4096;
+
+pub const MODEL_TYPE_PROJECTED: u16 = 1;
+pub const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+pub const RASTER_PIXEL_IS_AREA: u16 = 1;
+pub const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+/// Value of a single GeoKey.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoKeyValue {
+    Short(u16),
+    Doubles(Vec<f64>),
+    Ascii(String),
+}
+
+/// Decoded GeoKeyDirectory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeoKeys {
+    pub keys: BTreeMap<u16, GeoKeyValue>,
+}
+
+impl GeoKeys {
+    /// Decodes the GeoKeyDirectory of `ifd`, if it has one.
+    pub fn from_ifd(ifd: &Ifd) -> Option<GeoKeys> {
+        let dir = ifd.integers(TAG_GEO_KEY_DIRECTORY)?;
+        if dir.len() < 4 {
+            return None;
+        }
+        let doubles = ifd.floats(TAG_GEO_DOUBLE_PARAMS).unwrap_or_default();
+        let ascii = ifd.ascii(TAG_GEO_ASCII_PARAMS).unwrap_or("");
+
+        let mut keys = BTreeMap::new();
+        for entry in dir[4..].chunks_exact(4).take(dir[3] as usize) {
+            let (key, location, count, value) = (
+                entry[0] as u16,
+                entry[1] as u16,
+                entry[2] as usize,
+                entry[3] as usize,
+            );
+            let decoded = match location {
+                0 => GeoKeyValue::Short(value as u16),
+                TAG_GEO_DOUBLE_PARAMS => match doubles.get(value..value + count) {
+                    Some(v) => GeoKeyValue::Doubles(v.to_vec()),
+                    None => continue,
+                },
+                TAG_GEO_ASCII_PARAMS => match ascii.get(value..value + count) {
+                    Some(s) => GeoKeyValue::Ascii(s.trim_end_matches(['|', '\0']).to_string()),
+                    None => continue,
+                },
+                TAG_GEO_KEY_DIRECTORY => match dir.get(value) {
+                    Some(&v) => GeoKeyValue::Short(v as u16),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            keys.insert(key, decoded);
+        }
+        Some(GeoKeys { keys })
+    }
+
+    pub fn short(&self, key: u16) -> Option<u16> {
+        match self.keys.get(&key)? {
+            GeoKeyValue::Short(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// EPSG code of the horizontal CRS, projected or geographic.
+    /// User-defined (32767) and undefined codes yield `None`.
+    pub fn epsg(&self) -> Option<u32> {
+        let code = match self.short(KEY_GT_MODEL_TYPE) {
+            Some(MODEL_TYPE_GEOGRAPHIC) => self.short(KEY_GEOGRAPHIC_TYPE)?,
+            _ => self
+                .short(KEY_PROJECTED_CS_TYPE)
+                .or_else(|| self.short(KEY_GEOGRAPHIC_TYPE))?,
+        };
+        (code != 0 && code != 32767).then_some(code as u32)
+    }
+
+    pub fn pixel_is_point(&self) -> bool {
+        self.short(KEY_GT_RASTER_TYPE) == Some(RASTER_PIXEL_IS_POINT)
+    }
+
+    /// Keys of a PixelIsArea raster in the CRS `EPSG:<code>`. Codes in the
+    /// 4000s are taken for geographic CRSs, as EPSG allocates them.
+    pub fn for_epsg(code: u32) -> GeoKeys {
+        let geographic = (4000..5000).contains(&code);
+        let (model, crs_key) = if geographic {
+            (MODEL_TYPE_GEOGRAPHIC, KEY_GEOGRAPHIC_TYPE)
+        } else {
+            (MODEL_TYPE_PROJECTED, KEY_PROJECTED_CS_TYPE)
+        };
+        let keys = [
+            (KEY_GT_MODEL_TYPE, model),
+            (KEY_GT_RASTER_TYPE, RASTER_PIXEL_IS_AREA),
+            // Codes past the GeoKey range can only be user-defined.
+            (crs_key, u16::try_from(code).unwrap_or(32767)),
+        ];
+        GeoKeys {
+            keys: keys
+                .into_iter()
+                .map(|(key, value)| (key, GeoKeyValue::Short(value)))
+                .collect(),
+        }
+    }
+
+    /// The GeoKeyDirectory tag holding the short-valued keys; keys stored
+    /// in the double or ASCII parameter tags are left out.
+    pub fn to_directory(&self) -> Vec<u16> {
+        let shorts: Vec<(u16, u16)> = self
+            .keys
+            .iter()
+            .filter_map(|(&key, value)| match value {
+                GeoKeyValue::Short(v) => Some((key, *v)),
+                _ => None,
+            })
+            .collect();
+        let mut directory = vec![1, 1, 0, shorts.len() as u16];
+        for (key, value) in shorts {
+            directory.extend([key, 0, 1, value]);
+        }
+        directory
+    }
+}
+
+/// GDAL-style geotransform of `ifd`, from ModelTransformation or from a
+/// tiepoint plus pixel scale. PixelIsPoint rasters are shifted by half a
+/// pixel so the transform always addresses pixel corners.
+pub fn geo_transform(ifd: &Ifd, keys: Option<&GeoKeys>) -> Option<[f64; 6]> {
+    let mut gt = if let Some(m) = ifd.floats(TAG_MODEL_TRANSFORMATION) {
+        if m.len() < 16 {
+            return None;
+        }
+        [m[3], m[0], m[1], m[7], m[4], m[5]]
+    } else {
+        let tie = ifd.floats(TAG_MODEL_TIEPOINT)?;
+        let scale = ifd.floats(TAG_MODEL_PIXEL_SCALE)?;
+        if tie.len() < 6 || scale.len() < 2 {
+            return None;
+        }
+        let (i, j, x, y) = (tie[0], tie[1], tie[3], tie[4]);
+        [
+            x - i * scale[0],
+            scale[0],
+            0.0,
+            y + j * scale[1],
+            0.0,
+            -scale[1],
+        ]
+    };
+
+    if keys.is_some_and(GeoKeys::pixel_is_point) {
+        gt[0] -= (gt[1] + gt[2]) / 2.0;
+        gt[3] -= (gt[4] + gt[5]) / 2.0;
+    }
+    Some(gt)
+}
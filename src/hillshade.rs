@@ -0,0 +1,145 @@
+//! Shaded relief of the DEM, computed natively from the written GeoTIFF
+//! the way `gdaldem hillshade` does: Horn's gradient, a light at `azimuth`
+//! and `altitude`, and brightness from 1 (in shadow) to 255 (facing the
+//! light), 0 being nodata.
+//!
+//! The multidirectional mode follows `gdaldem -multidirectional`: lights
+//! from the west, north-west, north and south-west, each weighted by how
+//! much the slope faces along its direction.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{GeoTiffOptions, HillshadeOptions};
+use crate::raster::DataType;
+use crate::rows::{RowError, RowSource};
+use crate::terrain::{for_each_row, gradient, Neighbourhood};
+use crate::tiff_rows::GeoTiffRows;
+use crate::tiff_writer::{options_recipe, GeoTiffImage, GeoTiffWriter};
+
+/// Light azimuths of the multidirectional mode, in degrees.
+const MULTIDIRECTIONAL_AZIMUTHS: [f64; 4] = [225.0, 270.0, 315.0, 360.0];
+
+/// Value of pixels without a height.
+pub const HILLSHADE_NODATA: f64 = 0.0;
+
+/// The hillshade of the DEM GeoTIFF, as planned.
+#[derive(Debug, Clone)]
+pub struct NativeHillshade {
+    /// The DEM GeoTIFF.
+    pub input: PathBuf,
+    pub options: HillshadeOptions,
+    pub geotiff: GeoTiffOptions,
+}
+
+impl NativeHillshade {
+    /// Everything the result depends on besides the DEM's pixels.
+    pub fn recipe(&self) -> String {
+        let o = &self.options;
+        format!(
+            "hillshade {} azimuth={} altitude={} z_factor={} multidirectional={} {}",
+            self.input.display(),
+            o.azimuth,
+            o.altitude,
+            o.z_factor,
+            o.multidirectional,
+            options_recipe(&self.geotiff)
+        )
+    }
+
+    /// Shades `input` into the `Byte` GeoTIFF `output`, on the DEM's grid.
+    pub fn run(&self, output: &Path) -> Result<(), RowError> {
+        let mut dem = GeoTiffRows::open(&self.input)?;
+        let gt = dem.geo_transform();
+        let image = GeoTiffImage {
+            width: dem.width(),
+            height: dem.height(),
+            geo_transform: gt,
+            crs: dem.crs().map(str::to_string),
+            nodata: HILLSHADE_NODATA,
+            data_type: DataType::Byte,
+        };
+        let mut writer = GeoTiffWriter::create(output, image, &self.geotiff)
+            .map_err(|e| RowError::io(output, e))?;
+        let light = Light::new(&self.options);
+        let (res_x, res_y) = (gt[1], -gt[5]);
+        let mut shaded = Vec::with_capacity(dem.width());
+        for_each_row(&mut dem, self.options.block_rows, |row| {
+            shaded.clear();
+            shaded.extend(row.iter().map(|z| match z {
+                Some(z) => light.shade(z, res_x, res_y),
+                None => f32::NAN,
+            }));
+            writer
+                .write_row(&shaded)
+                .map_err(|e| RowError::io(output, e))
+        })?;
+        writer.finish().map_err(|e| RowError::io(output, e))
+    }
+}
+
+/// The lighting of [`HillshadeOptions`], its trigonometry done once.
+#[derive(Debug, Clone)]
+pub struct Light {
+    z_factor: f64,
+    sin_altitude: f64,
+    /// `(azimuth, sin(azimuth) cos(altitude), cos(azimuth) cos(altitude))`
+    /// of each light.
+    lights: Vec<(f64, f64, f64)>,
+    multidirectional: bool,
+}
+
+impl Light {
+    pub fn new(options: &HillshadeOptions) -> Light {
+        let altitude = options.altitude.to_radians();
+        let azimuths = if options.multidirectional {
+            MULTIDIRECTIONAL_AZIMUTHS.to_vec()
+        } else {
+            vec![options.azimuth]
+        };
+        Light {
+            z_factor: options.z_factor,
+            sin_altitude: altitude.sin(),
+            lights: azimuths
+                .into_iter()
+                .map(|azimuth| {
+                    let azimuth = azimuth.to_radians();
+                    (
+                        azimuth,
+                        azimuth.sin() * altitude.cos(),
+                        azimuth.cos() * altitude.cos(),
+                    )
+                })
+                .collect(),
+            multidirectional: options.multidirectional,
+        }
+    }
+
+    /// Brightness of the pixel at the centre of `z`, from 1 to 255.
+    pub fn shade(&self, z: &Neighbourhood, res_x: f64, res_y: f64) -> f32 {
+        let (east, north) = gradient(z, res_x, res_y);
+        let (gx, gy) = (east * self.z_factor, north * self.z_factor);
+        let norm = (1.0 + gx * gx + gy * gy).sqrt();
+        // The surface normal (-gx, -gy, 1) against the direction of the
+        // light, both normalised.
+        let lit = |&(_, east, north): &(f64, f64, f64)| {
+            ((self.sin_altitude - gx * east - gy * north) / norm).max(0.0)
+        };
+        let steepness = gx * gx + gy * gy;
+        let brightness = if !self.multidirectional {
+            lit(&self.lights[0])
+        } else if steepness == 0.0 {
+            self.sin_altitude
+        } else {
+            // The four weights add up to twice the steepness.
+            self.lights
+                .iter()
+                .map(|light| {
+                    let along = gx * light.0.sin() + gy * light.0.cos();
+                    along * along / steepness * lit(light)
+                })
+                .sum::<f64>()
+                / 2.0
+        };
+        (1.0 + 254.0 * brightness) as f32
+    }
+}
@@ -0,0 +1,42 @@
+//! Input discovery: the tiles of a delivery, found recursively under its
+//! directory, and the selection narrowing them down for a build.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::aoi::Aoi;
+
+/// Which of the tiles found in an input directory a build uses.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// Only the tiles touching this area.
+    pub aoi: Option<Aoi>,
+    /// Only the tiles whose IGN dalle name puts their origin in this
+    /// range, `[min_x, min_y, max_x, max_y]` in kilometres.
+    pub dalles: Option<[u32; 4]>,
+}
+
+/// Recursively lists the files under `dir` whose extension matches
+/// `extension` (case-insensitively), sorted by path so builds are
+/// reproducible.
+pub fn find_inputs(dir: &Path, extension: &str) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir).follow_links(true) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let matches = entry
+            .path()
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case(extension));
+        if matches {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
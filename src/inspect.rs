@@ -0,0 +1,68 @@
+use std::path::{Path, PathBuf};
+
+use vrt_maker::asc::AscReader;
+use vrt_maker::inputs::find_inputs;
+use vrt_maker::PipelineError;
+
+/// Prints the header and value statistics of every `.asc` tile under
+/// `paths`, failing on the first malformed tile after listing the rest.
+pub fn inspect(paths: &[PathBuf]) -> Result<(), PipelineError> {
+    let mut tiles = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let found = find_inputs(path, "asc").map_err(|e| PipelineError::input(path, e))?;
+            tiles.extend(found);
+        } else {
+            tiles.push(path.clone());
+        }
+    }
+
+    let mut first_error = None;
+    for tile in &tiles {
+        if let Err(e) = inspect_tile(tile) {
+            println!("{}: INVALID {}", tile.display(), e);
+            first_error.get_or_insert(PipelineError::input(tile, e));
+        }
+    }
+    println!("{} tile(s) inspected", tiles.len());
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn inspect_tile(path: &Path) -> Result<(), vrt_maker::asc::AscError> {
+    let reader = AscReader::open(path)?;
+    let header = *reader.header();
+    let stats = reader.statistics()?;
+
+    let nodata = header
+        .nodata
+        .map_or_else(|| "none".to_string(), |v| v.to_string());
+    println!(
+        "{}: {}x{} cells of {}x{} from ({}, {}) to ({}, {}), nodata {}",
+        path.display(),
+        header.ncols,
+        header.nrows,
+        header.cell_x,
+        header.cell_y,
+        header.x_min(),
+        header.y_min(),
+        header.x_max(),
+        header.y_max(),
+        nodata
+    );
+    if stats.valid > 0 {
+        println!(
+            "    min {} max {} mean {:.3}, {:.2}% nodata",
+            stats.min,
+            stats.max,
+            stats.mean,
+            stats.nodata_ratio() * 100.0
+        );
+    } else {
+        println!("    no valid cells");
+    }
+    Ok(())
+}
@@ -0,0 +1,369 @@
+//! JPEG 2000 (`.jp2`) box reader, enough to learn a tile's size and
+//! georeferencing without decoding the codestream.
+//!
+//! Georeferencing is looked up, in order, in a GeoJP2 `uuid` box (an
+//! embedded degenerate GeoTIFF), a GMLJP2 `asoc` box and finally a world
+//! file (`.j2w`, `.jpw` or `.wld`) next to the image.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::geotiff::{self, GeoKeys};
+use crate::raster::{read_prj_sidecar, DataType, RasterInfo};
+use crate::tiff::TiffReader;
+
+const GEOJP2_UUID: [u8; 16] = [
+    0xb1, 0x4b, 0xf8, 0xbd, 0x08, 0x3d, 0x4b, 0x43, 0xa5, 0xae, 0x8c, 0xd7, 0xd5, 0xa6, 0xce, 0x03,
+];
+
+/// Largest metadata box read into memory; codestreams are skipped.
+const MAX_METADATA_BOX: u64 = 16 << 20;
+
+/// Callback receiving each leaf box type and payload.
+type BoxVisitor<'a> = dyn FnMut([u8; 4], &[u8]) -> Result<(), Jp2Error> + 'a;
+
+#[derive(Debug)]
+pub enum Jp2Error {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for Jp2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Jp2Error::Io(e) => write!(f, "{}", e),
+            Jp2Error::Format(msg) => write!(f, "malformed JP2: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Jp2Error {}
+
+impl From<io::Error> for Jp2Error {
+    fn from(e: io::Error) -> Self {
+        Jp2Error::Io(e)
+    }
+}
+
+/// Image header (`ihdr`) fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub components: u16,
+    /// Bit depth of the first component.
+    pub bits: u8,
+    pub signed: bool,
+}
+
+impl ImageHeader {
+    pub fn data_type(&self) -> DataType {
+        match (self.bits, self.signed) {
+            (0..=8, _) => DataType::Byte,
+            (9..=16, false) => DataType::UInt16,
+            (9..=16, true) => DataType::Int16,
+            (_, false) => DataType::UInt32,
+            (_, true) => DataType::Int32,
+        }
+    }
+}
+
+/// Georeferencing found inside a JP2 file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Georeference {
+    pub geo_transform: [f64; 6],
+    /// `EPSG:<code>` when the file names its CRS.
+    pub crs: Option<String>,
+}
+
+/// Metadata boxes of interest in a JP2 file.
+#[derive(Debug, Clone, Default)]
+pub struct Jp2Metadata {
+    pub header: Option<ImageHeader>,
+    /// Georeferencing from the GeoJP2 box.
+    pub geojp2: Option<Georeference>,
+    /// Georeferencing from the GMLJP2 box.
+    pub gml: Option<Georeference>,
+}
+
+impl Jp2Metadata {
+    pub fn read(path: &Path) -> Result<Jp2Metadata, Jp2Error> {
+        let mut file = BufReader::new(File::open(path)?);
+        let end = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut meta = Jp2Metadata::default();
+        let mut signature = false;
+        walk_boxes(&mut file, end, &mut |kind, payload| {
+            match &kind {
+                b"jP  " => signature = true,
+                b"ihdr" => meta.header = Some(parse_ihdr(payload)?),
+                b"uuid" if payload.starts_with(&GEOJP2_UUID) => {
+                    meta.geojp2 = parse_geojp2(&payload[16..])
+                }
+                b"xml " => {
+                    let xml = String::from_utf8_lossy(payload);
+                    if xml.contains("RectifiedGrid") && meta.gml.is_none() {
+                        meta.gml = parse_gml(&xml);
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+
+        if !signature {
+            return Err(Jp2Error::Format("missing JP2 signature box".to_string()));
+        }
+        Ok(meta)
+    }
+}
+
+/// Size, bands and georeferencing of a `.jp2` tile.
+pub fn raster_info(path: &Path) -> Result<RasterInfo, Jp2Error> {
+    let meta = Jp2Metadata::read(path)?;
+    let header = meta
+        .header
+        .ok_or_else(|| Jp2Error::Format("missing image header box".to_string()))?;
+
+    let (geo_transform, mut crs) = match meta.geojp2.or(meta.gml) {
+        Some(georef) => (georef.geo_transform, georef.crs),
+        None => match read_world_file(path)? {
+            Some(gt) => (gt, None),
+            None => {
+                return Err(Jp2Error::Format(
+                    "no GeoJP2, GMLJP2 or world file georeferencing".to_string(),
+                ))
+            }
+        },
+    };
+    if crs.is_none() {
+        crs = read_prj_sidecar(path)?;
+    }
+
+    Ok(RasterInfo {
+        path: path.to_path_buf(),
+        width: header.width as usize,
+        height: header.height as usize,
+        bands: header.components as usize,
+        data_type: header.data_type(),
+        geo_transform,
+        nodata: None,
+        crs,
+    })
+}
+
+/// Visits every box in `[pos, end)`, descending into superboxes. Payloads
+/// larger than [`MAX_METADATA_BOX`] (the codestream) are skipped unread.
+fn walk_boxes<R: Read + Seek>(
+    reader: &mut R,
+    end: u64,
+    visit: &mut BoxVisitor<'_>,
+) -> Result<(), Jp2Error> {
+    let mut pos = reader.stream_position()?;
+    while pos + 8 <= end {
+        let mut head = [0u8; 8];
+        reader.read_exact(&mut head)?;
+        let mut length = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as u64;
+        let kind = [head[4], head[5], head[6], head[7]];
+        let mut header_len = 8;
+        if length == 1 {
+            let mut xl = [0u8; 8];
+            reader.read_exact(&mut xl)?;
+            length = u64::from_be_bytes(xl);
+            header_len = 16;
+        } else if length == 0 {
+            length = end - pos;
+        }
+        if length < header_len || pos.checked_add(length).is_none_or(|e| e > end) {
+            return Err(Jp2Error::Format(format!(
+                "box {:?} overruns its container",
+                String::from_utf8_lossy(&kind)
+            )));
+        }
+        let payload_len = length - header_len;
+
+        match &kind {
+            b"jp2h" | b"asoc" | b"res " => {
+                walk_boxes(&mut *reader, pos + length, visit)?;
+            }
+            _ if payload_len <= MAX_METADATA_BOX => {
+                let mut payload = vec![0u8; payload_len as usize];
+                reader.read_exact(&mut payload)?;
+                visit(kind, &payload)?;
+            }
+            _ => {}
+        }
+        pos += length;
+        reader.seek(SeekFrom::Start(pos))?;
+    }
+    Ok(())
+}
+
+fn parse_ihdr(payload: &[u8]) -> Result<ImageHeader, Jp2Error> {
+    if payload.len() < 14 {
+        return Err(Jp2Error::Format("short image header box".to_string()));
+    }
+    let bpc = payload[10];
+    Ok(ImageHeader {
+        height: u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]),
+        width: u32::from_be_bytes([payload[4], payload[5], payload[6], payload[7]]),
+        components: u16::from_be_bytes([payload[8], payload[9]]),
+        // 255 means per-component depths in a bpcc box; 8 bits is by far
+        // the common case for orthophotos.
+        bits: if bpc == 255 { 8 } else { (bpc & 0x7f) + 1 },
+        signed: bpc != 255 && bpc & 0x80 != 0,
+    })
+}
+
+fn parse_geojp2(tiff: &[u8]) -> Option<Georeference> {
+    let mut reader = TiffReader::new(Cursor::new(tiff)).ok()?;
+    let ifd = reader.read_ifds().ok()?.into_iter().next()?;
+    let keys = GeoKeys::from_ifd(&ifd);
+    let geo_transform = geotiff::geo_transform(&ifd, keys.as_ref())?;
+    Some(Georeference {
+        geo_transform,
+        crs: keys
+            .as_ref()
+            .and_then(GeoKeys::epsg)
+            .map(|code| format!("EPSG:{}", code)),
+    })
+}
+
+/// Reads the `RectifiedGrid` of a GMLJP2 root instance. The GML origin is
+/// the centre of the top-left pixel.
+fn parse_gml(xml: &str) -> Option<Georeference> {
+    let grid = &xml[xml.find("RectifiedGrid")?..];
+    let origin_block = element_text(grid, "origin")?;
+    let origin = element_text(origin_block, "pos")
+        .or_else(|| element_text(origin_block, "coordinates"))
+        .and_then(parse_pair)?;
+
+    let mut vectors = Vec::new();
+    let mut rest = grid;
+    while let Some(start) = find_element(rest, "offsetVector") {
+        rest = &rest[start..];
+        vectors.push(element_text(rest, "offsetVector").and_then(parse_pair)?);
+        rest = &rest[1..];
+    }
+    if vectors.len() < 2 {
+        return None;
+    }
+    let (dx, rx) = vectors[0];
+    let (ry, dy) = vectors[1];
+
+    let srs = attribute(grid, "srsName").or_else(|| attribute(xml, "srsName"));
+    Some(Georeference {
+        geo_transform: [
+            origin.0 - (dx + ry) / 2.0,
+            dx,
+            ry,
+            origin.1 - (rx + dy) / 2.0,
+            rx,
+            dy,
+        ],
+        crs: srs
+            .and_then(epsg_from_urn)
+            .map(|code| format!("EPSG:{}", code)),
+    })
+}
+
+/// Extracts the EPSG code from `EPSG:2154`, `urn:ogc:def:crs:EPSG::2154`
+/// or `http://www.opengis.net/def/crs/EPSG/0/2154`.
+pub fn epsg_from_urn(name: &str) -> Option<u32> {
+    let upper = name.to_ascii_uppercase();
+    if !upper.contains("EPSG") {
+        return None;
+    }
+    let digits: String = name
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    digits.parse().ok()
+}
+
+/// Byte offset of the first `<name` or `<prefix:name` start tag.
+fn find_element(xml: &str, name: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(i) = xml[from..].find('<') {
+        let at = from + i;
+        let tag = &xml[at + 1..];
+        let local = match tag.find(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            Some(end) => &tag[..end],
+            None => tag,
+        };
+        let local = local.rsplit(':').next().unwrap_or(local);
+        if local == name {
+            return Some(at);
+        }
+        from = at + 1;
+    }
+    None
+}
+
+/// Text between the first `<name>` start tag and its matching end tag.
+fn element_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = find_element(xml, name)?;
+    let open_end = start + xml[start..].find('>')? + 1;
+    let body = &xml[open_end..];
+    let mut from = 0;
+    while let Some(i) = body[from..].find("</") {
+        let at = from + i;
+        let tag = &body[at + 2..];
+        let end = tag.find('>')?;
+        let local = tag[..end].trim();
+        if local.rsplit(':').next() == Some(name) {
+            return Some(&body[..at]);
+        }
+        from = at + 2;
+    }
+    None
+}
+
+fn attribute<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{}=\"", name);
+    let start = xml.find(&key)? + key.len();
+    let end = xml[start..].find('"')?;
+    Some(&xml[start..start + end])
+}
+
+fn parse_pair(text: &str) -> Option<(f64, f64)> {
+    let mut values = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<f64>());
+    Some((values.next()?.ok()?, values.next()?.ok()?))
+}
+
+/// Reads an ESRI world file next to `path`. World files give the centre of
+/// the top-left pixel.
+pub fn read_world_file(path: &Path) -> io::Result<Option<[f64; 6]>> {
+    for extension in ["j2w", "J2W", "jpw", "JPW", "wld", "WLD"] {
+        let candidate: PathBuf = path.with_extension(extension);
+        if !candidate.is_file() {
+            continue;
+        }
+        let text = fs::read_to_string(&candidate)?;
+        let values: Vec<f64> = text
+            .split_whitespace()
+            .map_while(|v| v.parse().ok())
+            .collect();
+        if values.len() < 6 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has fewer than 6 values", candidate.display()),
+            ));
+        }
+        let [a, d, b, e, c, f] = [
+            values[0], values[1], values[2], values[3], values[4], values[5],
+        ];
+        return Ok(Some([c - (a + b) / 2.0, a, b, f - (d + e) / 2.0, d, e]));
+    }
+    Ok(None)
+}
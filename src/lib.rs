@@ -0,0 +1,48 @@
+//! Builds orthophoto and DEM GeoTIFFs from tiled JP2 and ESRI ASCII grid
+//! deliveries by driving the GDAL command-line tools.
+//!
+//! The [`Pipeline`] builder runs the whole build; the individual stages
+//! ([`build_ortho_vrt`], [`build_dem_vrt`], [`resize_and_convert`]) are
+//! exposed for callers that need finer control.
+
+pub mod align;
+pub mod aoi;
+pub mod asc;
+pub mod cog;
+pub mod config;
+pub mod contours;
+pub mod coverage;
+pub mod crs;
+pub mod dalle;
+pub mod derivatives;
+pub mod doctor;
+pub mod error;
+pub mod fill;
+pub mod gdal;
+pub mod geo;
+pub mod geoid;
+pub mod geotiff;
+pub mod hillshade;
+pub mod inputs;
+pub mod jp2;
+pub mod manifest;
+pub mod pipeline;
+pub mod plan;
+pub mod raster;
+pub mod resample;
+pub mod rows;
+pub mod terrain;
+pub mod tiff;
+pub mod tiff_rows;
+pub mod tiff_writer;
+pub mod vrt;
+
+pub use config::Config;
+pub use error::{PipelineError, Stage};
+pub use gdal::{RecordingRunner, SystemRunner, ToolCommand, ToolRunner};
+pub use pipeline::{
+    build_dem_vrt, build_ortho_vrt, plan_contours, plan_convert, plan_dem_vrt, plan_hillshade,
+    plan_ortho_vrt, plan_terrain, plan_warp, resize_and_convert, BuildOutputs, Pipeline,
+    PipelineBuilder, Stages,
+};
+pub use plan::{Plan, Step};
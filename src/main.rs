@@ -1,101 +1,185 @@
-use std::fs;
-use std::path::Path;
-use std::process::Command;
+mod cli;
+mod inspect;
 
-fn ensure_directories() -> std::io::Result<()> {
-    fs::create_dir_all("out/tmp")?;
-    Ok(())
-}
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+
+use vrt_maker::{cog, doctor, Pipeline, PipelineError, Plan};
+
+use cli::{Cli, Command};
 
-fn cleanup_vrts() -> std::io::Result<()> {
-    let tmp_dir = Path::new("out/tmp");
-    if tmp_dir.exists() {
-        for entry in fs::read_dir(tmp_dir)? {
-            let entry = entry?;
-            if entry.path().extension().unwrap_or_default() == "vrt" {
-                fs::remove_file(entry.path())?;
+fn run(cli: &Cli) -> Result<(), PipelineError> {
+    if let Command::Inspect(args) = &cli.command {
+        return inspect::inspect(&args.paths);
+    }
+    if let Command::ValidateCog(args) = &cli.command {
+        return validate_cog(&args.paths);
+    }
+    if let Command::Doctor = &cli.command {
+        let report = doctor::check_all();
+        if !report.is_ok() {
+            return Err(PipelineError::Preflight(report));
+        }
+        print!("{}", report);
+        println!("All checks passed.");
+        return Ok(());
+    }
+    if let Some((args, stages)) = cli.command.build() {
+        let pipeline = Pipeline::builder()
+            .config(args.resolve()?)
+            .stages(stages)
+            .force(args.force)
+            .build()?;
+        if args.dry_run {
+            let plan = pipeline.plan()?;
+            print_plan(&plan, &pipeline.up_to_date(&plan)?);
+        } else {
+            let outputs = pipeline.run()?;
+            for coverage in outputs.coverage.iter().filter(|c| c.has_problems()) {
+                eprint!("warning: {}", coverage);
             }
+            for mismatch in &outputs.name_mismatches {
+                eprintln!("warning: tile name mismatch: {}", mismatch);
+            }
+            if let Some(alignment) = outputs.alignment.filter(|a| a.crops()) {
+                eprintln!("warning: {}", alignment);
+            }
+            println!(
+                "{} step(s) run, {} up to date",
+                outputs.executed, outputs.skipped
+            );
         }
     }
     Ok(())
 }
 
-fn build_ortho_vrt() -> bool {
-    Command::new("sh")
-        .arg("-c")
-        .arg("gdalbuildvrt -resolution highest out/tmp/mosaic.vrt data/jp2/*.jp2")
-        .status()
-        .map(|status| status.success())
-        .unwrap_or(false)
-}
-
-fn build_dem_vrt() -> bool {
-    let commands = [
-        "gdalbuildvrt -resolution highest out/tmp/temp_dem.vrt data/asc/*.asc",
-        "gdal_fillnodata -md 200 -si 1 out/tmp/temp_dem.vrt out/tmp/temp_filled_dem.vrt",
-        "gdalwarp -tr 0.2 0.2 -r cubicspline -dstnodata 0 -wo NUM_THREADS=ALL_CPUS out/tmp/temp_filled_dem.vrt out/tmp/dem.vrt"
-    ];
-
-    for cmd in &commands {
-        let success = Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
-            .status()
-            .map(|status| status.success())
-            .unwrap_or(false);
-
-        if !success {
-            eprintln!("Command failed: {}", cmd);
-            return false;
+/// Prints the layout report of every file, failing on the first one that
+/// is not a COG after checking the rest.
+fn validate_cog(paths: &[PathBuf]) -> Result<(), PipelineError> {
+    let mut first_error = None;
+    for path in paths {
+        match cog::validate(path) {
+            Ok(report) => {
+                print!("{}", report);
+                if let Some(error) = report.errors.first() {
+                    let message = format!("not a Cloud Optimized GeoTIFF: {}", error);
+                    first_error.get_or_insert(PipelineError::input(path, message));
+                }
+            }
+            Err(e) => {
+                println!("{}: INVALID {}", path.display(), e);
+                first_error.get_or_insert(PipelineError::input(path, e));
+            }
         }
     }
-    true
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
 
-fn resize_and_convert() -> bool {
-    let commands = [
-        "gdal_translate -of GTiff out/tmp/mosaic.vrt out/orthophoto.tiff",
-        "gdal_translate -of GTiff -a_nodata 0 out/tmp/dem.vrt out/dem.tiff",
-    ];
-
-    for cmd in &commands {
-        let success = Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
-            .status()
-            .map(|status| status.success())
-            .unwrap_or(false);
-
-        if !success {
-            eprintln!("Command failed: {}", cmd);
-            return false;
+fn print_plan(plan: &Plan, up_to_date: &[bool]) {
+    println!("Dry run: nothing will be executed.");
+    let mut step_index = 0;
+    for stage in &plan.stages {
+        if stage.inputs.is_empty() && stage.steps.is_empty() {
+            continue;
+        }
+        println!();
+        println!("[{}]", stage.stage);
+        match (stage.inputs.len(), stage.excluded.len()) {
+            (0, _) => {}
+            (used, 0) => println!("  {} input tile(s)", used),
+            (used, excluded) => println!(
+                "  {} input tile(s), {} more left out by the selection",
+                used, excluded
+            ),
+        }
+        for mismatch in &stage.name_mismatches {
+            println!("  name mismatch: {}", mismatch);
+        }
+        if let Some(crs) = &stage.crs {
+            match crs.distinct().as_slice() {
+                [] => println!("  CRS unknown"),
+                [one] => println!("  CRS {}", one),
+                many => {
+                    let list: Vec<String> = many.iter().map(ToString::to_string).collect();
+                    println!("  CRS {} (reprojected where needed)", list.join(", "));
+                }
+            }
+        }
+        match &stage.coverage {
+            Some(coverage) if coverage.has_problems() => {
+                for line in coverage.to_string().lines() {
+                    println!("  {}", line);
+                }
+            }
+            Some(coverage) => println!(
+                "  complete {} x {} tile grid",
+                coverage.columns, coverage.rows
+            ),
+            None => {}
+        }
+        for step in &stage.steps {
+            let fresh = up_to_date.get(step_index).copied().unwrap_or(false);
+            step_index += 1;
+            if fresh {
+                println!("  $ {}  (up to date, skipped)", step);
+            } else {
+                println!("  $ {}", step);
+            }
         }
     }
-    true
-}
 
-fn main() {
-    if let Err(e) = ensure_directories() {
-        eprintln!("Failed to create directories: {}", e);
-        return;
+    if let Some(alignment) = &plan.alignment {
+        println!();
+        println!("Extent: {}", alignment);
     }
 
-    if let Err(e) = cleanup_vrts() {
-        eprintln!("Failed to cleanup VRTs: {}", e);
-        return;
+    println!();
+    println!("Intermediate files:");
+    for path in plan.intermediates() {
+        println!("  {}", path.display());
     }
 
-    if !build_ortho_vrt() {
-        eprintln!("Failed to build orthophoto VRT");
-        return;
+    println!();
+    println!("Outputs:");
+    for path in &plan.outputs {
+        match plan.estimates.iter().find(|e| &e.path == path) {
+            Some(e) => println!(
+                "  {}: {} x {} px, {} band(s) {}, ~{} uncompressed",
+                path.display(),
+                e.width,
+                e.height,
+                e.bands,
+                e.data_type.as_gdal(),
+                human_size(e.bytes())
+            ),
+            None => println!("  {}: size unknown", path.display()),
+        }
     }
+}
 
-    if !build_dem_vrt() {
-        eprintln!("Failed to process DEM VRT");
-        return;
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
     }
+    format!("{:.1} {}", size, UNITS[unit])
+}
 
-    if !resize_and_convert() {
-        eprintln!("Failed to create final TIFFs");
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
     }
 }
@@ -0,0 +1,216 @@
+//! Build manifest used to skip steps whose inputs and settings have not
+//! changed since the previous run.
+//!
+//! Every input file is fingerprinted by size, modification time and
+//! SHA-256; the hash is only recomputed when size or mtime moved. A step's
+//! fingerprint combines its recipe (command line or VRT document) with the
+//! fingerprints of its inputs, so a change anywhere upstream propagates to
+//! every dependent step.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{PipelineError, Stage};
+use crate::plan::{Plan, Step};
+
+/// Manifest file name inside the output directory.
+pub const MANIFEST_FILE: &str = ".vrt_maker-manifest.toml";
+
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    #[serde(default)]
+    pub files: Vec<FileRecord>,
+    #[serde(default)]
+    pub steps: Vec<StepRecord>,
+}
+
+/// Fingerprint of an input file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified_secs: u64,
+    pub modified_nanos: u32,
+    pub sha256: String,
+}
+
+/// Fingerprint of a completed step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub stage: String,
+    pub output: PathBuf,
+    /// The command as run, for humans reading the manifest.
+    pub command: String,
+    pub fingerprint: String,
+}
+
+/// Decides which steps of a plan need to run and records completed ones.
+pub struct Tracker {
+    path: PathBuf,
+    force: bool,
+    previous: Manifest,
+    current: Manifest,
+    /// Files fingerprinted during this run.
+    checked: HashSet<PathBuf>,
+}
+
+impl Tracker {
+    /// Loads the manifest of `out_dir`. A missing, unreadable or outdated
+    /// manifest simply makes every step stale. With `force`, the previous
+    /// manifest is ignored altogether.
+    pub fn load(out_dir: &Path, force: bool) -> Tracker {
+        let path = out_dir.join(MANIFEST_FILE);
+        let previous = if force {
+            Manifest::default()
+        } else {
+            fs::read_to_string(&path)
+                .ok()
+                .and_then(|text| toml::from_str::<Manifest>(&text).ok())
+                .filter(|m| m.version == MANIFEST_VERSION)
+                .unwrap_or_default()
+        };
+        let current = Manifest {
+            version: MANIFEST_VERSION,
+            ..previous.clone()
+        };
+        Tracker {
+            path,
+            force,
+            previous,
+            current,
+            checked: HashSet::new(),
+        }
+    }
+
+    /// Fingerprints every step of `plan`, in plan order.
+    pub fn fingerprints(&mut self, plan: &Plan) -> Result<Vec<String>, PipelineError> {
+        let mut produced: HashMap<&Path, String> = HashMap::new();
+        let mut fingerprints = Vec::new();
+
+        for step in plan.steps() {
+            let mut hasher = Sha256::new();
+            hasher.update(step.recipe().as_bytes());
+            for input in &step.inputs {
+                hasher.update(b"\n");
+                match produced.get(input.as_path()) {
+                    Some(upstream) => hasher.update(format!("step {}", upstream).as_bytes()),
+                    None => {
+                        let record = self.file_record(input)?;
+                        hasher.update(format!("file {}", record.sha256).as_bytes());
+                    }
+                }
+            }
+            let fingerprint = hex(&hasher.finalize());
+            produced.insert(&step.output, fingerprint.clone());
+            fingerprints.push(fingerprint);
+        }
+        Ok(fingerprints)
+    }
+
+    /// Whether `step` already produced its output with this fingerprint.
+    pub fn is_fresh(&self, step: &Step, fingerprint: &str) -> bool {
+        !self.force
+            && step.output.exists()
+            && self
+                .previous
+                .steps
+                .iter()
+                .any(|r| r.output == step.output && r.fingerprint == fingerprint)
+    }
+
+    /// Records a successful step and writes the manifest to disk.
+    pub fn record(&mut self, step: &Step, fingerprint: &str) -> Result<(), PipelineError> {
+        self.current.steps.retain(|r| r.output != step.output);
+        self.current.steps.push(StepRecord {
+            stage: step.stage.to_string(),
+            output: step.output.clone(),
+            command: step.to_string(),
+            fingerprint: fingerprint.to_string(),
+        });
+        self.save()
+    }
+
+    pub fn save(&self) -> Result<(), PipelineError> {
+        let text = toml::to_string(&self.current).map_err(|e| {
+            PipelineError::io(
+                Stage::Prepare,
+                "failed to encode the build manifest",
+                io::Error::other(e),
+            )
+        })?;
+        fs::write(&self.path, text).map_err(|e| {
+            PipelineError::io(
+                Stage::Prepare,
+                format!("failed to write {}", self.path.display()),
+                e,
+            )
+        })
+    }
+
+    fn file_record(&mut self, path: &Path) -> Result<FileRecord, PipelineError> {
+        if self.checked.contains(path) {
+            if let Some(record) = self.current.files.iter().find(|r| r.path == path) {
+                return Ok(record.clone());
+            }
+        }
+
+        let metadata = fs::metadata(path).map_err(|e| PipelineError::input(path, e))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        let (size, modified_secs, modified_nanos) =
+            (metadata.len(), modified.as_secs(), modified.subsec_nanos());
+
+        let cached = self.previous.files.iter().find(|r| {
+            r.path == path
+                && r.size == size
+                && r.modified_secs == modified_secs
+                && r.modified_nanos == modified_nanos
+        });
+        let sha256 = match cached {
+            Some(record) => record.sha256.clone(),
+            None => hash_file(path).map_err(|e| PipelineError::input(path, e))?,
+        };
+
+        let record = FileRecord {
+            path: path.to_path_buf(),
+            size,
+            modified_secs,
+            modified_nanos,
+            sha256,
+        };
+        self.current.files.retain(|r| r.path != path);
+        self.current.files.push(record.clone());
+        self.checked.insert(path.to_path_buf());
+        Ok(record)
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,1544 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::align::{align, Alignment, Grid, Layer, NoOverlap};
+use crate::aoi::Aoi;
+use crate::config::{
+    Compression, Config, ConfigError, ContourFormat, ContourOptions, CoverageOptions, CrsOptions,
+    DemOptions, ExtentOptions, FillBackend, FillOptions, GeoTiffBackend, GeoTiffOptions,
+    HillshadeOptions, OrthoOptions, Predictor, Resampling, Resolution, TerrainOptions, TiffFormat,
+    VerticalOptions, VrtBackend, VrtOptions, WarpBackend, WarpOptions,
+};
+use crate::contours::NativeContours;
+use crate::coverage::Coverage;
+use crate::crs::{self, CrsId, CrsReport, METRES_PER_DEGREE};
+use crate::dalle::{check_names, DalleName, NameMismatch};
+use crate::derivatives::NativeDerivative;
+use crate::doctor;
+use crate::error::{PipelineError, Stage};
+use crate::fill::NativeFill;
+use crate::gdal::{SystemRunner, ToolCommand, ToolRunner};
+use crate::geo::Extent;
+use crate::geoid::NativeGeoid;
+use crate::hillshade::NativeHillshade;
+use crate::inputs::{find_inputs, Selection};
+use crate::manifest::Tracker;
+use crate::plan::{Action, Plan, RasterEstimate, StagePlan, Step};
+use crate::raster::{self, DataType};
+use crate::resample::NativeResample;
+use crate::tiff_writer::NativeGeoTiff;
+use crate::vrt::{Mosaic, RawVrt, VrtError};
+
+/// Which products a [`Pipeline`] builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stages {
+    pub ortho: bool,
+    pub dem: bool,
+}
+
+impl Stages {
+    pub const ALL: Stages = Stages {
+        ortho: true,
+        dem: true,
+    };
+    pub const ORTHO: Stages = Stages {
+        ortho: true,
+        dem: false,
+    };
+    pub const DEM: Stages = Stages {
+        ortho: false,
+        dem: true,
+    };
+}
+
+impl Default for Stages {
+    fn default() -> Self {
+        Stages::ALL
+    }
+}
+
+/// Files produced by a successful [`Pipeline::run`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildOutputs {
+    pub ortho: Option<PathBuf>,
+    pub dem: Option<PathBuf>,
+    pub hillshade: Option<PathBuf>,
+    /// Terrain derivatives, in `terrain.derivatives` order.
+    pub derivatives: Vec<PathBuf>,
+    /// Contour files, in `contours.formats` order.
+    pub contours: Vec<PathBuf>,
+    /// Steps executed during the run.
+    pub executed: usize,
+    /// Steps skipped because the manifest showed them up to date.
+    pub skipped: usize,
+    /// Tile coverage of the stages whose tiles could be read.
+    pub coverage: Vec<Coverage>,
+    /// Bounds both outputs were cropped to, if any.
+    pub alignment: Option<Alignment>,
+    /// Tiles whose IGN dalle name disagrees with their header.
+    pub name_mismatches: Vec<NameMismatch>,
+}
+
+impl BuildOutputs {
+    fn new(config: &Config, stages: Stages) -> Self {
+        BuildOutputs {
+            ortho: stages.ortho.then(|| config.outputs.ortho_path()),
+            dem: stages.dem.then(|| config.outputs.dem_path()),
+            hillshade: (stages.dem && config.hillshade.enabled)
+                .then(|| config.outputs.hillshade_path()),
+            derivatives: match stages.dem {
+                true => config
+                    .terrain
+                    .derivatives
+                    .iter()
+                    .map(|&d| config.outputs.derivative_path(d))
+                    .collect(),
+                false => Vec::new(),
+            },
+            contours: match stages.dem && config.contours.enabled {
+                true => config
+                    .contours
+                    .formats
+                    .iter()
+                    .map(|&f| config.outputs.contours_path(f))
+                    .collect(),
+                false => Vec::new(),
+            },
+            executed: 0,
+            skipped: 0,
+            coverage: Vec::new(),
+            alignment: None,
+            name_mismatches: Vec::new(),
+        }
+    }
+}
+
+/// A configured orthophoto/DEM build.
+///
+/// ```no_run
+/// use vrt_maker::{Pipeline, Stages};
+///
+/// let outputs = Pipeline::builder()
+///     .asc_dir("site/asc")
+///     .out_dir("site/out")
+///     .stages(Stages::DEM)
+///     .build()?
+///     .run()?;
+/// println!("DEM written to {:?}", outputs.dem);
+/// # Ok::<(), vrt_maker::PipelineError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    config: Config,
+    stages: Stages,
+    force: bool,
+}
+
+impl Pipeline {
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+
+    /// Wraps an already loaded project file, validating it first.
+    pub fn from_config(config: Config, stages: Stages) -> Result<Pipeline, PipelineError> {
+        config.validate()?;
+        Ok(Pipeline {
+            config,
+            stages,
+            force: false,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn stages(&self) -> Stages {
+        self.stages
+    }
+
+    /// Resolves inputs and lists every step of the build without
+    /// executing anything.
+    pub fn plan(&self) -> Result<Plan, PipelineError> {
+        let config = &self.config;
+        let tmp_dir = config.outputs.tmp_dir();
+        let mut plan = Plan::default();
+        let selection = Selection {
+            aoi: config.extent.aoi.as_deref().map(Aoi::load).transpose()?,
+            dalles: config.inputs.tiles,
+        };
+        let aoi = selection.aoi.as_ref();
+
+        let mut ortho_layer = None;
+        let mut dem_layer = None;
+        let mut dem_degrees = None;
+        if self.stages.ortho {
+            let ortho = plan_ortho_vrt(
+                &config.inputs.jp2_dir,
+                &tmp_dir,
+                &config.vrt,
+                &config.crs,
+                &selection,
+            )?;
+            ortho_layer = ortho
+                .mosaic
+                .as_ref()
+                .map(|m| (ortho_output(m, &config.ortho), m.bands, m.data_type));
+            plan.stages.push(ortho);
+        }
+        if self.stages.dem {
+            let dem = plan_dem_vrt(
+                &config.inputs.asc_dir,
+                &tmp_dir,
+                &config.vrt,
+                &config.crs,
+                &selection,
+                &config.dem,
+            )?;
+            dem_layer = dem.mosaic.as_ref().map(|m| dem_output(m, &config.dem));
+            dem_degrees = geographic_dem(config, dem.mosaic.as_ref());
+            plan.stages.push(dem);
+        }
+
+        // Each stage checked its own tiles; the two products must also
+        // agree with each other.
+        if config.crs.inputs.is_none() {
+            let all = plan
+                .stages
+                .iter()
+                .filter_map(|s| s.crs.as_ref())
+                .fold(CrsReport::default(), CrsReport::merge);
+            if !all.is_consistent() {
+                return Err(PipelineError::MixedCrs(all));
+            }
+        }
+
+        if config.coverage.require_complete {
+            let incomplete = plan
+                .stages
+                .iter()
+                .filter_map(|s| s.coverage.as_ref())
+                .find(|c| !c.is_complete());
+            if let Some(coverage) = incomplete {
+                return Err(PipelineError::IncompleteCoverage(Box::new(
+                    coverage.clone(),
+                )));
+            }
+        }
+
+        // The bounding box and the AOI polygon both restrict the area kept.
+        let bbox = config
+            .extent
+            .bounds
+            .map(|[x0, y0, x1, y1]| Extent::new(x0, y0, x1, y1));
+        let area = match (bbox, aoi.map(Aoi::extent)) {
+            (Some(a), Some(b)) => Some(a.intersection(&b).ok_or(NoOverlap {
+                ortho: None,
+                dem: None,
+                aoi: Some(b),
+            })?),
+            (a, b) => a.or(b),
+        };
+        plan.alignment = if config.extent.align || area.is_some() {
+            align(
+                ortho_layer.as_ref().map(|(layer, _, _)| layer),
+                dem_layer.as_ref(),
+                area.as_ref(),
+            )?
+        } else {
+            None
+        };
+
+        let bounds = plan.alignment.map(|a| a.bounds);
+        // Sizes in another CRS are only known once GDAL has reprojected.
+        if config.crs.output.is_some() {
+            ortho_layer = None;
+            dem_layer = None;
+        }
+        if let Some((layer, bands, data_type)) = &ortho_layer {
+            plan.estimates.push(RasterEstimate {
+                path: config.outputs.ortho_path(),
+                bands: *bands,
+                data_type: *data_type,
+                ..estimate(layer, bounds.as_ref())
+            });
+        }
+        if let Some(layer) = &dem_layer {
+            plan.estimates.push(RasterEstimate {
+                path: config.outputs.dem_path(),
+                bands: 1,
+                data_type: DataType::Float32,
+                ..estimate(layer, bounds.as_ref())
+            });
+        }
+
+        let source_crs = config.crs.inputs.clone().or_else(|| {
+            plan.stages
+                .iter()
+                .find_map(|s| s.mosaic.as_ref()?.crs.clone())
+        });
+        let warp = match (&config.crs.output, source_crs) {
+            (Some(output), None) => {
+                return Err(ConfigError::Invalid(format!(
+                    "crs.output = {:?} needs inputs whose CRS is known",
+                    output
+                ))
+                .into())
+            }
+            (Some(_), Some(source_crs)) => {
+                plan_warp(config, self.stages, &source_crs, bounds.as_ref(), aoi)
+            }
+            (None, _) => None,
+        };
+        // Reprojection crops and masks; the conversion then only changes
+        // the format.
+        let convert = match warp {
+            Some(warp) => {
+                plan.stages.push(warp);
+                plan_convert(config, self.stages, None, None)
+            }
+            None => plan_convert(config, self.stages, bounds.as_ref(), aoi),
+        };
+        // Files the conversion leaves in the working directory are not
+        // products.
+        plan.outputs = convert
+            .steps
+            .iter()
+            .map(|s| s.output.clone())
+            .filter(|p| !p.starts_with(&tmp_dir))
+            .collect();
+        plan.stages.push(convert);
+        if self.stages.dem && config.hillshade.enabled {
+            if let Some((crs, latitude)) = &dem_degrees {
+                check_z_factor(
+                    "hillshade.z_factor",
+                    config.hillshade.z_factor,
+                    crs,
+                    *latitude,
+                )?;
+            }
+            let hillshade = plan_hillshade(config);
+            plan.outputs
+                .extend(hillshade.steps.iter().map(|s| s.output.clone()));
+            plan.stages.push(hillshade);
+        }
+        if self.stages.dem && !config.terrain.derivatives.is_empty() {
+            if let Some((crs, latitude)) = &dem_degrees {
+                check_z_factor("terrain.z_factor", config.terrain.z_factor, crs, *latitude)?;
+            }
+            let terrain = plan_terrain(config);
+            plan.outputs
+                .extend(terrain.steps.iter().map(|s| s.output.clone()));
+            plan.stages.push(terrain);
+        }
+        if self.stages.dem && config.contours.enabled {
+            let contours = plan_contours(config);
+            plan.outputs.extend(
+                contours
+                    .steps
+                    .iter()
+                    .map(|s| s.output.clone())
+                    .filter(|p| !p.starts_with(&tmp_dir)),
+            );
+            plan.stages.push(contours);
+        }
+        Ok(plan)
+    }
+
+    /// For each step of `plan`, whether the build manifest shows it up to
+    /// date. Always all `false` when forcing.
+    pub fn up_to_date(&self, plan: &Plan) -> Result<Vec<bool>, PipelineError> {
+        let mut tracker = Tracker::load(&self.config.outputs.dir, self.force);
+        let fingerprints = tracker.fingerprints(plan)?;
+        Ok(plan
+            .steps()
+            .zip(&fingerprints)
+            .map(|(step, fp)| tracker.is_fresh(step, fp))
+            .collect())
+    }
+
+    /// Runs every selected stage in order, stopping at the first failure.
+    /// Steps recorded as up to date in the build manifest are skipped
+    /// unless the pipeline was built with [`PipelineBuilder::force`].
+    ///
+    /// The GDAL tools needed by the remaining steps are checked first, so a
+    /// missing installation fails with [`PipelineError::Preflight`] before
+    /// anything is written.
+    pub fn run(&self) -> Result<BuildOutputs, PipelineError> {
+        self.execute(&self.plan()?, &SystemRunner, true)
+    }
+
+    /// Like [`Pipeline::run`], with GDAL invoked through `runner` and no
+    /// pre-flight check.
+    pub fn run_with(&self, runner: &dyn ToolRunner) -> Result<BuildOutputs, PipelineError> {
+        self.execute(&self.plan()?, runner, false)
+    }
+
+    fn execute(
+        &self,
+        plan: &Plan,
+        runner: &dyn ToolRunner,
+        preflight: bool,
+    ) -> Result<BuildOutputs, PipelineError> {
+        let mut tracker = Tracker::load(&self.config.outputs.dir, self.force);
+        let fingerprints = tracker.fingerprints(plan)?;
+        let fresh: Vec<bool> = plan
+            .steps()
+            .zip(&fingerprints)
+            .map(|(step, fp)| tracker.is_fresh(step, fp))
+            .collect();
+
+        if preflight {
+            let pending: Vec<&Step> = plan
+                .steps()
+                .zip(&fresh)
+                .filter(|(_, &fresh)| !fresh)
+                .map(|(step, _)| step)
+                .collect();
+            self.preflight(&pending)?;
+        }
+
+        ensure_directories(&self.config)
+            .map_err(|e| PipelineError::io(Stage::Prepare, "failed to create directories", e))?;
+        if self.force {
+            cleanup_vrts(&self.config.outputs.tmp_dir())
+                .map_err(|e| PipelineError::io(Stage::Prepare, "failed to clean up VRTs", e))?;
+        }
+
+        let mut outputs = BuildOutputs::new(&self.config, self.stages);
+        outputs.coverage = plan
+            .stages
+            .iter()
+            .filter_map(|s| s.coverage.clone())
+            .collect();
+        outputs.alignment = plan.alignment;
+        outputs.name_mismatches = plan
+            .stages
+            .iter()
+            .flat_map(|s| s.name_mismatches.iter().cloned())
+            .collect();
+        for ((step, fingerprint), fresh) in plan.steps().zip(&fingerprints).zip(fresh) {
+            if fresh {
+                outputs.skipped += 1;
+                continue;
+            }
+            step.execute(runner)?;
+            tracker.record(step, fingerprint)?;
+            outputs.executed += 1;
+        }
+        Ok(outputs)
+    }
+
+    /// Checks the tools run by `steps`, and the JPEG 2000 driver when one
+    /// of them reads the orthophoto tiles.
+    fn preflight(&self, steps: &[&Step]) -> Result<(), PipelineError> {
+        let ortho = self.config.outputs.ortho_path();
+        let mut tools: Vec<&str> = Vec::new();
+        let mut jp2 = false;
+        for step in steps {
+            let commands = match &step.action {
+                Action::Run(command) => std::slice::from_ref(command),
+                Action::Chain(commands) => commands.as_slice(),
+                _ => continue,
+            };
+            for command in commands {
+                if !tools.contains(&command.program.as_str()) {
+                    tools.push(&command.program);
+                }
+            }
+            jp2 |= step.stage == Stage::OrthoVrt || step.output == ortho;
+        }
+        if tools.is_empty() {
+            return Ok(());
+        }
+
+        let report = doctor::check(&tools, jp2);
+        if report.is_ok() {
+            Ok(())
+        } else {
+            Err(PipelineError::Preflight(report))
+        }
+    }
+}
+
+/// Builder for [`Pipeline`], starting from the default settings.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineBuilder {
+    config: Config,
+    stages: Stages,
+    force: bool,
+}
+
+impl PipelineBuilder {
+    /// Starts from a project file instead of the defaults.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn jp2_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.inputs.jp2_dir = dir.into();
+        self
+    }
+
+    pub fn asc_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.inputs.asc_dir = dir.into();
+        self
+    }
+
+    pub fn out_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.outputs.dir = dir.into();
+        self
+    }
+
+    pub fn tmp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.outputs.tmp_dir = Some(dir.into());
+        self
+    }
+
+    pub fn ortho_name(mut self, name: impl Into<String>) -> Self {
+        self.config.outputs.ortho = name.into();
+        self
+    }
+
+    pub fn dem_name(mut self, name: impl Into<String>) -> Self {
+        self.config.outputs.dem = name.into();
+        self
+    }
+
+    pub fn vrt(mut self, vrt: VrtOptions) -> Self {
+        self.config.vrt = vrt;
+        self
+    }
+
+    pub fn fill(mut self, fill: FillOptions) -> Self {
+        self.config.dem.fill = fill;
+        self
+    }
+
+    pub fn ortho(mut self, ortho: OrthoOptions) -> Self {
+        self.config.ortho = ortho;
+        self
+    }
+
+    pub fn geotiff(mut self, geotiff: GeoTiffOptions) -> Self {
+        self.config.geotiff = geotiff;
+        self
+    }
+
+    pub fn warp(mut self, warp: WarpOptions) -> Self {
+        self.config.dem.warp = warp;
+        self
+    }
+
+    pub fn vertical(mut self, vertical: VerticalOptions) -> Self {
+        self.config.dem.vertical = vertical;
+        self
+    }
+
+    pub fn hillshade(mut self, hillshade: HillshadeOptions) -> Self {
+        self.config.hillshade = hillshade;
+        self
+    }
+
+    pub fn terrain(mut self, terrain: TerrainOptions) -> Self {
+        self.config.terrain = terrain;
+        self
+    }
+
+    pub fn contours(mut self, contours: ContourOptions) -> Self {
+        self.config.contours = contours;
+        self
+    }
+
+    pub fn crs(mut self, crs: CrsOptions) -> Self {
+        self.config.crs = crs;
+        self
+    }
+
+    pub fn extent(mut self, extent: ExtentOptions) -> Self {
+        self.config.extent = extent;
+        self
+    }
+
+    pub fn coverage(mut self, coverage: CoverageOptions) -> Self {
+        self.config.coverage = coverage;
+        self
+    }
+
+    pub fn stages(mut self, stages: Stages) -> Self {
+        self.stages = stages;
+        self
+    }
+
+    /// Reruns every step even when the build manifest says it is up to
+    /// date.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn build(self) -> Result<Pipeline, PipelineError> {
+        let mut pipeline = Pipeline::from_config(self.config, self.stages)?;
+        pipeline.force = self.force;
+        Ok(pipeline)
+    }
+}
+
+fn ensure_directories(config: &Config) -> std::io::Result<()> {
+    fs::create_dir_all(&config.outputs.dir)?;
+    fs::create_dir_all(config.outputs.tmp_dir())?;
+    Ok(())
+}
+
+fn cleanup_vrts(tmp_dir: &Path) -> std::io::Result<()> {
+    if tmp_dir.exists() {
+        for entry in fs::read_dir(tmp_dir)? {
+            let entry = entry?;
+            if entry.path().extension().unwrap_or_default() == "vrt" {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn discover_inputs(
+    stage: Stage,
+    dir: &Path,
+    extension: &'static str,
+) -> Result<Vec<PathBuf>, PipelineError> {
+    let files = find_inputs(dir, extension)
+        .map_err(|e| PipelineError::io(stage, format!("failed to list {}", dir.display()), e))?;
+    if files.is_empty() {
+        return Err(PipelineError::NoInputs {
+            stage,
+            dir: dir.to_path_buf(),
+            extension,
+        });
+    }
+    Ok(files)
+}
+
+fn mosaic_error(output: &Path, e: VrtError) -> PipelineError {
+    match e {
+        VrtError::Incompatible { path, message } => PipelineError::Input { path, message },
+        VrtError::Empty => PipelineError::input(output, e),
+    }
+}
+
+/// Plans the mosaic VRT of `tiles` at `output`, either written natively or
+/// by `gdalbuildvrt` depending on `vrt.backend`. The mosaic layout is
+/// returned whenever the tiles can be read natively.
+fn plan_mosaic(
+    stage: Stage,
+    tiles: &[PathBuf],
+    output: &Path,
+    vrt: &VrtOptions,
+) -> Result<(Step, Option<Mosaic>), PipelineError> {
+    match vrt.backend {
+        VrtBackend::Gdal => {
+            let mosaic = raster::probe_all(tiles)
+                .ok()
+                .and_then(|infos| Mosaic::new(infos, vrt.resolution).ok());
+            let command = ToolCommand::new("gdalbuildvrt")
+                .args(["-resolution", vrt.resolution.as_gdal()])
+                .arg(output)
+                .args(tiles);
+            Ok((Step::run(stage, command, tiles.to_vec(), output), mosaic))
+        }
+        VrtBackend::Native => {
+            let absolute = tiles
+                .iter()
+                .map(|t| fs::canonicalize(t).map_err(|e| PipelineError::input(t, e)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mosaic = Mosaic::new(raster::probe_all(&absolute)?, vrt.resolution)
+                .map_err(|e| mosaic_error(output, e))?;
+            let step = Step {
+                stage,
+                action: Action::WriteVrt(Box::new(mosaic.clone())),
+                inputs: tiles.to_vec(),
+                output: output.to_path_buf(),
+            };
+            Ok((step, Some(mosaic)))
+        }
+    }
+}
+
+/// Reads the CRS of every tile. Without `crs.inputs`, tiles must agree;
+/// with it, tiles in another CRS get a `gdalwarp` step producing a
+/// reprojected VRT that replaces them in the mosaic.
+///
+/// Returns the tiles to mosaic, the reprojection steps and the report.
+fn harmonize_crs(
+    stage: Stage,
+    tiles: &[PathBuf],
+    tmp_dir: &Path,
+    crs: &CrsOptions,
+) -> Result<(Vec<PathBuf>, Vec<Step>, CrsReport), PipelineError> {
+    let report = CrsReport::new(
+        stage,
+        tiles.iter().map(|t| {
+            let definition = raster::probe(t).ok().and_then(|info| info.crs);
+            (t.clone(), definition.and_then(|d| CrsId::identify(&d)))
+        }),
+    );
+    let Some(definition) = &crs.inputs else {
+        if !report.is_consistent() {
+            return Err(PipelineError::MixedCrs(report));
+        }
+        return Ok((tiles.to_vec(), Vec::new(), report));
+    };
+    let target = CrsId::identify(definition).ok_or_else(|| {
+        ConfigError::Invalid(format!("crs.inputs is not a CRS: {:?}", definition))
+    })?;
+    let outliers: Vec<PathBuf> = report.outliers(&target).map(Path::to_path_buf).collect();
+
+    let mut mosaic_tiles = Vec::with_capacity(tiles.len());
+    let mut steps = Vec::new();
+    for tile in tiles {
+        if !outliers.contains(tile) {
+            mosaic_tiles.push(tile.clone());
+            continue;
+        }
+        let stem = tile.file_stem().unwrap_or_default().to_string_lossy();
+        let mut output = tmp_dir.join(format!("{}.reprojected.vrt", stem));
+        if mosaic_tiles.contains(&output) {
+            output = tmp_dir.join(format!("{}-{}.reprojected.vrt", stem, steps.len()));
+        }
+        let command = ToolCommand::new("gdalwarp")
+            .arg("-t_srs")
+            .arg(definition)
+            .args(["-r", crs.resampling.as_gdal()])
+            .args(["-of", "VRT"])
+            .arg(tile)
+            .arg(&output);
+        steps.push(Step::run(stage, command, vec![tile.clone()], &output));
+        mosaic_tiles.push(output);
+    }
+    Ok((mosaic_tiles, steps, report))
+}
+
+/// Refuses DEM tiles declaring different vertical references, or one
+/// other than `dem.vertical.datum`. Tiles declaring none are taken to be
+/// in the configured one.
+fn check_vertical(tiles: &[PathBuf], vertical: &VerticalOptions) -> Result<(), PipelineError> {
+    let declared = tiles.iter().filter_map(|t| {
+        let definition = raster::probe(t).ok().and_then(|info| info.crs)?;
+        Some((t.clone(), Some(CrsId::vertical(&definition)?)))
+    });
+    let report = CrsReport::new(Stage::DemVrt, declared);
+    let expected = vertical
+        .datum
+        .as_deref()
+        .and_then(CrsId::vertical_reference);
+    let consistent = match &expected {
+        Some(expected) => report.outliers(expected).next().is_none(),
+        None => report.is_consistent(),
+    };
+    if consistent {
+        Ok(())
+    } else {
+        Err(PipelineError::MixedVertical { expected, report })
+    }
+}
+
+/// Plans the mosaic of a stage after bringing its tiles to a common CRS.
+/// Reprojected tiles only exist once `gdalwarp` has run, so their mosaic
+/// always goes through `gdalbuildvrt`.
+fn plan_harmonized_mosaic(
+    stage: Stage,
+    tiles: &[PathBuf],
+    tmp_dir: &Path,
+    output: &Path,
+    vrt: &VrtOptions,
+    crs: &CrsOptions,
+) -> Result<(Vec<Step>, Option<Mosaic>, CrsReport), PipelineError> {
+    let (mosaic_tiles, mut steps, report) = harmonize_crs(stage, tiles, tmp_dir, crs)?;
+    let vrt = if steps.is_empty() {
+        vrt.clone()
+    } else {
+        VrtOptions {
+            backend: VrtBackend::Gdal,
+            ..vrt.clone()
+        }
+    };
+    let (step, mosaic) = plan_mosaic(stage, &mosaic_tiles, output, &vrt)?;
+    steps.push(step);
+    Ok((steps, mosaic, report))
+}
+
+/// The orthophoto as `gdal_translate` writes it: the mosaic unchanged, or
+/// resampled to `ortho.resolution`.
+fn ortho_output(mosaic: &Mosaic, ortho: &OrthoOptions) -> Layer {
+    if let Some(resolution) = ortho.resolution {
+        return resampled(mosaic, resolution);
+    }
+    let gt = &mosaic.geo_transform;
+    Layer {
+        extent: mosaic.extent(),
+        grid: Grid {
+            origin_x: gt[0],
+            origin_y: gt[3],
+            res_x: gt[1],
+            res_y: -gt[5],
+        },
+    }
+}
+
+/// The DEM as warped to `dem.warp.resolution`.
+fn dem_output(mosaic: &Mosaic, dem: &DemOptions) -> Layer {
+    resampled(mosaic, dem.warp.resolution)
+}
+
+/// `mosaic` as `gdalwarp -tr` resamples it: the top-left corner kept, the
+/// size rounded to whole pixels of `resolution`.
+fn resampled(mosaic: &Mosaic, resolution: Resolution) -> Layer {
+    let extent = mosaic.extent();
+    let (res_x, res_y) = resolution.xy();
+    let width = (0.5 + extent.width() / res_x) as usize;
+    let height = (0.5 + extent.height() / res_y) as usize;
+    Layer {
+        extent: Extent::new(
+            extent.min_x,
+            extent.max_y - height as f64 * res_y,
+            extent.min_x + width as f64 * res_x,
+            extent.max_y,
+        ),
+        grid: Grid {
+            origin_x: extent.min_x,
+            origin_y: extent.max_y,
+            res_x,
+            res_y,
+        },
+    }
+}
+
+/// Pixel size of `layer` once cropped to `bounds`; bands and data type are
+/// left to the caller.
+fn estimate(layer: &Layer, bounds: Option<&Extent>) -> RasterEstimate {
+    let extent = bounds.unwrap_or(&layer.extent);
+    RasterEstimate {
+        path: PathBuf::new(),
+        width: (0.5 + extent.width() / layer.grid.res_x) as usize,
+        height: (0.5 + extent.height() / layer.grid.res_y) as usize,
+        bands: 1,
+        data_type: DataType::Byte,
+    }
+}
+
+/// Splits the tiles found in `dir` into those `selection` keeps and those
+/// it leaves out. Tiles whose extent cannot be read are kept by the AOI.
+fn select_tiles(
+    stage: Stage,
+    dir: &Path,
+    tiles: Vec<PathBuf>,
+    selection: &Selection,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), PipelineError> {
+    let mut excluded = Vec::new();
+    let mut kept = Vec::new();
+    let found = tiles.len();
+    for tile in tiles {
+        let named = selection
+            .dalles
+            .is_none_or(|range| DalleName::parse(&tile).is_some_and(|n| n.within(&range)));
+        if named {
+            kept.push(tile);
+        } else {
+            excluded.push(tile);
+        }
+    }
+    if let (Some(range), true) = (selection.dalles, kept.is_empty()) {
+        return Err(ConfigError::Invalid(format!(
+            "inputs.tiles {:?} matches none of the {} tile name(s) in {}",
+            range,
+            found,
+            dir.display()
+        ))
+        .into());
+    }
+
+    let Some(aoi) = &selection.aoi else {
+        return Ok((kept, excluded));
+    };
+    let mut inside = Vec::new();
+    let mut covered: Option<Extent> = None;
+    for tile in kept {
+        match raster::probe(&tile).map(|info| info.extent()) {
+            Ok(extent) if !aoi.intersects(&extent) => {
+                covered = Some(covered.map_or(extent, |c| c.union(&extent)));
+                excluded.push(tile);
+            }
+            _ => inside.push(tile),
+        }
+    }
+    if inside.is_empty() {
+        let (ortho, dem) = match stage {
+            Stage::OrthoVrt => (covered, None),
+            _ => (None, covered),
+        };
+        return Err(PipelineError::NoOverlap(NoOverlap {
+            ortho,
+            dem,
+            aoi: Some(aoi.extent()),
+        }));
+    }
+    excluded.sort();
+    Ok((inside, excluded))
+}
+
+/// Compares the dalle name of every readable tile with its header.
+fn check_dalle_names(stage: Stage, tiles: &[PathBuf]) -> Vec<NameMismatch> {
+    let infos: Vec<_> = tiles
+        .iter()
+        .filter(|t| DalleName::parse(t).is_some())
+        .filter_map(|t| raster::probe(t).ok())
+        .collect();
+    check_names(stage, &infos)
+}
+
+fn check_coverage(stage: Stage, mosaic: Option<&Mosaic>) -> Option<Coverage> {
+    mosaic.and_then(|m| Coverage::check(stage, &m.tiles))
+}
+
+/// Resolves the `.jp2` tiles under `jp2_dir` kept by `selection` and plans
+/// their mosaic into `<tmp_dir>/mosaic.vrt`, reprojecting tiles as `crs`
+/// requires.
+pub fn plan_ortho_vrt(
+    jp2_dir: &Path,
+    tmp_dir: &Path,
+    vrt: &VrtOptions,
+    crs: &CrsOptions,
+    selection: &Selection,
+) -> Result<StagePlan, PipelineError> {
+    let found = discover_inputs(Stage::OrthoVrt, jp2_dir, "jp2")?;
+    let (tiles, excluded) = select_tiles(Stage::OrthoVrt, jp2_dir, found, selection)?;
+    let output = tmp_dir.join("mosaic.vrt");
+    let (steps, mosaic, report) =
+        plan_harmonized_mosaic(Stage::OrthoVrt, &tiles, tmp_dir, &output, vrt, crs)?;
+
+    let name_mismatches = check_dalle_names(Stage::OrthoVrt, &tiles);
+    Ok(StagePlan {
+        stage: Stage::OrthoVrt,
+        inputs: tiles,
+        steps,
+        excluded,
+        name_mismatches,
+        coverage: check_coverage(Stage::OrthoVrt, mosaic.as_ref()),
+        mosaic,
+        crs: Some(report),
+    })
+}
+
+/// Mosaics every `.jp2` tile under `jp2_dir` kept by `selection` into
+/// `<tmp_dir>/mosaic.vrt` and returns the VRT path.
+pub fn build_ortho_vrt(
+    jp2_dir: &Path,
+    tmp_dir: &Path,
+    vrt: &VrtOptions,
+    crs: &CrsOptions,
+    selection: &Selection,
+    runner: &dyn ToolRunner,
+) -> Result<PathBuf, PipelineError> {
+    let plan = plan_ortho_vrt(jp2_dir, tmp_dir, vrt, crs, selection)?;
+    plan.execute(runner)?;
+    Ok(plan.output().to_path_buf())
+}
+
+/// Resolves the `.asc` tiles under `asc_dir` kept by `selection` and plans
+/// the mosaic, hole filling and warping steps ending in `<tmp_dir>/dem.vrt`.
+pub fn plan_dem_vrt(
+    asc_dir: &Path,
+    tmp_dir: &Path,
+    vrt: &VrtOptions,
+    crs: &CrsOptions,
+    selection: &Selection,
+    dem: &DemOptions,
+) -> Result<StagePlan, PipelineError> {
+    let found = discover_inputs(Stage::DemVrt, asc_dir, "asc")?;
+    let (tiles, excluded) = select_tiles(Stage::DemVrt, asc_dir, found, selection)?;
+    let temp_dem = tmp_dir.join("temp_dem.vrt");
+    let temp_filled_dem = tmp_dir.join("temp_filled_dem.vrt");
+    let dem_vrt = tmp_dir.join("dem.vrt");
+    let (res_x, res_y) = dem.warp.resolution.xy();
+
+    check_vertical(&tiles, &dem.vertical)?;
+    let (mut steps, mosaic, report) =
+        plan_harmonized_mosaic(Stage::DemVrt, &tiles, tmp_dir, &temp_dem, vrt, crs)?;
+    let fill = match dem.fill.backend {
+        FillBackend::Gdal => {
+            let command = ToolCommand::new("gdal_fillnodata")
+                .arg("-md")
+                .arg(dem.fill.max_distance.to_string())
+                .arg("-si")
+                .arg(dem.fill.smoothing_iterations.to_string())
+                .arg(&temp_dem)
+                .arg(&temp_filled_dem);
+            Step::run(Stage::DemVrt, command, vec![temp_dem], &temp_filled_dem)
+        }
+        FillBackend::Native => {
+            // The filler reads the tiles themselves, not reprojected copies.
+            let mosaic = mosaic.clone().filter(|_| steps.len() == 1).ok_or_else(|| {
+                ConfigError::Invalid(
+                    "dem.fill.backend = \"native\" needs .asc tiles readable in place, \
+                         without reprojection"
+                        .to_string(),
+                )
+            })?;
+            Step {
+                stage: Stage::DemVrt,
+                action: Action::Fill(Box::new(NativeFill {
+                    mosaic,
+                    options: dem.fill.clone(),
+                })),
+                inputs: vec![temp_dem],
+                output: temp_filled_dem.clone(),
+            }
+        }
+    };
+    // The geoid correction works on the raw raster of the native filler,
+    // which config validation guarantees.
+    let geoid = match (&dem.vertical.geoid, &fill.action) {
+        (Some(grid), Action::Fill(filler)) => Some(Step {
+            stage: Stage::DemVrt,
+            action: Action::Geoid(Box::new(NativeGeoid {
+                input: temp_filled_dem.clone(),
+                raster: filler.output(),
+                grid: grid.clone(),
+            })),
+            inputs: vec![temp_filled_dem.clone(), grid.clone()],
+            output: tmp_dir.join("temp_ellipsoidal_dem.vrt"),
+        }),
+        _ => None,
+    };
+    let warp_input = geoid
+        .as_ref()
+        .map_or(&temp_filled_dem, |g| &g.output)
+        .clone();
+    let warp = match dem.warp.backend {
+        WarpBackend::Gdal => {
+            let command = ToolCommand::new("gdalwarp")
+                .arg("-tr")
+                .arg(res_x.to_string())
+                .arg(res_y.to_string())
+                .args(["-r", dem.warp.resampling.as_gdal()])
+                .arg("-dstnodata")
+                .arg(dem.warp.nodata.to_string())
+                .arg("-wo")
+                .arg(format!("NUM_THREADS={}", dem.warp.threads))
+                .arg(&warp_input)
+                .arg(&dem_vrt);
+            Step::run(Stage::DemVrt, command, vec![warp_input], &dem_vrt)
+        }
+        WarpBackend::Native => {
+            // The resampler reads the raw raster the native filler writes.
+            let Action::Fill(filler) = &fill.action else {
+                return Err(ConfigError::Invalid(
+                    "dem.warp.backend = \"native\" needs dem.fill.backend = \"native\"".to_string(),
+                )
+                .into());
+            };
+            let layer = dem_output(&filler.mosaic, dem);
+            let grid = &layer.grid;
+            let target = RawVrt {
+                width: (layer.extent.width() / grid.res_x).round() as usize,
+                height: (layer.extent.height() / grid.res_y).round() as usize,
+                geo_transform: [
+                    grid.origin_x,
+                    grid.res_x,
+                    0.0,
+                    grid.origin_y,
+                    0.0,
+                    -grid.res_y,
+                ],
+                crs: filler.mosaic.crs.clone(),
+                nodata: dem.warp.nodata,
+            };
+            Step {
+                stage: Stage::DemVrt,
+                action: Action::Resample(Box::new(NativeResample {
+                    input: warp_input.clone(),
+                    source: filler.output(),
+                    target,
+                    resampling: dem.warp.resampling,
+                    block_rows: dem.warp.block_rows,
+                })),
+                inputs: vec![warp_input],
+                output: dem_vrt,
+            }
+        }
+    };
+
+    steps.push(fill);
+    steps.extend(geoid);
+    steps.push(warp);
+
+    let name_mismatches = check_dalle_names(Stage::DemVrt, &tiles);
+    Ok(StagePlan {
+        stage: Stage::DemVrt,
+        inputs: tiles,
+        steps,
+        excluded,
+        name_mismatches,
+        coverage: check_coverage(Stage::DemVrt, mosaic.as_ref()),
+        mosaic,
+        crs: Some(report),
+    })
+}
+
+/// Mosaics every `.asc` tile under `asc_dir` kept by `selection`, fills its
+/// holes with `gdal_fillnodata` and warps it to the target resolution,
+/// returning the path of the resulting `<tmp_dir>/dem.vrt`.
+pub fn build_dem_vrt(
+    asc_dir: &Path,
+    tmp_dir: &Path,
+    vrt: &VrtOptions,
+    crs: &CrsOptions,
+    selection: &Selection,
+    dem: &DemOptions,
+    runner: &dyn ToolRunner,
+) -> Result<PathBuf, PipelineError> {
+    let plan = plan_dem_vrt(asc_dir, tmp_dir, vrt, crs, selection, dem)?;
+    plan.execute(runner)?;
+    Ok(plan.output().to_path_buf())
+}
+
+/// Value given to orthophoto pixels outside a non-rectangular AOI, or
+/// outside the source once reprojected.
+const ORTHO_NODATA: u8 = 0;
+
+/// The VRTs converted into the final GeoTIFFs: the orthophoto mosaic and
+/// the warped DEM, or their reprojections to `crs.output`.
+fn final_vrts(config: &Config) -> (PathBuf, PathBuf) {
+    let tmp_dir = config.outputs.tmp_dir();
+    if config.crs.output.is_some() {
+        (
+            tmp_dir.join("ortho_reprojected.vrt"),
+            tmp_dir.join("dem_reprojected.vrt"),
+        )
+    } else {
+        (tmp_dir.join("mosaic.vrt"), tmp_dir.join("dem.vrt"))
+    }
+}
+
+/// Plans the reprojection of the intermediate VRTs of the selected stages
+/// from `source_crs` to `crs.output`, or nothing when the outputs keep the
+/// inputs' CRS. `bounds` and `aoi`, in the inputs' CRS, crop and mask the
+/// outputs on the way; pixels off the source become nodata.
+pub fn plan_warp(
+    config: &Config,
+    stages: Stages,
+    source_crs: &str,
+    bounds: Option<&Extent>,
+    aoi: Option<&Aoi>,
+) -> Option<StagePlan> {
+    let target = config.crs.output.as_ref()?;
+    let tmp_dir = config.outputs.tmp_dir();
+    let mut steps = Vec::new();
+    let cutline = aoi.filter(|a| !a.is_rectangle()).map(|aoi| {
+        let path = tmp_dir.join("aoi.geojson");
+        steps.push(Step {
+            stage: Stage::Warp,
+            action: Action::WriteCutline(Box::new(aoi.clone())),
+            inputs: Vec::new(),
+            output: path.clone(),
+        });
+        path
+    });
+    let warp = |source: PathBuf, output: PathBuf, nodata: String| {
+        let mut inputs = vec![source.clone()];
+        let mut command = ToolCommand::new("gdalwarp")
+            .arg("-t_srs")
+            .arg(target)
+            .args(["-r", config.crs.resampling.as_gdal()]);
+        if let Some(resolution) = config.crs.output_resolution {
+            let (x, y) = resolution.xy();
+            command = command.arg("-tr").arg(x.to_string()).arg(y.to_string());
+        }
+        if let Some(b) = bounds {
+            command = command
+                .arg("-te")
+                .args([b.min_x, b.min_y, b.max_x, b.max_y].map(|v| v.to_string()))
+                .arg("-te_srs")
+                .arg(source_crs);
+        }
+        if let Some(cutline) = &cutline {
+            inputs.push(cutline.clone());
+            command = command.arg("-cutline").arg(cutline);
+        }
+        let command = command
+            .arg("-dstnodata")
+            .arg(nodata)
+            .args(["-of", "VRT"])
+            .arg(&source)
+            .arg(&output);
+        Step::run(Stage::Warp, command, inputs, output)
+    };
+
+    let (ortho, dem) = final_vrts(config);
+    if stages.ortho {
+        let mosaic = tmp_dir.join("mosaic.vrt");
+        steps.push(warp(mosaic, ortho, ORTHO_NODATA.to_string()));
+    }
+    if stages.dem {
+        let dem_vrt = tmp_dir.join("dem.vrt");
+        steps.push(warp(dem_vrt, dem, config.dem.warp.nodata.to_string()));
+    }
+
+    Some(StagePlan::steps(Stage::Warp, steps))
+}
+
+/// Plans the conversion of the intermediate VRTs of the selected stages
+/// into the final GeoTIFFs named in `config.outputs`, cropped to `bounds`
+/// when given. A non-rectangular `aoi` is written out as a cutline and
+/// the outputs go through `gdalwarp` instead, masking the pixels outside
+/// it as nodata. The layout and compression come from `config.geotiff`.
+/// With `crs.output`, the VRTs converted are those of [`plan_warp`].
+pub fn plan_convert(
+    config: &Config,
+    stages: Stages,
+    bounds: Option<&Extent>,
+    aoi: Option<&Aoi>,
+) -> StagePlan {
+    let tmp_dir = config.outputs.tmp_dir();
+    let geotiff = &config.geotiff;
+    let native_dem = geotiff.backend == GeoTiffBackend::Native;
+    let mask = aoi.filter(|a| !a.is_rectangle());
+    let mut steps = Vec::new();
+    let cutline = mask.filter(|_| stages.ortho || !native_dem).map(|aoi| {
+        let path = tmp_dir.join("aoi.geojson");
+        steps.push(Step {
+            stage: Stage::Convert,
+            action: Action::WriteCutline(Box::new(aoi.clone())),
+            inputs: Vec::new(),
+            output: path.clone(),
+        });
+        path
+    });
+    // Output resampling, for the products not already at their resolution.
+    let resize = |resize: Option<(Resolution, Resampling)>| -> Vec<String> {
+        resize
+            .into_iter()
+            .flat_map(|(resolution, resampling)| {
+                let (x, y) = resolution.xy();
+                [
+                    "-tr".to_string(),
+                    x.to_string(),
+                    y.to_string(),
+                    "-r".to_string(),
+                    resampling.as_gdal().to_string(),
+                ]
+            })
+            .collect()
+    };
+    // Output nodata: the DEM always declares one, the orthophoto only
+    // needs one for the pixels masked outside the AOI.
+    let convert = |source: &Path,
+                   output: &Path,
+                   nodata: Option<String>,
+                   mut options: Vec<String>,
+                   floating_point: bool| {
+        options.extend(creation_options(geotiff, floating_point));
+        let mut inputs = vec![source.to_path_buf()];
+        let command = match &cutline {
+            Some(cutline) => {
+                inputs.push(cutline.clone());
+                ToolCommand::new("gdalwarp")
+                    .args(["-of", geotiff.format.as_gdal()])
+                    .args(options)
+                    .arg("-cutline")
+                    .arg(cutline)
+                    .args(bounds.into_iter().flat_map(|b| {
+                        let te = [b.min_x, b.min_y, b.max_x, b.max_y];
+                        std::iter::once("-te".to_string()).chain(te.map(|v| v.to_string()))
+                    }))
+                    .arg("-dstnodata")
+                    .arg(nodata.unwrap_or_else(|| ORTHO_NODATA.to_string()))
+            }
+            None => ToolCommand::new("gdal_translate")
+                .args(["-of", geotiff.format.as_gdal()])
+                .args(options)
+                .args(
+                    nodata
+                        .into_iter()
+                        .flat_map(|n| ["-a_nodata".to_string(), n]),
+                )
+                .args(bounds.into_iter().flat_map(|b| {
+                    let projwin = [b.min_x, b.max_y, b.max_x, b.min_y];
+                    std::iter::once("-projwin".to_string()).chain(projwin.map(|v| v.to_string()))
+                })),
+        };
+        let command = command.arg(source).arg(output);
+        let action = if geotiff.overviews.is_empty() {
+            Action::Run(command)
+        } else {
+            Action::Chain(vec![
+                command,
+                add_overviews(geotiff, output, floating_point),
+            ])
+        };
+        Step {
+            stage: Stage::Convert,
+            action,
+            inputs,
+            output: output.to_path_buf(),
+        }
+    };
+
+    let (mosaic, mut dem_vrt) = final_vrts(config);
+    let datum = config.dem.vertical.recorded();
+    if let Some(datum) = datum.filter(|_| stages.dem && !native_dem) {
+        // gdalwarp cannot set metadata on its output but, like
+        // gdal_translate, copies its source's: tag the source for both.
+        let tagged = tmp_dir.join("dem_datum.vrt");
+        let command = ToolCommand::new("gdal_translate")
+            .args(["-of", "VRT", "-mo"])
+            .arg(format!("VERTICAL_DATUM={}", datum))
+            .arg(&dem_vrt)
+            .arg(&tagged);
+        steps.push(Step::run(Stage::Convert, command, vec![dem_vrt], &tagged));
+        dem_vrt = tagged;
+    }
+    if stages.ortho {
+        let resolution = config
+            .ortho
+            .resolution
+            .map(|r| (r, config.ortho.resampling));
+        steps.push(convert(
+            &mosaic,
+            &config.outputs.ortho_path(),
+            None,
+            resize(resolution),
+            false,
+        ));
+    }
+    if stages.dem {
+        if native_dem {
+            steps.push(Step {
+                stage: Stage::Convert,
+                action: Action::WriteGeoTiff(Box::new(NativeGeoTiff {
+                    input: dem_vrt.clone(),
+                    bounds: bounds.copied(),
+                    aoi: mask.cloned(),
+                    options: geotiff.clone(),
+                    vertical_datum: datum.map(str::to_string),
+                })),
+                inputs: vec![dem_vrt],
+                output: config.outputs.dem_path(),
+            });
+        } else {
+            let nodata = config.dem.warp.nodata.to_string();
+            steps.push(convert(
+                &dem_vrt,
+                &config.outputs.dem_path(),
+                Some(nodata),
+                Vec::new(),
+                true,
+            ));
+        }
+    }
+
+    StagePlan::steps(Stage::Convert, steps)
+}
+
+/// The CRS of the DEM GeoTIFF when it is geographic, with the latitude of
+/// its centre. A DEM only warped to degrees is put on the equator, its
+/// latitude being unknown until GDAL has reprojected it.
+fn geographic_dem(config: &Config, mosaic: Option<&Mosaic>) -> Option<(String, f64)> {
+    let source = config.crs.inputs.clone().or_else(|| mosaic?.crs.clone());
+    let crs = config.crs.output.clone().or_else(|| source.clone())?;
+    if !crs::is_geographic(&crs) {
+        return None;
+    }
+    let latitude = match (source, mosaic) {
+        (Some(source), Some(mosaic)) if crs::is_geographic(&source) => {
+            let extent = mosaic.extent();
+            (extent.min_y + extent.max_y) / 2.0
+        }
+        _ => 0.0,
+    };
+    Some((crs, latitude))
+}
+
+/// Refuses a `z_factor` leaving heights in metres over a DEM in degrees,
+/// as explained on [`METRES_PER_DEGREE`].
+fn check_z_factor(key: &str, z_factor: f64, crs: &str, latitude: f64) -> Result<(), ConfigError> {
+    let scale = 1.0 / (METRES_PER_DEGREE * latitude.to_radians().cos().abs());
+    if z_factor <= 10.0 * scale {
+        return Ok(());
+    }
+    Err(ConfigError::Invalid(format!(
+        "{} = {} leaves heights unscaled over {}, which is in degrees: \
+         use about {:.2e} for heights in metres at latitude {:.1}",
+        key,
+        z_factor,
+        CrsId::identify(crs).map_or(crs.to_string(), |id| id.to_string()),
+        scale,
+        latitude
+    )))
+}
+
+/// Plans the shaded relief of the DEM GeoTIFF, written next to it.
+pub fn plan_hillshade(config: &Config) -> StagePlan {
+    let dem = config.outputs.dem_path();
+    let step = Step {
+        stage: Stage::Hillshade,
+        action: Action::Hillshade(Box::new(NativeHillshade {
+            input: dem.clone(),
+            options: config.hillshade.clone(),
+            geotiff: config.geotiff.clone(),
+        })),
+        inputs: vec![dem],
+        output: config.outputs.hillshade_path(),
+    };
+    StagePlan::steps(Stage::Hillshade, vec![step])
+}
+
+/// Plans one GeoTIFF per terrain derivative of the DEM GeoTIFF, written
+/// next to it.
+pub fn plan_terrain(config: &Config) -> StagePlan {
+    let dem = config.outputs.dem_path();
+    let steps = config
+        .terrain
+        .derivatives
+        .iter()
+        .map(|&derivative| Step {
+            stage: Stage::Terrain,
+            action: Action::Derivative(Box::new(NativeDerivative {
+                input: dem.clone(),
+                derivative,
+                options: config.terrain.clone(),
+                geotiff: config.geotiff.clone(),
+            })),
+            inputs: vec![dem.clone()],
+            output: config.outputs.derivative_path(derivative),
+        })
+        .collect();
+    StagePlan::steps(Stage::Terrain, steps)
+}
+
+/// Plans the contour lines of the DEM GeoTIFF: traced into GeoJSON, in the
+/// working directory unless wanted, then converted to a GeoPackage by
+/// `ogr2ogr` when asked for.
+pub fn plan_contours(config: &Config) -> StagePlan {
+    let dem = config.outputs.dem_path();
+    let contours = &config.contours;
+    let geojson = if contours.formats.contains(&ContourFormat::GeoJson) {
+        config.outputs.contours_path(ContourFormat::GeoJson)
+    } else {
+        config.outputs.tmp_dir().join("contours.geojson")
+    };
+    let mut steps = vec![Step {
+        stage: Stage::Contours,
+        action: Action::Contours(Box::new(NativeContours {
+            input: dem.clone(),
+            options: contours.clone(),
+        })),
+        inputs: vec![dem],
+        output: geojson.clone(),
+    }];
+    if contours.formats.contains(&ContourFormat::Gpkg) {
+        let gpkg = config.outputs.contours_path(ContourFormat::Gpkg);
+        let command = ToolCommand::new("ogr2ogr")
+            .args(["-f", "GPKG", "-overwrite", "-nln", &config.outputs.contours])
+            .arg(&gpkg)
+            .arg(&geojson);
+        steps.push(Step::run(Stage::Contours, command, vec![geojson], gpkg));
+    }
+    StagePlan::steps(Stage::Contours, steps)
+}
+
+/// `-co` options giving GDAL the tiling and compression of `geotiff`.
+fn creation_options(geotiff: &GeoTiffOptions, floating_point: bool) -> Vec<String> {
+    if geotiff.format == TiffFormat::Cog {
+        return cog_options(geotiff, floating_point);
+    }
+    let mut options = Vec::new();
+    if geotiff.tiled {
+        options.push("TILED=YES".to_string());
+        options.push(format!("BLOCKXSIZE={}", geotiff.tile_size));
+        options.push(format!("BLOCKYSIZE={}", geotiff.tile_size));
+    }
+    if geotiff.compression != Compression::None {
+        options.push(format!("COMPRESS={}", geotiff.compression.as_gdal()));
+        let predictor = geotiff.predictor_for(floating_point);
+        if predictor != Predictor::None {
+            options.push(format!("PREDICTOR={}", predictor.code()));
+        }
+        match (geotiff.compression, geotiff.level) {
+            (Compression::Deflate, Some(level)) => options.push(format!("ZLEVEL={}", level)),
+            (Compression::Zstd, Some(level)) => options.push(format!("ZSTD_LEVEL={}", level)),
+            _ => {}
+        }
+    }
+    options
+        .into_iter()
+        .flat_map(|o| ["-co".to_string(), o])
+        .collect()
+}
+
+/// `-co` options of GDAL's COG driver, which tiles the output and adds
+/// its overviews itself. It compresses with LZW unless told otherwise.
+fn cog_options(geotiff: &GeoTiffOptions, floating_point: bool) -> Vec<String> {
+    let mut options = vec![
+        format!("BLOCKSIZE={}", geotiff.tile_size),
+        format!("COMPRESS={}", geotiff.compression.as_gdal()),
+    ];
+    if geotiff.compression != Compression::None {
+        let predictor = match geotiff.predictor_for(floating_point) {
+            Predictor::None => "NO",
+            Predictor::Horizontal => "STANDARD",
+            Predictor::FloatingPoint => "FLOATING_POINT",
+        };
+        options.push(format!("PREDICTOR={}", predictor));
+        if let Some(level) = geotiff.level {
+            options.push(format!("LEVEL={}", level));
+        }
+    }
+    let resampling = match geotiff.overview_resampling {
+        Resampling::Near => "NEAREST",
+        other => other.as_gdal(),
+    };
+    options.push(format!("RESAMPLING={}", resampling.to_uppercase()));
+    options
+        .into_iter()
+        .flat_map(|o| ["-co".to_string(), o])
+        .collect()
+}
+
+/// `gdaladdo` adding the internal overviews of `geotiff` to `output`,
+/// compressed like the full-resolution image.
+fn add_overviews(geotiff: &GeoTiffOptions, output: &Path, floating_point: bool) -> ToolCommand {
+    let resampling = match geotiff.overview_resampling {
+        Resampling::Near => "nearest",
+        other => other.as_gdal(),
+    };
+    let mut command = ToolCommand::new("gdaladdo").args(["-r", resampling]);
+    let predictor = geotiff.predictor_for(floating_point);
+    if predictor != Predictor::None {
+        command = command
+            .args(["--config", "PREDICTOR_OVERVIEW"])
+            .arg(predictor.code().to_string());
+    }
+    command
+        .arg(output)
+        .args(geotiff.overviews.iter().map(u32::to_string))
+}
+
+/// Converts the intermediate VRTs of the selected stages into the final
+/// GeoTIFFs named in `config.outputs`, cropped to `bounds` and masked
+/// outside `aoi` when given.
+pub fn resize_and_convert(
+    config: &Config,
+    stages: Stages,
+    bounds: Option<&Extent>,
+    aoi: Option<&Aoi>,
+    runner: &dyn ToolRunner,
+) -> Result<BuildOutputs, PipelineError> {
+    let plan = plan_convert(config, stages, bounds, aoi);
+    plan.execute(runner)?;
+    Ok(BuildOutputs {
+        executed: plan.steps.len(),
+        ..BuildOutputs::new(config, stages)
+    })
+}
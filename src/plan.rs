@@ -0,0 +1,351 @@
+//! The list of actions a build performs, resolved up front so it can be
+//! printed (`--dry-run`) or executed.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::align::Alignment;
+use crate::aoi::Aoi;
+use crate::config::{Derivative, SlopeUnits, TiffFormat};
+use crate::contours::NativeContours;
+use crate::coverage::Coverage;
+use crate::crs::CrsReport;
+use crate::dalle::NameMismatch;
+use crate::derivatives::NativeDerivative;
+use crate::error::{PipelineError, Stage};
+use crate::fill::NativeFill;
+use crate::gdal::{quote_arg, ToolCommand, ToolRunner};
+use crate::geoid::NativeGeoid;
+use crate::hillshade::NativeHillshade;
+use crate::raster::DataType;
+use crate::resample::NativeResample;
+use crate::rows::RowError;
+use crate::tiff_writer::NativeGeoTiff;
+use crate::vrt::Mosaic;
+
+/// What a [`Step`] does.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Run an external GDAL tool.
+    Run(ToolCommand),
+    /// Run GDAL tools one after the other, each working on the output of
+    /// the step (a GeoTIFF, then its overviews).
+    Chain(Vec<ToolCommand>),
+    /// Write a mosaic VRT with the built-in writer.
+    WriteVrt(Box<Mosaic>),
+    /// Write the AOI as a GeoJSON cutline.
+    WriteCutline(Box<Aoi>),
+    /// Fill DEM holes with the built-in filler.
+    Fill(Box<NativeFill>),
+    /// Add geoid heights to the DEM's.
+    Geoid(Box<NativeGeoid>),
+    /// Resample the DEM with the built-in resampler.
+    Resample(Box<NativeResample>),
+    /// Write the DEM GeoTIFF with the built-in writer.
+    WriteGeoTiff(Box<NativeGeoTiff>),
+    /// Shade the DEM GeoTIFF's relief.
+    Hillshade(Box<NativeHillshade>),
+    Derivative(Box<NativeDerivative>),
+    Contours(Box<NativeContours>),
+}
+
+/// One action of a build, the files it reads and the file it produces.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub stage: Stage,
+    pub action: Action,
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+}
+
+impl Step {
+    pub fn run(
+        stage: Stage,
+        command: ToolCommand,
+        inputs: Vec<PathBuf>,
+        output: impl Into<PathBuf>,
+    ) -> Step {
+        Step {
+            stage,
+            action: Action::Run(command),
+            inputs,
+            output: output.into(),
+        }
+    }
+
+    /// Everything that determines the step's result besides its input
+    /// files: the full command line, or the complete VRT document.
+    pub fn recipe(&self) -> String {
+        match &self.action {
+            Action::Run(command) => command.to_string(),
+            Action::Chain(commands) => chain(commands),
+            Action::WriteVrt(mosaic) => mosaic.to_xml(),
+            Action::WriteCutline(aoi) => aoi.to_geojson(),
+            Action::Fill(fill) => fill.recipe(),
+            Action::Geoid(geoid) => geoid.recipe(),
+            Action::Resample(resample) => resample.recipe(),
+            Action::WriteGeoTiff(geotiff) => geotiff.recipe(),
+            Action::Hillshade(hillshade) => hillshade.recipe(),
+            Action::Derivative(derivative) => derivative.recipe(),
+            Action::Contours(contours) => contours.recipe(),
+        }
+    }
+
+    pub fn execute(&self, runner: &dyn ToolRunner) -> Result<(), PipelineError> {
+        let written = match &self.action {
+            Action::Run(command) => return runner.run(self.stage, command, &self.output),
+            Action::Chain(commands) => {
+                return commands
+                    .iter()
+                    .try_for_each(|c| runner.run(self.stage, c, &self.output))
+            }
+            Action::WriteVrt(mosaic) => mosaic.write(&self.output),
+            Action::WriteCutline(aoi) => fs::write(&self.output, aoi.to_geojson()),
+            Action::Fill(fill) => return self.native(fill.run(&self.output)),
+            Action::Geoid(geoid) => return self.native(geoid.run(&self.output)),
+            Action::Resample(resample) => return self.native(resample.run(&self.output)),
+            Action::WriteGeoTiff(geotiff) => return self.native(geotiff.run(&self.output)),
+            Action::Hillshade(hillshade) => return self.native(hillshade.run(&self.output)),
+            Action::Derivative(derivative) => return self.native(derivative.run(&self.output)),
+            Action::Contours(contours) => return self.native(contours.run(&self.output)),
+        };
+        written.map_err(|e| {
+            PipelineError::io(
+                self.stage,
+                format!("failed to write {}", self.output.display()),
+                e,
+            )
+        })
+    }
+}
+
+impl Step {
+    fn native(&self, result: Result<(), RowError>) -> Result<(), PipelineError> {
+        result.map_err(|e| match e {
+            RowError::Input { path, message } => PipelineError::Input { path, message },
+            RowError::Io { path, source } => PipelineError::io(
+                self.stage,
+                format!("failed to write {}", path.display()),
+                source,
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action {
+            Action::Run(command) => write!(f, "{}", command),
+            Action::Chain(commands) => write!(f, "{}", chain(commands)),
+            Action::WriteVrt(mosaic) => write!(
+                f,
+                "(built-in) write VRT {} from {} tile(s), {} x {} px",
+                quote_arg(&self.output.to_string_lossy()),
+                mosaic.tiles.len(),
+                mosaic.width,
+                mosaic.height
+            ),
+            Action::WriteCutline(aoi) => write!(
+                f,
+                "(built-in) write cutline {} from {} polygon(s)",
+                quote_arg(&self.output.to_string_lossy()),
+                aoi.polygons.len()
+            ),
+            Action::Fill(fill) => write!(
+                f,
+                "(built-in) fill nodata into {} ({}, max distance {} px, {} smoothing pass(es))",
+                quote_arg(&self.output.to_string_lossy()),
+                fill.options.strategy.as_str(),
+                fill.options.max_distance,
+                fill.options.smoothing_iterations
+            ),
+            Action::Geoid(geoid) => write!(
+                f,
+                "(built-in) add geoid heights {} to {} into {}",
+                quote_arg(&geoid.grid.to_string_lossy()),
+                quote_arg(&geoid.input.to_string_lossy()),
+                quote_arg(&self.output.to_string_lossy())
+            ),
+            Action::Resample(resample) => write!(
+                f,
+                "(built-in) resample {} into {} ({}, {} x {} px at {} x {})",
+                quote_arg(&resample.input.to_string_lossy()),
+                quote_arg(&self.output.to_string_lossy()),
+                resample.resampling.as_gdal(),
+                resample.target.width,
+                resample.target.height,
+                resample.target.geo_transform[1],
+                -resample.target.geo_transform[5]
+            ),
+            Action::WriteGeoTiff(geotiff) => {
+                let o = &geotiff.options;
+                let cog = o.format == TiffFormat::Cog;
+                write!(
+                    f,
+                    "(built-in) write {} {} from {} ({}, {}",
+                    if cog { "COG" } else { "GeoTIFF" },
+                    quote_arg(&self.output.to_string_lossy()),
+                    quote_arg(&geotiff.input.to_string_lossy()),
+                    o.compression.as_gdal().to_lowercase(),
+                    if o.tiled || cog { "tiled" } else { "striped" }
+                )?;
+                if cog {
+                    write!(f, ", overviews auto")?;
+                } else if !o.overviews.is_empty() {
+                    let factors: Vec<String> = o.overviews.iter().map(u32::to_string).collect();
+                    write!(f, ", overviews {}", factors.join(" "))?;
+                }
+                write!(f, ")")
+            }
+            Action::Hillshade(hillshade) => {
+                let o = &hillshade.options;
+                write!(
+                    f,
+                    "(built-in) hillshade {} into {} (",
+                    quote_arg(&hillshade.input.to_string_lossy()),
+                    quote_arg(&self.output.to_string_lossy())
+                )?;
+                if o.multidirectional {
+                    write!(f, "multidirectional")?;
+                } else {
+                    write!(f, "azimuth {}", o.azimuth)?;
+                }
+                write!(f, ", altitude {}, z-factor {})", o.altitude, o.z_factor)
+            }
+            Action::Derivative(derivative) => {
+                let o = &derivative.options;
+                write!(
+                    f,
+                    "(built-in) {} of {} into {} (",
+                    derivative.derivative.name(),
+                    quote_arg(&derivative.input.to_string_lossy()),
+                    quote_arg(&self.output.to_string_lossy())
+                )?;
+                if derivative.derivative == Derivative::Slope {
+                    let units = match o.slope_units {
+                        SlopeUnits::Degrees => "degrees",
+                        SlopeUnits::Percent => "percent",
+                    };
+                    write!(f, "{}, ", units)?;
+                }
+                write!(f, "z-factor {})", o.z_factor)
+            }
+            Action::Contours(contours) => {
+                let o = &contours.options;
+                write!(
+                    f,
+                    "(built-in) contours of {} into {} (every {} from {}",
+                    quote_arg(&contours.input.to_string_lossy()),
+                    quote_arg(&self.output.to_string_lossy()),
+                    o.interval,
+                    o.base
+                )?;
+                if o.index != 0 {
+                    write!(f, ", index every {}", o.index)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Commands as a shell would chain them.
+fn chain(commands: &[ToolCommand]) -> String {
+    let commands: Vec<String> = commands.iter().map(ToString::to_string).collect();
+    commands.join(" && ")
+}
+
+/// The steps of one stage plus what was learned while resolving them.
+#[derive(Debug, Clone)]
+pub struct StagePlan {
+    pub stage: Stage,
+    /// Input tiles found for the stage.
+    pub inputs: Vec<PathBuf>,
+    /// Tiles found but left out by the tile selection or the AOI.
+    pub excluded: Vec<PathBuf>,
+    /// Input tiles whose IGN dalle name disagrees with their header.
+    pub name_mismatches: Vec<NameMismatch>,
+    pub steps: Vec<Step>,
+    /// Layout of the input mosaic, when the tiles could be read natively.
+    pub mosaic: Option<Mosaic>,
+    /// Tile coverage, checked whenever the mosaic layout is known.
+    pub coverage: Option<Coverage>,
+    /// CRS of every input tile.
+    pub crs: Option<CrsReport>,
+}
+
+impl StagePlan {
+    /// A stage made of `steps` alone, working on the outputs of earlier
+    /// stages rather than on input tiles.
+    pub fn steps(stage: Stage, steps: Vec<Step>) -> StagePlan {
+        StagePlan {
+            stage,
+            inputs: Vec::new(),
+            excluded: Vec::new(),
+            name_mismatches: Vec::new(),
+            steps,
+            mosaic: None,
+            coverage: None,
+            crs: None,
+        }
+    }
+
+    /// File handed to the next stage: the output of the last step.
+    pub fn output(&self) -> &Path {
+        self.steps
+            .last()
+            .map_or(Path::new(""), |s| s.output.as_path())
+    }
+
+    pub fn execute(&self, runner: &dyn ToolRunner) -> Result<(), PipelineError> {
+        self.steps.iter().try_for_each(|s| s.execute(runner))
+    }
+}
+
+/// Expected size of a final raster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RasterEstimate {
+    pub path: PathBuf,
+    pub width: usize,
+    pub height: usize,
+    pub bands: usize,
+    pub data_type: DataType,
+}
+
+impl RasterEstimate {
+    /// Uncompressed pixel payload in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.width as u64 * self.height as u64 * self.bands as u64 * self.data_type.size() as u64
+    }
+}
+
+/// A fully resolved build.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    pub stages: Vec<StagePlan>,
+    /// Final products, in build order.
+    pub outputs: Vec<PathBuf>,
+    /// Size estimates for the products whose inputs could be read.
+    pub estimates: Vec<RasterEstimate>,
+    /// Bounds every product is cropped to, if any.
+    pub alignment: Option<Alignment>,
+}
+
+impl Plan {
+    pub fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.stages.iter().flat_map(|s| s.steps.iter())
+    }
+
+    /// Files written by the plan that are not final products.
+    pub fn intermediates(&self) -> Vec<&Path> {
+        self.steps()
+            .map(|s| s.output.as_path())
+            .filter(|p| !self.outputs.iter().any(|o| o == p))
+            .collect()
+    }
+
+    pub fn execute(&self, runner: &dyn ToolRunner) -> Result<(), PipelineError> {
+        self.stages.iter().try_for_each(|s| s.execute(runner))
+    }
+}
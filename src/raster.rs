@@ -0,0 +1,151 @@
+//! Raster metadata read natively from the input tiles.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::PipelineError;
+use crate::geo::Extent;
+use crate::{asc, jp2};
+
+/// Pixel data types, named as in GDAL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Byte,
+    UInt16,
+    Int16,
+    UInt32,
+    Int32,
+    Float32,
+    Float64,
+}
+
+impl DataType {
+    pub fn as_gdal(self) -> &'static str {
+        match self {
+            DataType::Byte => "Byte",
+            DataType::UInt16 => "UInt16",
+            DataType::Int16 => "Int16",
+            DataType::UInt32 => "UInt32",
+            DataType::Int32 => "Int32",
+            DataType::Float32 => "Float32",
+            DataType::Float64 => "Float64",
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            DataType::Byte => 1,
+            DataType::UInt16 | DataType::Int16 => 2,
+            DataType::UInt32 | DataType::Int32 | DataType::Float32 => 4,
+            DataType::Float64 => 8,
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, DataType::Float32 | DataType::Float64)
+    }
+
+    pub fn is_signed(self) -> bool {
+        !matches!(self, DataType::Byte | DataType::UInt16 | DataType::UInt32)
+    }
+
+    /// The smallest type holding every value of both `self` and `other`,
+    /// as GDAL's `GDALDataTypeUnion` without the 64-bit integers: an
+    /// unsigned type mixed with a signed one needs the next signed size
+    /// (`UInt16` and `Int16` give `Int32`), and integers too wide for the
+    /// other type's significand go to `Float64`.
+    pub fn promote(self, other: DataType) -> DataType {
+        if self == other {
+            return self;
+        }
+        if self.is_float() || other.is_float() {
+            // Float32 holds integers of up to 24 bits exactly.
+            let wide = |t: DataType| t == DataType::Float64 || (!t.is_float() && t.size() == 4);
+            return if wide(self) || wide(other) {
+                DataType::Float64
+            } else {
+                DataType::Float32
+            };
+        }
+        let signed = self.is_signed() || other.is_signed();
+        // Bytes an integer type needs under the result's signedness.
+        let needs = |t: DataType| {
+            if signed && !t.is_signed() {
+                t.size() * 2
+            } else {
+                t.size()
+            }
+        };
+        match (signed, needs(self).max(needs(other))) {
+            (false, 1) => DataType::Byte,
+            (false, 2) => DataType::UInt16,
+            (false, _) => DataType::UInt32,
+            (true, 2) => DataType::Int16,
+            (true, 4) => DataType::Int32,
+            (true, _) => DataType::Float64,
+        }
+    }
+}
+
+/// Size, georeferencing and pixel layout of one input tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterInfo {
+    pub path: PathBuf,
+    pub width: usize,
+    pub height: usize,
+    pub bands: usize,
+    pub data_type: DataType,
+    /// GDAL-style geotransform addressing pixel corners.
+    pub geo_transform: [f64; 6],
+    pub nodata: Option<f64>,
+    /// CRS as found in the file or its sidecar: a WKT string or an
+    /// `EPSG:<code>` identifier.
+    pub crs: Option<String>,
+}
+
+impl RasterInfo {
+    pub fn extent(&self) -> Extent {
+        Extent::from_geo_transform(&self.geo_transform, self.width, self.height)
+    }
+
+    /// Pixel width and (positive) pixel height.
+    pub fn pixel_size(&self) -> (f64, f64) {
+        (self.geo_transform[1], self.geo_transform[5].abs())
+    }
+
+    pub fn is_north_up(&self) -> bool {
+        self.geo_transform[2] == 0.0 && self.geo_transform[4] == 0.0
+    }
+}
+
+/// Reads the metadata of a `.asc` or `.jp2` tile without decoding pixels.
+pub fn probe(path: &Path) -> Result<RasterInfo, PipelineError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "asc" => asc::raster_info(path).map_err(|e| PipelineError::input(path, e)),
+        "jp2" => jp2::raster_info(path).map_err(|e| PipelineError::input(path, e)),
+        _ => Err(PipelineError::input(path, "unsupported raster format")),
+    }
+}
+
+/// Reads every tile in `paths`, stopping at the first unreadable one.
+pub fn probe_all(paths: &[PathBuf]) -> Result<Vec<RasterInfo>, PipelineError> {
+    paths.iter().map(|p| probe(p)).collect()
+}
+
+/// Returns the contents of the `.prj` file next to `path`, if any.
+pub fn read_prj_sidecar(path: &Path) -> io::Result<Option<String>> {
+    for extension in ["prj", "PRJ"] {
+        let prj = path.with_extension(extension);
+        if prj.is_file() {
+            let wkt = fs::read_to_string(prj)?;
+            return Ok(Some(wkt.trim().to_string()));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,275 @@
+//! Native replacement for the DEM's `gdalwarp -tr`: resamples a raster
+//! onto another north-up grid of the same CRS, a block of output rows at a
+//! time, so only the source rows under that block are held in memory.
+//!
+//! The kernels follow GDAL's. When downsampling, the interpolating kernels
+//! are stretched over the whole output pixel, and `average` weighs every
+//! source pixel by how much of it the output pixel covers. Nodata (`NaN`)
+//! pixels are left out and the weights of the others renormalised; an
+//! output pixel whose centre falls outside the source, or whose kernel
+//! reaches no valid pixel, is nodata.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+
+use crate::config::Resampling;
+use crate::rows::{RawRows, RawWriter, RowError, RowSource};
+use crate::vrt::RawVrt;
+
+/// A resampling of a raw raster into another, as planned.
+#[derive(Debug, Clone)]
+pub struct NativeResample {
+    /// VRT of the raster read, as written by [`RawWriter`].
+    pub input: PathBuf,
+    pub source: RawVrt,
+    pub target: RawVrt,
+    pub resampling: Resampling,
+    /// Output rows produced at a time.
+    pub block_rows: usize,
+}
+
+impl NativeResample {
+    /// Everything the result depends on besides the source pixels.
+    pub fn recipe(&self) -> String {
+        let describe = |vrt: &RawVrt| {
+            let gt = &vrt.geo_transform;
+            format!(
+                "{}x{} [{}, {}, {}, {}] nodata={}",
+                vrt.width, vrt.height, gt[0], gt[1], gt[3], gt[5], vrt.nodata
+            )
+        };
+        format!(
+            "resample {} {} from {} to {}",
+            self.resampling.as_gdal(),
+            self.input.display(),
+            describe(&self.source),
+            describe(&self.target)
+        )
+    }
+
+    /// Resamples `input` into `output` (a VRT) and its `.bin` pixel file.
+    pub fn run(&self, output: &Path) -> Result<(), RowError> {
+        let mut source = RawRows::open(&self.input, &self.source)?;
+        let mut writer = RawWriter::create(output, self.target.clone())?;
+        resample_rows(
+            &mut source,
+            &self.source.geo_transform,
+            &self.target,
+            self.resampling,
+            self.block_rows,
+            |row| writer.write_row(row),
+        )?;
+        writer.finish()
+    }
+}
+
+/// Resamples `source`, laid out by `source_transform`, onto the grid of
+/// `target`, handing each output row to `sink` top row first.
+///
+/// # Panics
+///
+/// If `resampling` is not one of the kernels implemented natively (see
+/// [`Resampling::is_native`]).
+pub fn resample_rows(
+    source: &mut dyn RowSource,
+    source_transform: &[f64; 6],
+    target: &RawVrt,
+    resampling: Resampling,
+    block_rows: usize,
+    mut sink: impl FnMut(&[f32]) -> Result<(), RowError>,
+) -> Result<(), RowError> {
+    assert!(
+        resampling.is_native(),
+        "no native {} resampling",
+        resampling.as_gdal()
+    );
+    let (s, t) = (source_transform, &target.geo_transform);
+    let columns: Vec<Taps> = (0..target.width)
+        .map(|c| {
+            let x = t[0] + (c as f64 + 0.5) * t[1];
+            let u = (x - s[0]) / s[1];
+            Taps::new(resampling, u, t[1] / s[1], source.width())
+        })
+        .collect();
+
+    let mut rows = SourceRows::default();
+    let mut out = vec![f32::NAN; target.width];
+    let mut start = 0;
+    while start < target.height {
+        let end = (start + block_rows.max(1)).min(target.height);
+        let taps: Vec<Taps> = (start..end)
+            .map(|r| {
+                let y = t[3] + (r as f64 + 0.5) * t[5];
+                let v = (y - s[3]) / s[5];
+                Taps::new(resampling, v, t[5] / s[5], source.height())
+            })
+            .collect();
+        let reach = taps.iter().filter(|t| !t.weights.is_empty());
+        if let (Some(first), Some(last)) = (
+            reach.clone().map(|t| t.first).min(),
+            reach.map(Taps::end).max(),
+        ) {
+            rows.hold(source, first..last)?;
+        }
+        for row_taps in &taps {
+            for (value, column_taps) in out.iter_mut().zip(&columns) {
+                *value = rows.combine(row_taps, column_taps);
+            }
+            sink(&out)?;
+        }
+        start = end;
+    }
+    Ok(())
+}
+
+/// Source pixels an output pixel draws from along one axis, and their
+/// weights. Empty when the output pixel's centre is off the source.
+struct Taps {
+    first: usize,
+    weights: Vec<f64>,
+}
+
+impl Taps {
+    /// Taps for the output pixel centred on `center`, in source pixels
+    /// along an axis of `len` pixels, `scale` source pixels wide.
+    fn new(resampling: Resampling, center: f64, scale: f64, len: usize) -> Taps {
+        if !(center >= 0.0 && center < len as f64) {
+            return Taps {
+                first: 0,
+                weights: Vec::new(),
+            };
+        }
+        let (first, last, weight): (f64, f64, Box<dyn Fn(f64) -> f64>) = match resampling {
+            Resampling::Near => {
+                let pixel = center.floor();
+                (pixel, pixel, Box::new(|_| 1.0))
+            }
+            Resampling::Average => {
+                let (from, to) = (center - scale / 2.0, center + scale / 2.0);
+                let overlap = move |i: f64| (to.min(i + 1.0) - from.max(i)).max(0.0);
+                (
+                    from.floor(),
+                    (to.ceil() - 1.0).max(from.floor()),
+                    Box::new(overlap),
+                )
+            }
+            _ => {
+                let stretch = scale.max(1.0);
+                let support = radius(resampling) * stretch;
+                let kernel = move |i: f64| kernel(resampling, (i + 0.5 - center) / stretch);
+                (
+                    (center - 0.5 - support).ceil(),
+                    (center - 0.5 + support).floor(),
+                    Box::new(kernel),
+                )
+            }
+        };
+        let first = first.max(0.0) as usize;
+        let last = (last.max(0.0) as usize).min(len - 1);
+        Taps {
+            first,
+            weights: (first..=last).map(|i| weight(i as f64)).collect(),
+        }
+    }
+
+    fn end(&self) -> usize {
+        self.first + self.weights.len()
+    }
+}
+
+fn radius(resampling: Resampling) -> f64 {
+    match resampling {
+        Resampling::Bilinear => 1.0,
+        Resampling::Lanczos => 3.0,
+        _ => 2.0,
+    }
+}
+
+fn kernel(resampling: Resampling, x: f64) -> f64 {
+    let x = x.abs();
+    match resampling {
+        Resampling::Bilinear => (1.0 - x).max(0.0),
+        // Keys' cubic convolution, a = -0.5.
+        Resampling::Cubic => {
+            if x < 1.0 {
+                (1.5 * x - 2.5) * x * x + 1.0
+            } else if x < 2.0 {
+                ((-0.5 * x + 2.5) * x - 4.0) * x + 2.0
+            } else {
+                0.0
+            }
+        }
+        // Cubic B-spline.
+        Resampling::CubicSpline => {
+            if x < 1.0 {
+                (4.0 - 6.0 * x * x + 3.0 * x * x * x) / 6.0
+            } else if x < 2.0 {
+                (2.0 - x).powi(3) / 6.0
+            } else {
+                0.0
+            }
+        }
+        Resampling::Lanczos => {
+            if x == 0.0 {
+                1.0
+            } else if x < 3.0 {
+                3.0 * (PI * x).sin() * (PI * x / 3.0).sin() / (PI * PI * x * x)
+            } else {
+                0.0
+            }
+        }
+        _ => unreachable!("not an interpolating kernel"),
+    }
+}
+
+/// The source rows currently held, `rows[0]` being row `first`.
+#[derive(Default)]
+struct SourceRows {
+    rows: VecDeque<Vec<f32>>,
+    first: usize,
+}
+
+impl SourceRows {
+    /// Drops the rows before `wanted` and reads up to its end.
+    fn hold(
+        &mut self,
+        source: &mut dyn RowSource,
+        wanted: std::ops::Range<usize>,
+    ) -> Result<(), RowError> {
+        while self.first < wanted.start && !self.rows.is_empty() {
+            self.rows.pop_front();
+            self.first += 1;
+        }
+        // Rows skipped entirely still have to be read past.
+        while self.first + self.rows.len() < wanted.end {
+            let mut row = vec![0.0; source.width()];
+            source.read_row(&mut row)?;
+            if self.first + self.rows.len() < wanted.start {
+                self.first += 1;
+            } else {
+                self.rows.push_back(row);
+            }
+        }
+        Ok(())
+    }
+
+    fn combine(&self, rows: &Taps, columns: &Taps) -> f32 {
+        let (mut sum, mut weights) = (0.0, 0.0);
+        for (r, wy) in (rows.first..).zip(&rows.weights) {
+            let row = &self.rows[r - self.first];
+            for (&value, wx) in row[columns.first..].iter().zip(&columns.weights) {
+                let w = wy * wx;
+                if !value.is_nan() && w != 0.0 {
+                    sum += w * value as f64;
+                    weights += w;
+                }
+            }
+        }
+        if weights.abs() < 1e-9 {
+            f32::NAN
+        } else {
+            (sum / weights) as f32
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Row-by-row access to whole rasters, so the native processing stages run
+//! in bounded memory however large the mosaic.
+//!
+//! Pixels travel as `f32` with `NaN` standing for nodata; the declared
+//! nodata value only appears again when a raster is written.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::asc::{AscError, AscReader};
+use crate::vrt::{Mosaic, RawVrt};
+
+/// Why a raster could not be streamed.
+#[derive(Debug)]
+pub enum RowError {
+    /// A source raster cannot be read.
+    Input { path: PathBuf, message: String },
+    /// An output could not be written.
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl RowError {
+    pub(crate) fn input(path: &Path, message: impl fmt::Display) -> Self {
+        RowError::Input {
+            path: path.to_path_buf(),
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn io(path: &Path, source: io::Error) -> Self {
+        RowError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RowError::Input { path, message } => write!(f, "{}: {}", path.display(), message),
+            RowError::Io { path, source } => {
+                write!(f, "failed to write {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+/// A single-band raster read top row first.
+pub trait RowSource {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// Fills `row` (`width` values) with the next row.
+    fn read_row(&mut self, row: &mut [f32]) -> Result<(), RowError>;
+}
+
+/// Where a tile lands in the mosaic, in pixels.
+#[derive(Debug, Clone)]
+struct Placement {
+    path: PathBuf,
+    column: usize,
+    row: usize,
+    height: usize,
+}
+
+/// Streams a mosaic of `.asc` tiles sharing the mosaic's pixel grid,
+/// opening each tile when its first row is reached. Like a VRT, later
+/// tiles are drawn over earlier ones except where they hold nodata.
+pub struct MosaicRows {
+    width: usize,
+    height: usize,
+    placements: Vec<Placement>,
+    open: Vec<(usize, AscReader<BufReader<File>>)>,
+    next_row: usize,
+    scratch: Vec<f32>,
+}
+
+impl MosaicRows {
+    pub fn new(mosaic: &Mosaic) -> Result<MosaicRows, RowError> {
+        let gt = &mosaic.geo_transform;
+        let (res_x, res_y) = (gt[1], -gt[5]);
+        let mut placements = Vec::with_capacity(mosaic.tiles.len());
+        for tile in &mosaic.tiles {
+            let is_asc = tile
+                .path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("asc"));
+            if !is_asc {
+                return Err(RowError::input(&tile.path, "only .asc tiles can be read"));
+            }
+            let (x, y) = tile.pixel_size();
+            let same = |a: f64, b: f64| (a - b).abs() <= b * 1e-6;
+            let column = (tile.geo_transform[0] - gt[0]) / res_x;
+            let row = (gt[3] - tile.geo_transform[3]) / res_y;
+            let on_grid = |v: f64| (v - v.round()).abs() < 1e-3 && v.round() >= 0.0;
+            if !(same(x, res_x) && same(y, res_y) && on_grid(column) && on_grid(row)) {
+                return Err(RowError::input(
+                    &tile.path,
+                    "tile is not on the mosaic's pixel grid",
+                ));
+            }
+            placements.push(Placement {
+                path: tile.path.clone(),
+                column: column.round() as usize,
+                row: row.round() as usize,
+                height: tile.height,
+            });
+        }
+        Ok(MosaicRows {
+            width: mosaic.width,
+            height: mosaic.height,
+            placements,
+            open: Vec::new(),
+            next_row: 0,
+            scratch: Vec::new(),
+        })
+    }
+}
+
+impl RowSource for MosaicRows {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn read_row(&mut self, row: &mut [f32]) -> Result<(), RowError> {
+        let r = self.next_row;
+        for (index, placement) in self.placements.iter().enumerate() {
+            if placement.row == r && placement.height > 0 {
+                let reader = AscReader::open(&placement.path)
+                    .map_err(|e: AscError| RowError::input(&placement.path, e))?;
+                let at = self.open.partition_point(|(i, _)| *i < index);
+                self.open.insert(at, (index, reader));
+            }
+        }
+
+        row.fill(f32::NAN);
+        for (index, reader) in &mut self.open {
+            let placement = &self.placements[*index];
+            self.scratch.resize(reader.header().ncols, 0.0);
+            reader
+                .read_row(&mut self.scratch)
+                .map_err(|e| RowError::input(&placement.path, e))?;
+            let header = reader.header();
+            let targets = row.iter_mut().skip(placement.column);
+            for (target, &value) in targets.zip(&self.scratch) {
+                if !header.is_nodata(value) && value.is_finite() {
+                    *target = value;
+                }
+            }
+        }
+        self.open
+            .retain(|(index, reader)| reader.rows_read() < self.placements[*index].height);
+        self.next_row += 1;
+        Ok(())
+    }
+}
+
+/// Streams a raster written by [`RawWriter`] back, nodata becoming `NaN`.
+pub struct RawRows {
+    width: usize,
+    height: usize,
+    nodata: f32,
+    data_path: PathBuf,
+    input: BufReader<File>,
+    bytes: Vec<u8>,
+}
+
+impl RawRows {
+    /// Opens the `.bin` next to `vrt_path`, which `vrt` describes.
+    pub fn open(vrt_path: &Path, vrt: &RawVrt) -> Result<RawRows, RowError> {
+        let data_path = vrt_path.with_extension("bin");
+        let file = File::open(&data_path).map_err(|e| RowError::input(&data_path, e))?;
+        let expected = vrt.width as u64 * vrt.height as u64 * 4;
+        let size = file
+            .metadata()
+            .map_err(|e| RowError::input(&data_path, e))?
+            .len();
+        if size != expected {
+            return Err(RowError::input(
+                &data_path,
+                format!(
+                    "holds {} bytes, {} x {} Float32 pixels need {}",
+                    size, vrt.width, vrt.height, expected
+                ),
+            ));
+        }
+        Ok(RawRows {
+            width: vrt.width,
+            height: vrt.height,
+            nodata: vrt.nodata as f32,
+            data_path,
+            input: BufReader::new(file),
+            bytes: Vec::new(),
+        })
+    }
+}
+
+impl RowSource for RawRows {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn read_row(&mut self, row: &mut [f32]) -> Result<(), RowError> {
+        self.bytes.resize(self.width * 4, 0);
+        self.input
+            .read_exact(&mut self.bytes)
+            .map_err(|e| RowError::input(&self.data_path, e))?;
+        for (value, bytes) in row.iter_mut().zip(self.bytes.chunks_exact(4)) {
+            let v = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            *value = if v == self.nodata { f32::NAN } else { v };
+        }
+        Ok(())
+    }
+}
+
+/// Rows around a block being processed.
+pub struct Window<'a> {
+    /// Rows held, `rows[0]` being row `first_row` of the raster.
+    pub rows: &'a [Vec<f32>],
+    pub first_row: usize,
+    /// Rows of the raster this block is responsible for.
+    pub block: Range<usize>,
+    pub width: usize,
+}
+
+impl Window<'_> {
+    /// Raster rows held, margins included.
+    pub fn held(&self) -> Range<usize> {
+        self.first_row..self.first_row + self.rows.len()
+    }
+
+    /// The pixel at raster row `row`, which must be held.
+    pub fn get(&self, row: usize, column: usize) -> f32 {
+        self.rows[row - self.first_row][column]
+    }
+}
+
+/// Reads `source` once, calling `process` for every block of `block_rows`
+/// rows with up to `margin` extra rows above and below it.
+pub fn for_each_block<E>(
+    source: &mut dyn RowSource,
+    block_rows: usize,
+    margin: usize,
+    mut process: impl FnMut(&Window) -> Result<(), E>,
+) -> Result<(), E>
+where
+    E: From<RowError>,
+{
+    let (width, height) = (source.width(), source.height());
+    let mut rows: VecDeque<Vec<f32>> = VecDeque::new();
+    let mut first_row = 0;
+    let mut start = 0;
+    while start < height {
+        let end = (start + block_rows.max(1)).min(height);
+        let wanted = start.saturating_sub(margin)..(end + margin).min(height);
+        while first_row < wanted.start {
+            rows.pop_front();
+            first_row += 1;
+        }
+        while first_row + rows.len() < wanted.end {
+            let mut row = vec![0.0; width];
+            source.read_row(&mut row)?;
+            rows.push_back(row);
+        }
+        process(&Window {
+            rows: rows.make_contiguous(),
+            first_row,
+            block: start..end,
+            width,
+        })?;
+        start = end;
+    }
+    Ok(())
+}
+
+/// Writes a single-band `Float32` raster as raw little-endian pixels next
+/// to a VRT describing them, which GDAL reads like any other raster.
+pub struct RawWriter {
+    vrt: RawVrt,
+    vrt_path: PathBuf,
+    data_path: PathBuf,
+    out: BufWriter<File>,
+    nodata: f32,
+    rows_written: usize,
+}
+
+impl RawWriter {
+    /// Creates `<vrt_path without extension>.bin` for the pixels; the VRT
+    /// itself is written by [`RawWriter::finish`].
+    pub fn create(vrt_path: &Path, vrt: RawVrt) -> Result<RawWriter, RowError> {
+        let data_path = vrt_path.with_extension("bin");
+        let file = File::create(&data_path).map_err(|e| RowError::io(&data_path, e))?;
+        Ok(RawWriter {
+            nodata: vrt.nodata as f32,
+            vrt,
+            vrt_path: vrt_path.to_path_buf(),
+            data_path,
+            out: BufWriter::new(file),
+            rows_written: 0,
+        })
+    }
+
+    pub fn write_row(&mut self, row: &[f32]) -> Result<(), RowError> {
+        assert_eq!(row.len(), self.vrt.width, "row must hold width values");
+        let mut bytes = Vec::with_capacity(row.len() * 4);
+        for &value in row {
+            let value = if value.is_nan() { self.nodata } else { value };
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        self.out
+            .write_all(&bytes)
+            .map_err(|e| RowError::io(&self.data_path, e))?;
+        self.rows_written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), RowError> {
+        assert_eq!(
+            self.rows_written, self.vrt.height,
+            "every row must be written"
+        );
+        self.out
+            .flush()
+            .map_err(|e| RowError::io(&self.data_path, e))?;
+        let file_name = self.data_path.file_name().unwrap_or_default();
+        std::fs::write(&self.vrt_path, self.vrt.to_xml(Path::new(file_name)))
+            .map_err(|e| RowError::io(&self.vrt_path, e))
+    }
+}
@@ -0,0 +1,66 @@
+//! 3 x 3 neighbourhoods of the DEM, shared by the rasters derived from it.
+//!
+//! The DEM is read a block of rows at a time with one extra row above and
+//! below, so edge pixels of a block see their neighbours and results do
+//! not depend on where blocks are cut. Neighbours outside the raster or
+//! without a height take the centre's, so the raster's edges and the rims
+//! of holes get a value as well; pixels without a height stay nodata.
+
+use crate::rows::{for_each_block, RowError, RowSource, Window};
+
+/// Heights around a pixel, row-major from the north-west corner, the
+/// pixel itself at index 4.
+pub type Neighbourhood = [f64; 9];
+
+/// The neighbourhood of the pixel at `row`, `column`, or `None` when the
+/// pixel has no height.
+pub fn neighbourhood(window: &Window, row: usize, column: usize) -> Option<Neighbourhood> {
+    let centre = window.get(row, column);
+    if centre.is_nan() {
+        return None;
+    }
+    let held = window.held();
+    let mut z = [centre as f64; 9];
+    for (i, value) in z.iter_mut().enumerate() {
+        let (r, c) = (
+            (row + i / 3).checked_sub(1),
+            (column + i % 3).checked_sub(1),
+        );
+        let (Some(r), Some(c)) = (r, c) else {
+            continue;
+        };
+        if held.contains(&r) && c < window.width {
+            let neighbour = window.get(r, c);
+            if !neighbour.is_nan() {
+                *value = neighbour as f64;
+            }
+        }
+    }
+    Some(z)
+}
+
+/// Height change per map unit eastwards and northwards, by Horn's
+/// weighted differences as `gdaldem` computes them.
+pub fn gradient(z: &Neighbourhood, res_x: f64, res_y: f64) -> (f64, f64) {
+    let east = ((z[2] + 2.0 * z[5] + z[8]) - (z[0] + 2.0 * z[3] + z[6])) / (8.0 * res_x);
+    let north = ((z[0] + 2.0 * z[1] + z[2]) - (z[6] + 2.0 * z[7] + z[8])) / (8.0 * res_y);
+    (east, north)
+}
+
+/// Reads `source` once, `block_rows` rows at a time, handing `process` the
+/// neighbourhoods of each row, top row first.
+pub fn for_each_row(
+    source: &mut dyn RowSource,
+    block_rows: usize,
+    mut process: impl FnMut(&[Option<Neighbourhood>]) -> Result<(), RowError>,
+) -> Result<(), RowError> {
+    let mut row = Vec::with_capacity(source.width());
+    for_each_block(source, block_rows, 1, |window| {
+        for r in window.block.clone() {
+            row.clear();
+            row.extend((0..window.width).map(|c| neighbourhood(window, r, c)));
+            process(&row)?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,371 @@
+//! Minimal TIFF structure reader: header and IFD entries, classic and
+//! BigTIFF, either byte order. Pixel data is not decoded here.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+pub const TAG_NEW_SUBFILE_TYPE: u16 = 254;
+pub const TAG_IMAGE_WIDTH: u16 = 256;
+pub const TAG_IMAGE_LENGTH: u16 = 257;
+pub const TAG_BITS_PER_SAMPLE: u16 = 258;
+pub const TAG_COMPRESSION: u16 = 259;
+pub const TAG_PHOTOMETRIC: u16 = 262;
+pub const TAG_STRIP_OFFSETS: u16 = 273;
+pub const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+pub const TAG_ROWS_PER_STRIP: u16 = 278;
+pub const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+pub const TAG_PLANAR_CONFIGURATION: u16 = 284;
+pub const TAG_PREDICTOR: u16 = 317;
+pub const TAG_TILE_WIDTH: u16 = 322;
+pub const TAG_TILE_LENGTH: u16 = 323;
+pub const TAG_TILE_OFFSETS: u16 = 324;
+pub const TAG_TILE_BYTE_COUNTS: u16 = 325;
+pub const TAG_SAMPLE_FORMAT: u16 = 339;
+pub const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+pub const TAG_MODEL_TIEPOINT: u16 = 33922;
+pub const TAG_MODEL_TRANSFORMATION: u16 = 34264;
+pub const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+pub const TAG_GEO_DOUBLE_PARAMS: u16 = 34736;
+pub const TAG_GEO_ASCII_PARAMS: u16 = 34737;
+pub const TAG_GDAL_METADATA: u16 = 42112;
+pub const TAG_GDAL_NODATA: u16 = 42113;
+
+pub const COMPRESSION_NONE: u16 = 1;
+pub const COMPRESSION_LZW: u16 = 5;
+pub const COMPRESSION_DEFLATE: u16 = 8;
+pub const COMPRESSION_ZSTD: u16 = 50000;
+
+#[derive(Debug)]
+pub enum TiffError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for TiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TiffError::Io(e) => write!(f, "{}", e),
+            TiffError::Format(msg) => write!(f, "malformed TIFF: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for TiffError {}
+
+impl From<io::Error> for TiffError {
+    fn from(e: io::Error) -> Self {
+        TiffError::Io(e)
+    }
+}
+
+/// The decoded value of an IFD entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// BYTE, SHORT, LONG, LONG8 and their signed variants.
+    Integers(Vec<i64>),
+    /// FLOAT, DOUBLE and (S)RATIONAL.
+    Floats(Vec<f64>),
+    Ascii(String),
+    /// UNDEFINED and unknown field types.
+    Bytes(Vec<u8>),
+}
+
+/// One image file directory.
+#[derive(Debug, Clone, Default)]
+pub struct Ifd {
+    /// File offset the IFD was read from.
+    pub offset: u64,
+    pub entries: BTreeMap<u16, Value>,
+}
+
+impl Ifd {
+    pub fn get(&self, tag: u16) -> Option<&Value> {
+        self.entries.get(&tag)
+    }
+
+    pub fn integers(&self, tag: u16) -> Option<&[i64]> {
+        match self.get(tag)? {
+            Value::Integers(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, tag: u16) -> Option<i64> {
+        self.integers(tag)?.first().copied()
+    }
+
+    /// Numeric values of any numeric type, widened to `f64`.
+    pub fn floats(&self, tag: u16) -> Option<Vec<f64>> {
+        match self.get(tag)? {
+            Value::Floats(v) => Some(v.clone()),
+            Value::Integers(v) => Some(v.iter().map(|&i| i as f64).collect()),
+            _ => None,
+        }
+    }
+
+    pub fn ascii(&self, tag: u16) -> Option<&str> {
+        match self.get(tag)? {
+            Value::Ascii(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the IFD chain of a TIFF stream.
+pub struct TiffReader<R> {
+    reader: R,
+    big_endian: bool,
+    big_tiff: bool,
+    first_ifd: u64,
+    /// Length of the stream, bounding every count read from it.
+    len: u64,
+}
+
+impl<R: Read + Seek> TiffReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, TiffError> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        let big_endian = match &header[..2] {
+            b"II" => false,
+            b"MM" => true,
+            _ => return Err(TiffError::Format("bad byte-order mark".to_string())),
+        };
+        let mut tiff = TiffReader {
+            reader,
+            big_endian,
+            big_tiff: false,
+            first_ifd: 0,
+            len,
+        };
+        let version = tiff.u16_from(&header[2..4]);
+        tiff.first_ifd = match version {
+            42 => tiff.read_u32()? as u64,
+            43 => {
+                tiff.big_tiff = true;
+                let _offset_size = tiff.read_u16()?;
+                let _reserved = tiff.read_u16()?;
+                tiff.read_u64()?
+            }
+            other => return Err(TiffError::Format(format!("unknown version {}", other))),
+        };
+        Ok(tiff)
+    }
+
+    pub fn is_big_tiff(&self) -> bool {
+        self.big_tiff
+    }
+
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    /// Offset of the first IFD as stored in the header.
+    pub fn first_ifd_offset(&self) -> u64 {
+        self.first_ifd
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads every IFD in the main chain (SubIFDs are not followed).
+    pub fn read_ifds(&mut self) -> Result<Vec<Ifd>, TiffError> {
+        let mut ifds = Vec::new();
+        let mut next = self.first_ifd;
+        while next != 0 {
+            if ifds.iter().any(|ifd: &Ifd| ifd.offset == next) {
+                return Err(TiffError::Format("IFD chain loops".to_string()));
+            }
+            let (ifd, following) = self.read_ifd(next)?;
+            ifds.push(ifd);
+            next = following;
+        }
+        Ok(ifds)
+    }
+
+    /// Reads the IFD at `offset`, returning it and the next IFD offset.
+    pub fn read_ifd(&mut self, offset: u64) -> Result<(Ifd, u64), TiffError> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let count = if self.big_tiff {
+            self.read_u64()?
+        } else {
+            self.read_u16()? as u64
+        };
+        let entry_size = if self.big_tiff { 20 } else { 12 };
+        let at = self.reader.stream_position()?;
+        let mut raw = vec![0u8; self.checked_size("IFD", count, entry_size, at)?];
+        self.reader.read_exact(&mut raw)?;
+        let next = if self.big_tiff {
+            self.read_u64()?
+        } else {
+            self.read_u32()? as u64
+        };
+
+        let mut ifd = Ifd {
+            offset,
+            entries: BTreeMap::new(),
+        };
+        for entry in raw.chunks(entry_size) {
+            let tag = self.u16_from(&entry[0..2]);
+            let field_type = self.u16_from(&entry[2..4]);
+            let (count, inline) = if self.big_tiff {
+                (self.u64_from(&entry[4..12]), &entry[12..20])
+            } else {
+                (self.u32_from(&entry[4..8]) as u64, &entry[8..12])
+            };
+            let size = count.checked_mul(type_size(field_type) as u64);
+            let bytes = match size {
+                Some(size) if size <= inline.len() as u64 => inline[..size as usize].to_vec(),
+                _ => {
+                    let at = if self.big_tiff {
+                        self.u64_from(inline)
+                    } else {
+                        self.u32_from(inline) as u64
+                    };
+                    let what = format!("tag {}", tag);
+                    let mut buf =
+                        vec![0u8; self.checked_size(&what, count, type_size(field_type), at)?];
+                    self.reader.seek(SeekFrom::Start(at))?;
+                    self.reader.read_exact(&mut buf)?;
+                    buf
+                }
+            };
+            ifd.entries.insert(tag, self.decode(field_type, &bytes));
+        }
+        Ok((ifd, next))
+    }
+
+    /// Size of `count` values of `size` bytes at `at`, refused when it
+    /// overflows or runs past the end of the stream.
+    fn checked_size(
+        &self,
+        what: &str,
+        count: u64,
+        size: usize,
+        at: u64,
+    ) -> Result<usize, TiffError> {
+        count
+            .checked_mul(size as u64)
+            .filter(|&bytes| at.checked_add(bytes).is_some_and(|end| end <= self.len))
+            .and_then(|bytes| usize::try_from(bytes).ok())
+            .ok_or_else(|| {
+                TiffError::Format(format!(
+                    "{} of {} values runs past the end of the file",
+                    what, count
+                ))
+            })
+    }
+
+    fn decode(&self, field_type: u16, bytes: &[u8]) -> Value {
+        match field_type {
+            1 => Value::Integers(bytes.iter().map(|&b| b as i64).collect()),
+            6 => Value::Integers(bytes.iter().map(|&b| b as i8 as i64).collect()),
+            2 => Value::Ascii(
+                String::from_utf8_lossy(bytes)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            ),
+            3 => Value::Integers(bytes.chunks(2).map(|c| self.u16_from(c) as i64).collect()),
+            8 => Value::Integers(
+                bytes
+                    .chunks(2)
+                    .map(|c| self.u16_from(c) as i16 as i64)
+                    .collect(),
+            ),
+            4 | 13 => Value::Integers(bytes.chunks(4).map(|c| self.u32_from(c) as i64).collect()),
+            9 => Value::Integers(
+                bytes
+                    .chunks(4)
+                    .map(|c| self.u32_from(c) as i32 as i64)
+                    .collect(),
+            ),
+            16..=18 => Value::Integers(bytes.chunks(8).map(|c| self.u64_from(c) as i64).collect()),
+            5 => Value::Floats(
+                bytes
+                    .chunks(8)
+                    .map(|c| self.u32_from(&c[..4]) as f64 / self.u32_from(&c[4..]) as f64)
+                    .collect(),
+            ),
+            10 => Value::Floats(
+                bytes
+                    .chunks(8)
+                    .map(|c| {
+                        self.u32_from(&c[..4]) as i32 as f64 / self.u32_from(&c[4..]) as i32 as f64
+                    })
+                    .collect(),
+            ),
+            11 => Value::Floats(
+                bytes
+                    .chunks(4)
+                    .map(|c| f32::from_bits(self.u32_from(c)) as f64)
+                    .collect(),
+            ),
+            12 => Value::Floats(
+                bytes
+                    .chunks(8)
+                    .map(|c| f64::from_bits(self.u64_from(c)))
+                    .collect(),
+            ),
+            _ => Value::Bytes(bytes.to_vec()),
+        }
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        let mut b = [0u8; 2];
+        self.reader.read_exact(&mut b)?;
+        Ok(self.u16_from(&b))
+    }
+
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut b = [0u8; 4];
+        self.reader.read_exact(&mut b)?;
+        Ok(self.u32_from(&b))
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut b = [0u8; 8];
+        self.reader.read_exact(&mut b)?;
+        Ok(self.u64_from(&b))
+    }
+
+    fn u16_from(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+
+    fn u32_from(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+
+    fn u64_from(&self, b: &[u8]) -> u64 {
+        let b = [b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]];
+        if self.big_endian {
+            u64::from_be_bytes(b)
+        } else {
+            u64::from_le_bytes(b)
+        }
+    }
+}
+
+/// Size in bytes of one value of a TIFF field type.
+pub fn type_size(field_type: u16) -> usize {
+    match field_type {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 | 13 => 4,
+        5 | 10 | 12 | 16 | 17 | 18 => 8,
+        _ => 1,
+    }
+}
@@ -0,0 +1,348 @@
+//! Row-by-row reading of single-band GeoTIFFs, so the stages computed
+//! from the DEM read `out/dem.tiff` whichever backend wrote it.
+//!
+//! Strips and tiles are decoded a row of blocks at a time: uncompressed,
+//! DEFLATE, LZW or ZSTD, with or without a predictor, any of GDAL's
+//! integer or floating-point sample types.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::geotiff::{geo_transform, GeoKeys};
+use crate::raster::DataType;
+use crate::rows::{RowError, RowSource};
+use crate::tiff::{
+    Ifd, TiffReader, COMPRESSION_DEFLATE, COMPRESSION_LZW, COMPRESSION_NONE, COMPRESSION_ZSTD,
+    TAG_BITS_PER_SAMPLE, TAG_COMPRESSION, TAG_GDAL_NODATA, TAG_IMAGE_LENGTH, TAG_IMAGE_WIDTH,
+    TAG_PREDICTOR, TAG_ROWS_PER_STRIP, TAG_SAMPLES_PER_PIXEL, TAG_SAMPLE_FORMAT,
+    TAG_STRIP_BYTE_COUNTS, TAG_STRIP_OFFSETS, TAG_TILE_BYTE_COUNTS, TAG_TILE_LENGTH,
+    TAG_TILE_OFFSETS, TAG_TILE_WIDTH,
+};
+
+/// Adobe's code for DEFLATE, still written by some tools.
+const COMPRESSION_ADOBE_DEFLATE: u16 = 32946;
+
+/// Streams the first band of a GeoTIFF top row first, nodata and `NaN`
+/// pixels becoming `NaN`.
+pub struct GeoTiffRows {
+    path: PathBuf,
+    input: BufReader<File>,
+    big_endian: bool,
+    width: usize,
+    height: usize,
+    geo_transform: [f64; 6],
+    crs: Option<String>,
+    nodata: Option<f64>,
+    data_type: DataType,
+    compression: u16,
+    predictor: u16,
+    /// Block size; strips are blocks as wide as the image.
+    block: (usize, usize),
+    offsets: Vec<u64>,
+    counts: Vec<u64>,
+    /// The decoded row of blocks holding the next rows, `width` wide.
+    rows: Vec<f32>,
+    rows_start: usize,
+    next_row: usize,
+}
+
+impl GeoTiffRows {
+    pub fn open(path: &Path) -> Result<GeoTiffRows, RowError> {
+        let error = |message: String| RowError::input(path, message);
+        let file = File::open(path).map_err(|e| RowError::input(path, e))?;
+        let mut reader = TiffReader::new(BufReader::new(file)).map_err(|e| error(e.to_string()))?;
+        let ifd = reader
+            .read_ifds()
+            .map_err(|e| error(e.to_string()))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| error("the file holds no image".to_string()))?;
+        let big_endian = reader.is_big_endian();
+
+        let dimension = |tag| {
+            ifd.integer(tag)
+                .filter(|&v| v > 0)
+                .map(|v| v as usize)
+                .ok_or_else(|| error(format!("missing or invalid tag {}", tag)))
+        };
+        let (width, height) = (dimension(TAG_IMAGE_WIDTH)?, dimension(TAG_IMAGE_LENGTH)?);
+        let (block, offsets, counts) = if ifd.get(TAG_TILE_WIDTH).is_some() {
+            (
+                (dimension(TAG_TILE_WIDTH)?, dimension(TAG_TILE_LENGTH)?),
+                TAG_TILE_OFFSETS,
+                TAG_TILE_BYTE_COUNTS,
+            )
+        } else {
+            let rows = ifd
+                .integer(TAG_ROWS_PER_STRIP)
+                .map_or(height, |r| (r as usize).clamp(1, height));
+            ((width, rows), TAG_STRIP_OFFSETS, TAG_STRIP_BYTE_COUNTS)
+        };
+        let list = |tag| -> Result<Vec<u64>, RowError> {
+            let values = ifd
+                .integers(tag)
+                .ok_or_else(|| error(format!("missing tag {}", tag)))?;
+            Ok(values.iter().map(|&v| v as u64).collect())
+        };
+        let (offsets, counts) = (list(offsets)?, list(counts)?);
+        let blocks = width.div_ceil(block.0) * height.div_ceil(block.1);
+        if offsets.len() < blocks || counts.len() < blocks {
+            return Err(error(format!(
+                "{} blocks expected, {} offsets found",
+                blocks,
+                offsets.len()
+            )));
+        }
+
+        if ifd.integer(TAG_SAMPLES_PER_PIXEL).unwrap_or(1) != 1 {
+            return Err(error("only single-band rasters can be read".to_string()));
+        }
+        let data_type = sample_type(&ifd).map_err(error)?;
+        let compression = ifd.integer(TAG_COMPRESSION).unwrap_or(1) as u16;
+        if ![
+            COMPRESSION_NONE,
+            COMPRESSION_LZW,
+            COMPRESSION_DEFLATE,
+            COMPRESSION_ADOBE_DEFLATE,
+            COMPRESSION_ZSTD,
+        ]
+        .contains(&compression)
+        {
+            return Err(error(format!("unsupported compression {}", compression)));
+        }
+        let predictor = ifd.integer(TAG_PREDICTOR).unwrap_or(1) as u16;
+        if !(1..=3).contains(&predictor) {
+            return Err(error(format!("unsupported predictor {}", predictor)));
+        }
+
+        let keys = GeoKeys::from_ifd(&ifd);
+        let geo_transform = geo_transform(&ifd, keys.as_ref())
+            .ok_or_else(|| error("the file is not georeferenced".to_string()))?;
+        let nodata = ifd
+            .ascii(TAG_GDAL_NODATA)
+            .and_then(|n| n.trim().parse::<f64>().ok());
+        Ok(GeoTiffRows {
+            path: path.to_path_buf(),
+            input: reader.into_inner(),
+            big_endian,
+            width,
+            height,
+            geo_transform,
+            crs: keys
+                .and_then(|k| k.epsg())
+                .map(|code| format!("EPSG:{}", code)),
+            nodata,
+            data_type,
+            compression,
+            predictor,
+            block,
+            offsets,
+            counts,
+            rows: Vec::new(),
+            rows_start: 0,
+            next_row: 0,
+        })
+    }
+
+    /// GDAL-style geotransform addressing pixel corners.
+    pub fn geo_transform(&self) -> [f64; 6] {
+        self.geo_transform
+    }
+
+    /// `EPSG:<code>`, when the GeoKeys carry one.
+    pub fn crs(&self) -> Option<&str> {
+        self.crs.as_deref()
+    }
+
+    pub fn nodata(&self) -> Option<f64> {
+        self.nodata
+    }
+
+    /// Decodes the row of blocks starting at image row `start`.
+    fn load_blocks(&mut self, start: usize) -> Result<(), RowError> {
+        let (block_width, block_height) = self.block;
+        let rows = block_height.min(self.height - start);
+        let across = self.width.div_ceil(block_width);
+        let first = start / block_height * across;
+        self.rows.clear();
+        self.rows.resize(self.width * rows, f32::NAN);
+        for b in 0..across {
+            let samples = self.read_block(first + b)?;
+            let x0 = b * block_width;
+            let columns = block_width.min(self.width - x0);
+            for r in 0..rows {
+                let from = &samples[r * block_width..r * block_width + columns];
+                self.rows[r * self.width + x0..][..columns].copy_from_slice(from);
+            }
+        }
+        self.rows_start = start;
+        Ok(())
+    }
+
+    /// The samples of block `index`, `block.0 * block.1` of them even for
+    /// a short last strip.
+    fn read_block(&mut self, index: usize) -> Result<Vec<f32>, RowError> {
+        let (block_width, block_height) = self.block;
+        let size = self.data_type.size();
+        let expected = block_width * block_height * size;
+        let (offset, count) = (self.offsets[index], self.counts[index]);
+        if offset == 0 || count == 0 {
+            // Sparse block: never written, all nodata.
+            return Ok(vec![f32::NAN; block_width * block_height]);
+        }
+        let mut raw = vec![0; count as usize];
+        self.input
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.input.read_exact(&mut raw))
+            .map_err(|e| RowError::input(&self.path, e))?;
+        let mut bytes = self.decompress(raw)?;
+        bytes.resize(expected, 0);
+        let row_bytes = block_width * size;
+        match self.predictor {
+            2 => {
+                for row in bytes.chunks_mut(row_bytes) {
+                    undo_horizontal(row, size, self.big_endian);
+                }
+            }
+            3 => {
+                for row in bytes.chunks_mut(row_bytes) {
+                    undo_floating_point(row, size, self.big_endian);
+                }
+            }
+            _ => {}
+        }
+        let nodata = self.nodata;
+        Ok(bytes
+            .chunks_exact(size)
+            .map(|b| {
+                let value = decode(b, self.data_type, self.big_endian);
+                if nodata.is_some_and(|n| value == n) {
+                    f32::NAN
+                } else {
+                    value as f32
+                }
+            })
+            .collect())
+    }
+
+    fn decompress(&self, raw: Vec<u8>) -> Result<Vec<u8>, RowError> {
+        let error = |e: &dyn std::fmt::Display| RowError::input(&self.path, e);
+        match self.compression {
+            COMPRESSION_LZW => {
+                weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                    .decode(&raw)
+                    .map_err(|e| error(&e))
+            }
+            COMPRESSION_DEFLATE | COMPRESSION_ADOBE_DEFLATE => {
+                let mut out = Vec::new();
+                flate2::read::ZlibDecoder::new(&raw[..])
+                    .read_to_end(&mut out)
+                    .map_err(|e| error(&e))?;
+                Ok(out)
+            }
+            COMPRESSION_ZSTD => zstd::decode_all(&raw[..]).map_err(|e| error(&e)),
+            _ => Ok(raw),
+        }
+    }
+}
+
+impl RowSource for GeoTiffRows {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn read_row(&mut self, row: &mut [f32]) -> Result<(), RowError> {
+        let held = self.rows.len() / self.width;
+        if self.next_row >= self.rows_start + held || self.rows.is_empty() {
+            self.load_blocks(self.next_row)?;
+        }
+        let at = (self.next_row - self.rows_start) * self.width;
+        row.copy_from_slice(&self.rows[at..at + self.width]);
+        self.next_row += 1;
+        Ok(())
+    }
+}
+
+/// The GDAL data type of the samples of `ifd`.
+fn sample_type(ifd: &Ifd) -> Result<DataType, String> {
+    let bits = ifd.integer(TAG_BITS_PER_SAMPLE).unwrap_or(1);
+    let format = ifd.integer(TAG_SAMPLE_FORMAT).unwrap_or(1);
+    match (format, bits) {
+        (1, 8) => Ok(DataType::Byte),
+        (1, 16) => Ok(DataType::UInt16),
+        (2, 16) => Ok(DataType::Int16),
+        (1, 32) => Ok(DataType::UInt32),
+        (2, 32) => Ok(DataType::Int32),
+        (3, 32) => Ok(DataType::Float32),
+        (3, 64) => Ok(DataType::Float64),
+        _ => Err(format!(
+            "unsupported samples: {} bits, sample format {}",
+            bits, format
+        )),
+    }
+}
+
+fn decode(bytes: &[u8], data_type: DataType, big_endian: bool) -> f64 {
+    macro_rules! read {
+        ($t:ty) => {{
+            let array = bytes.try_into().expect("sample size");
+            if big_endian {
+                <$t>::from_be_bytes(array) as f64
+            } else {
+                <$t>::from_le_bytes(array) as f64
+            }
+        }};
+    }
+    match data_type {
+        DataType::Byte => bytes[0] as f64,
+        DataType::UInt16 => read!(u16),
+        DataType::Int16 => read!(i16),
+        DataType::UInt32 => read!(u32),
+        DataType::Int32 => read!(i32),
+        DataType::Float32 => read!(f32),
+        DataType::Float64 => read!(f64),
+    }
+}
+
+/// Undoes horizontal differencing (predictor 2) over one block row of
+/// samples of `size` bytes.
+fn undo_horizontal(row: &mut [u8], size: usize, big_endian: bool) {
+    let read = |b: &[u8]| {
+        let mut value = 0u64;
+        for i in 0..size {
+            let byte = if big_endian { b[i] } else { b[size - 1 - i] };
+            value = value << 8 | byte as u64;
+        }
+        value
+    };
+    for i in (size..row.len() / size * size).step_by(size) {
+        let sum = read(&row[i - size..i]).wrapping_add(read(&row[i..i + size]));
+        for k in 0..size {
+            let byte = (sum >> (8 * k)) as u8;
+            let at = if big_endian { i + size - 1 - k } else { i + k };
+            row[at] = byte;
+        }
+    }
+}
+
+/// Undoes the floating-point predictor (3): bytes differenced across the
+/// row, each sample's bytes split into planes most significant first.
+fn undo_floating_point(row: &mut [u8], size: usize, big_endian: bool) {
+    for i in 1..row.len() {
+        row[i] = row[i].wrapping_add(row[i - 1]);
+    }
+    let planes = row.to_vec();
+    let count = row.len() / size;
+    for (i, sample) in row.chunks_mut(size).enumerate() {
+        for (k, byte) in sample.iter_mut().enumerate() {
+            // Plane k holds the k-th most significant byte of each sample.
+            let plane = if big_endian { k } else { size - 1 - k };
+            *byte = planes[plane * count + i];
+        }
+    }
+}
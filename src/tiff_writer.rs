@@ -0,0 +1,890 @@
+//! Native GeoTIFF writer: single-band rasters streamed row by row into a
+//! tiled or striped TIFF, compressed with DEFLATE, LZW or ZSTD, carrying
+//! the GeoKeys, nodata tag and internal overviews GDAL would write.
+//!
+//! Blocks go to the file as soon as their rows are complete, overviews
+//! alongside the full-resolution image, and the IFDs last, so memory holds
+//! a single row of blocks per level. Files whose pixels could outgrow
+//! 4 GiB are written as BigTIFF.
+//!
+//! Cloud Optimized GeoTIFFs need the opposite order: the IFDs first, then
+//! the tiles of the smallest overview up to the full-resolution image.
+//! Their blocks are spilled to a scratch file next to the output and
+//! copied into place once every IFD is known.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::aoi::Aoi;
+use crate::config::{Compression, GeoTiffOptions, Predictor, Resampling, TiffFormat};
+use crate::crs::CrsId;
+use crate::geo::Extent;
+use crate::geotiff::{GeoKeyValue, GeoKeys, KEY_VERTICAL_CS_TYPE};
+use crate::raster::DataType;
+use crate::rows::{RawRows, RowError, RowSource};
+use crate::tiff::{
+    COMPRESSION_DEFLATE, COMPRESSION_LZW, COMPRESSION_NONE, COMPRESSION_ZSTD, TAG_BITS_PER_SAMPLE,
+    TAG_COMPRESSION, TAG_GDAL_METADATA, TAG_GDAL_NODATA, TAG_GEO_KEY_DIRECTORY, TAG_IMAGE_LENGTH,
+    TAG_IMAGE_WIDTH, TAG_MODEL_PIXEL_SCALE, TAG_MODEL_TIEPOINT, TAG_NEW_SUBFILE_TYPE,
+    TAG_PHOTOMETRIC, TAG_PLANAR_CONFIGURATION, TAG_PREDICTOR, TAG_ROWS_PER_STRIP,
+    TAG_SAMPLES_PER_PIXEL, TAG_SAMPLE_FORMAT, TAG_STRIP_BYTE_COUNTS, TAG_STRIP_OFFSETS,
+    TAG_TILE_BYTE_COUNTS, TAG_TILE_LENGTH, TAG_TILE_OFFSETS, TAG_TILE_WIDTH,
+};
+use crate::vrt::{escape, RawVrt};
+
+/// Strips are sized to hold about this many bytes, as GDAL does.
+const STRIP_BYTES: usize = 8192;
+
+/// The ghost area GDAL writes after the header of a COG, telling readers
+/// the layout can be relied on.
+const COG_METADATA: &str =
+    "LAYOUT=IFDS_BEFORE_DATA\nBLOCK_ORDER=ROW_MAJOR\nKNOWN_INCOMPATIBLE_EDITION=NO\n";
+
+/// A single-band raster to write.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoTiffImage {
+    pub width: usize,
+    pub height: usize,
+    pub geo_transform: [f64; 6],
+    /// CRS definition, written as GeoKeys when it has an EPSG code.
+    pub crs: Option<String>,
+    /// Value written for `NaN` pixels and declared in the nodata tag.
+    pub nodata: f64,
+    /// `Byte` or `Float32`.
+    pub data_type: DataType,
+}
+
+impl GeoTiffImage {
+    fn is_float(&self) -> bool {
+        self.data_type == DataType::Float32
+    }
+}
+
+/// Writes a [`GeoTiffImage`] whose rows are handed over top row first,
+/// `NaN` standing for nodata.
+pub struct GeoTiffWriter {
+    path: PathBuf,
+    out: BufWriter<File>,
+    /// The scratch file blocks go to first, for a COG. Declared after
+    /// `out`, which may hold it open, so that it is closed first.
+    spill: Option<SpillFile>,
+    position: u64,
+    big_tiff: bool,
+    image: GeoTiffImage,
+    encoder: Encoder,
+    overview_resampling: Resampling,
+    /// The full-resolution image, then one level per overview.
+    levels: Vec<Level>,
+    rows_written: usize,
+    vertical_datum: Option<String>,
+}
+
+impl GeoTiffWriter {
+    /// # Panics
+    ///
+    /// If the image is neither `Byte` nor `Float32`, or the overview
+    /// resampling is neither `near` nor `average`.
+    pub fn create(
+        path: &Path,
+        image: GeoTiffImage,
+        options: &GeoTiffOptions,
+    ) -> io::Result<GeoTiffWriter> {
+        assert!(
+            matches!(image.data_type, DataType::Byte | DataType::Float32),
+            "only Byte and Float32 rasters can be written"
+        );
+        assert!(
+            matches!(
+                options.overview_resampling,
+                Resampling::Near | Resampling::Average
+            ),
+            "overviews are computed with near or average"
+        );
+        let sample_size = image.data_type.size();
+        let cog = options.format == TiffFormat::Cog;
+        let tiled = options.tiled || cog;
+        let mut factors: Vec<usize> = std::iter::once(1)
+            .chain(options.overviews.iter().map(|&f| f as usize))
+            .collect();
+        if cog {
+            // Halve until the overview fits in a single tile, as GDAL does.
+            let tile = options.tile_size as usize;
+            while let Some(&f) = factors
+                .last()
+                .filter(|&&f| image.width.div_ceil(f) > tile || image.height.div_ceil(f) > tile)
+            {
+                factors.push(f * 2);
+            }
+        }
+        let levels: Vec<Level> = factors
+            .into_iter()
+            .map(|factor| {
+                let width = image.width.div_ceil(factor);
+                let height = image.height.div_ceil(factor);
+                let block = if tiled {
+                    (options.tile_size as usize, options.tile_size as usize)
+                } else {
+                    (width, (STRIP_BYTES / (width * sample_size).max(1)).max(1))
+                };
+                Level::new(factor, width, height, block, tiled)
+            })
+            .collect();
+        let payload: u64 = levels
+            .iter()
+            .map(|l| (l.blocks() * l.block.0 * l.block.1 * sample_size) as u64)
+            .sum();
+        let big_tiff = payload + (1 << 20) > u32::MAX as u64;
+
+        let spill = cog.then(|| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(".blocks");
+            SpillFile(PathBuf::from(name))
+        });
+        let file = match &spill {
+            // Read back by `finish`.
+            Some(spill) => File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&spill.0)?,
+            None => File::create(path)?,
+        };
+        let mut writer = GeoTiffWriter {
+            path: path.to_path_buf(),
+            spill,
+            out: BufWriter::new(file),
+            position: 0,
+            big_tiff,
+            encoder: Encoder {
+                compression: options.compression,
+                predictor: options.predictor_for(image.is_float()),
+                level: options.level,
+                data_type: image.data_type,
+                nodata: image.nodata,
+            },
+            image,
+            overview_resampling: options.overview_resampling,
+            levels,
+            rows_written: 0,
+            vertical_datum: None,
+        };
+        // A COG's header is written by `finish`, ahead of the IFDs.
+        if writer.spill.is_none() {
+            writer.write_header()?;
+        }
+        Ok(writer)
+    }
+
+    /// Records the vertical reference of the heights: as GDAL metadata,
+    /// and as the VerticalCSTypeGeoKey when it has an EPSG code.
+    pub fn set_vertical_datum(&mut self, datum: &str) {
+        self.vertical_datum = Some(datum.to_string());
+    }
+
+    pub fn is_big_tiff(&self) -> bool {
+        self.big_tiff
+    }
+
+    pub fn write_row(&mut self, row: &[f32]) -> io::Result<()> {
+        assert_eq!(row.len(), self.image.width, "row must hold width values");
+        assert!(self.rows_written < self.image.height, "too many rows");
+        // The levels write through `self`, so they are set aside meanwhile.
+        let mut levels = std::mem::take(&mut self.levels);
+        let result = self.feed(&mut levels, row);
+        self.levels = levels;
+        self.rows_written += 1;
+        result
+    }
+
+    fn feed(&mut self, levels: &mut [Level], row: &[f32]) -> io::Result<()> {
+        let (width, height) = (self.image.width, self.image.height);
+        levels[0].push(row.to_vec(), self)?;
+        for level in &mut levels[1..] {
+            let resampling = self.overview_resampling;
+            if let Some(reduced) = level.reduce(self.rows_written, row, width, height, resampling) {
+                level.push(reduced, self)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the IFDs once every row has been written.
+    pub fn finish(mut self) -> io::Result<()> {
+        assert_eq!(
+            self.rows_written, self.image.height,
+            "every row must be written"
+        );
+        if let Some(spill) = self.spill.take() {
+            return self.finish_cog(spill);
+        }
+        self.pad()?;
+        let first_ifd = self.position;
+        let levels = std::mem::take(&mut self.levels);
+        for (index, level) in levels.iter().enumerate() {
+            let fields = self.fields(level, index == 0, &level.offsets);
+            self.write_ifd(fields, index + 1 == levels.len())?;
+        }
+        self.patch_first_ifd(first_ifd)
+    }
+
+    /// Writes the header, the ghost area and every IFD to the output, then
+    /// copies the spilled blocks after them, smallest overview first. The
+    /// spill file goes whether or not this succeeds.
+    fn finish_cog(mut self, spill: SpillFile) -> io::Result<()> {
+        self.out.flush()?;
+        let output = BufWriter::new(File::create(&self.path)?);
+        let blocks = std::mem::replace(&mut self.out, output)
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        self.position = 0;
+        self.write_header()?;
+        let ghost = format!(
+            "GDAL_STRUCTURAL_METADATA_SIZE={:06} bytes\n{}",
+            COG_METADATA.len(),
+            COG_METADATA
+        );
+        self.write(ghost.as_bytes())?;
+        self.pad()?;
+        let first_ifd = self.position;
+
+        // IFD sizes do not depend on the offsets they hold, so the blocks'
+        // final offsets are known before any IFD is written.
+        let levels = std::mem::take(&mut self.levels);
+        let ifds: u64 = levels
+            .iter()
+            .enumerate()
+            .map(|(index, level)| self.ifd_size(&self.fields(level, index == 0, &level.offsets)))
+            .sum();
+        let mut next = first_ifd + ifds;
+        let mut offsets = vec![Vec::new(); levels.len()];
+        for (index, level) in levels.iter().enumerate().rev() {
+            for &count in &level.counts {
+                offsets[index].push(next);
+                next += count;
+            }
+        }
+        for (index, level) in levels.iter().enumerate() {
+            let fields = self.fields(level, index == 0, &offsets[index]);
+            self.write_ifd(fields, index + 1 == levels.len())?;
+        }
+
+        let mut blocks = BufReader::new(blocks);
+        for level in levels.iter().rev() {
+            for (&offset, &count) in level.offsets.iter().zip(&level.counts) {
+                blocks.seek(SeekFrom::Start(offset))?;
+                io::copy(&mut (&mut blocks).take(count), &mut self.out)?;
+                self.position += count;
+            }
+        }
+        drop(blocks);
+        drop(spill);
+        self.patch_first_ifd(first_ifd)
+    }
+
+    /// Writes the file header; the first IFD offset is filled in once
+    /// known.
+    fn write_header(&mut self) -> io::Result<()> {
+        if self.big_tiff {
+            self.write(&[b'I', b'I', 43, 0, 8, 0, 0, 0])?;
+            self.write(&0u64.to_le_bytes())
+        } else {
+            self.write(&[b'I', b'I', 42, 0])?;
+            self.write(&0u32.to_le_bytes())
+        }
+    }
+
+    /// Keeps the next write on a word boundary, where IFDs must start.
+    fn pad(&mut self) -> io::Result<()> {
+        if self.position % 2 == 1 {
+            self.write(&[0])?;
+        }
+        Ok(())
+    }
+
+    /// Points the header at the first IFD and flushes the file.
+    fn patch_first_ifd(mut self, first_ifd: u64) -> io::Result<()> {
+        self.out
+            .seek(SeekFrom::Start(if self.big_tiff { 8 } else { 4 }))?;
+        if self.big_tiff {
+            self.out.write_all(&first_ifd.to_le_bytes())?;
+        } else {
+            self.out.write_all(&(first_ifd as u32).to_le_bytes())?;
+        }
+        self.out.flush()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Encodes and writes one block, returning its offset and size.
+    fn write_block(&mut self, samples: &[f32], block_width: usize) -> io::Result<(u64, u64)> {
+        let bytes = self.encoder.encode(samples, block_width)?;
+        let offset = self.position;
+        self.write(&bytes)?;
+        Ok((offset, bytes.len() as u64))
+    }
+
+    /// The IFD entries of `level`, its blocks stored at `offsets`.
+    fn fields(&self, level: &Level, main: bool, offsets: &[u64]) -> BTreeMap<u16, Field> {
+        let image = &self.image;
+        let encoder = &self.encoder;
+        let mut fields = BTreeMap::new();
+        if !main {
+            // Reduced-resolution version of the main image.
+            fields.insert(TAG_NEW_SUBFILE_TYPE, Field::Long(vec![1]));
+        }
+        fields.insert(TAG_IMAGE_WIDTH, Field::Long(vec![level.width as u32]));
+        fields.insert(TAG_IMAGE_LENGTH, Field::Long(vec![level.height as u32]));
+        let bits = 8 * image.data_type.size() as u16;
+        fields.insert(TAG_BITS_PER_SAMPLE, Field::Short(vec![bits]));
+        let compression = match encoder.compression {
+            Compression::None => COMPRESSION_NONE,
+            Compression::Deflate => COMPRESSION_DEFLATE,
+            Compression::Lzw => COMPRESSION_LZW,
+            Compression::Zstd => COMPRESSION_ZSTD,
+        };
+        fields.insert(TAG_COMPRESSION, Field::Short(vec![compression]));
+        // BlackIsZero.
+        fields.insert(TAG_PHOTOMETRIC, Field::Short(vec![1]));
+        fields.insert(TAG_SAMPLES_PER_PIXEL, Field::Short(vec![1]));
+        fields.insert(TAG_PLANAR_CONFIGURATION, Field::Short(vec![1]));
+        if encoder.predictor != Predictor::None {
+            fields.insert(TAG_PREDICTOR, Field::Short(vec![encoder.predictor.code()]));
+        }
+        let offsets = self.offsets(offsets.to_vec());
+        let counts = self.offsets(level.counts.clone());
+        if level.tiled {
+            fields.insert(TAG_TILE_WIDTH, Field::Long(vec![level.block.0 as u32]));
+            fields.insert(TAG_TILE_LENGTH, Field::Long(vec![level.block.1 as u32]));
+            fields.insert(TAG_TILE_OFFSETS, offsets);
+            fields.insert(TAG_TILE_BYTE_COUNTS, counts);
+        } else {
+            fields.insert(TAG_ROWS_PER_STRIP, Field::Long(vec![level.block.1 as u32]));
+            fields.insert(TAG_STRIP_OFFSETS, offsets);
+            fields.insert(TAG_STRIP_BYTE_COUNTS, counts);
+        }
+        let sample_format = if image.is_float() { 3 } else { 1 };
+        fields.insert(TAG_SAMPLE_FORMAT, Field::Short(vec![sample_format]));
+        if main {
+            let gt = &image.geo_transform;
+            fields.insert(
+                TAG_MODEL_PIXEL_SCALE,
+                Field::Double(vec![gt[1], -gt[5], 0.0]),
+            );
+            fields.insert(
+                TAG_MODEL_TIEPOINT,
+                Field::Double(vec![0.0, 0.0, 0.0, gt[0], gt[3], 0.0]),
+            );
+            let epsg = image
+                .crs
+                .as_deref()
+                .and_then(CrsId::identify)
+                .and_then(|id| id.epsg());
+            let vertical = self.vertical_datum.as_deref();
+            if let Some(code) = epsg {
+                let mut keys = GeoKeys::for_epsg(code);
+                let vertical_code = vertical
+                    .and_then(CrsId::vertical_reference)
+                    .and_then(|id| id.epsg())
+                    .and_then(|code| u16::try_from(code).ok());
+                if let Some(vertical_code) = vertical_code {
+                    keys.keys
+                        .insert(KEY_VERTICAL_CS_TYPE, GeoKeyValue::Short(vertical_code));
+                }
+                fields.insert(TAG_GEO_KEY_DIRECTORY, Field::Short(keys.to_directory()));
+            }
+            if let Some(datum) = vertical {
+                let metadata = format!(
+                    "<GDALMetadata>\n  <Item name=\"VERTICAL_DATUM\">{}</Item>\n</GDALMetadata>",
+                    escape(datum)
+                );
+                fields.insert(TAG_GDAL_METADATA, Field::Ascii(metadata));
+            }
+        }
+        fields.insert(TAG_GDAL_NODATA, Field::Ascii(image.nodata.to_string()));
+        fields
+    }
+
+    fn offsets(&self, values: Vec<u64>) -> Field {
+        if self.big_tiff {
+            Field::Long8(values)
+        } else {
+            Field::Long(values.into_iter().map(|v| v as u32).collect())
+        }
+    }
+
+    /// Bytes taken by an IFD and its out-of-line values.
+    fn ifd_size(&self, fields: &BTreeMap<u16, Field>) -> u64 {
+        let (count_size, entry_size, inline) = if self.big_tiff {
+            (8, 20, 8)
+        } else {
+            (2, 12, 4)
+        };
+        let values: usize = fields
+            .values()
+            .map(|field| field.bytes().len())
+            .filter(|&len| len > inline)
+            .map(|len| len + len % 2)
+            .sum();
+        count_size + fields.len() as u64 * entry_size + inline as u64 + values as u64
+    }
+
+    /// Writes an IFD at the current (even) position, its out-of-line
+    /// values right after it, and points it at the IFD that follows.
+    fn write_ifd(&mut self, fields: BTreeMap<u16, Field>, last: bool) -> io::Result<()> {
+        let (count_size, entry_size, inline) = if self.big_tiff {
+            (8, 20, 8)
+        } else {
+            (2, 12, 4)
+        };
+        let data_start =
+            self.position + count_size + fields.len() as u64 * entry_size + inline as u64;
+        let mut entries = Vec::new();
+        let mut data = Vec::new();
+        for (tag, field) in &fields {
+            let bytes = field.bytes();
+            entries.extend_from_slice(&tag.to_le_bytes());
+            entries.extend_from_slice(&field.type_code().to_le_bytes());
+            if self.big_tiff {
+                entries.extend_from_slice(&(field.count() as u64).to_le_bytes());
+            } else {
+                entries.extend_from_slice(&(field.count() as u32).to_le_bytes());
+            }
+            if bytes.len() <= inline {
+                let mut value = bytes.clone();
+                value.resize(inline, 0);
+                entries.extend_from_slice(&value);
+            } else {
+                let offset = data_start + data.len() as u64;
+                if self.big_tiff {
+                    entries.extend_from_slice(&offset.to_le_bytes());
+                } else {
+                    entries.extend_from_slice(&(offset as u32).to_le_bytes());
+                }
+                data.extend_from_slice(&bytes);
+                if bytes.len() % 2 == 1 {
+                    data.push(0);
+                }
+            }
+        }
+        let next = if last {
+            0
+        } else {
+            data_start + data.len() as u64
+        };
+        if self.big_tiff {
+            self.write(&(fields.len() as u64).to_le_bytes())?;
+            self.write(&entries)?;
+            self.write(&next.to_le_bytes())?;
+        } else {
+            self.write(&(fields.len() as u16).to_le_bytes())?;
+            self.write(&entries)?;
+            self.write(&(next as u32).to_le_bytes())?;
+        }
+        self.write(&data)
+    }
+}
+
+/// A COG's scratch file, as big as the whole image: removed when dropped,
+/// so that neither a failed `finish` nor an abandoned writer leaves it
+/// behind.
+struct SpillFile(PathBuf);
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// One resolution level being written.
+struct Level {
+    /// Decimation factor from the full-resolution image.
+    factor: usize,
+    width: usize,
+    height: usize,
+    tiled: bool,
+    /// Block width and height: the tile size, or the image width by the
+    /// rows per strip.
+    block: (usize, usize),
+    /// Rows of the current row of blocks.
+    rows: Vec<Vec<f32>>,
+    rows_done: usize,
+    offsets: Vec<u64>,
+    counts: Vec<u64>,
+    /// Accumulated full-resolution pixels of the overview row in progress.
+    sums: Vec<f64>,
+    samples: Vec<u32>,
+    picked: Vec<f32>,
+}
+
+impl Level {
+    fn new(
+        factor: usize,
+        width: usize,
+        height: usize,
+        block: (usize, usize),
+        tiled: bool,
+    ) -> Level {
+        Level {
+            factor,
+            width,
+            height,
+            tiled,
+            block,
+            rows: Vec::new(),
+            rows_done: 0,
+            offsets: Vec::new(),
+            counts: Vec::new(),
+            sums: vec![0.0; width],
+            samples: vec![0; width],
+            picked: vec![f32::NAN; width],
+        }
+    }
+
+    fn blocks_across(&self) -> usize {
+        self.width.div_ceil(self.block.0)
+    }
+
+    fn blocks(&self) -> usize {
+        self.blocks_across() * self.height.div_ceil(self.block.1)
+    }
+
+    /// Adds full-resolution row `r` to the overview row in progress,
+    /// returning the overview row once its last source row is in.
+    fn reduce(
+        &mut self,
+        r: usize,
+        row: &[f32],
+        width: usize,
+        height: usize,
+        resampling: Resampling,
+    ) -> Option<Vec<f32>> {
+        let f = self.factor;
+        let first = r / f * f;
+        let rows_in = f.min(height - first);
+        let position = r - first;
+        if resampling == Resampling::Near {
+            // The pixel nearest the centre of each f x f block.
+            if position == (f / 2).min(rows_in - 1) {
+                for (c, picked) in self.picked.iter_mut().enumerate() {
+                    let start = c * f;
+                    *picked = row[start + (f / 2).min(width - start - 1)];
+                }
+            }
+        } else {
+            for (c, &value) in row.iter().enumerate() {
+                if !value.is_nan() {
+                    self.sums[c / f] += value as f64;
+                    self.samples[c / f] += 1;
+                }
+            }
+        }
+        if position + 1 < rows_in {
+            return None;
+        }
+        if resampling == Resampling::Near {
+            return Some(self.picked.clone());
+        }
+        let reduced = self
+            .sums
+            .iter()
+            .zip(&self.samples)
+            .map(|(&sum, &n)| {
+                if n == 0 {
+                    f32::NAN
+                } else {
+                    (sum / n as f64) as f32
+                }
+            })
+            .collect();
+        self.sums.fill(0.0);
+        self.samples.fill(0);
+        Some(reduced)
+    }
+
+    /// Queues a row, writing the row of blocks it completes.
+    fn push(&mut self, row: Vec<f32>, writer: &mut GeoTiffWriter) -> io::Result<()> {
+        self.rows.push(row);
+        if self.rows.len() < self.block.1 && self.rows_done + self.rows.len() < self.height {
+            return Ok(());
+        }
+        let (block_width, block_height) = self.block;
+        // Tiles are always whole, padded with nodata; the last strip only
+        // holds the rows left.
+        let rows = if self.tiled {
+            block_height
+        } else {
+            self.rows.len()
+        };
+        let mut samples = Vec::with_capacity(block_width * rows);
+        for column in 0..self.blocks_across() {
+            samples.clear();
+            let start = column * block_width;
+            for r in 0..rows {
+                match self.rows.get(r) {
+                    Some(row) => {
+                        let end = (start + block_width).min(self.width);
+                        samples.extend_from_slice(&row[start..end]);
+                        samples.resize(samples.len() + start + block_width - end, f32::NAN);
+                    }
+                    None => samples.resize(samples.len() + block_width, f32::NAN),
+                }
+            }
+            let (offset, count) = writer.write_block(&samples, block_width)?;
+            self.offsets.push(offset);
+            self.counts.push(count);
+        }
+        self.rows_done += self.rows.len();
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+/// Turns blocks of pixels into the bytes stored in the file.
+struct Encoder {
+    compression: Compression,
+    predictor: Predictor,
+    level: Option<u32>,
+    data_type: DataType,
+    nodata: f64,
+}
+
+impl Encoder {
+    fn encode(&self, samples: &[f32], block_width: usize) -> io::Result<Vec<u8>> {
+        let size = self.data_type.size();
+        let mut bytes = Vec::with_capacity(samples.len() * size);
+        for &value in samples {
+            let value = if value.is_nan() {
+                self.nodata
+            } else {
+                value as f64
+            };
+            match self.data_type {
+                DataType::Byte => bytes.push(value.round().clamp(0.0, 255.0) as u8),
+                _ => bytes.extend_from_slice(&(value as f32).to_le_bytes()),
+            }
+        }
+        for row in bytes.chunks_mut(block_width * size) {
+            predict(row, size, self.predictor);
+        }
+        match self.compression {
+            Compression::None => Ok(bytes),
+            Compression::Deflate => {
+                let level = flate2::Compression::new(self.level.unwrap_or(6));
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(&bytes)?;
+                encoder.finish()
+            }
+            Compression::Lzw => {
+                weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                    .encode(&bytes)
+                    .map_err(io::Error::other)
+            }
+            Compression::Zstd => zstd::bulk::compress(&bytes, self.level.unwrap_or(9) as i32),
+        }
+    }
+}
+
+/// Applies `predictor` to one row of little-endian samples of `size`
+/// bytes, as libtiff does.
+fn predict(row: &mut [u8], size: usize, predictor: Predictor) {
+    match predictor {
+        Predictor::None => {}
+        Predictor::Horizontal if size == 1 => {
+            for i in (1..row.len()).rev() {
+                row[i] = row[i].wrapping_sub(row[i - 1]);
+            }
+        }
+        Predictor::Horizontal => {
+            let mut words: Vec<u32> = row
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            for i in (1..words.len()).rev() {
+                words[i] = words[i].wrapping_sub(words[i - 1]);
+            }
+            for (bytes, word) in row.chunks_exact_mut(4).zip(words) {
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+        }
+        Predictor::FloatingPoint => {
+            // Most significant bytes of every sample first, then the
+            // next, and so on, differenced as one run of bytes.
+            let n = row.len() / size;
+            let mut planes = vec![0u8; row.len()];
+            for i in 0..n {
+                for b in 0..size {
+                    planes[b * n + i] = row[i * size + size - 1 - b];
+                }
+            }
+            for i in (1..planes.len()).rev() {
+                planes[i] = planes[i].wrapping_sub(planes[i - 1]);
+            }
+            row.copy_from_slice(&planes);
+        }
+    }
+}
+
+/// The value of an IFD entry.
+enum Field {
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Long8(Vec<u64>),
+    Double(Vec<f64>),
+    Ascii(String),
+}
+
+impl Field {
+    fn type_code(&self) -> u16 {
+        match self {
+            Field::Short(_) => 3,
+            Field::Long(_) => 4,
+            Field::Long8(_) => 16,
+            Field::Double(_) => 12,
+            Field::Ascii(_) => 2,
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Field::Short(v) => v.len(),
+            Field::Long(v) => v.len(),
+            Field::Long8(v) => v.len(),
+            Field::Double(v) => v.len(),
+            Field::Ascii(s) => s.len() + 1,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Field::Short(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Field::Long(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Field::Long8(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Field::Double(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Field::Ascii(s) => s.bytes().chain(std::iter::once(0)).collect(),
+        }
+    }
+}
+
+/// The settings of `options` a written file depends on, for recipes.
+pub(crate) fn options_recipe(o: &GeoTiffOptions) -> String {
+    format!(
+        "format={} tiled={} tile_size={} compression={} predictor={:?} level={:?} \
+         overviews={:?} overview_resampling={}",
+        o.format.as_gdal(),
+        o.tiled,
+        o.tile_size,
+        o.compression.as_gdal(),
+        o.predictor,
+        o.level,
+        o.overviews,
+        o.overview_resampling.as_gdal()
+    )
+}
+
+/// The DEM written as the final GeoTIFF, as planned: the raw raster at
+/// `input` cropped to `bounds` and masked outside `aoi`.
+#[derive(Debug, Clone)]
+pub struct NativeGeoTiff {
+    /// VRT of the raster read, as written by [`crate::rows::RawWriter`].
+    pub input: PathBuf,
+    pub bounds: Option<Extent>,
+    /// Pixels whose centre falls outside become nodata.
+    pub aoi: Option<Aoi>,
+    pub options: GeoTiffOptions,
+    /// Vertical reference of the heights, recorded in the metadata.
+    pub vertical_datum: Option<String>,
+}
+
+impl NativeGeoTiff {
+    /// Everything the result depends on besides the source pixels.
+    pub fn recipe(&self) -> String {
+        let mut recipe = format!(
+            "geotiff {} {}",
+            self.input.display(),
+            options_recipe(&self.options)
+        );
+        if let Some(b) = &self.bounds {
+            recipe.push_str(&format!(
+                " bounds={},{},{},{}",
+                b.min_x, b.min_y, b.max_x, b.max_y
+            ));
+        }
+        if let Some(datum) = &self.vertical_datum {
+            recipe.push_str(" vertical_datum=");
+            recipe.push_str(datum);
+        }
+        if let Some(aoi) = &self.aoi {
+            recipe.push_str(" aoi=");
+            recipe.push_str(&aoi.to_geojson());
+        }
+        recipe
+    }
+
+    pub fn run(&self, output: &Path) -> Result<(), RowError> {
+        let source = RawVrt::load(&self.input).map_err(|e| RowError::input(&self.input, e))?;
+        let gt = source.geo_transform;
+        let (columns, rows) = match &self.bounds {
+            Some(b) => {
+                let column =
+                    |x: f64| (((x - gt[0]) / gt[1]).round().max(0.0) as usize).min(source.width);
+                let row =
+                    |y: f64| (((y - gt[3]) / gt[5]).round().max(0.0) as usize).min(source.height);
+                (column(b.min_x)..column(b.max_x), row(b.max_y)..row(b.min_y))
+            }
+            None => (0..source.width, 0..source.height),
+        };
+        if columns.is_empty() || rows.is_empty() {
+            return Err(RowError::input(
+                &self.input,
+                "the output bounds do not overlap the raster",
+            ));
+        }
+        let image = GeoTiffImage {
+            width: columns.len(),
+            height: rows.len(),
+            geo_transform: [
+                gt[0] + columns.start as f64 * gt[1],
+                gt[1],
+                0.0,
+                gt[3] + rows.start as f64 * gt[5],
+                0.0,
+                gt[5],
+            ],
+            crs: source.crs.clone(),
+            nodata: source.nodata,
+            data_type: DataType::Float32,
+        };
+        let igt = image.geo_transform;
+        let mut pixels = RawRows::open(&self.input, &source)?;
+        let mut writer = GeoTiffWriter::create(output, image, &self.options)
+            .map_err(|e| RowError::io(output, e))?;
+        if let Some(datum) = &self.vertical_datum {
+            writer.set_vertical_datum(datum);
+        }
+        let mut row = vec![0.0; source.width];
+        for r in 0..rows.end {
+            pixels.read_row(&mut row)?;
+            if r < rows.start {
+                continue;
+            }
+            let out = &mut row[columns.clone()];
+            if let Some(aoi) = &self.aoi {
+                let y = igt[3] + ((r - rows.start) as f64 + 0.5) * igt[5];
+                for (c, value) in out.iter_mut().enumerate() {
+                    if !aoi.contains(igt[0] + (c as f64 + 0.5) * igt[1], y) {
+                        *value = f32::NAN;
+                    }
+                }
+            }
+            writer.write_row(out).map_err(|e| RowError::io(output, e))?;
+        }
+        writer.finish().map_err(|e| RowError::io(output, e))
+    }
+}
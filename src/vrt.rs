@@ -0,0 +1,325 @@
+//! Native replacement for `gdalbuildvrt`: computes the mosaic layout of a
+//! set of tiles and writes it as a `VRTDataset` XML file.
+
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::VrtResolution;
+use crate::geo::Extent;
+use crate::raster::{DataType, RasterInfo};
+
+#[derive(Debug)]
+pub enum VrtError {
+    /// The tile list is empty.
+    Empty,
+    /// A tile cannot be mosaicked with the others.
+    Incompatible { path: PathBuf, message: String },
+}
+
+impl fmt::Display for VrtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VrtError::Empty => write!(f, "no tiles to mosaic"),
+            VrtError::Incompatible { path, message } => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VrtError {}
+
+/// Layout of a mosaic: the union of all tiles at a common resolution, in
+/// the same way as `gdalbuildvrt -resolution <strategy>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mosaic {
+    pub width: usize,
+    pub height: usize,
+    pub geo_transform: [f64; 6],
+    pub bands: usize,
+    pub data_type: DataType,
+    /// Band nodata, taken from the first tile that declares one.
+    pub nodata: Option<f64>,
+    pub crs: Option<String>,
+    pub tiles: Vec<RasterInfo>,
+}
+
+impl Mosaic {
+    pub fn new(tiles: Vec<RasterInfo>, resolution: VrtResolution) -> Result<Mosaic, VrtError> {
+        let first = tiles.first().ok_or(VrtError::Empty)?;
+        let bands = first.bands;
+
+        let mut extent = first.extent();
+        let mut data_type = first.data_type;
+        for tile in &tiles {
+            let incompatible = |message: String| VrtError::Incompatible {
+                path: tile.path.clone(),
+                message,
+            };
+            if !tile.is_north_up() {
+                return Err(incompatible(
+                    "rotated rasters are not supported".to_string(),
+                ));
+            }
+            if tile.bands != bands {
+                return Err(incompatible(format!(
+                    "has {} band(s) but {} has {}",
+                    tile.bands,
+                    first.path.display(),
+                    bands
+                )));
+            }
+            extent = extent.union(&tile.extent());
+            data_type = data_type.promote(tile.data_type);
+        }
+
+        let (res_x, res_y) = pick_resolution(&tiles, resolution);
+        let width = (0.5 + extent.width() / res_x) as usize;
+        let height = (0.5 + extent.height() / res_y) as usize;
+
+        Ok(Mosaic {
+            width,
+            height,
+            geo_transform: [extent.min_x, res_x, 0.0, extent.max_y, 0.0, -res_y],
+            bands,
+            data_type,
+            nodata: tiles.iter().find_map(|t| t.nodata),
+            crs: tiles.iter().find_map(|t| t.crs.clone()),
+            tiles,
+        })
+    }
+
+    pub fn extent(&self) -> Extent {
+        Extent::from_geo_transform(&self.geo_transform, self.width, self.height)
+    }
+
+    /// Renders the mosaic as VRT XML. Source paths are written as given, so
+    /// pass absolute paths when the VRT lives elsewhere.
+    pub fn to_xml(&self) -> String {
+        let gt = &self.geo_transform;
+        let mut xml = String::new();
+        let _ = writeln!(
+            xml,
+            "<VRTDataset rasterXSize=\"{}\" rasterYSize=\"{}\">",
+            self.width, self.height
+        );
+        if let Some(crs) = &self.crs {
+            let _ = writeln!(xml, "  <SRS>{}</SRS>", escape(crs));
+        }
+        let _ = writeln!(
+            xml,
+            "  <GeoTransform>{}, {}, {}, {}, {}, {}</GeoTransform>",
+            gt[0], gt[1], gt[2], gt[3], gt[4], gt[5]
+        );
+
+        for band in 1..=self.bands {
+            let _ = writeln!(
+                xml,
+                "  <VRTRasterBand dataType=\"{}\" band=\"{}\">",
+                self.data_type.as_gdal(),
+                band
+            );
+            if let Some(nodata) = self.nodata {
+                let _ = writeln!(xml, "    <NoDataValue>{}</NoDataValue>", nodata);
+            }
+            let _ = writeln!(
+                xml,
+                "    <ColorInterp>{}</ColorInterp>",
+                color_interp(self.bands, band)
+            );
+            for tile in &self.tiles {
+                self.write_source(&mut xml, tile, band);
+            }
+            xml.push_str("  </VRTRasterBand>\n");
+        }
+        xml.push_str("</VRTDataset>\n");
+        xml
+    }
+
+    fn write_source(&self, xml: &mut String, tile: &RasterInfo, band: usize) {
+        let gt = &self.geo_transform;
+        let (tile_res_x, tile_res_y) = tile.pixel_size();
+        let extent = tile.extent();
+        let x_off = (extent.min_x - gt[0]) / gt[1];
+        let y_off = (gt[3] - extent.max_y) / -gt[5];
+        let x_size = tile.width as f64 * tile_res_x / gt[1];
+        let y_size = tile.height as f64 * tile_res_y / -gt[5];
+
+        // Sources with a nodata value need ComplexSource so their holes do
+        // not overwrite neighbouring tiles.
+        let element = if tile.nodata.is_some() {
+            "ComplexSource"
+        } else {
+            "SimpleSource"
+        };
+        let _ = writeln!(xml, "    <{}>", element);
+        let _ = writeln!(
+            xml,
+            "      <SourceFilename relativeToVRT=\"0\">{}</SourceFilename>",
+            escape(&tile.path.to_string_lossy())
+        );
+        let _ = writeln!(xml, "      <SourceBand>{}</SourceBand>", band);
+        let _ = writeln!(
+            xml,
+            "      <SourceProperties RasterXSize=\"{}\" RasterYSize=\"{}\" DataType=\"{}\" />",
+            tile.width,
+            tile.height,
+            tile.data_type.as_gdal()
+        );
+        let _ = writeln!(
+            xml,
+            "      <SrcRect xOff=\"0\" yOff=\"0\" xSize=\"{}\" ySize=\"{}\" />",
+            tile.width, tile.height
+        );
+        let _ = writeln!(
+            xml,
+            "      <DstRect xOff=\"{}\" yOff=\"{}\" xSize=\"{}\" ySize=\"{}\" />",
+            x_off, y_off, x_size, y_size
+        );
+        if let Some(nodata) = tile.nodata {
+            let _ = writeln!(xml, "      <NODATA>{}</NODATA>", nodata);
+        }
+        let _ = writeln!(xml, "    </{}>", element);
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_xml())
+    }
+}
+
+/// A single-band `Float32` raster stored as raw little-endian pixels,
+/// described by a `VRTRawRasterBand`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawVrt {
+    pub width: usize,
+    pub height: usize,
+    pub geo_transform: [f64; 6],
+    pub crs: Option<String>,
+    pub nodata: f64,
+}
+
+impl RawVrt {
+    /// Renders the VRT pointing at `data_file`, relative to the VRT.
+    pub fn to_xml(&self, data_file: &Path) -> String {
+        let gt = &self.geo_transform;
+        let mut xml = String::new();
+        let _ = writeln!(
+            xml,
+            "<VRTDataset rasterXSize=\"{}\" rasterYSize=\"{}\">",
+            self.width, self.height
+        );
+        if let Some(crs) = &self.crs {
+            let _ = writeln!(xml, "  <SRS>{}</SRS>", escape(crs));
+        }
+        let _ = writeln!(
+            xml,
+            "  <GeoTransform>{}, {}, {}, {}, {}, {}</GeoTransform>",
+            gt[0], gt[1], gt[2], gt[3], gt[4], gt[5]
+        );
+        xml.push_str(
+            "  <VRTRasterBand dataType=\"Float32\" band=\"1\" subClass=\"VRTRawRasterBand\">\n",
+        );
+        let _ = writeln!(xml, "    <NoDataValue>{}</NoDataValue>", self.nodata);
+        let _ = writeln!(
+            xml,
+            "    <SourceFilename relativeToVRT=\"1\">{}</SourceFilename>",
+            escape(&data_file.to_string_lossy())
+        );
+        xml.push_str("    <ImageOffset>0</ImageOffset>\n");
+        xml.push_str("    <PixelOffset>4</PixelOffset>\n");
+        let _ = writeln!(xml, "    <LineOffset>{}</LineOffset>", self.width * 4);
+        xml.push_str("    <ByteOrder>LSB</ByteOrder>\n");
+        xml.push_str("  </VRTRasterBand>\n");
+        xml.push_str("</VRTDataset>\n");
+        xml
+    }
+
+    /// Reads back the layout of a VRT written by [`RawVrt::to_xml`].
+    pub fn load(path: &Path) -> io::Result<RawVrt> {
+        let xml = fs::read_to_string(path)?;
+        let invalid = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not a raw raster VRT: {}", what),
+            )
+        };
+        let attribute = |name: &str| -> Option<usize> {
+            let start = xml.find(&format!("{}=\"", name))? + name.len() + 2;
+            xml[start..].split('"').next()?.parse().ok()
+        };
+        let element = |name: &str| -> Option<String> {
+            let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+            let end = start + xml[start..].find(&format!("</{}>", name))?;
+            Some(unescape(&xml[start..end]))
+        };
+        let width = attribute("rasterXSize").ok_or_else(|| invalid("no rasterXSize"))?;
+        let height = attribute("rasterYSize").ok_or_else(|| invalid("no rasterYSize"))?;
+        let values: Vec<f64> = element("GeoTransform")
+            .ok_or_else(|| invalid("no GeoTransform"))?
+            .split(',')
+            .map(|v| v.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("bad GeoTransform"))?;
+        let geo_transform: [f64; 6] = values.try_into().map_err(|_| invalid("bad GeoTransform"))?;
+        let nodata = element("NoDataValue")
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(|| invalid("no NoDataValue"))?;
+        Ok(RawVrt {
+            width,
+            height,
+            geo_transform,
+            crs: element("SRS"),
+            nodata,
+        })
+    }
+}
+
+fn pick_resolution(tiles: &[RasterInfo], resolution: VrtResolution) -> (f64, f64) {
+    let sizes = tiles.iter().map(RasterInfo::pixel_size);
+    match resolution {
+        VrtResolution::Highest => sizes.fold((f64::INFINITY, f64::INFINITY), |(ax, ay), (x, y)| {
+            (ax.min(x), ay.min(y))
+        }),
+        VrtResolution::Lowest => sizes.fold((0.0, 0.0), |(ax, ay), (x, y)| (ax.max(x), ay.max(y))),
+        VrtResolution::Average => {
+            let (sx, sy) = sizes.fold((0.0, 0.0), |(ax, ay), (x, y)| (ax + x, ay + y));
+            let n = tiles.len() as f64;
+            (sx / n, sy / n)
+        }
+    }
+}
+
+fn color_interp(bands: usize, band: usize) -> &'static str {
+    match (bands, band) {
+        (3 | 4, 1) => "Red",
+        (3 | 4, 2) => "Green",
+        (3 | 4, 3) => "Blue",
+        (4, 4) => "Alpha",
+        _ => "Gray",
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
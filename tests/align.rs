@@ -0,0 +1,116 @@
+//! Common bounds of the orthophoto and the DEM, and the crop applied to
+//! both outputs.
+
+use std::fs;
+
+use vrt_maker::align::{align, Grid, Layer};
+use vrt_maker::config::ExtentOptions;
+use vrt_maker::geo::Extent;
+use vrt_maker::{Pipeline, PipelineError, RecordingRunner, Stages};
+
+fn layer(extent: [f64; 4], res: f64) -> Layer {
+    let [min_x, min_y, max_x, max_y] = extent;
+    Layer {
+        extent: Extent::new(min_x, min_y, max_x, max_y),
+        grid: Grid {
+            origin_x: min_x,
+            origin_y: max_y,
+            res_x: res,
+            res_y: res,
+        },
+    }
+}
+
+#[test]
+fn snaps_inward_to_the_pixel_grid() {
+    let grid = Grid {
+        origin_x: 0.0,
+        origin_y: 100.0,
+        res_x: 5.0,
+        res_y: 5.0,
+    };
+    let snapped = grid.snap_inward(&Extent::new(2.0, 13.0, 48.0, 99.0));
+    assert_eq!(snapped, Extent::new(5.0, 15.0, 45.0, 95.0));
+    assert!(grid.is_aligned(&snapped));
+    assert!(!grid.is_aligned(&Extent::new(2.0, 15.0, 45.0, 95.0)));
+}
+
+#[test]
+fn crops_both_layers_to_their_intersection() {
+    let ortho = layer([0.0, 0.0, 100.0, 100.0], 0.5);
+    let dem = layer([50.0, 0.0, 150.0, 100.0], 5.0);
+    let alignment = align(Some(&ortho), Some(&dem), None).unwrap().unwrap();
+
+    assert_eq!(alignment.bounds, Extent::new(50.0, 0.0, 100.0, 100.0));
+    assert_eq!(alignment.ortho.unwrap().percent, 50.0);
+    assert_eq!(alignment.dem.unwrap().percent, 50.0);
+    assert!(alignment.exact);
+    assert!(alignment.crops());
+    assert!(alignment
+        .to_string()
+        .contains("orthophoto cropped by 50.0%, DEM cropped by 50.0%"));
+
+    // A single layer without an AOI has nothing to be aligned with.
+    assert_eq!(align(Some(&ortho), None, None), Ok(None));
+}
+
+#[test]
+fn disjoint_layers_do_not_overlap() {
+    let ortho = layer([0.0, 0.0, 100.0, 100.0], 1.0);
+    let dem = layer([200.0, 0.0, 300.0, 100.0], 1.0);
+    let err = align(Some(&ortho), Some(&dem), None).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("outputs have no area in common:"));
+}
+
+#[test]
+fn bounds_crop_the_dem_with_projwin() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    let text = "ncols 4\nnrows 4\nxllcorner 1000\nyllcorner 2000\ncellsize 1\n\
+                1 2 3 4\n1 2 3 4\n1 2 3 4\n1 2 3 4\n";
+    fs::write(asc.join("a.asc"), text).unwrap();
+    let builder = Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .stages(Stages::DEM);
+
+    let runner = RecordingRunner::new();
+    let outputs = builder
+        .clone()
+        .extent(ExtentOptions {
+            bounds: Some([1000.9, 2000.9, 1003.0, 2003.1]),
+            ..ExtentOptions::default()
+        })
+        .build()
+        .unwrap()
+        .run_with(&runner)
+        .unwrap();
+
+    let alignment = outputs.alignment.unwrap();
+    // Snapped inward onto the 0.2 m grid of the warped DEM.
+    assert_eq!(
+        alignment.bounds,
+        Extent::new(1001.0, 2001.0, 1003.0, 2003.0)
+    );
+    let translate = runner.commands().last().unwrap().lossy_args();
+    let projwin = translate.iter().position(|a| a == "-projwin").unwrap();
+    assert_eq!(
+        translate[projwin + 1..projwin + 5],
+        ["1001", "2003", "1003", "2001"]
+    );
+
+    let err = builder
+        .extent(ExtentOptions {
+            bounds: Some([0.0, 0.0, 10.0, 10.0]),
+            ..ExtentOptions::default()
+        })
+        .build()
+        .unwrap()
+        .plan()
+        .unwrap_err();
+    assert!(matches!(err, PipelineError::NoOverlap(_)));
+    assert_eq!(err.exit_code(), PipelineError::EXIT_INVALID_INPUT);
+}
@@ -0,0 +1,106 @@
+//! Area-of-interest files, tile selection and masking of the outputs.
+
+use std::fs;
+use std::path::Path;
+
+use vrt_maker::aoi::Aoi;
+use vrt_maker::config::ExtentOptions;
+use vrt_maker::geo::Extent;
+use vrt_maker::{Pipeline, PipelineError, RecordingRunner, Stages};
+
+fn load(dir: &Path, name: &str, text: &str) -> Aoi {
+    let path = dir.join(name);
+    fs::write(&path, text).unwrap();
+    Aoi::load(&path).unwrap()
+}
+
+#[test]
+fn reads_wkt_and_geojson_polygons() {
+    let dir = tempfile::tempdir().unwrap();
+    let wkt = load(
+        dir.path(),
+        "site.wkt",
+        "SRID=2154;POLYGON ((0 0, 10 0, 10 10, 0 10, 0 0), (4 4, 6 4, 6 6, 4 6, 4 4))",
+    );
+    assert_eq!(wkt.polygons.len(), 1);
+    assert_eq!(wkt.polygons[0].holes.len(), 1);
+    assert!(wkt.contains(1.0, 1.0));
+    assert!(!wkt.contains(5.0, 5.0));
+    assert!(!wkt.is_rectangle());
+
+    let geojson = load(
+        dir.path(),
+        "site.geojson",
+        r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {}, "geometry": {"type": "Point", "coordinates": [1, 1]}},
+            {"type": "Feature", "properties": {}, "geometry":
+                {"type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]]]}}
+        ]}"#,
+    );
+    assert_eq!(geojson.extent(), Extent::new(0.0, 0.0, 10.0, 10.0));
+    assert!(geojson.is_rectangle());
+
+    let path = dir.path().join("line.wkt");
+    fs::write(&path, "LINESTRING (0 0, 1 1)").unwrap();
+    assert!(Aoi::load(&path)
+        .unwrap_err()
+        .to_string()
+        .contains("expected POLYGON or MULTIPOLYGON"));
+}
+
+#[test]
+fn intersects_extents_sharing_area() {
+    let dir = tempfile::tempdir().unwrap();
+    let triangle = load(dir.path(), "t.wkt", "POLYGON ((0 0, 4 0, 0 4, 0 0))");
+    // Edge crossing only.
+    assert!(triangle.intersects(&Extent::new(1.5, 1.5, 3.0, 3.0)));
+    // Inside the bounding box but beyond the hypotenuse.
+    assert!(!triangle.intersects(&Extent::new(3.0, 3.0, 4.0, 4.0)));
+    // Touching the edge only.
+    assert!(!triangle.intersects(&Extent::new(4.0, 0.0, 5.0, 1.0)));
+}
+
+#[test]
+fn selects_tiles_and_masks_outputs_outside_the_polygon() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    for (name, x) in [("a", 0), ("b", 2), ("c", 4)] {
+        let text = format!("ncols 2\nnrows 2\nxllcorner {x}\nyllcorner 0\ncellsize 1\n1 2\n3 4\n");
+        fs::write(asc.join(format!("{name}.asc")), text).unwrap();
+    }
+    let aoi = dir.path().join("site.wkt");
+    fs::write(&aoi, "POLYGON ((0 0, 3 0, 0 2, 0 0))").unwrap();
+    let pipeline = Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .extent(ExtentOptions {
+            aoi: Some(aoi.clone()),
+            ..ExtentOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap();
+
+    let plan = pipeline.plan().unwrap();
+    assert_eq!(plan.stages[0].inputs.len(), 2);
+    assert!(plan.stages[0].excluded[0].ends_with("c.asc"));
+
+    let runner = RecordingRunner::new();
+    pipeline.run_with(&runner).unwrap();
+    let cutline = dir.path().join("out/tmp/aoi.geojson");
+    assert!(fs::read_to_string(&cutline)
+        .unwrap()
+        .contains("\"MultiPolygon\""));
+    let warp = runner.commands().last().unwrap().lossy_args();
+    assert_eq!(
+        warp[..4],
+        ["-of", "GTiff", "-cutline", &*cutline.to_string_lossy()]
+    );
+    assert_eq!(warp[4..9], ["-te", "0", "0", "3", "2"]);
+    assert_eq!(warp[9..11], ["-dstnodata", "0"]);
+
+    fs::write(&aoi, "POLYGON ((100 100, 110 100, 100 110, 100 100))").unwrap();
+    let err = pipeline.plan().unwrap_err();
+    assert!(matches!(err, PipelineError::NoOverlap(_)));
+}
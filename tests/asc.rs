@@ -0,0 +1,132 @@
+//! ESRI ASCII grids: header variants, value layout, and the errors of
+//! malformed files.
+
+use vrt_maker::asc::{Anchor, AscError, AscReader};
+
+fn reader(text: &str) -> Result<AscReader<&[u8]>, AscError> {
+    AscReader::new(text.as_bytes())
+}
+
+#[test]
+fn corner_and_centre_origins() {
+    let corner = reader("ncols 4\nnrows 2\nxllcorner 100\nyllcorner 200\ncellsize 5\n")
+        .unwrap()
+        .header()
+        .to_owned();
+    assert_eq!(corner.anchor, Anchor::Corner);
+    assert_eq!(corner.geo_transform(), [100.0, 5.0, 0.0, 210.0, 0.0, -5.0]);
+
+    // The same grid, its origin given at the centre of the lower-left cell.
+    let centre = reader("NCOLS 4\nNROWS 2\nXLLCENTER 102.5\nYLLCENTER 202.5\nCELLSIZE 5\n")
+        .unwrap()
+        .header()
+        .to_owned();
+    assert_eq!(centre.anchor, Anchor::Center);
+    assert_eq!(centre.geo_transform(), corner.geo_transform());
+    assert_eq!((centre.x_max(), centre.y_min()), (120.0, 200.0));
+
+    let mixed = reader("ncols 4\nnrows 2\nxllcorner 100\nyllcenter 202.5\ncellsize 5\n");
+    assert!(mixed
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("mixes corner and center origins"));
+}
+
+#[test]
+fn dx_and_dy_give_rectangular_cells() {
+    let header = reader("ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ndx 2\ndy 0.5\n")
+        .unwrap()
+        .header()
+        .to_owned();
+    assert_eq!((header.cell_x, header.cell_y), (2.0, 0.5));
+    assert_eq!(header.geo_transform(), [0.0, 2.0, 0.0, 1.0, 0.0, -0.5]);
+
+    for text in [
+        "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ndx 2\n",
+        "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\ndx 2\ndy 2\n",
+    ] {
+        let err = reader(text).err().unwrap().to_string();
+        assert!(err.contains("either cellsize or both dx and dy"), "{}", err);
+    }
+}
+
+#[test]
+fn values_may_wrap_across_lines() {
+    let text = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n\
+                1 2\n3 4 5\n\n6\n";
+    let asc = reader(text).unwrap();
+    assert_eq!(asc.read_all().unwrap(), [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+    let mut asc = reader(text).unwrap();
+    let mut row = [0.0; 3];
+    assert!(asc.read_row(&mut row).unwrap());
+    assert_eq!(row, [1.0, 2.0, 3.0]);
+    assert!(asc.read_row(&mut row).unwrap());
+    assert!(!asc.read_row(&mut row).unwrap());
+    assert_eq!(asc.rows_read(), 2);
+}
+
+#[test]
+fn truncated_and_overlong_bodies_are_errors() {
+    let header = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n";
+    let err = reader(&format!("{}1 2 3\n4 5\n", header))
+        .unwrap()
+        .read_all()
+        .unwrap_err();
+    assert!(matches!(err, AscError::Data { .. }), "{}", err);
+    assert!(
+        err.to_string()
+            .contains("file ends in row 2 of 2 after 2 of 3 values"),
+        "{}",
+        err
+    );
+
+    let err = reader(&format!("{}1 2 3\n4 5 6\n7\n", header))
+        .unwrap()
+        .statistics()
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("unexpected values after the 2 declared rows"),
+        "{}",
+        err
+    );
+
+    // The first row is read with the header.
+    let err = reader(&format!("{}1 2 x\n", header)).err().unwrap();
+    assert!(err.to_string().contains("\"x\" is not a number"), "{}", err);
+}
+
+#[test]
+fn header_errors_name_their_line() {
+    let err = reader("ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\nzone 31\n")
+        .err()
+        .unwrap();
+    assert!(matches!(err, AscError::Header { line: 6, .. }), "{}", err);
+    assert!(err.to_string().contains("unknown key \"zone\""), "{}", err);
+
+    let err = reader("ncols 3\nxllcorner 0\nyllcorner 0\ncellsize 1\n1 2 3\n")
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("missing nrows"), "{}", err);
+    let err = reader("ncols 0\n").err().unwrap();
+    assert!(err.to_string().contains("ncols must be a positive integer"));
+}
+
+#[test]
+fn statistics_skip_nodata() {
+    let text = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n\
+                NODATA_value -99999\n1 -99999 3\n-99999 -99999 8\n";
+    let stats = reader(text).unwrap().statistics().unwrap();
+    assert_eq!((stats.valid, stats.nodata), (3, 3));
+    assert_eq!((stats.min, stats.max), (1.0, 8.0));
+    assert_eq!(stats.mean, 4.0);
+    assert_eq!(stats.nodata_ratio(), 0.5);
+
+    // Without a NODATA_value every value counts.
+    let text = "ncols 2\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\n-99999 1\n";
+    let stats = reader(text).unwrap().statistics().unwrap();
+    assert_eq!((stats.valid, stats.nodata), (2, 0));
+    assert_eq!(stats.nodata_ratio(), 0.0);
+}
@@ -0,0 +1,156 @@
+//! Command-line parsing: the build subcommands, and the directories and
+//! output names they override in the project file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use vrt_maker::config::ConfigError;
+use vrt_maker::Stages;
+
+#[allow(dead_code)]
+#[path = "../src/cli.rs"]
+mod cli;
+
+use cli::{Cli, Command};
+
+fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+    Cli::try_parse_from(["vrt_maker"].iter().chain(args))
+}
+
+/// A project file in `dir` naming its own directories and outputs.
+fn project(dir: &Path) -> String {
+    let path = dir.join("vrt_maker.toml");
+    fs::write(
+        &path,
+        "[inputs]\njp2_dir = \"/data/jp2\"\nasc_dir = \"/data/asc\"\n\n\
+         [outputs]\ndir = \"/data/out\"\northo = \"ortho.tiff\"\ndem = \"mnt.tiff\"\n",
+    )
+    .unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn subcommands_select_the_stages() {
+    for (name, stages) in [
+        ("ortho", Stages::ORTHO),
+        ("dem", Stages::DEM),
+        ("all", Stages::ALL),
+    ] {
+        let cli = parse(&[name, "--dry-run"]).unwrap();
+        let (args, selected) = cli.command.build().unwrap();
+        assert_eq!(selected, stages, "{}", name);
+        assert!(args.dry_run && !args.force);
+    }
+    assert!(parse(&["doctor"]).unwrap().command.build().is_none());
+    assert!(matches!(
+        parse(&["inspect", "tiles"]).unwrap().command,
+        Command::Inspect(_)
+    ));
+
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["build"]).is_err());
+    assert!(parse(&["inspect"]).is_err());
+    assert!(parse(&["dem", "--out"]).is_err());
+}
+
+#[test]
+fn arguments_override_the_project_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = project(dir.path());
+
+    let cli = parse(&["all", "--config", &config]).unwrap();
+    let resolved = cli.command.build().unwrap().0.resolve().unwrap();
+    assert_eq!(resolved.inputs.asc_dir, PathBuf::from("/data/asc"));
+    assert_eq!(resolved.outputs.dem, "mnt.tiff");
+    assert_eq!(resolved.outputs.tmp_dir(), PathBuf::from("/data/out/tmp"));
+
+    let cli = parse(&[
+        "dem",
+        "-c",
+        &config,
+        "--jp2-dir",
+        "ortho tiles",
+        "--asc-dir",
+        "dem tiles",
+        "-o",
+        "build",
+        "--tmp-dir",
+        "scratch",
+        "--ortho-name",
+        "photo.tiff",
+        "--dem-name",
+        "height.tiff",
+    ])
+    .unwrap();
+    let resolved = cli.command.build().unwrap().0.resolve().unwrap();
+    assert_eq!(resolved.inputs.jp2_dir, PathBuf::from("ortho tiles"));
+    assert_eq!(resolved.inputs.asc_dir, PathBuf::from("dem tiles"));
+    assert_eq!(resolved.outputs.dir, PathBuf::from("build"));
+    assert_eq!(resolved.outputs.tmp_dir(), PathBuf::from("scratch"));
+    assert_eq!(resolved.outputs.ortho_path(), Path::new("build/photo.tiff"));
+    assert_eq!(resolved.outputs.dem_path(), Path::new("build/height.tiff"));
+}
+
+#[test]
+fn overrides_are_validated() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = project(dir.path());
+    let cli = parse(&["dem", "-c", &config, "--dem-name", "out/dem.tiff"]).unwrap();
+    let err = cli.command.build().unwrap().0.resolve().unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)), "{}", err);
+    assert!(err
+        .to_string()
+        .contains("outputs.dem must be a plain file name"));
+
+    let cli = parse(&["all", "-c", &config, "--ortho-name", "mnt.tiff"]).unwrap();
+    let err = cli.command.build().unwrap().0.resolve().unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("outputs.ortho and outputs.dem must differ"));
+
+    let missing = dir.path().join("missing.toml");
+    let cli = parse(&["all", "-c", &missing.to_string_lossy()]).unwrap();
+    let err = cli.command.build().unwrap().0.resolve().unwrap_err();
+    assert!(matches!(err, ConfigError::Io(..)), "{}", err);
+}
+
+#[test]
+fn bounds_are_four_comma_separated_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = project(dir.path());
+    let resolve = |bounds: &str| {
+        let cli = parse(&["dem", "-c", &config, "--bounds", bounds]).unwrap();
+        cli.command.build().unwrap().0.resolve()
+    };
+    let resolved = resolve("-10,0,10.5,20").unwrap();
+    assert_eq!(resolved.extent.bounds, Some([-10.0, 0.0, 10.5, 20.0]));
+    let err = resolve("0,0,10").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("--bounds takes XMIN,YMIN,XMAX,YMAX, got 3 value(s)"),
+        "{}",
+        err
+    );
+    assert!(parse(&["dem", "--bounds", "0,0,x,10"]).is_err());
+}
+
+#[test]
+fn tile_ranges_are_four_comma_separated_values() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = project(dir.path());
+    let resolve = |tiles: &str| {
+        let cli = parse(&["dem", "-c", &config, "--tiles", tiles]).unwrap();
+        cli.command.build().unwrap().0.resolve()
+    };
+    let resolved = resolve("620,6120,622,6121").unwrap();
+    assert_eq!(resolved.inputs.tiles, Some([620, 6120, 622, 6121]));
+    let err = resolve("620,6120,622,6121,3").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("--tiles takes XMIN,YMIN,XMAX,YMAX, got 5 value(s)"),
+        "{}",
+        err
+    );
+    assert!(parse(&["dem", "--tiles", "620,-6120,622,6121"]).is_err());
+}
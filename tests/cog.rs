@@ -0,0 +1,165 @@
+//! Cloud Optimized GeoTIFF output: the native layout, GDAL's COG driver
+//! options, and the validator.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use vrt_maker::cog;
+use vrt_maker::config::{Compression, GeoTiffOptions, TiffFormat};
+use vrt_maker::raster::DataType;
+use vrt_maker::tiff::{TAG_IMAGE_WIDTH, TAG_TILE_OFFSETS};
+use vrt_maker::tiff_writer::{GeoTiffImage, GeoTiffWriter};
+use vrt_maker::{Config, Stages};
+
+mod common;
+
+use common::{read_ifds, write_dem};
+
+/// Writes a 600 x 300 `Float32` ramp.
+fn write(path: &Path, options: &GeoTiffOptions) {
+    let rows: Vec<Vec<f32>> = (0..300)
+        .map(|r| (0..600).map(|c| (r * 1000 + c) as f32).collect())
+        .collect();
+    write_dem(path, [0.0, 1.0, 0.0, 300.0, 0.0, -1.0], &rows, options);
+}
+
+#[test]
+fn native_cog_passes_validation() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dem.tiff");
+    let options = GeoTiffOptions {
+        format: TiffFormat::Cog,
+        ..GeoTiffOptions::default()
+    };
+    write(&path, &options);
+    let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+    assert_eq!(entries.len(), 1, "the block scratch file is removed");
+
+    let report = cog::validate(&path).unwrap();
+    assert!(report.is_valid(), "{}", report);
+    assert!(report.warnings.is_empty(), "{}", report);
+
+    // Overviews down to a single 256 px tile: 300 x 150, then 150 x 75.
+    let ifds = read_ifds(&path);
+    let widths: Vec<_> = ifds
+        .iter()
+        .map(|ifd| ifd.integer(TAG_IMAGE_WIDTH).unwrap())
+        .collect();
+    assert_eq!(widths, [600, 300, 150]);
+
+    // Blocks moved into place still hold their pixels.
+    let offsets = ifds[0].integers(TAG_TILE_OFFSETS).unwrap();
+    let mut file = File::open(&path).unwrap();
+    file.seek(SeekFrom::Start(offsets[1] as u64 + 4 * (256 + 3)))
+        .unwrap();
+    let mut pixel = [0; 4];
+    file.read_exact(&mut pixel).unwrap();
+    assert_eq!(f32::from_le_bytes(pixel), (1000 + 256 + 3) as f32);
+}
+
+#[test]
+fn block_scratch_file_never_outlives_the_writer() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = GeoTiffOptions {
+        format: TiffFormat::Cog,
+        ..GeoTiffOptions::default()
+    };
+    let image = GeoTiffImage {
+        width: 600,
+        height: 300,
+        geo_transform: [0.0, 1.0, 0.0, 300.0, 0.0, -1.0],
+        crs: Some("EPSG:2154".to_string()),
+        nodata: -99999.0,
+        data_type: DataType::Float32,
+    };
+    let blocks = dir.path().join("dem.tiff.blocks");
+
+    // Abandoned halfway through.
+    let mut writer =
+        GeoTiffWriter::create(&dir.path().join("dem.tiff"), image.clone(), &options).unwrap();
+    writer.write_row(&[1.0; 600]).unwrap();
+    assert!(blocks.exists());
+    drop(writer);
+    assert!(!blocks.exists());
+
+    // Failing to create the output, here taken by a directory.
+    fs::create_dir(dir.path().join("dem.tiff")).unwrap();
+    let mut writer = GeoTiffWriter::create(&dir.path().join("dem.tiff"), image, &options).unwrap();
+    for _ in 0..300 {
+        writer.write_row(&[1.0; 600]).unwrap();
+    }
+    assert!(writer.finish().is_err());
+    assert!(!blocks.exists());
+}
+
+#[test]
+fn plain_geotiff_is_not_a_cog() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dem.tiff");
+    let options = GeoTiffOptions {
+        tiled: true,
+        overviews: vec![2],
+        ..GeoTiffOptions::default()
+    };
+    write(&path, &options);
+
+    let report = cog::validate(&path).unwrap();
+    assert!(!report.is_valid());
+    assert!(report
+        .errors
+        .iter()
+        .any(|e| e.starts_with("the first IFD is at byte")));
+    assert!(report
+        .errors
+        .iter()
+        .any(|e| e.contains("the full-resolution image start at byte")));
+    assert!(report.to_string().contains("not a Cloud Optimized GeoTIFF"));
+
+    let striped = dir.path().join("striped.tiff");
+    write(&striped, &GeoTiffOptions::default());
+    let report = cog::validate(&striped).unwrap();
+    assert!(report
+        .errors
+        .contains(&"the full-resolution image is stored in strips, not tiles".to_string()));
+}
+
+#[test]
+fn gdal_backend_uses_the_cog_driver() {
+    let geotiff = GeoTiffOptions {
+        format: TiffFormat::Cog,
+        compression: Compression::Deflate,
+        tile_size: 512,
+        ..GeoTiffOptions::default()
+    };
+    let config = Config {
+        geotiff: geotiff.clone(),
+        ..Config::default()
+    };
+    let plan = vrt_maker::plan_convert(&config, Stages::ALL, None, None);
+    let ortho = plan.steps[0].to_string();
+    assert!(
+        ortho.starts_with(
+            "gdal_translate -of COG -co BLOCKSIZE=512 -co COMPRESS=DEFLATE \
+             -co PREDICTOR=STANDARD -co RESAMPLING=AVERAGE "
+        ),
+        "{}",
+        ortho
+    );
+    let dem = plan.steps[1].to_string();
+    assert!(dem.contains("-co PREDICTOR=FLOATING_POINT"), "{}", dem);
+    assert!(!dem.contains("gdaladdo"), "{}", dem);
+
+    let config = Config {
+        geotiff: GeoTiffOptions {
+            overviews: vec![2, 4],
+            ..geotiff
+        },
+        ..Config::default()
+    };
+    assert!(config
+        .validate()
+        .unwrap_err()
+        .to_string()
+        .contains("geotiff.overviews cannot be set"));
+}
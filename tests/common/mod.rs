@@ -0,0 +1,107 @@
+//! Fixtures shared by the integration tests: ASCII grid tiles, and
+//! `Float32` GeoTIFFs written and read back natively.
+
+#![allow(dead_code)]
+
+use std::fs::{self, File};
+use std::path::Path;
+
+use vrt_maker::config::GeoTiffOptions;
+use vrt_maker::raster::DataType;
+use vrt_maker::rows::RowSource;
+use vrt_maker::tiff::{Ifd, TiffReader};
+use vrt_maker::tiff_rows::GeoTiffRows;
+use vrt_maker::tiff_writer::{GeoTiffImage, GeoTiffWriter};
+
+/// 1 m pixels from (1000, 2000), the usual layout for [`write_dem`].
+pub const GEO_TRANSFORM: [f64; 6] = [1000.0, 1.0, 0.0, 2000.0, 0.0, -1.0];
+
+/// Writes an ESRI ASCII grid of `size.0` x `size.1` square cells with its
+/// lower-left corner at `origin`, holding 1, 2, 3... top row first and
+/// declaring `nodata` when given.
+pub fn write_asc(
+    path: &Path,
+    size: (usize, usize),
+    origin: (f64, f64),
+    cellsize: f64,
+    nodata: Option<f64>,
+) {
+    let (ncols, nrows) = size;
+    let rows: Vec<String> = (0..nrows)
+        .map(|r| {
+            (r * ncols + 1..=(r + 1) * ncols)
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    let nodata = nodata
+        .map(|v| format!("NODATA_value {}\n", v))
+        .unwrap_or_default();
+    let text = format!(
+        "ncols {}\nnrows {}\nxllcorner {}\nyllcorner {}\ncellsize {}\n{}{}\n",
+        ncols,
+        nrows,
+        origin.0,
+        origin.1,
+        cellsize,
+        nodata,
+        rows.join("\n")
+    );
+    fs::write(path, text).unwrap();
+}
+
+/// Writes `<name>.asc`, a 4 x 2 tile of 1 m cells with its lower-left
+/// corner at (`x`, 0), and its `<name>.prj` holding `prj`.
+pub fn write_tile(dir: &Path, name: &str, x: u32, prj: &str) {
+    write_asc(
+        &dir.join(format!("{}.asc", name)),
+        (4, 2),
+        (x as f64, 0.0),
+        1.0,
+        None,
+    );
+    fs::write(dir.join(format!("{}.prj", name)), prj).unwrap();
+}
+
+/// Writes `rows` as a `Float32` DEM laid out by `geo_transform` in
+/// Lambert-93, `NaN` being nodata.
+pub fn write_dem(
+    path: &Path,
+    geo_transform: [f64; 6],
+    rows: &[Vec<f32>],
+    options: &GeoTiffOptions,
+) {
+    let image = GeoTiffImage {
+        width: rows[0].len(),
+        height: rows.len(),
+        geo_transform,
+        crs: Some("EPSG:2154".to_string()),
+        nodata: -99999.0,
+        data_type: DataType::Float32,
+    };
+    let mut writer = GeoTiffWriter::create(path, image, options).unwrap();
+    for row in rows {
+        writer.write_row(row).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+/// Reads every row of the GeoTIFF at `path`, nodata as `NaN`.
+pub fn read(path: &Path) -> Vec<Vec<f32>> {
+    let mut rows = GeoTiffRows::open(path).unwrap();
+    let mut out = Vec::new();
+    for _ in 0..rows.height() {
+        let mut row = vec![0.0; rows.width()];
+        rows.read_row(&mut row).unwrap();
+        out.push(row);
+    }
+    out
+}
+
+pub fn read_ifds(path: &Path) -> Vec<Ifd> {
+    TiffReader::new(File::open(path).unwrap())
+        .unwrap()
+        .read_ifds()
+        .unwrap()
+}
@@ -0,0 +1,94 @@
+//! Project files: the shipped example, paths relative to the file, and the
+//! errors of unknown keys, bad values and invalid settings.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use vrt_maker::config::{ConfigError, VrtResolution};
+use vrt_maker::Config;
+
+/// Loads `text` as `vrt_maker.toml` in `dir`.
+fn load(dir: &Path, text: &str) -> Result<Config, ConfigError> {
+    let path = dir.join("vrt_maker.toml");
+    fs::write(&path, text).unwrap();
+    Config::load(&path)
+}
+
+#[test]
+fn shipped_example_loads() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("vrt_maker.toml");
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.outputs.dem, "dem.tiff");
+}
+
+#[test]
+fn relative_paths_follow_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = load(
+        dir.path(),
+        "[inputs]\nasc_dir = \"tiles/asc\"\njp2_dir = \"/data/jp2\"\n\n\
+         [outputs]\ndir = \"build\"\n\n[vrt]\nresolution = \"lowest\"\n",
+    )
+    .unwrap();
+    assert_eq!(config.inputs.asc_dir, dir.path().join("tiles/asc"));
+    assert_eq!(config.inputs.jp2_dir, PathBuf::from("/data/jp2"));
+    assert_eq!(config.outputs.dir, dir.path().join("build"));
+    assert_eq!(config.vrt.resolution, VrtResolution::Lowest);
+    // Keys left out keep their defaults.
+    assert_eq!(config.outputs.ortho, "orthophoto.tiff");
+}
+
+#[test]
+fn unknown_keys_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vrt_maker.toml");
+    for (text, key) in [
+        ("[output]\ndir = \"out\"\n", "output"),
+        ("[outputs]\ndirectory = \"out\"\n", "directory"),
+        ("[dem.fill]\nmax_distnace = 10\n", "max_distnace"),
+    ] {
+        let err = load(dir.path(), text).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse(..)), "{}", err);
+        let message = err.to_string();
+        assert!(
+            message.starts_with(&format!("invalid {}: ", path.display())),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains(&format!("unknown field `{}`", key)),
+            "{}",
+            message
+        );
+    }
+}
+
+#[test]
+fn bad_values_and_settings_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let err = load(dir.path(), "[vrt]\nresolution = \"finest\"\n").unwrap_err();
+    assert!(matches!(err, ConfigError::Parse(..)), "{}", err);
+    assert!(
+        err.to_string().contains("unknown variant `finest`"),
+        "{}",
+        err
+    );
+
+    let err = load(dir.path(), "[outputs]\ndem = 3\n").unwrap_err();
+    assert!(matches!(err, ConfigError::Parse(..)), "{}", err);
+
+    // Parsed, then refused by validation.
+    let err = load(dir.path(), "[outputs]\ndem = \"out/dem.tiff\"\n").unwrap_err();
+    assert!(matches!(err, ConfigError::Invalid(_)), "{}", err);
+    assert_eq!(
+        err.to_string(),
+        "invalid configuration: outputs.dem must be a plain file name, got \"out/dem.tiff\""
+    );
+
+    let missing = dir.path().join("missing.toml");
+    let err = Config::load(&missing).unwrap_err();
+    assert!(matches!(err, ConfigError::Io(..)), "{}", err);
+    assert!(err
+        .to_string()
+        .starts_with(&format!("cannot read {}: ", missing.display())));
+}
@@ -0,0 +1,278 @@
+//! Contour lines: marching squares, lines stitched across blocks, index
+//! contours, nodata, and the stage writing GeoJSON and GeoPackage files.
+
+use std::fs;
+
+use serde_json::Value;
+use vrt_maker::config::{
+    ContourFormat, ContourOptions, FillBackend, FillOptions, GeoTiffBackend, GeoTiffOptions,
+    Resampling, Resolution, WarpBackend, WarpOptions,
+};
+use vrt_maker::contours::{trace_contours, ContourLine};
+use vrt_maker::error::Stage;
+use vrt_maker::rows::{RowError, RowSource};
+use vrt_maker::{Config, Pipeline, RecordingRunner, Stages};
+
+/// An in-memory raster of 1 m pixels with its top-left corner at (0, 0);
+/// `NaN` marks nodata.
+struct Grid {
+    rows: Vec<Vec<f32>>,
+    next: usize,
+}
+
+impl RowSource for Grid {
+    fn width(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn read_row(&mut self, row: &mut [f32]) -> Result<(), RowError> {
+        row.copy_from_slice(&self.rows[self.next]);
+        self.next += 1;
+        Ok(())
+    }
+}
+
+fn trace(rows: Vec<Vec<f32>>, options: &ContourOptions) -> Vec<ContourLine> {
+    let mut lines = Vec::new();
+    trace_contours(
+        &mut Grid { rows, next: 0 },
+        &[0.0, 1.0, 0.0, 0.0, 0.0, -1.0],
+        options,
+        |line| {
+            lines.push(line);
+            Ok(())
+        },
+    )
+    .unwrap();
+    lines
+}
+
+fn every(interval: f64, block_rows: usize) -> ContourOptions {
+    ContourOptions {
+        interval,
+        block_rows,
+        ..ContourOptions::default()
+    }
+}
+
+/// Heights falling away from a summit of 10 m at pixel (6, 6).
+fn hill() -> Vec<Vec<f32>> {
+    (0..13)
+        .map(|r| {
+            (0..13)
+                .map(|c| 10.0 - ((r as f32 - 6.0).hypot(c as f32 - 6.0)))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn lines_are_stitched_across_blocks() {
+    // Heights rising by 1 m per pixel eastwards: one straight line per
+    // contour, down the whole raster.
+    let ramp: Vec<Vec<f32>> = (0..9).map(|_| (0..6).map(|c| c as f32).collect()).collect();
+    for block_rows in [1, 2, 256] {
+        let lines = trace(ramp.clone(), &every(2.0, block_rows));
+        let elevations: Vec<f64> = lines.iter().map(|l| l.elevation).collect();
+        assert_eq!(elevations, [2.0, 4.0], "{}", block_rows);
+        for line in &lines {
+            assert_eq!(line.points.len(), 9, "{:?}", line);
+            let x = line.elevation + 0.5;
+            assert!(line.points.iter().all(|&(px, _)| px == x), "{:?}", line);
+            let (first, last) = (line.points[0].1, line.points[8].1);
+            assert_eq!(first.max(last), -0.5);
+            assert_eq!(first.min(last), -8.5);
+        }
+    }
+
+    let options = ContourOptions {
+        base: 0.5,
+        ..every(2.0, 256)
+    };
+    let whole = trace(hill(), &options);
+    for block_rows in [1, 3] {
+        let by_block = trace(
+            hill(),
+            &ContourOptions {
+                block_rows,
+                ..options.clone()
+            },
+        );
+        assert_eq!(whole, by_block, "{}", block_rows);
+    }
+    // Rings around the summit, each closed on itself; the contours further
+    // down are cut by the raster's edges.
+    for elevation in [4.5, 6.5, 8.5] {
+        let rings: Vec<&ContourLine> = whole.iter().filter(|l| l.elevation == elevation).collect();
+        assert_eq!(rings.len(), 1, "{:?}", rings);
+        let ring = &rings[0].points;
+        assert_eq!(ring.first(), ring.last(), "{:?}", ring);
+        let radius = 10.0 - elevation;
+        for &(x, y) in ring {
+            let distance = (x - 6.5).hypot(y + 6.5);
+            assert!(
+                (distance - radius).abs() < 0.3,
+                "{} at {:?}",
+                radius,
+                (x, y)
+            );
+        }
+    }
+    assert!(whole
+        .iter()
+        .any(|l| l.elevation == 2.5 && l.points.first() != l.points.last()));
+}
+
+#[test]
+fn index_contours_and_base() {
+    let options = ContourOptions {
+        interval: 1.0,
+        base: 0.5,
+        index: 2,
+        ..ContourOptions::default()
+    };
+    let ramp: Vec<Vec<f32>> = (0..3).map(|_| (0..5).map(|c| c as f32).collect()).collect();
+    let lines = trace(ramp, &options);
+    let levels: Vec<(f64, bool)> = lines.iter().map(|l| (l.elevation, l.index)).collect();
+    assert_eq!(
+        levels,
+        [(0.5, true), (1.5, false), (2.5, true), (3.5, false)]
+    );
+    assert_eq!(lines[0].points[0].0, 1.0);
+}
+
+#[test]
+fn nodata_cuts_lines() {
+    let mut rows: Vec<Vec<f32>> = (0..7).map(|_| (0..4).map(|c| c as f32).collect()).collect();
+    rows[3][1] = f32::NAN;
+    let lines = trace(rows, &every(1.0, 2));
+    // The contour at 1 m runs down column 1: cut around the hole, it
+    // leaves two lines.
+    let ones: Vec<&ContourLine> = lines.iter().filter(|l| l.elevation == 1.0).collect();
+    assert_eq!(ones.len(), 2, "{:?}", lines);
+    assert!(ones.iter().all(|l| l.points.len() == 3), "{:?}", ones);
+    assert_eq!(lines.iter().filter(|l| l.elevation == 2.0).count(), 2);
+}
+
+#[test]
+fn written_as_geojson_and_geopackage() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    fs::write(
+        asc.join("a.asc"),
+        "ncols 4\nnrows 3\nxllcorner 0\nyllcorner 0\ncellsize 1\n\
+         11.5 12.5 13.5 14.5\n11.5 12.5 13.5 14.5\n11.5 12.5 13.5 14.5\n",
+    )
+    .unwrap();
+    fs::write(asc.join("a.prj"), "EPSG:2154").unwrap();
+
+    let pipeline = |formats: Vec<ContourFormat>| {
+        Pipeline::builder()
+            .asc_dir(&asc)
+            .out_dir(dir.path().join("out"))
+            .fill(FillOptions {
+                backend: FillBackend::Native,
+                ..FillOptions::default()
+            })
+            .warp(WarpOptions {
+                backend: WarpBackend::Native,
+                resolution: Resolution::Square(1.0),
+                resampling: Resampling::Near,
+                ..WarpOptions::default()
+            })
+            .geotiff(GeoTiffOptions {
+                backend: GeoTiffBackend::Native,
+                ..GeoTiffOptions::default()
+            })
+            .contours(ContourOptions {
+                enabled: true,
+                interval: 1.0,
+                formats,
+                ..ContourOptions::default()
+            })
+            .stages(Stages::DEM)
+            .build()
+            .unwrap()
+    };
+
+    let geojson = dir.path().join("out/contours.geojson");
+    let gpkg = dir.path().join("out/contours.gpkg");
+    let both = pipeline(vec![ContourFormat::GeoJson, ContourFormat::Gpkg]);
+    let plan = both.plan().unwrap();
+    let stage = plan.stages.last().unwrap();
+    assert_eq!(stage.stage, Stage::Contours);
+    assert_eq!(
+        plan.outputs[plan.outputs.len() - 2..],
+        [geojson.clone(), gpkg.clone()]
+    );
+    assert!(stage.steps[0]
+        .to_string()
+        .starts_with("(built-in) contours of "));
+    let convert = stage.steps[1].to_string();
+    assert!(
+        convert.starts_with("ogr2ogr -f GPKG -overwrite -nln contours "),
+        "{}",
+        convert
+    );
+    assert!(convert.ends_with("contours.geojson"), "{}", convert);
+
+    let runner = RecordingRunner::new();
+    let outputs = both.run_with(&runner).unwrap();
+    assert_eq!(runner.programs(), ["ogr2ogr"]);
+    assert_eq!(outputs.contours, [geojson.clone(), gpkg]);
+    let document: Value = serde_json::from_str(&fs::read_to_string(&geojson).unwrap()).unwrap();
+    assert_eq!(
+        document["crs"]["properties"]["name"],
+        "urn:ogc:def:crs:EPSG::2154"
+    );
+    let features = document["features"].as_array().unwrap();
+    let elevations: Vec<f64> = features
+        .iter()
+        .map(|f| f["properties"]["elevation"].as_f64().unwrap())
+        .collect();
+    assert_eq!(elevations, [12.0, 13.0, 14.0]);
+    assert_eq!(features[0]["properties"]["index"], false);
+    assert_eq!(features[0]["geometry"]["type"], "LineString");
+
+    // A GeoPackage alone is traced in the working directory.
+    let plan = pipeline(vec![ContourFormat::Gpkg]).plan().unwrap();
+    let stage = plan.stages.last().unwrap();
+    assert!(stage.steps[0].output.ends_with("tmp/contours.geojson"));
+    assert!(!plan.outputs.contains(&stage.steps[0].output));
+}
+
+#[test]
+fn invalid_contours_are_refused() {
+    let invalid = |contours: ContourOptions| {
+        Config {
+            contours: ContourOptions {
+                enabled: true,
+                ..contours
+            },
+            ..Config::default()
+        }
+        .validate()
+        .unwrap_err()
+        .to_string()
+    };
+    assert!(invalid(every(0.0, 256)).contains("contours.interval must be positive"));
+    assert!(invalid(ContourOptions {
+        formats: Vec::new(),
+        ..ContourOptions::default()
+    })
+    .contains("contours.formats must list"));
+    assert_eq!(
+        invalid(ContourOptions {
+            formats: vec![ContourFormat::GeoJson, ContourFormat::GeoJson],
+            ..ContourOptions::default()
+        }),
+        "invalid configuration: contours.formats lists geojson twice"
+    );
+    let config: Config = toml::from_str("[contours]\nformats = [\"gpkg\"]\n").unwrap();
+    assert_eq!(config.contours.formats, [ContourFormat::Gpkg]);
+}
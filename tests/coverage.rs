@@ -0,0 +1,119 @@
+//! Tile grid detection and the coverage report.
+
+use std::fs;
+use std::path::PathBuf;
+
+use vrt_maker::coverage::Coverage;
+use vrt_maker::raster::{DataType, RasterInfo};
+use vrt_maker::{Pipeline, PipelineError, Stage, Stages};
+
+/// A 1 km tile of 1 m pixels with its top-left corner at `(x, y)` km.
+fn tile(name: &str, x: f64, y: f64) -> RasterInfo {
+    RasterInfo {
+        path: PathBuf::from(name),
+        width: 1000,
+        height: 1000,
+        bands: 1,
+        data_type: DataType::Float32,
+        geo_transform: [x * 1000.0, 1.0, 0.0, y * 1000.0, 0.0, -1.0],
+        nodata: Some(-99999.0),
+        crs: None,
+    }
+}
+
+#[test]
+fn complete_grid_has_no_problems() {
+    let tiles = [
+        tile("a", 620.0, 6120.0),
+        tile("b", 621.0, 6120.0),
+        tile("c", 620.0, 6119.0),
+        tile("d", 621.0, 6119.0),
+    ];
+    let coverage = Coverage::check(Stage::DemVrt, &tiles).unwrap();
+
+    assert_eq!((coverage.columns, coverage.rows), (2, 2));
+    assert_eq!(
+        (coverage.tile_width, coverage.tile_height),
+        (1000.0, 1000.0)
+    );
+    assert_eq!(coverage.origin, (620_000.0, 6_120_000.0));
+    assert!(coverage.is_complete());
+    assert!(!coverage.has_problems());
+}
+
+#[test]
+fn reports_missing_duplicate_and_overlapping_tiles() {
+    let tiles = [
+        tile("a", 620.0, 6120.0),
+        tile("b", 621.0, 6120.0),
+        tile("c", 622.0, 6120.0),
+        tile("d", 620.0, 6119.0),
+        tile("d-copy", 620.0, 6119.0),
+        tile("shifted", 621.5, 6118.0),
+        tile("e", 620.0, 6118.0),
+    ];
+    let coverage = Coverage::check(Stage::DemVrt, &tiles).unwrap();
+
+    assert_eq!((coverage.columns, coverage.rows), (3, 3));
+    let missing: Vec<_> = coverage.missing.iter().map(|c| (c.column, c.row)).collect();
+    assert_eq!(missing, [(1, 1), (2, 1)]);
+    assert_eq!(
+        coverage.duplicates,
+        [vec![PathBuf::from("d"), PathBuf::from("d-copy")]]
+    );
+    assert_eq!(coverage.off_grid, [PathBuf::from("shifted")]);
+    assert!(coverage.overlaps.is_empty());
+    assert!(!coverage.is_complete());
+
+    let text = coverage.to_string();
+    assert!(text.starts_with("[dem-vrt] 7 tile(s) on a 3 x 3 grid of 1000 x 1000 tiles\n"));
+    assert!(text.contains("  2 missing tile(s):\n    column 1, row 1: "));
+}
+
+#[test]
+fn off_grid_tiles_overlapping_the_grid_are_reported() {
+    let tiles = [
+        tile("a", 620.0, 6120.0),
+        tile("b", 621.0, 6120.0),
+        tile("shifted", 620.5, 6120.0),
+    ];
+    let coverage = Coverage::check(Stage::OrthoVrt, &tiles).unwrap();
+
+    assert_eq!(
+        coverage.overlaps,
+        [
+            (PathBuf::from("shifted"), PathBuf::from("a")),
+            (PathBuf::from("shifted"), PathBuf::from("b")),
+        ]
+    );
+    assert!(coverage.is_complete());
+}
+
+#[test]
+fn require_complete_aborts_the_build() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    for (name, x, y) in [("a", 0, 2), ("b", 2, 2), ("c", 0, 0)] {
+        let text =
+            format!("ncols 2\nnrows 2\nxllcorner {x}\nyllcorner {y}\ncellsize 1\n1 2\n3 4\n");
+        fs::write(asc.join(format!("{name}.asc")), text).unwrap();
+    }
+    let builder = Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .stages(Stages::DEM);
+
+    let plan = builder.clone().build().unwrap().plan().unwrap();
+    let coverage = plan.stages[0].coverage.as_ref().unwrap();
+    assert_eq!(coverage.missing.len(), 1);
+
+    let mut config = builder.build().unwrap().config().clone();
+    config.coverage.require_complete = true;
+    let err = Pipeline::from_config(config, Stages::DEM)
+        .unwrap()
+        .plan()
+        .unwrap_err();
+    assert!(matches!(err, PipelineError::IncompleteCoverage(_)));
+    assert_eq!(err.exit_code(), PipelineError::EXIT_INCOMPLETE_COVERAGE);
+}
@@ -0,0 +1,129 @@
+//! CRS identification and the consistency check across input tiles.
+
+use std::fs;
+
+use vrt_maker::config::CrsOptions;
+use vrt_maker::crs::{self, CrsId};
+use vrt_maker::{Pipeline, PipelineError, RecordingRunner, Stages};
+
+mod common;
+
+use common::write_tile;
+
+const LAMBERT_93_ESRI: &str = r#"PROJCS["RGF_1993_Lambert_93",GEOGCS["GCS_RGF_1993",DATUM["D_RGF_1993",SPHEROID["GRS_1980",6378137.0,298.257222101]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Lambert_Conformal_Conic"],UNIT["Meter",1.0]]"#;
+
+const LAMBERT_2_EPSG: &str = r#"PROJCS["NTF (Paris) / Lambert zone II",GEOGCS["NTF (Paris)",AUTHORITY["EPSG","4807"]],PROJECTION["Lambert_Conformal_Conic_1SP"],AUTHORITY["EPSG","27572"]]"#;
+
+#[test]
+fn identifies_codes_urns_and_wkt() {
+    assert_eq!(CrsId::identify("EPSG:2154"), Some(CrsId::Epsg(2154)));
+    assert_eq!(CrsId::identify("epsg: 4326"), Some(CrsId::Epsg(4326)));
+    assert_eq!(
+        CrsId::identify("urn:ogc:def:crs:EPSG::3857"),
+        Some(CrsId::Epsg(3857))
+    );
+    // The root authority wins over the nested GEOGCS one.
+    assert_eq!(CrsId::identify(LAMBERT_2_EPSG), Some(CrsId::Epsg(27572)));
+    assert_eq!(
+        CrsId::identify(
+            r#"PROJCRS["RGF93 v1 / Lambert-93",BASEGEOGCRS["RGF93 v1",ID["EPSG",4171]],ID["EPSG",2154]]"#
+        ),
+        Some(CrsId::Epsg(2154))
+    );
+    // ESRI .prj files carry no code; well-known names are mapped.
+    assert_eq!(CrsId::identify(LAMBERT_93_ESRI), Some(CrsId::Epsg(2154)));
+    assert_eq!(
+        CrsId::identify(r#"PROJCS["Local Grid",UNIT["Meter",1.0]]"#),
+        Some(CrsId::Named("localgrid".to_string()))
+    );
+    assert_eq!(CrsId::identify("  "), None);
+}
+
+#[test]
+fn geographic_crss_are_in_degrees() {
+    for definition in [
+        "EPSG:4326",
+        "EPSG:4171+5720",
+        r#"GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984"]]"#,
+        r#"COMPD_CS["WGS 84 + EGM96",GEOGCS["WGS 84"],VERT_CS["EGM96 height"]]"#,
+    ] {
+        assert!(crs::is_geographic(definition), "{}", definition);
+    }
+    // World Equidistant Cylindrical is in metres, 4978 geocentric.
+    for definition in ["EPSG:2154", "EPSG:4087", "EPSG:4978", LAMBERT_2_EPSG] {
+        assert!(!crs::is_geographic(definition), "{}", definition);
+    }
+}
+
+fn mixed_site() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    write_tile(&asc, "a", 0, LAMBERT_93_ESRI);
+    write_tile(&asc, "b", 4, "EPSG:2154");
+    write_tile(&asc, "c", 8, LAMBERT_2_EPSG);
+    dir
+}
+
+#[test]
+fn mixed_crs_refuses_to_build_with_a_per_file_report() {
+    let site = mixed_site();
+    let err = Pipeline::builder()
+        .asc_dir(site.path().join("asc"))
+        .out_dir(site.path().join("out"))
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+        .plan()
+        .unwrap_err();
+
+    let PipelineError::MixedCrs(report) = &err else {
+        panic!("unexpected error: {err}");
+    };
+    assert_eq!(report.distinct(), [CrsId::Epsg(2154), CrsId::Epsg(27572)]);
+    assert_eq!(err.exit_code(), PipelineError::EXIT_INVALID_INPUT);
+    let text = err.to_string();
+    assert!(text.contains("  EPSG:2154 (2 tile(s)):\n"));
+    assert!(text.contains("  EPSG:27572 (1 tile(s)):\n    [dem-vrt] "));
+    assert!(text.trim_end().ends_with("c.asc"));
+}
+
+#[test]
+fn outliers_are_reprojected_to_the_chosen_crs() {
+    let site = mixed_site();
+    let runner = RecordingRunner::new();
+    Pipeline::builder()
+        .asc_dir(site.path().join("asc"))
+        .out_dir(site.path().join("out"))
+        .crs(CrsOptions {
+            inputs: Some("EPSG:2154".to_string()),
+            ..CrsOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+        .run_with(&runner)
+        .unwrap();
+
+    assert_eq!(
+        runner.programs(),
+        [
+            "gdalwarp",
+            "gdalbuildvrt",
+            "gdal_fillnodata",
+            "gdalwarp",
+            "gdal_translate"
+        ]
+    );
+    let reproject = runner.commands()[0].lossy_args();
+    assert_eq!(
+        reproject[..6],
+        ["-t_srs", "EPSG:2154", "-r", "bilinear", "-of", "VRT"]
+    );
+    assert!(reproject[6].ends_with("c.asc"));
+    assert!(reproject[7].ends_with("c.reprojected.vrt"));
+
+    let mosaic = runner.commands()[1].lossy_args();
+    assert!(mosaic[3].ends_with("a.asc") && mosaic[4].ends_with("b.asc"));
+    assert!(mosaic[5].ends_with("c.reprojected.vrt"));
+}
@@ -0,0 +1,87 @@
+//! IGN dalle names: parsing, selection by coordinate range and the
+//! cross-check against the `.asc` header.
+
+use std::fs;
+use std::path::Path;
+
+use vrt_maker::dalle::DalleName;
+use vrt_maker::{Pipeline, PipelineError, Stages};
+
+mod common;
+
+use common::write_asc;
+
+#[test]
+fn parses_rge_alti_and_bd_ortho_names() {
+    let parse = |name: &str| DalleName::parse(Path::new(name));
+    assert_eq!(
+        parse("RGEALTI_FXX_0620_6120_MNT_LAMB93_IGN69.asc"),
+        Some(DalleName {
+            x_km: 620,
+            y_km: 6120
+        })
+    );
+    // The year is not taken for an easting.
+    assert_eq!(
+        parse("75-2021-0652-6862-LA93-0M20-E080.jp2"),
+        Some(DalleName {
+            x_km: 652,
+            y_km: 6862
+        })
+    );
+    assert_eq!(parse("tile_a.asc"), None);
+    assert_eq!(parse("scan_2021_1200.asc"), None);
+
+    let name = parse("RGEALTI_FXX_0620_6120_MNT.asc").unwrap();
+    assert_eq!(name.origin(), (620_000.0, 6_120_000.0));
+    assert!(name.within(&[620, 6119, 621, 6120]));
+    assert!(!name.within(&[621, 6119, 622, 6120]));
+}
+
+/// A 2 x 2 dalle of 500 m cells named after `name_km` with its top-left
+/// corner at `origin_km`.
+fn write_dalle(dir: &Path, name_km: (u32, u32), origin_km: (u32, u32)) {
+    let name = format!("RGEALTI_FXX_{:04}_{:04}_MNT.asc", name_km.0, name_km.1);
+    let origin = (
+        origin_km.0 as f64 * 1000.0,
+        (origin_km.1 - 1) as f64 * 1000.0,
+    );
+    write_asc(&dir.join(name), (2, 2), origin, 500.0, None);
+}
+
+#[test]
+fn selects_by_range_and_flags_misnamed_tiles() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    write_dalle(&asc, (620, 6120), (620, 6120));
+    write_dalle(&asc, (621, 6120), (622, 6120));
+    write_dalle(&asc, (625, 6120), (625, 6120));
+    let builder = Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .stages(Stages::DEM);
+
+    let mut config = builder.build().unwrap().config().clone();
+    config.inputs.tiles = Some([620, 6120, 622, 6120]);
+    let pipeline = Pipeline::from_config(config.clone(), Stages::DEM).unwrap();
+    let plan = pipeline.plan().unwrap();
+    let stage = &plan.stages[0];
+    assert_eq!(stage.inputs.len(), 2);
+    assert!(stage.excluded[0].ends_with("RGEALTI_FXX_0625_6120_MNT.asc"));
+
+    let [mismatch] = stage.name_mismatches.as_slice() else {
+        panic!("expected one mismatch: {:?}", stage.name_mismatches);
+    };
+    assert_eq!(mismatch.origin, (622_000.0, 6_120_000.0));
+    assert!(mismatch
+        .to_string()
+        .ends_with("name says (621000, 6120000), header origin is (622000, 6120000)"));
+
+    config.inputs.tiles = Some([700, 6000, 710, 6010]);
+    let err = Pipeline::from_config(config, Stages::DEM)
+        .unwrap()
+        .plan()
+        .unwrap_err();
+    assert_eq!(err.exit_code(), PipelineError::EXIT_CONFIG);
+}
@@ -0,0 +1,96 @@
+//! Pre-flight report parsing and formatting.
+
+use std::path::PathBuf;
+
+use vrt_maker::doctor::{GdalVersion, Jp2Check, Report, ToolCheck};
+use vrt_maker::PipelineError;
+
+fn version(major: u32, minor: u32, patch: u32) -> GdalVersion {
+    GdalVersion {
+        major,
+        minor,
+        patch,
+    }
+}
+
+#[test]
+fn parses_gdal_version_banners() {
+    assert_eq!(
+        GdalVersion::parse("GDAL 3.8.4, released 2024/02/08\n"),
+        Some(version(3, 8, 4))
+    );
+    assert_eq!(
+        GdalVersion::parse("GDAL 3.9.0dev-5a4b, released 2024/04/01"),
+        Some(version(3, 9, 0))
+    );
+    assert_eq!(GdalVersion::parse("GDAL 2.4"), Some(version(2, 4, 0)));
+    assert_eq!(GdalVersion::parse("usage: gdalwarp ..."), None);
+}
+
+#[test]
+fn report_lists_missing_tools() {
+    let report = Report {
+        tools: vec![
+            ToolCheck {
+                name: "gdalwarp".to_string(),
+                path: Some(PathBuf::from("/usr/bin/gdalwarp")),
+                version: Some(version(3, 8, 4)),
+                problem: None,
+            },
+            ToolCheck {
+                name: "gdal_fillnodata".to_string(),
+                path: None,
+                version: None,
+                problem: Some("not found on PATH".to_string()),
+            },
+        ],
+        jp2: Some(Jp2Check {
+            drivers: vec!["JP2OpenJPEG".to_string()],
+            problem: None,
+        }),
+        optional: Vec::new(),
+    };
+
+    assert!(!report.is_ok());
+    let table = report.to_string();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("gdalwarp          ok       3.8.4    /usr/bin/gdalwarp"));
+    assert!(lines[2].starts_with("gdal_fillnodata   MISSING  -        not found on PATH"));
+    assert!(lines[3].ends_with("JP2OpenJPEG"));
+
+    let err = PipelineError::Preflight(report);
+    assert_eq!(err.exit_code(), PipelineError::EXIT_SPAWN);
+    assert!(err
+        .to_string()
+        .starts_with("[prepare] GDAL pre-flight check failed:\nTOOL"));
+}
+
+#[test]
+fn optional_tools_do_not_fail_the_check() {
+    let report = Report {
+        tools: vec![ToolCheck {
+            name: "gdalwarp".to_string(),
+            path: Some(PathBuf::from("/usr/bin/gdalwarp")),
+            version: Some(version(3, 8, 4)),
+            problem: None,
+        }],
+        jp2: None,
+        optional: vec![ToolCheck {
+            name: "ogr2ogr".to_string(),
+            path: None,
+            version: None,
+            problem: Some("not found on PATH".to_string()),
+        }],
+    };
+
+    assert!(report.is_ok());
+    let table = report.to_string();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(
+        lines[2].starts_with("ogr2ogr   MISSING  -        not found on PATH (optional: "),
+        "{}",
+        table
+    );
+}
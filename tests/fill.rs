@@ -0,0 +1,172 @@
+//! Native nodata filling: strategies, block independence, and the step
+//! replacing `gdal_fillnodata` in the pipeline.
+
+use std::fs;
+
+use vrt_maker::config::{DemOptions, FillBackend, FillOptions, FillStrategy};
+use vrt_maker::fill::fill_rows;
+use vrt_maker::rows::{RowError, RowSource};
+use vrt_maker::{Pipeline, RecordingRunner, Stages};
+
+/// An in-memory raster; `NaN` marks holes.
+struct Grid {
+    width: usize,
+    rows: Vec<Vec<f32>>,
+    next: usize,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize, z: impl Fn(usize, usize) -> f32) -> Grid {
+        let rows = (0..height)
+            .map(|r| (0..width).map(|c| z(r, c)).collect())
+            .collect();
+        Grid {
+            width,
+            rows,
+            next: 0,
+        }
+    }
+
+    fn punch(mut self, rows: std::ops::Range<usize>, columns: std::ops::Range<usize>) -> Grid {
+        for r in rows {
+            for c in columns.clone() {
+                self.rows[r][c] = f32::NAN;
+            }
+        }
+        self
+    }
+}
+
+impl RowSource for Grid {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn read_row(&mut self, row: &mut [f32]) -> Result<(), RowError> {
+        row.copy_from_slice(&self.rows[self.next]);
+        self.next += 1;
+        Ok(())
+    }
+}
+
+fn fill(mut grid: Grid, options: &FillOptions) -> Vec<Vec<f32>> {
+    let mut out = Vec::new();
+    fill_rows(&mut grid, options, |row| {
+        out.push(row.to_vec());
+        Ok(())
+    })
+    .unwrap();
+    out
+}
+
+fn options(strategy: FillStrategy) -> FillOptions {
+    FillOptions {
+        backend: FillBackend::Native,
+        strategy,
+        smoothing_iterations: 0,
+        ..FillOptions::default()
+    }
+}
+
+#[test]
+fn strategies_fill_holes_from_their_surroundings() {
+    let plane = |r: usize, c: usize| 100.0 + 2.0 * c as f32 - 0.5 * r as f32;
+    let holed = || Grid::new(9, 9, plane).punch(3..6, 3..6);
+
+    let planar = fill(holed(), &options(FillStrategy::Planar));
+    assert!((planar[4][4] - plane(4, 4)).abs() < 1e-3);
+
+    let idw = fill(holed(), &options(FillStrategy::Idw));
+    assert!((idw[4][4] - plane(4, 4)).abs() < 1.0);
+    assert_eq!(idw[0][0], plane(0, 0));
+
+    let nearest = fill(holed(), &options(FillStrategy::Nearest));
+    assert!([plane(4, 2), plane(4, 6), plane(2, 4), plane(6, 4)].contains(&nearest[4][4]));
+
+    // Out of reach of any valid pixel: left as a hole.
+    let far = FillOptions {
+        max_distance: 1,
+        ..options(FillStrategy::Idw)
+    };
+    assert!(fill(holed(), &far)[4][4].is_nan());
+}
+
+#[test]
+fn blocks_do_not_change_the_result() {
+    let surface = |r: usize, c: usize| ((r * 7 + c * 3) % 11) as f32;
+    let holed = || {
+        Grid::new(12, 20, surface)
+            .punch(4..9, 2..7)
+            .punch(12..15, 8..11)
+    };
+    for strategy in [FillStrategy::Idw, FillStrategy::Laplacian] {
+        let whole = FillOptions {
+            smoothing_iterations: 2,
+            relaxation_iterations: 5,
+            block_rows: 100,
+            ..options(strategy)
+        };
+        let blocked = FillOptions {
+            block_rows: 3,
+            ..whole.clone()
+        };
+        assert_eq!(fill(holed(), &whole), fill(holed(), &blocked));
+    }
+}
+
+#[test]
+fn native_backend_replaces_gdal_fillnodata() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    fs::write(
+        asc.join("a.asc"),
+        "ncols 3\nnrows 3\nxllcorner 0\nyllcorner 0\ncellsize 1\nNODATA_value -99999\n\
+         1 1 1\n1 -99999 1\n1 1 1\n",
+    )
+    .unwrap();
+    let runner = RecordingRunner::new();
+    Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .fill(options(FillStrategy::Idw))
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+        .run_with(&runner)
+        .unwrap();
+
+    assert_eq!(runner.programs(), ["gdalwarp", "gdal_translate"]);
+    let tmp = dir.path().join("out/tmp");
+    let vrt = fs::read_to_string(tmp.join("temp_filled_dem.vrt")).unwrap();
+    assert!(vrt.contains("subClass=\"VRTRawRasterBand\""));
+    assert!(
+        vrt.contains("<SourceFilename relativeToVRT=\"1\">temp_filled_dem.bin</SourceFilename>")
+    );
+    let bytes = fs::read(tmp.join("temp_filled_dem.bin")).unwrap();
+    let pixels: Vec<f32> = bytes
+        .chunks(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+    assert_eq!(pixels, [1.0; 9]);
+
+    let config = vrt_maker::Config {
+        dem: DemOptions {
+            fill: FillOptions {
+                strategy: FillStrategy::Planar,
+                ..FillOptions::default()
+            },
+            ..DemOptions::default()
+        },
+        ..vrt_maker::Config::default()
+    };
+    assert!(config
+        .validate()
+        .unwrap_err()
+        .to_string()
+        .contains("needs dem.fill.backend = \"native\""));
+}
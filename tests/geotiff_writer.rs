@@ -0,0 +1,242 @@
+//! Native GeoTIFF writing: layout, compression, predictors, overviews, and
+//! the GDAL creation options planned otherwise.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use vrt_maker::config::{
+    Compression, FillBackend, FillOptions, GeoTiffBackend, GeoTiffOptions, Predictor, Resampling,
+    WarpBackend, WarpOptions,
+};
+use vrt_maker::geotiff::{geo_transform, GeoKeys};
+use vrt_maker::tiff::{
+    Ifd, TiffReader, TAG_COMPRESSION, TAG_GDAL_NODATA, TAG_IMAGE_WIDTH, TAG_NEW_SUBFILE_TYPE,
+    TAG_PREDICTOR, TAG_STRIP_BYTE_COUNTS, TAG_STRIP_OFFSETS, TAG_TILE_BYTE_COUNTS,
+    TAG_TILE_OFFSETS, TAG_TILE_WIDTH,
+};
+use vrt_maker::{Config, Pipeline, RecordingRunner, Stages};
+
+mod common;
+
+use common::{read_ifds, write_dem, GEO_TRANSFORM};
+
+/// Writes a 20 x 20 `Float32` ramp with a nodata pixel at the top left.
+fn write(path: &Path, options: &GeoTiffOptions) {
+    let mut rows: Vec<Vec<f32>> = (0..20)
+        .map(|r| (0..20).map(|c| (r * 20 + c) as f32).collect())
+        .collect();
+    rows[0][0] = f32::NAN;
+    write_dem(path, GEO_TRANSFORM, &rows, options);
+    let file = File::open(path).unwrap();
+    assert!(!TiffReader::new(file).unwrap().is_big_tiff());
+}
+
+/// The decoded first block of `ifd`, `width` pixels wide.
+fn first_block(path: &Path, ifd: &Ifd, width: usize) -> Vec<f32> {
+    let (offsets, counts) = match ifd.integers(TAG_TILE_OFFSETS) {
+        Some(offsets) => (offsets, ifd.integers(TAG_TILE_BYTE_COUNTS).unwrap()),
+        None => (
+            ifd.integers(TAG_STRIP_OFFSETS).unwrap(),
+            ifd.integers(TAG_STRIP_BYTE_COUNTS).unwrap(),
+        ),
+    };
+    let mut file = File::open(path).unwrap();
+    file.seek(SeekFrom::Start(offsets[0] as u64)).unwrap();
+    let mut raw = vec![0; counts[0] as usize];
+    file.read_exact(&mut raw).unwrap();
+    let mut bytes = match ifd.integer(TAG_COMPRESSION).unwrap() {
+        1 => raw,
+        5 => weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+            .decode(&raw)
+            .unwrap(),
+        8 => {
+            let mut out = Vec::new();
+            flate2::read::ZlibDecoder::new(&raw[..])
+                .read_to_end(&mut out)
+                .unwrap();
+            out
+        }
+        50000 => zstd::decode_all(&raw[..]).unwrap(),
+        other => panic!("unexpected compression {}", other),
+    };
+    if ifd.integer(TAG_PREDICTOR) == Some(3) {
+        // Undo the byte differencing, then put each value's bytes back
+        // together from the planes they were split into, MSB first.
+        for row in bytes.chunks_mut(width * 4) {
+            for i in 1..row.len() {
+                row[i] = row[i].wrapping_add(row[i - 1]);
+            }
+            let planes = row.to_vec();
+            for (i, value) in row.chunks_mut(4).enumerate() {
+                for (k, byte) in value.iter_mut().enumerate() {
+                    *byte = planes[k * width + i];
+                }
+            }
+        }
+        bytes
+            .chunks(4)
+            .map(|b| f32::from_be_bytes(b.try_into().unwrap()))
+            .collect()
+    } else {
+        bytes
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+}
+
+#[test]
+fn tiled_deflate_with_overviews() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dem.tiff");
+    let options = GeoTiffOptions {
+        backend: GeoTiffBackend::Native,
+        tiled: true,
+        tile_size: 16,
+        compression: Compression::Deflate,
+        overviews: vec![2],
+        overview_resampling: Resampling::Average,
+        ..GeoTiffOptions::default()
+    };
+    write(&path, &options);
+
+    let ifds = read_ifds(&path);
+    assert_eq!(ifds.len(), 2);
+    let main = &ifds[0];
+    assert_eq!(main.integer(TAG_TILE_WIDTH), Some(16));
+    assert_eq!(main.integer(TAG_COMPRESSION), Some(8));
+    assert_eq!(main.integer(TAG_PREDICTOR), Some(3));
+    assert_eq!(main.integers(TAG_TILE_OFFSETS).unwrap().len(), 4);
+    assert_eq!(main.ascii(TAG_GDAL_NODATA), Some("-99999"));
+    let keys = GeoKeys::from_ifd(main).unwrap();
+    assert_eq!(keys.epsg(), Some(2154));
+    assert_eq!(geo_transform(main, Some(&keys)), Some(GEO_TRANSFORM));
+
+    let tile = first_block(&path, main, 16);
+    assert_eq!(tile.len(), 16 * 16);
+    assert_eq!(tile[0], -99999.0);
+    assert_eq!(tile[16 + 2], 22.0);
+
+    let overview = &ifds[1];
+    assert_eq!(overview.integer(TAG_NEW_SUBFILE_TYPE), Some(1));
+    assert_eq!(overview.integer(TAG_IMAGE_WIDTH), Some(10));
+    let reduced = first_block(&path, overview, 16);
+    // The nodata pixel is left out of the average.
+    assert_eq!(reduced[0], (1.0 + 20.0 + 21.0) / 3.0);
+    assert_eq!(reduced[1], (2.0 + 3.0 + 22.0 + 23.0) / 4.0);
+}
+
+#[test]
+fn strips_round_trip_through_every_compression() {
+    let dir = tempfile::tempdir().unwrap();
+    for (compression, predictor) in [
+        (Compression::None, None),
+        (Compression::Lzw, Some(Predictor::None)),
+        (Compression::Zstd, None),
+        (Compression::Deflate, Some(Predictor::FloatingPoint)),
+    ] {
+        let path = dir.path().join(format!("{:?}.tiff", compression));
+        let options = GeoTiffOptions {
+            compression,
+            predictor,
+            level: None,
+            ..GeoTiffOptions::default()
+        };
+        write(&path, &options);
+        let ifds = read_ifds(&path);
+        assert_eq!(ifds.len(), 1);
+        assert!(ifds[0].integer(TAG_TILE_WIDTH).is_none());
+        let strip = first_block(&path, &ifds[0], 20);
+        assert_eq!(strip[0], -99999.0);
+        assert_eq!(strip[20 * 3 + 7], 67.0, "{:?}", compression);
+    }
+}
+
+#[test]
+fn native_backend_writes_the_dem_itself() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    fs::write(
+        asc.join("a.asc"),
+        "ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n1 2\n3 4\n",
+    )
+    .unwrap();
+    let runner = RecordingRunner::new();
+    Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .fill(FillOptions {
+            backend: FillBackend::Native,
+            ..FillOptions::default()
+        })
+        .warp(WarpOptions {
+            backend: WarpBackend::Native,
+            ..WarpOptions::default()
+        })
+        .geotiff(GeoTiffOptions {
+            backend: GeoTiffBackend::Native,
+            compression: Compression::Deflate,
+            ..GeoTiffOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+        .run_with(&runner)
+        .unwrap();
+
+    assert!(runner.programs().is_empty());
+    let ifds = read_ifds(&dir.path().join("out/dem.tiff"));
+    assert_eq!(ifds[0].integer(TAG_IMAGE_WIDTH), Some(10));
+}
+
+#[test]
+fn gdal_backend_passes_creation_options() {
+    let config = Config {
+        geotiff: GeoTiffOptions {
+            tiled: true,
+            compression: Compression::Deflate,
+            level: Some(9),
+            overviews: vec![2, 4],
+            ..GeoTiffOptions::default()
+        },
+        ..Config::default()
+    };
+    let plan = vrt_maker::plan_convert(&config, Stages::DEM, None, None);
+    let step = plan.steps[0].to_string();
+    assert!(
+        step.contains(
+            "gdal_translate -of GTiff -co TILED=YES -co BLOCKXSIZE=256 -co BLOCKYSIZE=256 \
+             -co COMPRESS=DEFLATE -co PREDICTOR=3 -co ZLEVEL=9 "
+        ),
+        "{}",
+        step
+    );
+    assert!(
+        step.contains(" && gdaladdo -r average --config PREDICTOR_OVERVIEW 3 "),
+        "{}",
+        step
+    );
+    assert!(step.ends_with("dem.tiff 2 4"), "{}", step);
+
+    let invalid = |geotiff: GeoTiffOptions| {
+        Config {
+            geotiff,
+            ..Config::default()
+        }
+        .validate()
+        .unwrap_err()
+        .to_string()
+    };
+    assert!(invalid(GeoTiffOptions {
+        tile_size: 100,
+        ..GeoTiffOptions::default()
+    })
+    .contains("multiple of 16"));
+    assert!(invalid(GeoTiffOptions {
+        overviews: vec![4, 2],
+        ..GeoTiffOptions::default()
+    })
+    .contains("increasing"));
+}
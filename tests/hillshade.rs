@@ -0,0 +1,267 @@
+//! Shaded relief: lighting, nodata, block independence, reading back
+//! native GeoTIFFs, and the stage following the DEM.
+
+use std::fs;
+use std::path::Path;
+
+use vrt_maker::config::{
+    Compression, FillBackend, FillOptions, GeoTiffBackend, GeoTiffOptions, HillshadeOptions,
+    Resampling, Resolution, WarpBackend, WarpOptions,
+};
+use vrt_maker::error::Stage;
+use vrt_maker::hillshade::NativeHillshade;
+use vrt_maker::tiff_rows::GeoTiffRows;
+use vrt_maker::{Config, Pipeline, RecordingRunner, Stages};
+
+mod common;
+
+use common::{read, write_asc, write_dem, GEO_TRANSFORM};
+
+/// Shades `dem` and reads the hillshade back, nodata as `NaN`.
+fn shade(dir: &Path, dem: &[Vec<f32>], options: HillshadeOptions) -> Vec<Vec<f32>> {
+    let input = dir.join("dem.tiff");
+    write_dem(&input, GEO_TRANSFORM, dem, &GeoTiffOptions::default());
+    let output = dir.join("hillshade.tiff");
+    NativeHillshade {
+        input,
+        options,
+        geotiff: GeoTiffOptions::default(),
+    }
+    .run(&output)
+    .unwrap();
+    read(&output)
+}
+
+/// The pixels away from the raster's edges, where missing neighbours
+/// flatten the slope.
+fn interior(rows: &[Vec<f32>]) -> impl Iterator<Item = f32> + '_ {
+    rows[1..rows.len() - 1]
+        .iter()
+        .flat_map(|row| row[1..row.len() - 1].iter().copied())
+}
+
+/// Heights rising by 1 m per metre eastwards: a slope facing west.
+fn west_facing() -> Vec<Vec<f32>> {
+    (0..6)
+        .map(|_| (0..6).map(|c| 100.0 + c as f32).collect())
+        .collect()
+}
+
+#[test]
+fn slopes_are_lit_from_the_light() {
+    let dir = tempfile::tempdir().unwrap();
+    let flat = vec![vec![50.0; 4]; 4];
+    // 1 + 254 sin(45°)
+    let shaded = shade(dir.path(), &flat, HillshadeOptions::default());
+    assert!(shaded.iter().flatten().all(|&v| v == 181.0), "{:?}", shaded);
+
+    let facing = shade(
+        dir.path(),
+        &west_facing(),
+        HillshadeOptions {
+            azimuth: 270.0,
+            ..HillshadeOptions::default()
+        },
+    );
+    assert!(interior(&facing).all(|v| v == 255.0), "{:?}", facing);
+    let away = shade(
+        dir.path(),
+        &west_facing(),
+        HillshadeOptions {
+            azimuth: 90.0,
+            ..HillshadeOptions::default()
+        },
+    );
+    assert!(interior(&away).all(|v| v == 1.0), "{:?}", away);
+
+    // Half-weighted lights at 225 and 315 degrees, full at 270, none at
+    // 360: (0.854 / 2 + 1 + 0.854 / 2) / 2.
+    let multidirectional = HillshadeOptions {
+        multidirectional: true,
+        ..HillshadeOptions::default()
+    };
+    let all_round = shade(dir.path(), &west_facing(), multidirectional.clone());
+    assert!(interior(&all_round).all(|v| v == 236.0), "{:?}", all_round);
+    let flat = shade(dir.path(), &flat, multidirectional);
+    assert!(flat.iter().flatten().all(|&v| v == 181.0), "{:?}", flat);
+}
+
+#[test]
+fn nodata_stays_and_blocks_do_not_show() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut dem: Vec<Vec<f32>> = (0..9)
+        .map(|r| {
+            (0..7)
+                .map(|c| ((r * 7 + c) as f32 * 0.37).sin() * 3.0 + r as f32)
+                .collect()
+        })
+        .collect();
+    dem[4][3] = f32::NAN;
+    let whole = shade(dir.path(), &dem, HillshadeOptions::default());
+    let by_row = shade(
+        dir.path(),
+        &dem,
+        HillshadeOptions {
+            block_rows: 1,
+            ..HillshadeOptions::default()
+        },
+    );
+    let bits =
+        |rows: &[Vec<f32>]| -> Vec<u32> { rows.iter().flatten().map(|v| v.to_bits()).collect() };
+    assert_eq!(bits(&whole), bits(&by_row));
+    assert!(whole[4][3].is_nan());
+    // The rim of the hole and the raster's edges are shaded all the same.
+    assert!(whole[4][2] >= 1.0 && whole[3][3] >= 1.0);
+    assert!(whole[0].iter().chain(&whole[8]).all(|&v| v >= 1.0));
+}
+
+#[test]
+fn reads_tiled_and_compressed_geotiffs() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut expected: Vec<Vec<f32>> = (0..40)
+        .map(|r| (0..35).map(|c| (r * 35 + c) as f32 / 4.0).collect())
+        .collect();
+    expected[0][0] = f32::NAN;
+    for (compression, tiled) in [
+        (Compression::None, false),
+        (Compression::Deflate, false),
+        (Compression::Lzw, true),
+        (Compression::Zstd, true),
+    ] {
+        let path = dir.path().join("dem.tiff");
+        let options = GeoTiffOptions {
+            tiled,
+            tile_size: 16,
+            compression,
+            ..GeoTiffOptions::default()
+        };
+        write_dem(&path, GEO_TRANSFORM, &expected, &options);
+        let rows = GeoTiffRows::open(&path).unwrap();
+        assert_eq!(rows.geo_transform(), GEO_TRANSFORM);
+        assert_eq!(rows.crs(), Some("EPSG:2154"));
+        assert_eq!(rows.nodata(), Some(-99999.0));
+        let read = read(&path);
+        assert!(read[0][0].is_nan(), "{:?}", compression);
+        assert_eq!(read[0][1..], expected[0][1..], "{:?}", compression);
+        assert_eq!(read[1..], expected[1..], "{:?}", compression);
+    }
+}
+
+#[test]
+fn follows_the_dem() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    fs::write(
+        asc.join("a.asc"),
+        "ncols 4\nnrows 3\nxllcorner 0\nyllcorner 0\ncellsize 1\n7 7 7 7\n7 7 7 7\n7 7 7 7\n",
+    )
+    .unwrap();
+    fs::write(asc.join("a.prj"), "EPSG:2154").unwrap();
+
+    let pipeline = Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .fill(FillOptions {
+            backend: FillBackend::Native,
+            ..FillOptions::default()
+        })
+        .warp(WarpOptions {
+            backend: WarpBackend::Native,
+            resolution: Resolution::Square(1.0),
+            resampling: Resampling::Near,
+            ..WarpOptions::default()
+        })
+        .geotiff(GeoTiffOptions {
+            backend: GeoTiffBackend::Native,
+            ..GeoTiffOptions::default()
+        })
+        .hillshade(HillshadeOptions {
+            enabled: true,
+            ..HillshadeOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap();
+    let plan = pipeline.plan().unwrap();
+    let last = plan.stages.last().unwrap();
+    assert_eq!(last.stage, Stage::Hillshade);
+    let output = dir.path().join("out/hillshade.tiff");
+    assert_eq!(plan.outputs.last(), Some(&output));
+    assert!(last.steps[0]
+        .to_string()
+        .starts_with("(built-in) hillshade "));
+
+    let runner = RecordingRunner::new();
+    let outputs = pipeline.run_with(&runner).unwrap();
+    assert!(runner.programs().is_empty());
+    assert_eq!(outputs.hillshade.as_ref(), Some(&output));
+    let rows = GeoTiffRows::open(&output).unwrap();
+    assert_eq!(rows.crs(), Some("EPSG:2154"));
+    assert_eq!(rows.nodata(), Some(0.0));
+    assert_eq!(read(&output), vec![vec![181.0; 4]; 3]);
+}
+
+#[test]
+fn invalid_lighting_is_refused() {
+    let invalid = |hillshade: HillshadeOptions| {
+        Config {
+            hillshade: HillshadeOptions {
+                enabled: true,
+                ..hillshade
+            },
+            ..Config::default()
+        }
+        .validate()
+        .unwrap_err()
+        .to_string()
+    };
+    assert!(invalid(HillshadeOptions {
+        altitude: 95.0,
+        ..HillshadeOptions::default()
+    })
+    .contains("hillshade.altitude must be within [0, 90]"));
+    assert!(invalid(HillshadeOptions {
+        z_factor: 0.0,
+        ..HillshadeOptions::default()
+    })
+    .contains("hillshade.z_factor must be positive"));
+    let mut config = Config::default();
+    config.hillshade.enabled = true;
+    config.outputs.hillshade = config.outputs.dem.clone();
+    assert!(config
+        .validate()
+        .unwrap_err()
+        .to_string()
+        .contains("outputs.hillshade must differ"));
+}
+
+#[test]
+fn unscaled_heights_over_degrees_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    write_asc(&asc.join("a.asc"), (4, 2), (2.0, 0.0), 0.001, None);
+    fs::write(asc.join("a.prj"), "EPSG:4326").unwrap();
+    let plan = |z_factor: f64| {
+        Pipeline::builder()
+            .asc_dir(&asc)
+            .out_dir(dir.path().join("out"))
+            .hillshade(HillshadeOptions {
+                enabled: true,
+                z_factor,
+                ..HillshadeOptions::default()
+            })
+            .stages(Stages::DEM)
+            .build()
+            .unwrap()
+            .plan()
+    };
+    let err = plan(1.0).unwrap_err().to_string();
+    assert!(
+        err.contains("hillshade.z_factor = 1 leaves heights unscaled over EPSG:4326"),
+        "{}",
+        err
+    );
+    plan(1.0 / 111120.0).unwrap();
+}
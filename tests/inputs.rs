@@ -0,0 +1,54 @@
+//! Input discovery: nested folders, paths with spaces and extension case,
+//! down to the arguments handed to GDAL.
+
+use std::fs;
+
+use vrt_maker::config::{VrtBackend, VrtOptions};
+use vrt_maker::inputs::find_inputs;
+use vrt_maker::{Pipeline, RecordingRunner, Stages};
+
+mod common;
+
+use common::write_asc;
+
+#[test]
+fn finds_tiles_in_nested_folders_and_paths_with_spaces() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("IGN delivery");
+    let nested = root.join("RGE ALTI/dalles 2021");
+    fs::create_dir_all(&nested).unwrap();
+    write_asc(&root.join("b tile.asc"), (4, 2), (4.0, 0.0), 1.0, None);
+    write_asc(&nested.join("a tile.ASC"), (4, 2), (0.0, 0.0), 1.0, None);
+    fs::write(nested.join("a tile.prj"), "EPSG:2154").unwrap();
+    fs::write(nested.join("a tile.asc.aux.xml"), "<PAMDataset/>").unwrap();
+    fs::create_dir_all(root.join("empty.asc")).unwrap();
+
+    let found = find_inputs(&root, "asc").unwrap();
+    assert_eq!(found, [nested.join("a tile.ASC"), root.join("b tile.asc")]);
+    assert!(find_inputs(&root, "jp2").unwrap().is_empty());
+    assert!(find_inputs(&dir.path().join("missing"), "asc").is_err());
+
+    // Each path reaches GDAL as a single argument, spaces and all.
+    let runner = RecordingRunner::new();
+    Pipeline::builder()
+        .asc_dir(&root)
+        .out_dir(dir.path().join("out dir"))
+        .vrt(VrtOptions {
+            backend: VrtBackend::Gdal,
+            ..VrtOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+        .run_with(&runner)
+        .unwrap();
+    let buildvrt = runner.commands()[0].lossy_args();
+    assert_eq!(runner.programs()[0], "gdalbuildvrt");
+    assert_eq!(
+        buildvrt[buildvrt.len() - 2..],
+        [
+            nested.join("a tile.ASC").to_string_lossy(),
+            root.join("b tile.asc").to_string_lossy(),
+        ]
+    );
+}
@@ -0,0 +1,113 @@
+//! The build manifest: which steps a rerun skips and which it runs again.
+
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use vrt_maker::manifest::Tracker;
+use vrt_maker::plan::StagePlan;
+use vrt_maker::{Plan, Stage, Step, ToolCommand};
+
+/// A plan translating `input` into `output` with the extra `args`.
+fn translate(input: &Path, output: &Path, args: &[&str]) -> Plan {
+    let command = ToolCommand::new("gdal_translate")
+        .args(args.iter().copied())
+        .arg(input)
+        .arg(output);
+    let step = Step::run(
+        Stage::Convert,
+        command,
+        vec![input.to_path_buf()],
+        output.to_path_buf(),
+    );
+    Plan {
+        stages: vec![StagePlan::steps(Stage::Convert, vec![step])],
+        ..Plan::default()
+    }
+}
+
+/// Whether a run of `plan` with a fresh tracker would skip its step.
+fn fresh(out: &Path, plan: &Plan, force: bool) -> bool {
+    let mut tracker = Tracker::load(out, force);
+    let fingerprints = tracker.fingerprints(plan).unwrap();
+    let step = plan.steps().next().unwrap();
+    tracker.is_fresh(step, &fingerprints[0])
+}
+
+/// Runs the step of `plan`, pretending to write its output.
+fn run(out: &Path, plan: &Plan) {
+    let mut tracker = Tracker::load(out, false);
+    let fingerprints = tracker.fingerprints(plan).unwrap();
+    let step = plan.steps().next().unwrap();
+    fs::write(&step.output, b"").unwrap();
+    tracker.record(step, &fingerprints[0]).unwrap();
+}
+
+fn set_modified(path: &Path, time: SystemTime) {
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(time)
+        .unwrap();
+}
+
+#[test]
+fn unchanged_steps_are_skipped_unless_forced() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("dem.vrt");
+    let output = dir.path().join("dem.tiff");
+    fs::write(&input, "<VRTDataset/>").unwrap();
+    let plan = translate(&input, &output, &[]);
+
+    assert!(!fresh(dir.path(), &plan, false));
+    run(dir.path(), &plan);
+    assert!(fresh(dir.path(), &plan, false));
+    assert!(!fresh(dir.path(), &plan, true));
+
+    // A missing output is rebuilt whatever the manifest says.
+    fs::remove_file(&output).unwrap();
+    assert!(!fresh(dir.path(), &plan, false));
+}
+
+#[test]
+fn changed_recipes_run_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("dem.vrt");
+    let output = dir.path().join("dem.tiff");
+    fs::write(&input, "<VRTDataset/>").unwrap();
+    run(dir.path(), &translate(&input, &output, &["-a_nodata", "0"]));
+    assert!(fresh(
+        dir.path(),
+        &translate(&input, &output, &["-a_nodata", "0"]),
+        false
+    ));
+    assert!(!fresh(
+        dir.path(),
+        &translate(&input, &output, &["-a_nodata", "-9999"]),
+        false
+    ));
+}
+
+#[test]
+fn touched_inputs_are_hashed_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("dem.vrt");
+    let output = dir.path().join("dem.tiff");
+    fs::write(&input, "<VRTDataset a/>").unwrap();
+    let modified = fs::metadata(&input).unwrap().modified().unwrap();
+    let plan = translate(&input, &output, &[]);
+    run(dir.path(), &plan);
+
+    // Rewritten at the same size and with its old mtime restored, the
+    // input keeps the hash cached in the manifest.
+    fs::write(&input, "<VRTDataset b/>").unwrap();
+    set_modified(&input, modified);
+    assert!(fresh(dir.path(), &plan, false));
+
+    // Once its mtime moves it is hashed again, and the change shows.
+    set_modified(&input, modified + Duration::from_secs(60));
+    assert!(!fresh(dir.path(), &plan, false));
+    run(dir.path(), &plan);
+    assert!(fresh(dir.path(), &plan, false));
+}
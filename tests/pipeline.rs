@@ -0,0 +1,291 @@
+//! End-to-end pipeline runs against a fake GDAL, checking which commands
+//! are issued, in which order, and how tool failures surface.
+
+use std::fs;
+use std::path::PathBuf;
+
+use tempfile::TempDir;
+use vrt_maker::config::{VrtBackend, VrtOptions};
+use vrt_maker::{
+    build_dem_vrt, build_ortho_vrt, resize_and_convert, Config, Pipeline, PipelineError,
+    RecordingRunner, Stage, Stages,
+};
+
+mod common;
+
+use common::write_asc;
+
+const NODATA: Option<f64> = Some(-99999.0);
+
+struct Site {
+    dir: TempDir,
+}
+
+impl Site {
+    /// Two 4 x 3 DEM tiles side by side and two (unreadable) JP2 tiles.
+    fn new() -> Site {
+        let dir = tempfile::tempdir().unwrap();
+        let asc = dir.path().join("asc");
+        let jp2 = dir.path().join("jp2");
+        fs::create_dir_all(&asc).unwrap();
+        fs::create_dir_all(&jp2).unwrap();
+        write_asc(&asc.join("a.asc"), (4, 3), (1000.0, 2000.0), 1.0, NODATA);
+        write_asc(&asc.join("b.asc"), (4, 3), (1004.0, 2000.0), 1.0, NODATA);
+        fs::write(jp2.join("a.jp2"), b"").unwrap();
+        fs::write(jp2.join("b.jp2"), b"").unwrap();
+        Site { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn pipeline(&self, stages: Stages) -> Pipeline {
+        Pipeline::builder()
+            .jp2_dir(self.path("jp2"))
+            .asc_dir(self.path("asc"))
+            .out_dir(self.path("out"))
+            .vrt(VrtOptions {
+                backend: VrtBackend::Gdal,
+                ..VrtOptions::default()
+            })
+            .stages(stages)
+            .build()
+            .unwrap()
+    }
+}
+
+fn args(runner: &RecordingRunner, index: usize) -> Vec<String> {
+    runner.commands()[index].lossy_args()
+}
+
+#[test]
+fn ortho_runs_buildvrt_then_translate() {
+    let site = Site::new();
+    let runner = RecordingRunner::new();
+    let outputs = site.pipeline(Stages::ORTHO).run_with(&runner).unwrap();
+
+    assert_eq!(runner.programs(), ["gdalbuildvrt", "gdal_translate"]);
+    let buildvrt = args(&runner, 0);
+    assert_eq!(buildvrt[..2], ["-resolution", "highest"]);
+    assert!(buildvrt[2].ends_with("mosaic.vrt"));
+    assert!(buildvrt[3].ends_with("a.jp2") && buildvrt[4].ends_with("b.jp2"));
+    let translate = args(&runner, 1);
+    assert_eq!(translate[..2], ["-of", "GTiff"]);
+    assert!(translate[3].ends_with("orthophoto.tiff"));
+
+    assert_eq!(outputs.ortho, Some(site.path("out/orthophoto.tiff")));
+    assert_eq!(outputs.dem, None);
+    assert_eq!(outputs.executed, 2);
+}
+
+#[test]
+fn dem_fills_warps_and_converts() {
+    let site = Site::new();
+    let runner = RecordingRunner::new();
+    site.pipeline(Stages::DEM).run_with(&runner).unwrap();
+
+    assert_eq!(
+        runner.programs(),
+        [
+            "gdalbuildvrt",
+            "gdal_fillnodata",
+            "gdalwarp",
+            "gdal_translate"
+        ]
+    );
+    let fill = args(&runner, 1);
+    assert_eq!(fill[..4], ["-md", "200", "-si", "1"]);
+    assert!(fill[4].ends_with("temp_dem.vrt") && fill[5].ends_with("temp_filled_dem.vrt"));
+    let warp = args(&runner, 2);
+    assert_eq!(warp[..5], ["-tr", "0.2", "0.2", "-r", "cubicspline"]);
+    assert!(warp.last().unwrap().ends_with("dem.vrt"));
+    let translate = args(&runner, 3);
+    assert!(translate.contains(&"-a_nodata".to_string()));
+    assert!(translate.last().unwrap().ends_with("dem.tiff"));
+}
+
+#[test]
+fn native_backend_writes_the_mosaic_without_gdalbuildvrt() {
+    let site = Site::new();
+    let runner = RecordingRunner::new();
+    let tmp_dir = site.path("tmp");
+    fs::create_dir_all(&tmp_dir).unwrap();
+
+    let dem_vrt = build_dem_vrt(
+        &site.path("asc"),
+        &tmp_dir,
+        &VrtOptions::default(),
+        &Default::default(),
+        &Default::default(),
+        &Default::default(),
+        &runner,
+    )
+    .unwrap();
+
+    assert_eq!(runner.programs(), ["gdal_fillnodata", "gdalwarp"]);
+    assert_eq!(dem_vrt, tmp_dir.join("dem.vrt"));
+    let xml = fs::read_to_string(tmp_dir.join("temp_dem.vrt")).unwrap();
+    assert!(xml.starts_with("<VRTDataset rasterXSize=\"8\" rasterYSize=\"3\">"));
+    assert_eq!(xml.matches("<ComplexSource>").count(), 2);
+}
+
+#[test]
+fn stage_functions_go_through_the_runner() {
+    let site = Site::new();
+    let runner = RecordingRunner::new();
+    let vrt = VrtOptions {
+        backend: VrtBackend::Gdal,
+        ..VrtOptions::default()
+    };
+    let tmp_dir = site.path("tmp");
+    fs::create_dir_all(&tmp_dir).unwrap();
+
+    let mosaic = build_ortho_vrt(
+        &site.path("jp2"),
+        &tmp_dir,
+        &vrt,
+        &Default::default(),
+        &Default::default(),
+        &runner,
+    )
+    .unwrap();
+    assert_eq!(mosaic, tmp_dir.join("mosaic.vrt"));
+
+    let mut config = Config::default();
+    config.outputs.dir = site.path("out");
+    config.outputs.tmp_dir = Some(tmp_dir);
+    fs::create_dir_all(&config.outputs.dir).unwrap();
+    let outputs = resize_and_convert(&config, Stages::ORTHO, None, None, &runner).unwrap();
+
+    assert_eq!(runner.programs(), ["gdalbuildvrt", "gdal_translate"]);
+    assert_eq!(outputs.executed, 1);
+}
+
+#[test]
+fn failing_tool_stops_the_build() {
+    let site = Site::new();
+    let runner = RecordingRunner::new().fail("gdalwarp", 1, "ERROR 1: out of memory");
+    let err = site.pipeline(Stages::ALL).run_with(&runner).unwrap_err();
+
+    match &err {
+        PipelineError::ToolFailed {
+            stage,
+            tool,
+            stderr,
+            ..
+        } => {
+            assert_eq!(*stage, Stage::DemVrt);
+            assert_eq!(tool, "gdalwarp");
+            assert_eq!(stderr, "ERROR 1: out of memory");
+        }
+        other => panic!("unexpected error: {other}"),
+    }
+    assert_eq!(err.exit_code(), PipelineError::EXIT_TOOL_FAILED);
+    assert_eq!(
+        runner.programs(),
+        [
+            "gdalbuildvrt",
+            "gdalbuildvrt",
+            "gdal_fillnodata",
+            "gdalwarp"
+        ]
+    );
+}
+
+#[test]
+fn missing_tool_is_a_spawn_error() {
+    let site = Site::new();
+    let runner = RecordingRunner::new().missing("gdal_translate");
+    let err = site.pipeline(Stages::ORTHO).run_with(&runner).unwrap_err();
+
+    assert!(matches!(
+        err,
+        PipelineError::Spawn {
+            stage: Stage::Convert,
+            ..
+        }
+    ));
+    assert_eq!(err.exit_code(), PipelineError::EXIT_SPAWN);
+}
+
+#[test]
+fn no_inputs_runs_nothing() {
+    let site = Site::new();
+    fs::remove_dir_all(site.path("asc")).unwrap();
+    fs::create_dir_all(site.path("asc")).unwrap();
+    let runner = RecordingRunner::new();
+    let err = site.pipeline(Stages::DEM).run_with(&runner).unwrap_err();
+
+    assert_eq!(err.exit_code(), PipelineError::EXIT_NO_INPUTS);
+    assert!(runner.commands().is_empty());
+}
+
+#[test]
+fn second_run_skips_up_to_date_steps() {
+    let site = Site::new();
+    let pipeline = site.pipeline(Stages::DEM);
+    let runner = RecordingRunner::new();
+    pipeline.run_with(&runner).unwrap();
+    runner.clear();
+
+    let outputs = pipeline.run_with(&runner).unwrap();
+    assert!(runner.commands().is_empty());
+    assert_eq!((outputs.executed, outputs.skipped), (0, 4));
+
+    write_asc(
+        &site.path("asc/b.asc"),
+        (4, 3),
+        (1005.0, 2000.0),
+        1.0,
+        NODATA,
+    );
+    let outputs = pipeline.run_with(&runner).unwrap();
+    assert_eq!(outputs.executed, 4);
+
+    runner.clear();
+    let forced = Pipeline::builder()
+        .config(pipeline.config().clone())
+        .stages(Stages::DEM)
+        .force(true)
+        .build()
+        .unwrap();
+    let outputs = forced.run_with(&runner).unwrap();
+    assert_eq!(runner.programs().len(), 4);
+    assert_eq!((outputs.executed, outputs.skipped), (4, 0));
+}
+
+#[test]
+fn dry_run_plans_without_running() {
+    let site = Site::new();
+    let pipeline = site.pipeline(Stages::DEM);
+    let runner = RecordingRunner::new();
+    let plan = pipeline.plan().unwrap();
+    assert_eq!(pipeline.up_to_date(&plan).unwrap(), [false; 4]);
+    assert!(runner.commands().is_empty());
+    assert!(!site.path("out").exists());
+
+    let tmp = site.path("out/tmp");
+    assert_eq!(
+        plan.intermediates(),
+        [
+            tmp.join("temp_dem.vrt"),
+            tmp.join("temp_filled_dem.vrt"),
+            tmp.join("dem.vrt"),
+        ]
+    );
+    assert_eq!(plan.outputs, [site.path("out/dem.tiff")]);
+    // Two 4 x 3 m tiles side by side, in 0.2 m pixels of Float32.
+    let estimate = &plan.estimates[0];
+    assert_eq!(estimate.path, site.path("out/dem.tiff"));
+    assert_eq!((estimate.width, estimate.height), (40, 15));
+    assert_eq!(estimate.bytes(), 40 * 15 * 4);
+
+    // Running executes the planned steps, in order.
+    pipeline.run_with(&runner).unwrap();
+    let programs: Vec<String> = plan
+        .steps()
+        .map(|s| s.to_string().split(' ').next().unwrap().to_string())
+        .collect();
+    assert_eq!(runner.programs(), programs);
+}
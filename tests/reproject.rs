@@ -0,0 +1,133 @@
+//! Reprojection of the outputs to another CRS in the warp stage.
+
+use std::fs;
+use std::path::Path;
+
+use vrt_maker::config::{CrsOptions, ExtentOptions, OrthoOptions, Resolution};
+use vrt_maker::{Config, Pipeline, PipelineError, RecordingRunner, Stages};
+
+mod common;
+
+use common::{write_asc, write_tile};
+
+/// A directory whose `asc` folder holds the single tile `a`, with a `.prj`
+/// when `prj` is given.
+fn site(prj: Option<&str>) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    match prj {
+        Some(prj) => write_tile(&asc, "a", 0, prj),
+        None => write_asc(&asc.join("a.asc"), (4, 2), (0.0, 0.0), 1.0, None),
+    }
+    dir
+}
+
+fn web_mercator() -> CrsOptions {
+    CrsOptions {
+        output: Some("EPSG:3857".to_string()),
+        ..CrsOptions::default()
+    }
+}
+
+fn pipeline(dir: &Path, crs: CrsOptions) -> Pipeline {
+    Pipeline::builder()
+        .asc_dir(dir.join("asc"))
+        .out_dir(dir.join("out"))
+        .crs(crs)
+        .extent(ExtentOptions {
+            bounds: Some([1.0, 0.0, 3.0, 2.0]),
+            ..ExtentOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn dem_is_reprojected_cropped_then_converted() {
+    let dir = site(Some("EPSG:2154"));
+    let runner = RecordingRunner::new();
+    let crs = CrsOptions {
+        output_resolution: Some(Resolution::Square(0.5)),
+        ..web_mercator()
+    };
+    let pipeline = pipeline(dir.path(), crs);
+    let plan = pipeline.plan().unwrap();
+    assert!(
+        plan.estimates.is_empty(),
+        "sizes are unknown once reprojected"
+    );
+    pipeline.run_with(&runner).unwrap();
+
+    assert_eq!(
+        runner.programs(),
+        ["gdal_fillnodata", "gdalwarp", "gdalwarp", "gdal_translate"]
+    );
+    let warp = runner.commands()[2].lossy_args();
+    assert_eq!(
+        warp[..15],
+        [
+            "-t_srs",
+            "EPSG:3857",
+            "-r",
+            "bilinear",
+            "-tr",
+            "0.5",
+            "0.5",
+            "-te",
+            "1",
+            "0",
+            "3",
+            "2",
+            "-te_srs",
+            "EPSG:2154",
+            "-dstnodata"
+        ]
+    );
+    assert_eq!(warp[15..18], ["0", "-of", "VRT"]);
+    assert!(warp[18].ends_with("dem.vrt"));
+    assert!(warp[19].ends_with("dem_reprojected.vrt"));
+
+    // The conversion reads the reprojection and crops nothing more.
+    let convert = runner.commands()[3].lossy_args();
+    assert!(!convert.contains(&"-projwin".to_string()), "{:?}", convert);
+    assert!(convert[convert.len() - 2].ends_with("dem_reprojected.vrt"));
+}
+
+#[test]
+fn reprojection_needs_a_known_source_crs() {
+    let dir = site(None);
+    let err = pipeline(dir.path(), web_mercator()).plan().unwrap_err();
+    assert!(matches!(err, PipelineError::Config(_)), "{}", err);
+    assert!(err.to_string().contains("needs inputs whose CRS is known"));
+}
+
+#[test]
+fn output_crs_settings_are_checked() {
+    let invalid = |crs: CrsOptions, ortho: OrthoOptions| {
+        Config {
+            crs,
+            ortho,
+            ..Config::default()
+        }
+        .validate()
+        .unwrap_err()
+        .to_string()
+    };
+    let nonsense = CrsOptions {
+        output: Some("mercator please".to_string()),
+        ..CrsOptions::default()
+    };
+    assert!(invalid(nonsense, OrthoOptions::default()).contains("crs.output must be"));
+    let orphan = CrsOptions {
+        output_resolution: Some(Resolution::Square(1.0)),
+        ..CrsOptions::default()
+    };
+    assert!(invalid(orphan, OrthoOptions::default()).contains("needs crs.output"));
+    let resized = OrthoOptions {
+        resolution: Some(Resolution::Square(0.5)),
+        ..OrthoOptions::default()
+    };
+    assert!(invalid(web_mercator(), resized).contains("use crs.output_resolution"));
+}
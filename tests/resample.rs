@@ -0,0 +1,228 @@
+//! Native resampling: kernels, nodata handling, block independence and the
+//! step replacing the DEM's `gdalwarp`.
+
+use std::fs;
+
+use vrt_maker::config::{
+    DemOptions, FillBackend, FillOptions, OrthoOptions, Resampling, Resolution, WarpBackend,
+    WarpOptions,
+};
+use vrt_maker::resample::resample_rows;
+use vrt_maker::rows::{RowError, RowSource};
+use vrt_maker::vrt::RawVrt;
+use vrt_maker::{Config, Pipeline, RecordingRunner, Stages};
+
+/// An in-memory raster of 1 m pixels with its top-left corner at (0, 0);
+/// `NaN` marks nodata.
+struct Grid {
+    rows: Vec<Vec<f32>>,
+    next: usize,
+}
+
+impl RowSource for Grid {
+    fn width(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn read_row(&mut self, row: &mut [f32]) -> Result<(), RowError> {
+        row.copy_from_slice(&self.rows[self.next]);
+        self.next += 1;
+        Ok(())
+    }
+}
+
+fn resample(
+    rows: Vec<Vec<f32>>,
+    pixel: f64,
+    size: (usize, usize),
+    resampling: Resampling,
+    block_rows: usize,
+) -> Vec<Vec<f32>> {
+    let target = RawVrt {
+        width: size.0,
+        height: size.1,
+        geo_transform: [0.0, pixel, 0.0, 0.0, 0.0, -pixel],
+        crs: None,
+        nodata: -1.0,
+    };
+    let mut out = Vec::new();
+    resample_rows(
+        &mut Grid { rows, next: 0 },
+        &[0.0, 1.0, 0.0, 0.0, 0.0, -1.0],
+        &target,
+        resampling,
+        block_rows,
+        |row| {
+            out.push(row.to_vec());
+            Ok(())
+        },
+    )
+    .unwrap();
+    out
+}
+
+fn plane(width: usize, height: usize) -> Vec<Vec<f32>> {
+    (0..height)
+        .map(|r| {
+            (0..width)
+                .map(|c| 10.0 + c as f32 - 2.0 * r as f32)
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn kernels_match_their_definitions() {
+    // Same grid: interpolating kernels give the pixels back.
+    for resampling in [
+        Resampling::Near,
+        Resampling::Bilinear,
+        Resampling::Cubic,
+        Resampling::Lanczos,
+    ] {
+        assert_eq!(
+            resample(plane(6, 6), 1.0, (6, 6), resampling, 8),
+            plane(6, 6)
+        );
+    }
+
+    // Halving the pixel size: a plane stays a plane away from the edges.
+    let fine = resample(plane(6, 6), 0.5, (12, 12), Resampling::Bilinear, 8);
+    assert_eq!(fine[5][5], 10.0 + 2.25 - 2.0 * 2.25);
+    let spline = resample(plane(8, 8), 0.5, (16, 16), Resampling::CubicSpline, 8);
+    assert!((spline[7][7] - (10.0 + 3.25 - 2.0 * 3.25)).abs() < 1e-4);
+
+    let near = resample(
+        vec![vec![1.0, 2.0], vec![3.0, 4.0]],
+        0.5,
+        (4, 4),
+        Resampling::Near,
+        8,
+    );
+    assert_eq!(near[0], [1.0, 1.0, 2.0, 2.0]);
+    assert_eq!(near[3], [3.0, 3.0, 4.0, 4.0]);
+
+    // Doubling it: average is the mean of the valid pixels covered.
+    let mut grid = plane(4, 4);
+    grid[0][0] = f32::NAN;
+    let coarse = resample(grid, 2.0, (2, 2), Resampling::Average, 8);
+    assert_eq!(coarse[0][0], (11.0 + 8.0 + 9.0) / 3.0);
+    assert_eq!(coarse[1][1], (8.0 + 9.0 + 6.0 + 7.0) / 4.0);
+}
+
+#[test]
+fn nodata_is_skipped_and_blocks_do_not_matter() {
+    let mut grid = plane(9, 9);
+    for row in &mut grid[3..6] {
+        row[3..6].fill(f32::NAN);
+    }
+    let near = resample(grid.clone(), 1.0, (9, 9), Resampling::Near, 8);
+    assert!(near[4][4].is_nan());
+    // Bilinear at the pixel centres only looks at the pixel itself.
+    let bilinear = resample(grid.clone(), 1.0, (9, 9), Resampling::Bilinear, 8);
+    assert!(bilinear[4][4].is_nan());
+    // Off the pixel centres, wider kernels reach past the hole.
+    let lanczos = resample(grid.clone(), 0.5, (18, 18), Resampling::Lanczos, 8);
+    assert!(lanczos[9][9].is_finite());
+
+    // Pixels centred off the source are nodata.
+    let wider = resample(plane(4, 4), 1.0, (5, 4), Resampling::Bilinear, 8);
+    assert!(wider[0][4].is_nan());
+
+    for resampling in [Resampling::Cubic, Resampling::Lanczos, Resampling::Average] {
+        let whole = resample(grid.clone(), 0.7, (12, 12), resampling, 100);
+        let blocked = resample(grid.clone(), 0.7, (12, 12), resampling, 1);
+        assert_eq!(format!("{:?}", whole), format!("{:?}", blocked));
+    }
+}
+
+#[test]
+fn native_backend_replaces_gdalwarp() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    fs::write(
+        asc.join("a.asc"),
+        "ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n1 2\n3 4\n",
+    )
+    .unwrap();
+    let runner = RecordingRunner::new();
+    Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .fill(FillOptions {
+            backend: FillBackend::Native,
+            ..FillOptions::default()
+        })
+        .warp(WarpOptions {
+            backend: WarpBackend::Native,
+            resolution: Resolution::Square(0.5),
+            resampling: Resampling::Near,
+            ..WarpOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+        .run_with(&runner)
+        .unwrap();
+
+    assert_eq!(runner.programs(), ["gdal_translate"]);
+    let tmp = dir.path().join("out/tmp");
+    let vrt = fs::read_to_string(tmp.join("dem.vrt")).unwrap();
+    assert!(vrt.contains("<VRTDataset rasterXSize=\"4\" rasterYSize=\"4\">"));
+    assert!(vrt.contains("<GeoTransform>0, 0.5, 0, 2, 0, -0.5</GeoTransform>"));
+    assert_eq!(fs::metadata(tmp.join("dem.bin")).unwrap().len(), 4 * 4 * 4);
+
+    let invalid = |dem: DemOptions| {
+        Config {
+            dem,
+            ..Config::default()
+        }
+        .validate()
+        .unwrap_err()
+        .to_string()
+    };
+    let native_warp = WarpOptions {
+        backend: WarpBackend::Native,
+        ..WarpOptions::default()
+    };
+    assert!(invalid(DemOptions {
+        warp: native_warp.clone(),
+        ..DemOptions::default()
+    })
+    .contains("needs dem.fill.backend = \"native\""));
+    assert!(invalid(DemOptions {
+        fill: FillOptions {
+            backend: FillBackend::Native,
+            ..FillOptions::default()
+        },
+        warp: WarpOptions {
+            resampling: Resampling::Mode,
+            ..native_warp
+        },
+        ..DemOptions::default()
+    })
+    .contains("\"mode\" is not available"));
+}
+
+#[test]
+fn orthophoto_resolution_resizes_the_output() {
+    let config = Config {
+        ortho: OrthoOptions {
+            resolution: Some(Resolution::Xy([0.5, 0.25])),
+            resampling: Resampling::Lanczos,
+        },
+        ..Config::default()
+    };
+    let plan = vrt_maker::plan_convert(&config, Stages::ORTHO, None, None);
+    let step = plan.steps[0].to_string();
+    assert!(
+        step.starts_with("gdal_translate -of GTiff -tr 0.5 0.25 -r lanczos "),
+        "{}",
+        step
+    );
+}
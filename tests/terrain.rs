@@ -0,0 +1,230 @@
+//! Terrain derivatives: their values on known surfaces, nodata, block
+//! independence, and the stage writing them next to the DEM.
+
+use std::fs;
+
+use vrt_maker::config::{
+    CrsOptions, Derivative, FillBackend, FillOptions, GeoTiffBackend, GeoTiffOptions, Resampling,
+    Resolution, SlopeUnits, TerrainOptions, WarpBackend, WarpOptions,
+};
+use vrt_maker::derivatives::{derive, NativeDerivative};
+use vrt_maker::error::Stage;
+use vrt_maker::tiff_rows::GeoTiffRows;
+use vrt_maker::{Config, Pipeline, RecordingRunner, Stages};
+
+mod common;
+
+use common::{read, write_asc, write_dem};
+
+fn value(derivative: Derivative, z: [f64; 9]) -> f32 {
+    derive(derivative, &TerrainOptions::default(), &z, 1.0, 1.0)
+}
+
+#[test]
+fn derivatives_of_known_surfaces() {
+    // Rising by 1 m per metre eastwards: a 45 degree slope facing west.
+    let ramp = [0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 0.0, 1.0, 2.0];
+    assert!((value(Derivative::Slope, ramp) - 45.0).abs() < 1e-5);
+    let percent = TerrainOptions {
+        slope_units: SlopeUnits::Percent,
+        ..TerrainOptions::default()
+    };
+    assert!((derive(Derivative::Slope, &percent, &ramp, 1.0, 1.0) - 100.0).abs() < 1e-5);
+    let steeper = TerrainOptions {
+        z_factor: 2.0,
+        ..percent
+    };
+    assert!((derive(Derivative::Slope, &steeper, &ramp, 1.0, 1.0) - 200.0).abs() < 1e-5);
+    assert_eq!(value(Derivative::Aspect, ramp), 270.0);
+    // Rising northwards: facing south.
+    let north = [2.0, 2.0, 2.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0];
+    assert_eq!(value(Derivative::Aspect, north), 180.0);
+    assert!(value(Derivative::Aspect, [5.0; 9]).is_nan());
+    assert_eq!(value(Derivative::Curvature, ramp), 0.0);
+
+    // A peak 4 m above a flat neighbourhood.
+    let mut peak = [1.0; 9];
+    peak[4] = 5.0;
+    // Eight differences of 4 m.
+    assert_eq!(value(Derivative::Tri, peak), 128f32.sqrt());
+    assert_eq!(value(Derivative::Tpi, peak), 4.0);
+    assert_eq!(value(Derivative::Roughness, peak), 4.0);
+    // Convex: -2 (D + E) 100 with D = E = -4.
+    assert_eq!(value(Derivative::Curvature, peak), 1600.0);
+    let mut pit = peak;
+    pit[4] = -3.0;
+    assert_eq!(value(Derivative::Tpi, pit), -4.0);
+    assert_eq!(value(Derivative::Curvature, pit), -1600.0);
+}
+
+#[test]
+fn nodata_stays_and_blocks_do_not_show() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("dem.tiff");
+    let mut dem: Vec<Vec<f32>> = (0..9)
+        .map(|r| {
+            (0..7)
+                .map(|c| ((r * 7 + c) as f32 * 0.37).sin() * 3.0 + r as f32)
+                .collect()
+        })
+        .collect();
+    dem[4][3] = f32::NAN;
+    // 2 m pixels, unlike the 1 m of the other DEMs.
+    let geo_transform = [0.0, 2.0, 0.0, 18.0, 0.0, -2.0];
+    write_dem(&input, geo_transform, &dem, &GeoTiffOptions::default());
+
+    let bits =
+        |rows: &[Vec<f32>]| -> Vec<u32> { rows.iter().flatten().map(|v| v.to_bits()).collect() };
+    for derivative in Derivative::ALL {
+        let derived = |block_rows: usize| {
+            let output = dir.path().join(format!("{}.tiff", derivative.name()));
+            NativeDerivative {
+                input: input.clone(),
+                derivative,
+                options: TerrainOptions {
+                    block_rows,
+                    ..TerrainOptions::default()
+                },
+                geotiff: GeoTiffOptions::default(),
+            }
+            .run(&output)
+            .unwrap();
+            read(&output)
+        };
+        let whole = derived(256);
+        assert_eq!(bits(&whole), bits(&derived(2)), "{:?}", derivative);
+        assert!(whole[4][3].is_nan(), "{:?}", derivative);
+        assert!(!whole[4][2].is_nan(), "{:?}", derivative);
+    }
+}
+
+#[test]
+fn written_next_to_the_dem() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    fs::write(
+        asc.join("a.asc"),
+        "ncols 4\nnrows 4\nxllcorner 0\nyllcorner 0\ncellsize 1\n\
+         1 2 3 4\n1 2 3 4\n1 2 3 4\n1 2 3 4\n",
+    )
+    .unwrap();
+    fs::write(asc.join("a.prj"), "EPSG:2154").unwrap();
+
+    let pipeline = Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .fill(FillOptions {
+            backend: FillBackend::Native,
+            ..FillOptions::default()
+        })
+        .warp(WarpOptions {
+            backend: WarpBackend::Native,
+            resolution: Resolution::Square(1.0),
+            resampling: Resampling::Near,
+            ..WarpOptions::default()
+        })
+        .geotiff(GeoTiffOptions {
+            backend: GeoTiffBackend::Native,
+            ..GeoTiffOptions::default()
+        })
+        .terrain(TerrainOptions {
+            derivatives: vec![Derivative::Slope, Derivative::Aspect],
+            ..TerrainOptions::default()
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap();
+    let plan = pipeline.plan().unwrap();
+    let last = plan.stages.last().unwrap();
+    assert_eq!(last.stage, Stage::Terrain);
+    let slope = dir.path().join("out/dem_slope.tiff");
+    let aspect = dir.path().join("out/dem_aspect.tiff");
+    assert_eq!(
+        plan.outputs[plan.outputs.len() - 2..],
+        [slope.clone(), aspect.clone()]
+    );
+    assert!(last.steps[0]
+        .to_string()
+        .starts_with("(built-in) slope of "));
+
+    let runner = RecordingRunner::new();
+    let outputs = pipeline.run_with(&runner).unwrap();
+    assert!(runner.programs().is_empty());
+    assert_eq!(outputs.derivatives, [slope.clone(), aspect.clone()]);
+    let rows = GeoTiffRows::open(&slope).unwrap();
+    assert_eq!(rows.crs(), Some("EPSG:2154"));
+    assert_eq!(rows.nodata(), Some(-9999.0));
+    let slope = read(&slope);
+    assert!((slope[1][1] - 45.0).abs() < 1e-5, "{:?}", slope);
+    assert_eq!(read(&aspect)[2][2], 270.0);
+}
+
+#[test]
+fn derivative_lists_are_checked() {
+    let mut config = Config::default();
+    config.terrain.derivatives = vec![Derivative::Slope, Derivative::Tpi, Derivative::Slope];
+    assert!(config
+        .validate()
+        .unwrap_err()
+        .to_string()
+        .contains("terrain.derivatives lists slope twice"));
+    config.terrain.derivatives = vec![Derivative::Slope];
+    config.terrain.z_factor = -1.0;
+    assert!(config
+        .validate()
+        .unwrap_err()
+        .to_string()
+        .contains("terrain.z_factor must be positive"));
+
+    let config: Config =
+        toml::from_str("[terrain]\nderivatives = [\"roughness\", \"curvature\"]\n").unwrap();
+    assert_eq!(
+        config.terrain.derivatives,
+        [Derivative::Roughness, Derivative::Curvature]
+    );
+    assert!(toml::from_str::<Config>("[terrain]\nderivatives = [\"hillshade\"]\n").is_err());
+    assert_eq!("tri".parse(), Ok(Derivative::Tri));
+}
+
+#[test]
+fn heights_over_degrees_must_be_scaled() {
+    // A tile at 60 degrees north, in degrees by its .prj alone.
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    write_asc(&asc.join("a.asc"), (4, 2), (10.0, 60.0), 0.001, None);
+    fs::write(asc.join("a.prj"), r#"GEOGCS["WGS 84",DATUM["WGS_1984"]]"#).unwrap();
+    let plan = |z_factor: f64, output: Option<&str>| {
+        Pipeline::builder()
+            .asc_dir(&asc)
+            .out_dir(dir.path().join("out"))
+            .crs(CrsOptions {
+                output: output.map(str::to_string),
+                ..CrsOptions::default()
+            })
+            .terrain(TerrainOptions {
+                derivatives: vec![Derivative::Slope],
+                z_factor,
+                ..TerrainOptions::default()
+            })
+            .stages(Stages::DEM)
+            .build()
+            .unwrap()
+            .plan()
+    };
+
+    let err = plan(1.0, None).unwrap_err().to_string();
+    assert!(
+        err.contains("terrain.z_factor = 1 leaves heights unscaled over EPSG:4326"),
+        "{}",
+        err
+    );
+    assert!(err.contains("use about 1.80e-5"), "{}", err);
+    // Up to ten times the scale at that latitude, feet for instance.
+    plan(1.0 / (111120.0 * 0.5), None).unwrap();
+    plan(1.5e-4, None).unwrap();
+    assert!(plan(2.0e-4, None).is_err());
+    // Warped to metres, heights need no scaling.
+    plan(1.0, Some("EPSG:4087")).unwrap();
+}
@@ -0,0 +1,219 @@
+//! Vertical references of the DEM heights: identification, the refusal of
+//! mixed references, the geoid correction and the recorded datum.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use vrt_maker::config::{
+    DemOptions, FillBackend, FillOptions, GeoTiffBackend, GeoTiffOptions, Resampling, Resolution,
+    VerticalOptions, WarpBackend, WarpOptions,
+};
+use vrt_maker::crs::CrsId;
+use vrt_maker::geotiff::{GeoKeys, KEY_VERTICAL_CS_TYPE};
+use vrt_maker::tiff::{TAG_GDAL_METADATA, TAG_STRIP_OFFSETS};
+use vrt_maker::{Config, Pipeline, PipelineError, RecordingRunner, Stages};
+
+mod common;
+
+use common::{read_ifds, write_tile};
+
+const LAMBERT93_IGN69: &str = r#"COMPD_CS["RGF93 / Lambert-93 + NGF-IGN69 height",
+    PROJCS["RGF93 / Lambert-93",GEOGCS["RGF93",DATUM["Reseau_Geodesique_Francais_1993",
+    SPHEROID["GRS 1980",6378137,298.257222101]],PRIMEM["Greenwich",0],
+    UNIT["degree",0.0174532925199433]],PROJECTION["Lambert_Conformal_Conic_2SP"],
+    UNIT["metre",1],AUTHORITY["EPSG","2154"]],
+    VERT_CS["NGF-IGN69 height",VERT_DATUM["Nivellement General de la France - IGN69",2005],
+    UNIT["metre",1],AUTHORITY["EPSG","5720"]],AUTHORITY["EPSG","5698"]]"#;
+
+const LAMBERT93_NAVD88: &str = r#"COMPD_CS["Lambert-93 + NAVD88",
+    PROJCS["RGF93 / Lambert-93",AUTHORITY["EPSG","2154"]],
+    VERT_CS["NAVD88 height",VERT_DATUM["North American Vertical Datum 1988",2005]]]"#;
+
+fn pipeline(dir: &Path, vertical: VerticalOptions) -> Pipeline {
+    Pipeline::builder()
+        .asc_dir(dir.join("asc"))
+        .out_dir(dir.join("out"))
+        .vertical(vertical)
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn identifies_vertical_references() {
+    assert_eq!(CrsId::vertical(LAMBERT93_IGN69), Some(CrsId::Epsg(5720)));
+    assert_eq!(CrsId::identify(LAMBERT93_IGN69), Some(CrsId::Epsg(2154)));
+    assert_eq!(CrsId::vertical(LAMBERT93_NAVD88), Some(CrsId::Epsg(5703)));
+    assert_eq!(CrsId::vertical("EPSG:2154+5720"), Some(CrsId::Epsg(5720)));
+    assert_eq!(CrsId::identify("EPSG:2154+5720"), Some(CrsId::Epsg(2154)));
+    assert_eq!(CrsId::vertical("EPSG:2154"), None);
+    assert_eq!(
+        CrsId::vertical_reference("EPSG:5720"),
+        Some(CrsId::Epsg(5720))
+    );
+}
+
+#[test]
+fn mixed_vertical_references_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    write_tile(&asc, "a", 0, LAMBERT93_IGN69);
+    write_tile(&asc, "b", 4, "EPSG:2154");
+
+    // A tile without vertical reference is taken to be in the declared one.
+    let ign69 = VerticalOptions {
+        datum: Some("EPSG:5720".to_string()),
+        ..VerticalOptions::default()
+    };
+    pipeline(dir.path(), ign69).plan().unwrap();
+    let navd88 = VerticalOptions {
+        datum: Some("EPSG:5703".to_string()),
+        ..VerticalOptions::default()
+    };
+    let err = pipeline(dir.path(), navd88).plan().unwrap_err();
+    assert!(
+        matches!(err, PipelineError::MixedVertical { .. }),
+        "{}",
+        err
+    );
+    assert!(err
+        .to_string()
+        .contains("than dem.vertical.datum = EPSG:5703"));
+
+    write_tile(&asc, "c", 8, LAMBERT93_NAVD88);
+    let err = pipeline(dir.path(), VerticalOptions::default())
+        .plan()
+        .unwrap_err();
+    assert_eq!(err.exit_code(), PipelineError::EXIT_INVALID_INPUT);
+    let message = err.to_string();
+    assert!(
+        message.contains("different vertical references"),
+        "{}",
+        message
+    );
+    assert!(message.contains("EPSG:5720 (1 tile(s))"), "{}", message);
+    assert!(message.contains("EPSG:5703 (1 tile(s))"), "{}", message);
+}
+
+#[test]
+fn geoid_heights_are_added_and_the_datum_recorded() {
+    let dir = tempfile::tempdir().unwrap();
+    let asc = dir.path().join("asc");
+    fs::create_dir_all(&asc).unwrap();
+    write_tile(&asc, "a", 0, "EPSG:2154");
+    // Nodes at x = 0, 2, 4 and y = 0, 2: the geoid rises by 1 m per metre
+    // eastwards.
+    let geoid = dir.path().join("geoid.asc");
+    fs::write(
+        &geoid,
+        "ncols 3\nnrows 2\nxllcorner -1\nyllcorner -1\ncellsize 2\n40 42 44\n40 42 44\n",
+    )
+    .unwrap();
+
+    let runner = RecordingRunner::new();
+    Pipeline::builder()
+        .asc_dir(&asc)
+        .out_dir(dir.path().join("out"))
+        .fill(FillOptions {
+            backend: FillBackend::Native,
+            ..FillOptions::default()
+        })
+        .warp(WarpOptions {
+            backend: WarpBackend::Native,
+            resolution: Resolution::Square(1.0),
+            resampling: Resampling::Near,
+            ..WarpOptions::default()
+        })
+        .geotiff(GeoTiffOptions {
+            backend: GeoTiffBackend::Native,
+            ..GeoTiffOptions::default()
+        })
+        .vertical(VerticalOptions {
+            datum: Some("EPSG:5720".to_string()),
+            geoid: Some(geoid),
+            output_datum: Some("EPSG:4965".to_string()),
+        })
+        .stages(Stages::DEM)
+        .build()
+        .unwrap()
+        .run_with(&runner)
+        .unwrap();
+    assert!(runner.programs().is_empty());
+
+    let path = dir.path().join("out/dem.tiff");
+    let ifds = read_ifds(&path);
+    let ifd = &ifds[0];
+    let metadata = ifd.ascii(TAG_GDAL_METADATA).unwrap();
+    assert!(
+        metadata.contains("<Item name=\"VERTICAL_DATUM\">EPSG:4965</Item>"),
+        "{}",
+        metadata
+    );
+    let keys = GeoKeys::from_ifd(ifd).unwrap();
+    assert_eq!(keys.epsg(), Some(2154));
+    assert_eq!(keys.short(KEY_VERTICAL_CS_TYPE), Some(4965));
+
+    let mut file = File::open(&path).unwrap();
+    let offset = ifd.integers(TAG_STRIP_OFFSETS).unwrap()[0];
+    file.seek(SeekFrom::Start(offset as u64)).unwrap();
+    let mut bytes = vec![0; 4 * 8];
+    file.read_exact(&mut bytes).unwrap();
+    let heights: Vec<f32> = bytes
+        .chunks(4)
+        .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+        .collect();
+    assert_eq!(heights, [41.5, 43.5, 45.5, 47.5, 45.5, 47.5, 49.5, 51.5]);
+}
+
+#[test]
+fn gdal_outputs_copy_the_datum_from_a_tagged_source() {
+    let config = Config {
+        dem: DemOptions {
+            vertical: VerticalOptions {
+                datum: Some("EPSG:5720".to_string()),
+                ..VerticalOptions::default()
+            },
+            ..DemOptions::default()
+        },
+        ..Config::default()
+    };
+    let plan = vrt_maker::plan_convert(&config, Stages::DEM, None, None);
+    let tag = plan.steps[0].to_string();
+    assert!(
+        tag.starts_with("gdal_translate -of VRT -mo VERTICAL_DATUM=EPSG:5720 "),
+        "{}",
+        tag
+    );
+    assert!(tag.ends_with("dem_datum.vrt"), "{}", tag);
+    assert_eq!(plan.steps[1].inputs, [plan.steps[0].output.clone()]);
+
+    let invalid = |vertical: VerticalOptions| {
+        Config {
+            dem: DemOptions {
+                vertical,
+                ..DemOptions::default()
+            },
+            ..Config::default()
+        }
+        .validate()
+        .unwrap_err()
+        .to_string()
+    };
+    let with_geoid = VerticalOptions {
+        geoid: Some("geoid.asc".into()),
+        ..VerticalOptions::default()
+    };
+    assert!(invalid(with_geoid.clone()).contains("needs dem.vertical.output_datum"));
+    let converted = VerticalOptions {
+        output_datum: Some("EPSG:4965".to_string()),
+        ..with_geoid
+    };
+    assert!(invalid(converted).contains("needs dem.fill.backend = \"native\""));
+    assert!(invalid(VerticalOptions {
+        datum: Some("sea level".to_string()),
+        ..VerticalOptions::default()
+    })
+    .contains("dem.vertical.datum must be"));
+}
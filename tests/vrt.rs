@@ -0,0 +1,306 @@
+//! Native mosaics and JPEG 2000 georeferencing: the VRT's grid and
+//! sources, type promotion, the GeoJP2, GMLJP2 and world file origins, and
+//! corrupt TIFF directories and JP2 boxes.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use vrt_maker::config::{GeoTiffOptions, VrtResolution};
+use vrt_maker::jp2;
+use vrt_maker::raster::{DataType, RasterInfo};
+use vrt_maker::tiff::{TiffError, TiffReader, TAG_MODEL_PIXEL_SCALE};
+use vrt_maker::tiff_writer::{GeoTiffImage, GeoTiffWriter};
+use vrt_maker::vrt::{Mosaic, VrtError};
+
+fn tile(name: &str, geo_transform: [f64; 6], size: usize, nodata: Option<f64>) -> RasterInfo {
+    RasterInfo {
+        path: PathBuf::from(name),
+        width: size,
+        height: size,
+        bands: 1,
+        data_type: DataType::Float32,
+        geo_transform,
+        nodata,
+        crs: Some("EPSG:2154".to_string()),
+    }
+}
+
+/// The lines of `xml` holding `element`, trimmed.
+fn lines<'a>(xml: &'a str, element: &str) -> Vec<&'a str> {
+    xml.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with(&format!("<{}", element)))
+        .collect()
+}
+
+#[test]
+fn highest_resolution_mosaic_covers_every_tile() {
+    // A 10 m square of 1 m pixels, and east of it the top half of another
+    // square in 0.5 m pixels.
+    let tiles = vec![
+        tile("a.tif", [0.0, 1.0, 0.0, 10.0, 0.0, -1.0], 10, None),
+        tile("b.tif", [10.0, 0.5, 0.0, 10.0, 0.0, -0.5], 10, None),
+    ];
+    let mosaic = Mosaic::new(tiles, VrtResolution::Highest).unwrap();
+    assert_eq!(mosaic.geo_transform, [0.0, 0.5, 0.0, 10.0, 0.0, -0.5]);
+    assert_eq!((mosaic.width, mosaic.height), (30, 20));
+    let extent = mosaic.extent();
+    assert_eq!(
+        (extent.min_x, extent.min_y, extent.max_x, extent.max_y),
+        (0.0, 0.0, 15.0, 10.0)
+    );
+
+    let xml = mosaic.to_xml();
+    assert_eq!(
+        lines(&xml, "DstRect"),
+        [
+            "<DstRect xOff=\"0\" yOff=\"0\" xSize=\"20\" ySize=\"20\" />",
+            "<DstRect xOff=\"20\" yOff=\"0\" xSize=\"10\" ySize=\"10\" />",
+        ]
+    );
+    assert_eq!(
+        lines(&xml, "SrcRect")[0],
+        "<SrcRect xOff=\"0\" yOff=\"0\" xSize=\"10\" ySize=\"10\" />"
+    );
+
+    let lowest = Mosaic::new(mosaic.tiles.clone(), VrtResolution::Lowest).unwrap();
+    assert_eq!((lowest.width, lowest.height), (15, 10));
+}
+
+#[test]
+fn tiles_with_nodata_are_complex_sources() {
+    let tiles = vec![
+        tile("a.tif", [0.0, 1.0, 0.0, 4.0, 0.0, -1.0], 4, Some(-99999.0)),
+        tile("b.tif", [4.0, 1.0, 0.0, 4.0, 0.0, -1.0], 4, None),
+    ];
+    let xml = Mosaic::new(tiles, VrtResolution::Highest).unwrap().to_xml();
+    assert_eq!(lines(&xml, "ComplexSource").len(), 1);
+    assert_eq!(lines(&xml, "SimpleSource").len(), 1);
+    assert_eq!(lines(&xml, "NODATA"), ["<NODATA>-99999</NODATA>"]);
+    assert_eq!(
+        lines(&xml, "NoDataValue"),
+        ["<NoDataValue>-99999</NoDataValue>"]
+    );
+    let complex = xml.find("<ComplexSource>").unwrap();
+    assert!(xml[complex..]
+        .starts_with("<ComplexSource>\n      <SourceFilename relativeToVRT=\"0\">a.tif"));
+}
+
+#[test]
+fn mixed_types_are_promoted() {
+    use DataType::*;
+    for (a, b, union) in [
+        (Byte, Byte, Byte),
+        (Byte, UInt16, UInt16),
+        (Byte, Int16, Int16),
+        (UInt16, Int16, Int32),
+        (UInt16, UInt32, UInt32),
+        (Int16, Int32, Int32),
+        (UInt32, Int16, Float64),
+        (UInt32, Int32, Float64),
+        (Int16, Float32, Float32),
+        (Int32, Float32, Float64),
+        (Float32, Float64, Float64),
+    ] {
+        assert_eq!(a.promote(b), union, "{:?} {:?}", a, b);
+        assert_eq!(b.promote(a), union, "{:?} {:?}", b, a);
+    }
+
+    let mut tiles = vec![
+        tile("a.tif", [0.0, 1.0, 0.0, 4.0, 0.0, -1.0], 4, None),
+        tile("b.tif", [4.0, 1.0, 0.0, 4.0, 0.0, -1.0], 4, None),
+    ];
+    tiles[0].data_type = UInt16;
+    tiles[1].data_type = Int16;
+    let mosaic = Mosaic::new(tiles.clone(), VrtResolution::Highest).unwrap();
+    assert_eq!(mosaic.data_type, Int32);
+    assert!(mosaic.to_xml().contains("dataType=\"Int32\""));
+
+    tiles[1].bands = 3;
+    assert!(matches!(
+        Mosaic::new(tiles, VrtResolution::Highest),
+        Err(VrtError::Incompatible { .. })
+    ));
+}
+
+/// A JP2 box of `kind` holding `payload`.
+fn jp2_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = (8 + payload.len() as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Writes a 200 x 100 single-component 8-bit JP2 holding `boxes` after
+/// its header, without a codestream.
+fn write_jp2(path: &Path, boxes: &[Vec<u8>]) {
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&100u32.to_be_bytes());
+    ihdr.extend_from_slice(&200u32.to_be_bytes());
+    ihdr.extend_from_slice(&1u16.to_be_bytes());
+    ihdr.extend_from_slice(&[7, 7, 0, 0]);
+    let mut file = jp2_box(b"jP  ", &[0x0d, 0x0a, 0x87, 0x0a]);
+    file.extend(jp2_box(b"jp2h", &jp2_box(b"ihdr", &ihdr)));
+    for b in boxes {
+        file.extend_from_slice(b);
+    }
+    fs::write(path, file).unwrap();
+}
+
+#[test]
+fn gmljp2_origin_is_a_pixel_centre() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ortho.jp2");
+    let gml = r#"<gml:FeatureCollection xmlns:gml="http://www.opengis.net/gml">
+  <gml:RectifiedGrid gml:id="grid" dimension="2" srsName="urn:ogc:def:crs:EPSG::2154">
+    <gml:origin><gml:Point><gml:pos>650000.25 6860000.75</gml:pos></gml:Point></gml:origin>
+    <gml:offsetVector>0.5 0</gml:offsetVector>
+    <gml:offsetVector>0 -0.5</gml:offsetVector>
+  </gml:RectifiedGrid>
+</gml:FeatureCollection>"#;
+    write_jp2(
+        &path,
+        &[jp2_box(b"asoc", &jp2_box(b"xml ", gml.as_bytes()))],
+    );
+
+    let info = jp2::raster_info(&path).unwrap();
+    assert_eq!((info.width, info.height, info.bands), (200, 100, 1));
+    assert_eq!(info.data_type, DataType::Byte);
+    assert_eq!(
+        info.geo_transform,
+        [650000.0, 0.5, 0.0, 6860001.0, 0.0, -0.5]
+    );
+    assert_eq!(info.crs.as_deref(), Some("EPSG:2154"));
+}
+
+#[test]
+fn world_file_origin_is_a_pixel_centre() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ortho.jp2");
+    write_jp2(&path, &[]);
+    assert!(jp2::raster_info(&path).is_err());
+
+    fs::write(
+        dir.path().join("ortho.j2w"),
+        "0.5\n0\n0\n-0.5\n650000.25\n6860000.75\n",
+    )
+    .unwrap();
+    fs::write(dir.path().join("ortho.prj"), "EPSG:2154").unwrap();
+    let info = jp2::raster_info(&path).unwrap();
+    assert_eq!(
+        info.geo_transform,
+        [650000.0, 0.5, 0.0, 6860001.0, 0.0, -0.5]
+    );
+    assert_eq!(info.crs.as_deref(), Some("EPSG:2154"));
+}
+
+#[test]
+fn geojp2_box_is_read_as_a_geotiff() {
+    let dir = tempfile::tempdir().unwrap();
+    let tiff = dir.path().join("degenerate.tif");
+    let geo_transform = [650000.0, 0.2, 0.0, 6860000.0, 0.0, -0.2];
+    let image = GeoTiffImage {
+        width: 1,
+        height: 1,
+        geo_transform,
+        crs: Some("EPSG:2154".to_string()),
+        nodata: 0.0,
+        data_type: DataType::Byte,
+    };
+    let mut writer = GeoTiffWriter::create(&tiff, image, &GeoTiffOptions::default()).unwrap();
+    writer.write_row(&[0.0]).unwrap();
+    writer.finish().unwrap();
+
+    const GEOJP2_UUID: [u8; 16] = [
+        0xb1, 0x4b, 0xf8, 0xbd, 0x08, 0x3d, 0x4b, 0x43, 0xa5, 0xae, 0x8c, 0xd7, 0xd5, 0xa6, 0xce,
+        0x03,
+    ];
+    let mut payload = GEOJP2_UUID.to_vec();
+    payload.extend(fs::read(&tiff).unwrap());
+    let path = dir.path().join("ortho.jp2");
+    // The GeoJP2 box wins over a world file.
+    fs::write(dir.path().join("ortho.j2w"), "1\n0\n0\n-1\n0.5\n0.5\n").unwrap();
+    write_jp2(&path, &[jp2_box(b"uuid", &payload)]);
+
+    let meta = jp2::Jp2Metadata::read(&path).unwrap();
+    let georef = meta.geojp2.unwrap();
+    assert_eq!(georef.geo_transform, geo_transform);
+    assert_eq!(georef.crs.as_deref(), Some("EPSG:2154"));
+    let info = jp2::raster_info(&path).unwrap();
+    assert_eq!(info.geo_transform, geo_transform);
+}
+
+#[test]
+fn corrupt_tiff_counts_are_refused() {
+    // A classic TIFF whose only IFD claims 65535 entries.
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+    tiff.extend_from_slice(&u16::MAX.to_le_bytes());
+    let err = TiffReader::new(Cursor::new(&tiff))
+        .unwrap()
+        .read_ifds()
+        .unwrap_err();
+    assert!(matches!(err, TiffError::Format(_)), "{}", err);
+
+    // One entry of 2^32 - 1 doubles.
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+    tiff.extend_from_slice(&1u16.to_le_bytes());
+    tiff.extend_from_slice(&TAG_MODEL_PIXEL_SCALE.to_le_bytes());
+    tiff.extend_from_slice(&12u16.to_le_bytes());
+    tiff.extend_from_slice(&u32::MAX.to_le_bytes());
+    tiff.extend_from_slice(&8u32.to_le_bytes());
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+    let err = TiffReader::new(Cursor::new(&tiff))
+        .unwrap()
+        .read_ifds()
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("runs past the end of the file"),
+        "{}",
+        err
+    );
+
+    // A BigTIFF entry whose byte count overflows.
+    let mut tiff = b"II+\0".to_vec();
+    tiff.extend_from_slice(&8u16.to_le_bytes());
+    tiff.extend_from_slice(&0u16.to_le_bytes());
+    tiff.extend_from_slice(&16u64.to_le_bytes());
+    tiff.extend_from_slice(&1u64.to_le_bytes());
+    tiff.extend_from_slice(&TAG_MODEL_PIXEL_SCALE.to_le_bytes());
+    tiff.extend_from_slice(&12u16.to_le_bytes());
+    tiff.extend_from_slice(&u64::MAX.to_le_bytes());
+    tiff.extend_from_slice(&16u64.to_le_bytes());
+    tiff.extend_from_slice(&0u64.to_le_bytes());
+    let err = TiffReader::new(Cursor::new(&tiff))
+        .unwrap()
+        .read_ifds()
+        .unwrap_err();
+    assert!(matches!(err, TiffError::Format(_)), "{}", err);
+}
+
+#[test]
+fn corrupt_jp2_box_lengths_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ortho.jp2");
+    // An extended length running far past the end of the file.
+    let mut xl = 1u32.to_be_bytes().to_vec();
+    xl.extend_from_slice(b"xml ");
+    xl.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+    write_jp2(&path, &[xl]);
+    let err = jp2::Jp2Metadata::read(&path).unwrap_err();
+    assert!(matches!(err, jp2::Jp2Error::Format(_)), "{}", err);
+    assert!(
+        err.to_string().contains("overruns its container"),
+        "{}",
+        err
+    );
+
+    // A box shorter than its own header.
+    write_jp2(
+        &path,
+        &[4u32.to_be_bytes().into_iter().chain(*b"xml ").collect()],
+    );
+    assert!(jp2::Jp2Metadata::read(&path).is_err());
+}